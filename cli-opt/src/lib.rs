@@ -13,7 +13,7 @@
 
 // You should have received a copy of the GNU General Public License
 // along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
-use std::str::FromStr;
+use std::{path::PathBuf, str::FromStr};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum EthApi {
@@ -35,10 +35,37 @@ impl FromStr for EthApi {
 	}
 }
 
+/// When the trace index is extended with new blocks.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TraceIndex {
+	Imported,
+	Finalized,
+}
+
+impl FromStr for TraceIndex {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(match s {
+			"imported" => Self::Imported,
+			"finalized" => Self::Finalized,
+			_ => return Err(format!("`{}` is not a supported trace index mode", s)),
+		})
+	}
+}
+
 pub struct RpcConfig {
 	pub ethapi: Vec<EthApi>,
 	pub ethapi_max_permits: u32,
 	pub ethapi_trace_max_count: u32,
 	pub ethapi_trace_cache_duration: u64,
+	pub ethapi_trace_cache_size: u64,
+	pub ethapi_debug_timeout: u64,
+	pub ethapi_debug_block_timeout: u64,
+	pub ethapi_trace_timeout: u64,
+	pub ethapi_trace_filter_timeout: u64,
+	pub ethapi_trace_index: Option<TraceIndex>,
+	pub ethapi_trace_index_backfill: Option<u32>,
+	pub ethapi_tracing_runtimes: Option<PathBuf>,
 	pub max_past_logs: u32,
 }
@@ -0,0 +1,461 @@
+// Copyright 2019-2021 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{
+	BlockTracer, CallListTracer, Deadline, ReplayTracer, SingleTracer, StepWindow, TimedOut,
+	Tracer, TracingRuntime, TracingRuntimes, TransactionOrigin,
+};
+use codec::{Decode, Encode};
+use ethereum::Transaction;
+use hedgeware_rpc_primitives_debug::{
+	block,
+	replay::{ReplayTraceType, TransactionReplay},
+	single::{TraceType, TransactionTrace},
+	v1, DebugRuntimeApi,
+};
+use sc_client_api::backend::Backend;
+use sp_api::{ApiError, ApiExt, BlockId, CallApiAt, ProvideRuntimeApi, RuntimeApiInfo};
+use sp_blockchain::HeaderBackend;
+use sp_runtime::{traits::Block as BlockT, DispatchError};
+use std::{fmt, time::Instant};
+
+/// First version of `DebugRuntimeApi` sending the events of the executions through host
+/// functions.
+pub const EVENTS_API_VERSION: u32 = 2;
+
+/// Error of a call to `DebugApi`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TraceError {
+	/// The runtime could not be called, or its result decoded.
+	Api(String),
+	/// The deadline has been reached, the execution being aborted.
+	TimedOut,
+	/// The state of the given block, which the calls are made on, is no longer available.
+	StatePruned(String),
+	/// The runtime does not implement the method, or `DebugRuntimeApi` at all.
+	Unsupported(String),
+}
+
+impl From<String> for TraceError {
+	fn from(error: String) -> Self {
+		Self::Api(error)
+	}
+}
+
+impl From<TimedOut> for TraceError {
+	fn from(_: TimedOut) -> Self {
+		Self::TimedOut
+	}
+}
+
+impl fmt::Display for TraceError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Api(error) | Self::Unsupported(error) => f.write_str(error),
+			Self::TimedOut => f.write_str("execution timeout"),
+			Self::StatePruned(block) => write!(f, "State of block {} is pruned", block),
+		}
+	}
+}
+
+/// Version of `DebugRuntimeApi` implemented by a runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ApiVersion {
+	/// The runtime builds the traces and returns them.
+	Legacy,
+	/// The runtime sends the events of the executions, the traces being built by the node.
+	Events,
+}
+
+impl ApiVersion {
+	fn new(has_events_api: bool, has_api: bool) -> Result<Self, TraceError> {
+		match (has_events_api, has_api) {
+			(true, _) => Ok(Self::Events),
+			(false, true) => Ok(Self::Legacy),
+			(false, false) => Err(TraceError::Unsupported(
+				"The runtime does not implement DebugRuntimeApi".to_string(),
+			)),
+		}
+	}
+}
+
+/// Runtime executing the calls of `DebugApi`.
+enum Runtime<'a, B: BlockT, C: ProvideRuntimeApi<B> + 'a, BE: Backend<B>> {
+	/// Client calling the on-chain runtime, through a new `ApiRef` for each call so that
+	/// the changes made by a call are not seen by the next ones.
+	OnChain(&'a C),
+	Override {
+		state: BE::State,
+		runtime: &'a TracingRuntime,
+		runtimes: &'a TracingRuntimes<B, BE>,
+	},
+}
+
+/// `DebugRuntimeApi` of the runtime a block has been built with, or of the tracing runtime
+/// replacing it.
+///
+/// The traces are decoded according to the version of the API: older runtimes return them,
+/// while newer ones send the events of the executions to the tracers of this crate.
+///
+/// The calls are independent from each other, the changes made by one being discarded.
+pub struct DebugApi<'a, B: BlockT, C: ProvideRuntimeApi<B> + 'a, BE: Backend<B>> {
+	at: BlockId<B>,
+	version: ApiVersion,
+	runtime: Runtime<'a, B, C, BE>,
+	deadline: Option<Deadline>,
+	step_window: Option<StepWindow>,
+}
+
+impl<'a, B, C, BE> DebugApi<'a, B, C, BE>
+where
+	B: BlockT,
+	C: ProvideRuntimeApi<B> + CallApiAt<B> + 'a,
+	C::Api: DebugRuntimeApi<B>,
+	BE: Backend<B>,
+{
+	/// API of the runtime at the given block, replaced by a tracing runtime if there is one for
+	/// its spec version.
+	pub fn new(
+		client: &'a C,
+		backend: &BE,
+		runtimes: Option<&'a TracingRuntimes<B, BE>>,
+		at: BlockId<B>,
+	) -> Result<Self, TraceError> {
+		check_state(backend, at)?;
+
+		if let Some(runtimes) = runtimes {
+			let spec_version = client
+				.runtime_version_at(&at)
+				.map_err(|e| format!("Failed to get the runtime version at {} : {:?}", at, e))?
+				.spec_version;
+
+			if let Some(runtime) = runtimes.get(spec_version) {
+				let id = <dyn DebugRuntimeApi<B>>::ID;
+				let version = ApiVersion::new(
+					runtime
+						.version()
+						.has_api_with(&id, |version| version >= EVENTS_API_VERSION),
+					runtime.version().has_api_with(&id, |_| true),
+				)?;
+				let state = backend
+					.state_at(at)
+					.map_err(|e| format!("Failed to get the state at {} : {:?}", at, e))?;
+
+				return Ok(Self {
+					at,
+					version,
+					runtime: Runtime::Override {
+						state,
+						runtime,
+						runtimes,
+					},
+					deadline: None,
+					step_window: None,
+				});
+			}
+		}
+
+		let api = client.runtime_api();
+		let version = ApiVersion::new(
+			api.has_api_with::<dyn DebugRuntimeApi<B>, _>(&at, |version| {
+				version >= EVENTS_API_VERSION
+			})
+			.map_err(|e| format!("Runtime api access error: {:?}", e))?,
+			api.has_api::<dyn DebugRuntimeApi<B>>(&at)
+				.map_err(|e| format!("Runtime api access error: {:?}", e))?,
+		)?;
+
+		Ok(Self {
+			at,
+			version,
+			runtime: Runtime::OnChain(client),
+			deadline: None,
+			step_window: None,
+		})
+	}
+
+	/// Abort the executions once the deadline is reached. Legacy runtimes cannot be aborted,
+	/// the deadline only being checked before calling them.
+	pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+		self.deadline = deadline.map(Deadline::new);
+		self
+	}
+
+	/// Same as `with_deadline`, with a deadline which can be moved while the executions run.
+	pub fn with_shared_deadline(mut self, deadline: Deadline) -> Self {
+		self.deadline = Some(deadline);
+		self
+	}
+
+	/// Only keep the steps of the window, if any, in the raw traces of transactions.
+	pub fn with_step_window(mut self, step_window: Option<StepWindow>) -> Self {
+		self.step_window = step_window;
+		self
+	}
+
+	/// Whether the runtime sends the events of the executions, and thus traces the transactions
+	/// of a block in a single call.
+	pub fn sends_events(&self) -> bool {
+		self.version == ApiVersion::Events
+	}
+
+	/// Trace a transaction, the extrinsics preceding it in its block being applied first.
+	pub fn trace_transaction(
+		&self,
+		extrinsics: Vec<B::Extrinsic>,
+		transaction: &Transaction,
+		trace_type: TraceType,
+	) -> Result<Result<TransactionTrace, DispatchError>, TraceError> {
+		match self.version {
+			ApiVersion::Legacy => {
+				let legacy_trace_type = v1::single::TraceType::from_trace_type(trace_type)
+					.ok_or_else(|| {
+						TraceError::Unsupported(format!(
+							"Tracer {:?} is not supported by the runtime of block {}",
+							trace_type, self.at
+						))
+					})?;
+				self.check_deadline()?;
+				let result = self
+					.call(
+						"trace_transaction",
+						(extrinsics, transaction, legacy_trace_type),
+						|api, at, (ext, tx, trace_type)| {
+							#[allow(deprecated)]
+							let result =
+								api.trace_transaction_before_version_2(at, ext, tx, trace_type);
+							result
+						},
+					)?
+					.map(TransactionTrace::from);
+				Ok(match self.step_window {
+					Some(window) => result.map(|trace| window.apply(trace)),
+					None => result,
+				})
+			}
+			ApiVersion::Events => {
+				let (tracer, result) = SingleTracer::new(trace_type)
+					.with_step_window(self.step_window)
+					.trace_until(self.deadline.clone(), || {
+						self.call(
+							"trace_transaction",
+							(extrinsics, transaction, trace_type),
+							|api, at, (ext, tx, trace_type)| {
+								api.trace_transaction(at, ext, tx, trace_type)
+							},
+						)
+					});
+				Ok(result??.map(|()| tracer.into_tx_trace()))
+			}
+		}
+	}
+
+	/// Trace the calls of all the extrinsics of a block.
+	pub fn trace_block(
+		&self,
+		extrinsics: Vec<B::Extrinsic>,
+	) -> Result<Result<Vec<block::TransactionTrace>, DispatchError>, TraceError> {
+		match self.version {
+			ApiVersion::Legacy => {
+				self.check_deadline()?;
+				let result = self.call("trace_block", (extrinsics,), |api, at, (ext,)| {
+					#[allow(deprecated)]
+					let result = api.trace_block_before_version_2(at, ext);
+					result
+				})?;
+				Ok(result.map(|traces| {
+					traces
+						.into_iter()
+						.map(block::TransactionTrace::from)
+						.collect()
+				}))
+			}
+			ApiVersion::Events => {
+				let (tracer, result) = BlockTracer::new(CallListTracer::new)
+					.trace_until(self.deadline.clone(), || {
+						self.call("trace_block", (extrinsics,), |api, at, (ext,)| {
+							api.trace_block(at, ext)
+						})
+					});
+				Ok(result??.map(|()| tracer.into_block_traces()))
+			}
+		}
+	}
+
+	/// Trace each Ethereum transaction of a block, and the EVM executions started by other
+	/// extrinsics.
+	pub fn trace_block_transactions(
+		&self,
+		extrinsics: Vec<B::Extrinsic>,
+		trace_type: TraceType,
+	) -> Result<Result<Vec<(TransactionOrigin, TransactionTrace)>, DispatchError>, TraceError> {
+		self.require_events_api("trace_block_transactions")?;
+
+		let step_window = self.step_window;
+		let (tracer, result) = BlockTracer::new(move || {
+			SingleTracer::new(trace_type).with_step_window(step_window)
+		})
+		.trace_until(self.deadline.clone(), || {
+			self.call(
+				"trace_block_transactions",
+				(extrinsics, trace_type),
+				|api, at, (ext, trace_type)| api.trace_block_transactions(at, ext, trace_type),
+			)
+		});
+		Ok(result??.map(|()| {
+			tracer
+				.into_transactions()
+				.into_iter()
+				.map(|(origin, tracer)| (origin, tracer.into_tx_trace()))
+				.collect()
+		}))
+	}
+
+	/// Replay a transaction, the extrinsics preceding it in its block being applied first.
+	pub fn replay_transaction(
+		&self,
+		extrinsics: Vec<B::Extrinsic>,
+		transaction: &Transaction,
+		trace_type: ReplayTraceType,
+	) -> Result<Result<TransactionReplay, DispatchError>, TraceError> {
+		self.require_events_api("replay_transaction")?;
+
+		let (tracer, result) =
+			ReplayTracer::new(trace_type).trace_until(self.deadline.clone(), || {
+				self.call(
+					"replay_transaction",
+					(extrinsics, transaction, trace_type),
+					|api, at, (ext, tx, trace_type)| {
+						api.replay_transaction(at, ext, tx, trace_type)
+					},
+				)
+			});
+		Ok(result??.map(|()| tracer.into_replay()))
+	}
+
+	/// Replay each Ethereum transaction of a block.
+	pub fn replay_block(
+		&self,
+		extrinsics: Vec<B::Extrinsic>,
+		trace_type: ReplayTraceType,
+	) -> Result<Result<Vec<TransactionReplay>, DispatchError>, TraceError> {
+		self.require_events_api("replay_block")?;
+
+		let (tracer, result) = BlockTracer::new(move || ReplayTracer::new(trace_type))
+			.trace_until(self.deadline.clone(), || {
+				self.call(
+					"replay_block",
+					(extrinsics, trace_type),
+					|api, at, (ext, trace_type)| api.replay_block(at, ext, trace_type),
+				)
+			});
+		Ok(result??.map(|()| {
+			tracer
+				.into_transactions()
+				.into_iter()
+				.map(|(_, tracer)| tracer.into_replay())
+				.collect()
+		}))
+	}
+
+	fn check_deadline(&self) -> Result<(), TimedOut> {
+		match &self.deadline {
+			Some(deadline) if deadline.expired() => Err(TimedOut),
+			_ => Ok(()),
+		}
+	}
+
+	/// Methods added by the version sending events have no legacy counterpart.
+	fn require_events_api(&self, method: &str) -> Result<(), TraceError> {
+		match self.version {
+			ApiVersion::Events => Ok(()),
+			ApiVersion::Legacy => Err(TraceError::Unsupported(format!(
+				"`{}` is not supported by the runtime of block {}",
+				method, self.at
+			))),
+		}
+	}
+
+	/// Call a method of `DebugRuntimeApi`, through the client for on-chain runtimes. Tracing
+	/// runtimes are called with the SCALE-encoded arguments, and their result is decoded the
+	/// same way the client does.
+	fn call<A: Encode, R: Decode>(
+		&self,
+		method: &str,
+		args: A,
+		on_chain: impl FnOnce(&C::Api, &BlockId<B>, A) -> Result<R, ApiError>,
+	) -> Result<R, String> {
+		match &self.runtime {
+			Runtime::OnChain(client) => on_chain(&*client.runtime_api(), &self.at, args)
+				.map_err(|e| format!("Runtime api access error: {:?}", e)),
+			Runtime::Override {
+				state,
+				runtime,
+				runtimes,
+			} => {
+				let output = runtimes.call(
+					state,
+					runtime,
+					&format!("DebugRuntimeApi_{}", method),
+					&args.encode(),
+				)?;
+				R::decode(&mut &output[..])
+					.map_err(|e| format!("Failed to decode the result of `{}` : {}", method, e))
+			}
+		}
+	}
+}
+
+/// Check that the state of the given block is still available, for a pruned state not to be
+/// reported as a failed runtime call. The state is only reported as pruned if the block is
+/// known, other errors of the backend being reported as such.
+pub fn check_state<B: BlockT, BE: Backend<B>>(
+	backend: &BE,
+	at: BlockId<B>,
+) -> Result<(), TraceError> {
+	let error = match backend.state_at(at) {
+		Ok(_) => return Ok(()),
+		Err(e) => e,
+	};
+
+	match backend.blockchain().header(at) {
+		Ok(Some(_)) => Err(TraceError::StatePruned(at.to_string())),
+		_ => Err(TraceError::Api(format!(
+			"Failed to get the state of block {} : {:?}",
+			at, error
+		))),
+	}
+}
+
+/// Check that the runtime at the given block sends the events of the executions, which is
+/// required to trace calls on top of its state.
+pub fn check_events_api<B, Api>(api: &Api, at: &BlockId<B>) -> Result<(), TraceError>
+where
+	B: BlockT,
+	Api: ApiExt<B> + DebugRuntimeApi<B>,
+{
+	let has_events_api = api
+		.has_api_with::<dyn DebugRuntimeApi<B>, _>(at, |version| version >= EVENTS_API_VERSION)
+		.map_err(|e| format!("Runtime api access error: {:?}", e))?;
+	if has_events_api {
+		Ok(())
+	} else {
+		Err(TraceError::Unsupported(format!(
+			"Tracing calls is not supported by the runtime of block {}",
+			at
+		)))
+	}
+}
@@ -15,15 +15,15 @@
 // along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::util::*;
-use ethereum_types::{H160, U256};
-use evm::{Capture, ExitError, ExitReason, ExitSucceed};
+use ethereum_types::{H160, H256, U256};
+use evm::ExitSucceed;
 use hedgeware_rpc_primitives_debug::{
-	single::{Call, CallInner, TransactionTrace},
-	CallResult, CallType, CreateResult,
+	single::{Call, CallInner, Log, TransactionTrace},
+	decode_revert_reason, CallResult, CallType, CreateResult,
 };
-use sp_std::collections::btree_map::BTreeMap;
+use std::collections::BTreeMap;
 
-/// Listen to EVM events to provide a overview of the internal transactions.
+/// Listen to the EVM events sent by the runtime to provide a overview of the internal transactions.
 /// It can be used to implement `trace_filter`.
 ///
 /// # Output example
@@ -74,6 +74,9 @@ pub struct CallListTracer {
 	// Then by looking at call traps events we can set this value to the correct
 	// call type, to be used when the following `Call` event is received.
 	call_type: Option<CallType>,
+
+	// Log of the current step, only emitted if the step succeeds.
+	pending_log: Option<PendingLog>,
 }
 
 #[derive(Debug)]
@@ -94,6 +97,67 @@ struct Context {
 	data: Vec<u8>,
 	// to / create address
 	to: H160,
+
+	logs: Vec<Log>,
+}
+
+#[derive(Debug)]
+struct PendingLog {
+	address: H160,
+	topics: Vec<H256>,
+	// Part of the data within the memory, the rest being zeros.
+	data: Vec<u8>,
+	size: usize,
+}
+
+impl PendingLog {
+	/// Read the log of a `LOG0` to `LOG4` opcode from the machine state before its execution.
+	fn from_step(address: H160, opcode: Opcode, stack: &[H256], memory: &[u8]) -> Option<Self> {
+		let topics_count = match opcode.0 {
+			0xa0..=0xa4 => (opcode.0 - 0xa0) as usize,
+			_ => return None,
+		};
+
+		// The top of the stack is the last item: offset, size and then the topics.
+		let mut items = stack.iter().rev();
+		let offset = items.next()?;
+		let size = stack_usize(items.next()?)?;
+		let topics = items.take(topics_count).cloned().collect();
+
+		let data = if size == 0 {
+			vec![]
+		} else {
+			let offset = stack_usize(offset)?;
+			memory
+				.get(offset..)
+				.map(|data| data[..std::cmp::min(size, data.len())].to_vec())
+				.unwrap_or_default()
+		};
+
+		Some(Self {
+			address,
+			topics,
+			data,
+			size,
+		})
+	}
+
+	fn into_log(mut self, position: u32) -> Log {
+		self.data.resize(self.size, 0);
+		Log {
+			address: self.address,
+			topics: self.topics,
+			data: self.data,
+			position,
+			reverted: false,
+		}
+	}
+}
+
+impl Default for CallListTracer {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl CallListTracer {
@@ -107,31 +171,9 @@ impl CallListTracer {
 			context_stack: vec![],
 
 			call_type: None,
-		}
-	}
 
-	/// Setup event listeners and execute provided closure.
-	///
-	/// Consume the tracer and return it alongside the return value of
-	/// the closure.
-	pub fn trace<R, F: FnOnce() -> R>(self, f: F) -> (Self, R) {
-		let wrapped = Rc::new(RefCell::new(self));
-
-		let result = {
-			let mut gasometer = ListenerProxy(Rc::clone(&wrapped));
-			let mut runtime = ListenerProxy(Rc::clone(&wrapped));
-			let mut evm = ListenerProxy(Rc::clone(&wrapped));
-
-			// Each line wraps the previous `f` into a `using` call.
-			// Listening to new events results in adding one new line.
-			// Order is irrelevant when registering listeners.
-			let f = || runtime_using(&mut runtime, f);
-			let f = || gasometer_using(&mut gasometer, f);
-			let f = || evm_using(&mut evm, f);
-			f()
-		};
-
-		(Rc::try_unwrap(wrapped).unwrap().into_inner(), result)
+			pending_log: None,
+		}
 	}
 
 	pub fn into_tx_trace(self) -> TransactionTrace {
@@ -139,8 +181,19 @@ impl CallListTracer {
 	}
 }
 
-impl GasometerListener for CallListTracer {
-	fn event(&mut self, event: GasometerEvent) {
+impl Listener for CallListTracer {
+	fn event(&mut self, event: Event) {
+		match event {
+			Event::Evm(event) => self.evm_event(event),
+			Event::Gasometer(event) => self.gasometer_event(event),
+			Event::Runtime(event) => self.runtime_event(event),
+			_ => (),
+		}
+	}
+}
+
+impl CallListTracer {
+	fn gasometer_event(&mut self, event: GasometerEvent) {
 		match event {
 			GasometerEvent::RecordCost { snapshot, .. }
 			| GasometerEvent::RecordDynamicCost { snapshot, .. }
@@ -153,21 +206,38 @@ impl GasometerListener for CallListTracer {
 				}
 			}
 			GasometerEvent::RecordTransaction { cost, .. } => self.transaction_cost = cost,
-			// We ignore other kinds of message if any (new ones may be added in the future).
-			#[allow(unreachable_patterns)]
-			_ => (),
+			GasometerEvent::RecordRefund { .. } => (),
 		}
 	}
-}
 
-impl RuntimeListener for CallListTracer {
-	fn event(&mut self, event: RuntimeEvent) {
+	fn runtime_event(&mut self, event: RuntimeEvent) {
 		match event {
+			RuntimeEvent::Step {
+				context,
+				opcode,
+				stack,
+				memory,
+				..
+			} => {
+				self.pending_log = match (stack, memory) {
+					(Some(stack), Some(memory)) => {
+						PendingLog::from_step(context.address, Opcode(opcode), &stack, &memory)
+					}
+					_ => None,
+				};
+			}
+			RuntimeEvent::StepResult { result: Ok(()), .. } => {
+				if let (Some(log), Some(context)) =
+					(self.pending_log.take(), self.context_stack.last_mut())
+				{
+					context.logs.push(log.into_log(context.subtraces));
+				}
+			}
 			RuntimeEvent::StepResult {
 				result: Err(Capture::Trap(opcode)),
 				..
 			} => {
-				if let Some(ContextType::Call(call_type)) = ContextType::from(*opcode) {
+				if let Some(ContextType::Call(call_type)) = ContextType::from(Opcode(opcode)) {
 					self.call_type = Some(call_type)
 				}
 			}
@@ -180,24 +250,32 @@ impl RuntimeListener for CallListTracer {
 					if context.entries_index == 0 {
 						gas_used += self.transaction_cost;
 					}
+					let entries_index = context.entries_index;
 
 					self.entries.insert(
 						context.entries_index,
 						match context.context_type {
 							ContextType::Call(call_type) => {
 								let res = match &reason {
-									ExitReason::Succeed(ExitSucceed::Returned) => {
-										CallResult::Output(return_value.to_vec())
-									}
-									ExitReason::Succeed(_) => CallResult::Output(vec![]),
-									ExitReason::Error(error) => {
-										CallResult::Error(error_message(error))
-									}
-
-									ExitReason::Revert(_) => {
-										CallResult::Error(b"execution reverted".to_vec())
-									}
-									ExitReason::Fatal(_) => CallResult::Error(vec![]),
+									ExitReason::Succeed(ExitSucceed::Returned) => CallResult::Output {
+										output: return_value.clone(),
+									},
+									ExitReason::Succeed(_) => CallResult::Output { output: vec![] },
+									ExitReason::Error(error) => CallResult::Error {
+										error: error_message(error),
+										revert_reason: None,
+										output: vec![],
+									},
+									ExitReason::Revert(_) => CallResult::Error {
+										error: b"execution reverted".to_vec(),
+										revert_reason: decode_revert_reason(&return_value),
+										output: return_value.clone(),
+									},
+									ExitReason::Fatal(_) => CallResult::Error {
+										error: vec![],
+										revert_reason: None,
+										output: vec![],
+									},
 								};
 
 								Call {
@@ -213,21 +291,30 @@ impl RuntimeListener for CallListTracer {
 										input: context.data,
 										res,
 									},
+									logs: context.logs,
 								}
 							}
 							ContextType::Create => {
 								let res = match &reason {
 									ExitReason::Succeed(_) => CreateResult::Success {
 										created_contract_address_hash: context.to,
-										created_contract_code: return_value.to_vec(),
+										created_contract_code: return_value.clone(),
 									},
 									ExitReason::Error(error) => CreateResult::Error {
 										error: error_message(error),
+										revert_reason: None,
+										output: vec![],
 									},
 									ExitReason::Revert(_) => CreateResult::Error {
 										error: b"execution reverted".to_vec(),
+										revert_reason: decode_revert_reason(&return_value),
+										output: return_value.clone(),
+									},
+									ExitReason::Fatal(_) => CreateResult::Error {
+										error: vec![],
+										revert_reason: None,
+										output: vec![],
 									},
-									ExitReason::Fatal(_) => CreateResult::Error { error: vec![] },
 								};
 
 								Call {
@@ -241,21 +328,28 @@ impl RuntimeListener for CallListTracer {
 										init: context.data,
 										res,
 									},
+									logs: context.logs,
 								}
 							}
 						},
 					);
+
+					// The logs of a failed call and of all its subcalls, which are the calls
+					// that started after it, are discarded.
+					if !matches!(reason, ExitReason::Succeed(_)) {
+						for (_, call) in self.entries.range_mut(entries_index..) {
+							for log in call.logs.iter_mut() {
+								log.reverted = true;
+							}
+						}
+					}
 				}
 			}
-			// We ignore other kinds of message if any (new ones may be added in the future).
-			#[allow(unreachable_patterns)]
-			_ => (),
+			RuntimeEvent::SLoad { .. } | RuntimeEvent::SStore { .. } => (),
 		}
 	}
-}
 
-impl EvmListener for CallListTracer {
-	fn event(&mut self, event: EvmEvent) {
+	fn evm_event(&mut self, event: EvmEvent) {
 		let trace_address = if let Some(context) = self.context_stack.last_mut() {
 			let mut trace_address = context.trace_address.clone();
 			trace_address.push(context.subtraces);
@@ -294,8 +388,10 @@ impl EvmListener for CallListTracer {
 					gas: 0,
 					start_gas: None,
 
-					data: input.to_vec(),
+					data: input,
 					to: context.address,
+
+					logs: vec![],
 				});
 
 				self.entries_next_index += 1;
@@ -322,8 +418,10 @@ impl EvmListener for CallListTracer {
 					gas: 0,
 					start_gas: None,
 
-					data: init_code.to_vec(),
+					data: init_code,
 					to: address,
+
+					logs: vec![],
 				});
 
 				self.entries_next_index += 1;
@@ -346,34 +444,16 @@ impl EvmListener for CallListTracer {
 							refund_address: target,
 							balance,
 						},
+						logs: vec![],
 					},
 				);
 
 				self.entries_next_index += 1;
 			}
-			// We ignore other kinds of message if any (new ones may be added in the future).
-			#[allow(unreachable_patterns)]
-			_ => (),
+			// Transactions are traced from their root call.
+			EvmEvent::TransactCall { .. }
+			| EvmEvent::TransactCreate { .. }
+			| EvmEvent::TransactCreate2 { .. } => (),
 		}
 	}
 }
-
-fn error_message(error: &ExitError) -> Vec<u8> {
-	match error {
-		ExitError::StackUnderflow => "stack underflow",
-		ExitError::StackOverflow => "stack overflow",
-		ExitError::InvalidJump => "invalid jump",
-		ExitError::InvalidRange => "invalid range",
-		ExitError::DesignatedInvalid => "designated invalid",
-		ExitError::CallTooDeep => "call too deep",
-		ExitError::CreateCollision => "create collision",
-		ExitError::CreateContractLimit => "create contract limit",
-		ExitError::OutOfOffset => "out of offset",
-		ExitError::OutOfGas => "out of gas",
-		ExitError::OutOfFund => "out of funds",
-		ExitError::Other(err) => err,
-		_ => "unexpected error",
-	}
-	.as_bytes()
-	.to_vec()
-}
@@ -0,0 +1,220 @@
+// Copyright 2019-2021 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{util::*, CallListTracer};
+use hedgeware_rpc_primitives_debug::{
+	single::{Call, CallFrame, CallFrameType, CallInner, TransactionTrace},
+	CallResult, CallType, CreateResult,
+};
+
+/// Listen to the EVM events sent by the runtime to provide the nested call frames of geth's built-in `callTracer`.
+///
+/// Events are handled by a `CallListTracer`, whose flat list of calls is then nested
+/// using the trace address of each call. With `with_log`, the logs emitted by each call are
+/// provided as well, except for the calls that failed.
+///
+/// # Output example
+///
+/// ```json
+///   {
+///     "type": "CALL",
+///     "from": "0xfe2882ac0a337a976aa73023c2a2a917f57ba2ed",
+///     "to": "0x3ca17a1c4995b95c600275e52da93d2e64dd591f",
+///     "value": "0x0",
+///     "gas": "0xf9be",
+///     "gasUsed": "0xf9be",
+///     "input": "0x",
+///     "output": "0x",
+///     "calls": [
+///       {
+///         "type": "STATICCALL",
+///         "from": "0x3ca17a1c4995b95c600275e52da93d2e64dd591f",
+///         "to": "0x1416aa2a27db08ce3a81a01cdfc981417d28a6e6",
+///         "gas": "0x9b9b",
+///         "gasUsed": "0x4f6d",
+///         "input": "0xfd63983b0000000000000000000000000000000000000000000000000000000000000006",
+///         "output": "0x000000000000000000000000000000000000000000000000000000000000000d"
+///       }
+///     ]
+///   }
+///   ```
+#[derive(Debug)]
+pub struct CallTracer {
+	inner: CallListTracer,
+	with_log: bool,
+}
+
+impl CallTracer {
+	pub fn new(with_log: bool) -> Self {
+		Self {
+			inner: CallListTracer::new(),
+			with_log,
+		}
+	}
+
+	/// Nest the calls into their parent frame.
+	///
+	/// A transaction that did not reach the EVM has no frame, in which case an empty
+	/// call list is returned.
+	pub fn into_tx_trace(self) -> TransactionTrace {
+		match self.inner.into_tx_trace() {
+			TransactionTrace::CallList(calls) => nest_calls(calls, self.with_log),
+			other => other,
+		}
+	}
+}
+
+/// Nest a flat list of calls, ordered by the moment they started, using their trace address.
+///
+/// A call whose parent cannot be found would mean the events of the EVM were lost or
+/// reordered. It is attached to the root frame rather than dropped, and reported.
+pub(crate) fn nest_calls(calls: Vec<Call>, with_log: bool) -> TransactionTrace {
+	let mut root: Option<CallFrame> = None;
+	let mut orphans = Vec::new();
+	// Calls are ordered by the moment they started, so a parent always comes before
+	// its children and children come in the order of their trace address.
+	for call in calls {
+		let trace_address = call.trace_address.clone();
+		let frame = call_frame(call, with_log);
+
+		match trace_address.split_last() {
+			// Only the root call has an empty trace address.
+			None => root = Some(frame),
+			Some((_, parent_address)) => {
+				let parent = root.as_mut().and_then(|root| {
+					parent_address
+						.iter()
+						.try_fold(root, |frame, index| frame.calls.get_mut(*index as usize))
+				});
+				match parent {
+					Some(parent) => parent.calls.push(frame),
+					None => {
+						tracing::warn!(
+							"No parent frame for the call at trace address {:?}, attached to the root frame",
+							trace_address,
+						);
+						orphans.push(frame);
+					}
+				}
+			}
+		}
+	}
+
+	match root {
+		// Attached once all calls are nested, as the trace addresses of the calls coming
+		// after an orphan index the children of the root frame.
+		Some(mut root) => {
+			root.calls.append(&mut orphans);
+			TransactionTrace::CallTracer(root)
+		}
+		None => {
+			if !orphans.is_empty() {
+				tracing::warn!(
+					"Dropped {} call frames of a transaction without a root call",
+					orphans.len(),
+				);
+			}
+			TransactionTrace::CallList(Vec::new())
+		}
+	}
+}
+
+impl Listener for CallTracer {
+	fn event(&mut self, event: Event) {
+		self.inner.event(event);
+	}
+}
+
+fn call_frame(call: Call, with_log: bool) -> CallFrame {
+	let mut frame = CallFrame {
+		call_type: CallFrameType::Call,
+		from: call.from,
+		to: None,
+		value: Some(call.value),
+		gas: call.gas,
+		gas_used: call.gas_used,
+		input: Vec::new(),
+		output: None,
+		error: None,
+		revert_reason: None,
+		logs: Vec::new(),
+		calls: Vec::new(),
+	};
+
+	if with_log {
+		frame.logs = call.logs.into_iter().filter(|log| !log.reverted).collect();
+	}
+
+	match call.inner {
+		CallInner::Call {
+			call_type,
+			to,
+			input,
+			res,
+		} => {
+			frame.call_type = call_type.into();
+			frame.to = Some(to);
+			frame.input = input;
+			if let CallType::DelegateCall | CallType::StaticCall = call_type {
+				frame.value = None;
+			}
+			match res {
+				CallResult::Output { output } => frame.output = Some(output),
+				CallResult::Error {
+					error,
+					revert_reason,
+					output,
+				} => {
+					frame.error = Some(error);
+					frame.revert_reason = revert_reason;
+					frame.output = Some(output).filter(|output| !output.is_empty());
+				}
+			}
+		}
+		CallInner::Create { init, res } => {
+			frame.call_type = CallFrameType::Create;
+			frame.input = init;
+			match res {
+				CreateResult::Success {
+					created_contract_address_hash,
+					created_contract_code,
+				} => {
+					frame.to = Some(created_contract_address_hash);
+					frame.output = Some(created_contract_code);
+				}
+				CreateResult::Error {
+					error,
+					revert_reason,
+					output,
+				} => {
+					frame.error = Some(error);
+					frame.revert_reason = revert_reason;
+					frame.output = Some(output).filter(|output| !output.is_empty());
+				}
+			}
+		}
+		CallInner::SelfDestruct {
+			balance,
+			refund_address,
+		} => {
+			frame.call_type = CallFrameType::SelfDestruct;
+			frame.to = Some(refund_address);
+			frame.value = Some(balance);
+		}
+	}
+
+	frame
+}
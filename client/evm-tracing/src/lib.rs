@@ -0,0 +1,291 @@
+// Copyright 2019-2021 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Client side of the EVM tracing.
+//!
+//! Tracing runtimes send the events of the traced executions through host functions, which
+//! are received by the listeners of this crate while the runtime API call is running. The
+//! traces are thus built natively as the execution progresses, instead of being returned by
+//! the runtime. Each trace is still returned as a whole once built, raw traces being split
+//! into windows of steps (`StepWindow`) rather than streamed.
+//!
+//! Runtimes implementing an older version of `DebugRuntimeApi` return the traces instead,
+//! `DebugApi` calling each runtime the way its version requires. Blocks can also be traced
+//! with tracing runtimes loaded by the node, replacing their on-chain runtime.
+
+mod api;
+mod call_list;
+mod call_tracer;
+mod overrides;
+mod pending;
+mod prestate_tracer;
+mod raw;
+mod replay_tracer;
+mod util;
+mod vm_tracer;
+
+#[cfg(test)]
+mod tests;
+
+pub use api::{check_events_api, check_state, DebugApi, TraceError, EVENTS_API_VERSION};
+pub use call_list::CallListTracer;
+pub use call_tracer::CallTracer;
+pub use hedgeware_evm_tracing_events::{Event, Listener, TransactionOrigin};
+pub use overrides::{TracingRuntime, TracingRuntimes};
+pub use pending::PendingBlock;
+pub use prestate_tracer::PrestateTracer;
+pub use raw::{RawTracer, StepWindow};
+pub use replay_tracer::ReplayTracer;
+pub use vm_tracer::VmTracer;
+
+use hedgeware_rpc_primitives_debug::{
+	block,
+	single::{TraceType, TransactionTrace},
+};
+use std::{
+	sync::{Arc, Mutex, PoisonError},
+	time::Instant,
+};
+
+/// The deadline of a traced execution has been reached, the runtime aborting it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimedOut;
+
+/// Deadline of a traced execution. An execution shared by several requests can be given the
+/// deadline of a later one, or aborted, by moving it from any of its clones while it runs.
+#[derive(Clone, Debug)]
+pub struct Deadline(Arc<Mutex<Instant>>);
+
+impl Deadline {
+	pub fn new(instant: Instant) -> Self {
+		Self(Arc::new(Mutex::new(instant)))
+	}
+
+	/// Instant at which the execution is aborted.
+	pub fn instant(&self) -> Instant {
+		*self.0.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Move the deadline, to the current instant to abort the execution.
+	pub fn set(&self, instant: Instant) {
+		*self.0.lock().unwrap_or_else(PoisonError::into_inner) = instant;
+	}
+
+	pub fn expired(&self) -> bool {
+		Instant::now() >= self.instant()
+	}
+}
+
+/// Listener building a trace from the events sent by the runtime.
+pub trait Tracer: Listener + Sized + 'static {
+	/// Setup the listener and execute provided closure, which calls the runtime API.
+	///
+	/// Consume the tracer and return it alongside the return value of
+	/// the closure.
+	fn trace<R, F: FnOnce() -> R>(mut self, f: F) -> (Self, R) {
+		let result = hedgeware_evm_tracing_events::using(&mut self, f);
+		(self, result)
+	}
+
+	/// Same as `trace`, the runtime being told to abort the execution once the deadline, if
+	/// any, is reached. It is only told so when sending an event, runtimes implementing an
+	/// older version of the host functions not being able to abort.
+	fn trace_until<R, F: FnOnce() -> R>(
+		self,
+		deadline: Option<Deadline>,
+		f: F,
+	) -> (Self, Result<R, TimedOut>) {
+		let deadline = match deadline {
+			Some(deadline) => deadline,
+			None => {
+				let (tracer, result) = self.trace(f);
+				return (tracer, Ok(result));
+			}
+		};
+		if deadline.expired() {
+			return (self, Err(TimedOut));
+		}
+
+		let (listener, result) = DeadlineListener {
+			tracer: self,
+			deadline,
+			expired: false,
+		}
+		.trace(f);
+
+		if listener.expired {
+			(listener.tracer, Err(TimedOut))
+		} else {
+			(listener.tracer, Ok(result))
+		}
+	}
+}
+
+impl<T: Listener + 'static> Tracer for T {}
+
+/// Forward the events to a tracer until a deadline.
+struct DeadlineListener<T> {
+	tracer: T,
+	deadline: Deadline,
+	expired: bool,
+}
+
+impl<T: Listener> Listener for DeadlineListener<T> {
+	fn event(&mut self, event: Event) {
+		self.tracer.event(event);
+	}
+
+	fn aborted(&mut self) -> bool {
+		self.expired = self.expired || self.deadline.expired();
+		self.expired || self.tracer.aborted()
+	}
+}
+
+/// Tracer of a single transaction, depending on the requested `TraceType`.
+#[derive(Debug)]
+pub enum SingleTracer {
+	Raw(RawTracer),
+	CallList(CallListTracer),
+	CallTracer(CallTracer),
+	Prestate(PrestateTracer),
+}
+
+impl SingleTracer {
+	pub fn new(trace_type: TraceType) -> Self {
+		match trace_type {
+			TraceType::Raw {
+				disable_storage,
+				disable_memory,
+				disable_stack,
+			} => Self::Raw(RawTracer::new(disable_storage, disable_memory, disable_stack)),
+			TraceType::CallList => Self::CallList(CallListTracer::new()),
+			TraceType::CallTracer { with_log } => Self::CallTracer(CallTracer::new(with_log)),
+			TraceType::Prestate { diff_mode } => Self::Prestate(PrestateTracer::new(diff_mode)),
+		}
+	}
+
+	/// Only keep the steps of the window, if any, for raw traces.
+	pub fn with_step_window(self, window: Option<StepWindow>) -> Self {
+		match (self, window) {
+			(Self::Raw(tracer), Some(window)) => Self::Raw(tracer.with_window(window)),
+			(tracer, _) => tracer,
+		}
+	}
+
+	pub fn into_tx_trace(self) -> TransactionTrace {
+		match self {
+			Self::Raw(tracer) => tracer.into_tx_trace(),
+			Self::CallList(tracer) => tracer.into_tx_trace(),
+			Self::CallTracer(tracer) => tracer.into_tx_trace(),
+			Self::Prestate(tracer) => tracer.into_tx_trace(),
+		}
+	}
+}
+
+impl Listener for SingleTracer {
+	fn event(&mut self, event: Event) {
+		match self {
+			Self::Raw(tracer) => tracer.event(event),
+			Self::CallList(tracer) => tracer.event(event),
+			Self::CallTracer(tracer) => tracer.event(event),
+			Self::Prestate(tracer) => tracer.event(event),
+		}
+	}
+}
+
+/// Tracer of several transactions, the events of each of them being received by a new
+/// listener. The runtime notifies the start of each transaction.
+pub struct BlockTracer<T> {
+	new_tracer: Box<dyn FnMut() -> T>,
+	/// Origin and tracer of each transaction, and whether it sent any event.
+	transactions: Vec<(TransactionOrigin, T, bool)>,
+}
+
+impl<T> BlockTracer<T> {
+	pub fn new(new_tracer: impl FnMut() -> T + 'static) -> Self {
+		Self {
+			new_tracer: Box::new(new_tracer),
+			transactions: vec![],
+		}
+	}
+
+	/// Tracers of the transactions, in the order they have been executed. Extrinsics which
+	/// aren't Ethereum transactions are only kept if they executed EVM code.
+	pub fn into_transactions(self) -> Vec<(TransactionOrigin, T)> {
+		self.transactions
+			.into_iter()
+			.filter(|(origin, _, executed)| *origin == TransactionOrigin::Ethereum || *executed)
+			.map(|(origin, tracer, _)| (origin, tracer))
+			.collect()
+	}
+}
+
+impl BlockTracer<CallListTracer> {
+	/// Flat list of the calls of the block, as returned by `trace_block`. Other extrinsics are
+	/// identified by their index and hash.
+	pub fn into_block_traces(self) -> Vec<block::TransactionTrace> {
+		let mut traces = vec![];
+		let mut eth_tx_index = 0;
+
+		for (origin, tracer) in self.into_transactions() {
+			let calls = match tracer.into_tx_trace() {
+				TransactionTrace::CallList(calls) => calls,
+				_ => continue,
+			};
+
+			// Convert traces from "single" format to "block" format.
+			match origin {
+				TransactionOrigin::Ethereum => {
+					traces.extend(
+						calls
+							.into_iter()
+							.map(|call| block::TransactionTrace::from_call(call, eth_tx_index)),
+					);
+					eth_tx_index += 1;
+				}
+				// Most extrinsics don't execute any EVM code.
+				TransactionOrigin::Substrate {
+					extrinsic_index,
+					extrinsic_hash,
+				} => traces.extend(calls.into_iter().map(|call| {
+					block::TransactionTrace::from_substrate_call(
+						call,
+						extrinsic_index,
+						extrinsic_hash,
+					)
+				})),
+			}
+		}
+
+		traces
+	}
+}
+
+impl<T: Listener> Listener for BlockTracer<T> {
+	fn event(&mut self, event: Event) {
+		match event {
+			Event::NewTransaction(origin) => {
+				self.transactions.push((origin, (self.new_tracer)(), false));
+			}
+			event => {
+				if let Some((_, tracer, executed)) = self.transactions.last_mut() {
+					*executed = true;
+					tracer.event(event);
+				}
+			}
+		}
+	}
+}
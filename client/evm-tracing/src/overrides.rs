@@ -0,0 +1,195 @@
+// Copyright 2019-2021 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use sc_client_api::backend::Backend;
+use sc_executor::RuntimeInfo;
+use sp_api::RuntimeVersion;
+use sp_core::traits::{CodeExecutor, FetchRuntimeCode, RuntimeCode, SpawnNamed};
+use sp_runtime::traits::{Block as BlockT, HashFor, NumberFor};
+use sp_state_machine::{
+	BasicExternalities, ChangesTrieState, ExecutionStrategy, OverlayedChanges, StateMachine,
+};
+use std::{borrow::Cow, fs, ops::RangeInclusive, path::Path};
+
+/// Runtime call executed on top of a state, with the code of a tracing runtime.
+type Execute<B, BE> = dyn Fn(&<BE as Backend<B>>::State, &RuntimeCode, &str, &[u8]) -> Result<Vec<u8>, String>
+	+ Send
+	+ Sync;
+
+/// WASM blob of a tracing runtime loaded by the node.
+pub struct TracingRuntime {
+	/// Spec versions of the on-chain runtimes it replaces.
+	spec_versions: RangeInclusive<u32>,
+	version: RuntimeVersion,
+	code: Vec<u8>,
+	hash: Vec<u8>,
+}
+
+impl TracingRuntime {
+	/// Version of the tracing runtime itself, which provides the version of its APIs.
+	pub fn version(&self) -> &RuntimeVersion {
+		&self.version
+	}
+
+	fn runtime_code(&self) -> RuntimeCode {
+		RuntimeCode {
+			code_fetcher: self,
+			heap_pages: None,
+			hash: self.hash.clone(),
+		}
+	}
+}
+
+impl FetchRuntimeCode for TracingRuntime {
+	fn fetch_runtime_code<'a>(&'a self) -> Option<Cow<'a, [u8]>> {
+		Some(Cow::Borrowed(&self.code))
+	}
+}
+
+/// Tracing runtimes used instead of the on-chain runtimes to trace the blocks built by them.
+///
+/// On-chain runtimes are built without the `evm-tracing` feature, and older ones may implement
+/// a `DebugRuntimeApi` the node can no longer decode. The blobs are loaded from a directory,
+/// each of them replacing the runtimes whose spec version is in the range given by its file
+/// name (`<name>-<from>-<to>.wasm`), or only its own spec version otherwise.
+pub struct TracingRuntimes<B: BlockT, BE: Backend<B>> {
+	runtimes: Vec<TracingRuntime>,
+	execute: Box<Execute<B, BE>>,
+}
+
+impl<B: BlockT, BE: Backend<B>> TracingRuntimes<B, BE> {
+	/// Load the tracing runtimes of a directory, which are executed by the given executor.
+	pub fn new<E>(
+		dir: &Path,
+		executor: E,
+		spawn_handle: impl SpawnNamed + Clone + Send + Sync + 'static,
+	) -> Result<Self, String>
+	where
+		E: CodeExecutor + RuntimeInfo + Clone + Send + Sync + 'static,
+	{
+		let mut runtimes = vec![];
+
+		let entries = fs::read_dir(dir).map_err(|e| {
+			format!(
+				"Failed to read the tracing runtimes directory {} : {}",
+				dir.display(),
+				e
+			)
+		})?;
+		for entry in entries {
+			let path = entry
+				.map_err(|e| format!("Failed to read the tracing runtimes directory : {}", e))?
+				.path();
+			if path.extension().map_or(true, |extension| extension != "wasm") {
+				continue;
+			}
+
+			let code = fs::read(&path)
+				.map_err(|e| format!("Failed to read tracing runtime {} : {}", path.display(), e))?;
+			let mut runtime = TracingRuntime {
+				spec_versions: 0..=0,
+				version: Default::default(),
+				hash: sp_core::blake2_256(&code).to_vec(),
+				code,
+			};
+			runtime.version = executor
+				.runtime_version(&mut BasicExternalities::default(), &runtime.runtime_code())
+				.map_err(|e| {
+					format!(
+						"Failed to read the version of tracing runtime {} : {}",
+						path.display(),
+						e
+					)
+				})?;
+			runtime.spec_versions = spec_versions(&path, &runtime.version)?;
+
+			runtimes.push(runtime);
+		}
+
+		// A block must not be traced with different runtimes depending on the load order.
+		runtimes.sort_by_key(|runtime| *runtime.spec_versions.start());
+		for pair in runtimes.windows(2) {
+			if pair[1].spec_versions.start() <= pair[0].spec_versions.end() {
+				return Err(format!(
+					"Tracing runtimes for spec versions {:?} and {:?} overlap",
+					pair[0].spec_versions, pair[1].spec_versions
+				));
+			}
+		}
+
+		let execute = move |state: &BE::State,
+		                    runtime_code: &RuntimeCode,
+		                    method: &str,
+		                    call_data: &[u8]| {
+			let mut overlay = OverlayedChanges::default();
+			StateMachine::new(
+				state,
+				None::<ChangesTrieState<HashFor<B>, NumberFor<B>>>,
+				&mut overlay,
+				&executor,
+				method,
+				call_data,
+				Default::default(),
+				runtime_code,
+				spawn_handle.clone(),
+			)
+			.execute(ExecutionStrategy::AlwaysWasm)
+			.map_err(|e| format!("{}", e))
+		};
+
+		Ok(Self {
+			runtimes,
+			execute: Box::new(execute),
+		})
+	}
+
+	/// Tracing runtime replacing the on-chain runtime with the given spec version, if any.
+	pub fn get(&self, spec_version: u32) -> Option<&TracingRuntime> {
+		self.runtimes
+			.iter()
+			.find(|runtime| runtime.spec_versions.contains(&spec_version))
+	}
+
+	/// Call a runtime API method of a tracing runtime on top of the given state. The changes
+	/// made by the call are discarded.
+	pub fn call(
+		&self,
+		state: &BE::State,
+		runtime: &TracingRuntime,
+		method: &str,
+		call_data: &[u8],
+	) -> Result<Vec<u8>, String> {
+		(self.execute)(state, &runtime.runtime_code(), method, call_data)
+	}
+}
+
+/// Spec versions replaced by a tracing runtime, from the name of its file.
+pub(crate) fn spec_versions(path: &Path, version: &RuntimeVersion) -> Result<RangeInclusive<u32>, String> {
+	let stem = path
+		.file_stem()
+		.and_then(|stem| stem.to_str())
+		.unwrap_or_default();
+	let mut bounds = stem.rsplit('-').map(|bound| bound.parse::<u32>());
+
+	match (bounds.next(), bounds.next()) {
+		(Some(Ok(to)), Some(Ok(from))) if from <= to => Ok(from..=to),
+		(Some(Ok(_)), Some(Ok(_))) => Err(format!(
+			"Invalid range of spec versions for tracing runtime {}",
+			path.display()
+		)),
+		_ => Ok(version.spec_version..=version.spec_version),
+	}
+}
@@ -0,0 +1,70 @@
+// Copyright 2019-2021 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use ethereum_types::H256;
+use hedgeware_rpc_primitives_txpool::TxPoolRuntimeApi;
+use sha3::{Digest, Keccak256};
+use sp_api::{BlockId, ProvideRuntimeApi};
+use sp_blockchain::HeaderBackend;
+use sp_runtime::traits::{Block as BlockT, NumberFor};
+
+/// Block which would be built on top of the best block with the ready extrinsics of the pool.
+pub struct PendingBlock<B: BlockT> {
+	/// The best block, the pending block being traced on top of its state.
+	pub parent: BlockId<B>,
+	/// Height of the best block.
+	pub parent_number: NumberFor<B>,
+	/// Extrinsics of the pending block, in the order they would be included.
+	pub extrinsics: Vec<B::Extrinsic>,
+	/// Hashes of the Ethereum transactions among the extrinsics.
+	pub transaction_hashes: Vec<H256>,
+}
+
+impl<B: BlockT> PendingBlock<B> {
+	/// Pending block made of the given ready extrinsics of the pool.
+	pub fn new<C>(client: &C, extrinsics: Vec<B::Extrinsic>) -> Result<Self, String>
+	where
+		C: ProvideRuntimeApi<B> + HeaderBackend<B>,
+		C::Api: TxPoolRuntimeApi<B>,
+	{
+		let info = client.info();
+		let parent = BlockId::Hash(info.best_hash);
+
+		// Use the runtime to match the (here) opaque extrinsics against ethereum transactions.
+		let transaction_hashes = client
+			.runtime_api()
+			.extrinsic_filter(&parent, extrinsics.clone(), vec![])
+			.map_err(|e| {
+				format!(
+					"Runtime error when filtering the pending transactions : {:?}",
+					e
+				)
+			})?
+			.ready
+			.iter()
+			.map(|transaction| {
+				H256::from_slice(Keccak256::digest(&rlp::encode(transaction)).as_slice())
+			})
+			.collect();
+
+		Ok(Self {
+			parent,
+			parent_number: info.best_number,
+			extrinsics,
+			transaction_hashes,
+		})
+	}
+}
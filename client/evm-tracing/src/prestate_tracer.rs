@@ -0,0 +1,242 @@
+// Copyright 2019-2021 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::util::*;
+
+use ethereum_types::{H160, H256, U256};
+use hedgeware_evm_tracing_events::AccountState as EventAccountState;
+use hedgeware_rpc_primitives_debug::{
+	replay::{AccountDiff, Bytes, Diff},
+	single::{PrestateAccount, TransactionTrace},
+};
+use std::collections::BTreeMap;
+
+/// Listen to the state events sent by the runtime to provide the state of every account
+/// touched by a transaction, as geth's built-in `prestateTracer` does.
+///
+/// The runtime collects the touched accounts and storage slots during the execution of the
+/// transaction, and sends their state from before the transaction, as well as their state
+/// after it in diff mode.
+///
+/// # Output example
+///
+/// ```json
+///   {
+///     "0x3ca17a1c4995b95c600275e52da93d2e64dd591f": {
+///       "balance": "0x0",
+///       "nonce": 1,
+///       "code": "0x6080604052...",
+///       "storage": {
+///         "0x0000000000000000000000000000000000000000000000000000000000000000": "0x000000000000000000000000000000000000000000000000000000000000000d"
+///       }
+///     },
+///     "0xfe2882ac0a337a976aa73023c2a2a917f57ba2ed": {
+///       "balance": "0x1bc16d674ec80000",
+///       "nonce": 3
+///     }
+///   }
+///   ```
+///
+/// In diff mode the output is split into `pre` and `post`, both restricted to the accounts
+/// and fields that have been modified. An account missing from `post` has been destroyed.
+#[derive(Debug)]
+pub struct PrestateTracer {
+	diff_mode: bool,
+
+	pre: BTreeMap<H160, AccountState>,
+	post: BTreeMap<H160, AccountState>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AccountState {
+	balance: U256,
+	nonce: U256,
+	code: Vec<u8>,
+	storage: BTreeMap<H256, H256>,
+}
+
+impl AccountState {
+	fn is_empty(&self) -> bool {
+		self.balance.is_zero() && self.nonce.is_zero() && self.code.is_empty()
+	}
+}
+
+impl From<EventAccountState> for AccountState {
+	fn from(state: EventAccountState) -> Self {
+		Self {
+			balance: state.balance,
+			nonce: state.nonce,
+			code: state.code,
+			storage: state.storage.into_iter().collect(),
+		}
+	}
+}
+
+impl PrestateTracer {
+	pub fn new(diff_mode: bool) -> Self {
+		Self {
+			diff_mode,
+
+			pre: BTreeMap::new(),
+			post: BTreeMap::new(),
+		}
+	}
+
+	pub fn into_tx_trace(self) -> TransactionTrace {
+		if !self.diff_mode {
+			return TransactionTrace::Prestate(
+				self.pre
+					.into_iter()
+					.map(|(address, state)| (address, prestate_account(state)))
+					.collect(),
+			);
+		}
+
+		let mut pre = BTreeMap::new();
+		let mut post = BTreeMap::new();
+
+		for (address, pre_state) in self.pre {
+			let post_state = match self.post.get(&address) {
+				Some(post_state) if *post_state != pre_state => post_state,
+				_ => continue,
+			};
+
+			// Only keep the modified storage slots, zero values being omitted like geth does.
+			let mut pre_storage = BTreeMap::new();
+			let mut post_storage = BTreeMap::new();
+			for (index, pre_value) in pre_state.storage.iter() {
+				let post_value = post_state.storage.get(index).cloned().unwrap_or_default();
+				if post_value != *pre_value {
+					if !pre_value.is_zero() {
+						pre_storage.insert(*index, *pre_value);
+					}
+					if !post_value.is_zero() {
+						post_storage.insert(*index, post_value);
+					}
+				}
+			}
+
+			// A self destructed account is only part of `pre`.
+			if !post_state.is_empty() || !post_storage.is_empty() {
+				post.insert(
+					address,
+					PrestateAccount {
+						balance: Some(post_state.balance)
+							.filter(|balance| *balance != pre_state.balance),
+						nonce: Some(post_state.nonce).filter(|nonce| *nonce != pre_state.nonce),
+						code: Some(post_state.code.clone())
+							.filter(|code| *code != pre_state.code && !code.is_empty()),
+						storage: post_storage,
+					},
+				);
+			}
+
+			if !pre_state.is_empty() || !pre_storage.is_empty() {
+				let mut account = prestate_account(pre_state);
+				account.storage = pre_storage;
+				pre.insert(address, account);
+			}
+		}
+
+		TransactionTrace::PrestateDiff { pre, post }
+	}
+
+	/// Changes of the touched accounts in the format of OpenEthereum's `stateDiff`. Only
+	/// available in diff mode, as the state after the transaction is needed.
+	///
+	/// An account which is empty before (resp. after) the transaction is reported as created
+	/// (resp. destroyed), with all its fields being part of the diff.
+	pub fn into_state_diff(self) -> BTreeMap<H160, AccountDiff> {
+		let mut state_diff = BTreeMap::new();
+
+		for (address, pre_state) in self.pre {
+			let post_state = match self.post.get(&address) {
+				Some(post_state) if *post_state != pre_state => post_state.clone(),
+				_ => continue,
+			};
+
+			let account_diff = match (pre_state.is_empty(), post_state.is_empty()) {
+				(true, true) => continue,
+				(true, false) => AccountDiff {
+					balance: Diff::Born(post_state.balance),
+					nonce: Diff::Born(post_state.nonce),
+					code: Diff::Born(Bytes(post_state.code)),
+					storage: post_state
+						.storage
+						.into_iter()
+						.filter(|(_, value)| !value.is_zero())
+						.map(|(index, value)| (index, Diff::Born(value)))
+						.collect(),
+				},
+				(false, true) => AccountDiff {
+					balance: Diff::Died(pre_state.balance),
+					nonce: Diff::Died(pre_state.nonce),
+					code: Diff::Died(Bytes(pre_state.code)),
+					storage: pre_state
+						.storage
+						.into_iter()
+						.filter(|(_, value)| !value.is_zero())
+						.map(|(index, value)| (index, Diff::Died(value)))
+						.collect(),
+				},
+				(false, false) => AccountDiff {
+					balance: Diff::new(pre_state.balance, post_state.balance),
+					nonce: Diff::new(pre_state.nonce, post_state.nonce),
+					code: Diff::new(Bytes(pre_state.code), Bytes(post_state.code)),
+					storage: pre_state
+						.storage
+						.into_iter()
+						.filter_map(|(index, pre_value)| {
+							let post_value =
+								post_state.storage.get(&index).cloned().unwrap_or_default();
+							match Diff::new(pre_value, post_value) {
+								Diff::Same => None,
+								diff => Some((index, diff)),
+							}
+						})
+						.collect(),
+				},
+			};
+
+			state_diff.insert(address, account_diff);
+		}
+
+		state_diff
+	}
+}
+
+fn prestate_account(state: AccountState) -> PrestateAccount {
+	PrestateAccount {
+		balance: Some(state.balance),
+		nonce: Some(state.nonce).filter(|nonce| !nonce.is_zero()),
+		code: Some(state.code).filter(|code| !code.is_empty()),
+		storage: state.storage,
+	}
+}
+
+impl Listener for PrestateTracer {
+	fn event(&mut self, event: Event) {
+		match event {
+			Event::PreState(state) => {
+				self.pre.insert(state.address, state.into());
+			}
+			Event::PostState(state) => {
+				self.post.insert(state.address, state.into());
+			}
+			_ => (),
+		}
+	}
+}
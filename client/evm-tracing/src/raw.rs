@@ -0,0 +1,493 @@
+// Copyright 2019-2020 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::util::*;
+
+use ethereum_types::{H160, H256};
+use hedgeware_rpc_primitives_debug::single::{RawStepLog, TransactionTrace};
+use std::{collections::BTreeMap, convert::TryFrom};
+
+/// Listen to the EVM events sent by the runtime to provide the intermediate machine state between opcode executions
+/// (stepping), resulting in a granular per opcode output.
+///
+/// # Output example
+///
+/// The output matches the one of geth's `structLogger`, with the addition of the memory
+/// size of each step. The storage of the current context is only provided by the steps
+/// changing it, the first step of a context and the first step following a subcall.
+///
+/// With a `StepWindow`, only the steps of the window are kept, and the total number of steps
+/// is provided as `totalSteps`.
+///
+/// ```json
+/// {
+///   "gas": 43568,
+///   "failed": false,
+///   "returnValue": "0x",
+///   "structLogs": [
+///     {
+///       "pc": 230,
+///       "op": "SSTORE",
+///       "gas": 62841,
+///       "gasCost": 20000,
+///       "depth": 1,
+///       "memSize": 32,
+///       "stack": [
+///         "00000000000000000000000000000000000000000000000000000000398f7223"
+///       ],
+///       "memory": [
+///         "0000000000000000000000000000000000000000000000000000000000000000"
+///       ],
+///       "storage": {
+///         "0000000000000000000000000000000000000000000000000000000000000000": "00000000000000000000000000000000000000000000000000000000398f7223"
+///       }
+///     }
+///   ]
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RawTracer {
+	disable_storage: bool,
+	disable_memory: bool,
+	disable_stack: bool,
+
+	window: Option<StepWindow>,
+	step_count: u64,
+	step_logs: Vec<RawStepLog>,
+	return_value: Vec<u8>,
+	failed: bool,
+	// Last snapshot of the transaction gasometer, which wraps the gasometer of the root
+	// context and is used to compute the gas used by the transaction.
+	transaction_snapshot: Option<Snapshot>,
+
+	new_context: bool,
+	context_stack: Vec<Context>,
+}
+
+#[derive(Debug)]
+struct Context {
+	storage_cache: BTreeMap<H256, H256>,
+	/// The storage cache changed since the last step of this context which provided it.
+	storage_changed: bool,
+	address: H160,
+	current_step: Option<Step>,
+	global_storage_changes: BTreeMap<H160, BTreeMap<H256, H256>>,
+	// Refunds are tracked by the gasometer of each context, and merged into the parent
+	// one when a context exits. The refund counter of the transaction is thus the refunds
+	// of the parent contexts when this context started, plus its own refunds.
+	parent_refund: i64,
+	refund: i64,
+}
+
+impl Context {
+	fn refund_counter(&self) -> u64 {
+		(self.parent_refund + self.refund).max(0) as u64
+	}
+}
+
+#[derive(Debug)]
+struct Step {
+	/// Current opcode.
+	opcode: Opcode,
+	/// Depth of the context.
+	depth: usize,
+	/// Remaining gas.
+	gas: u64,
+	/// Gas cost of the following opcode.
+	gas_cost: u64,
+	/// Program counter position.
+	position: usize,
+	/// Refund counter of the transaction.
+	refund: u64,
+	/// EVM memory size.
+	memory_size: usize,
+	/// EVM memory copy (if not disabled).
+	memory: Option<Vec<u8>>,
+	/// EVM stack copy (if not disabled).
+	stack: Option<Vec<H256>>,
+}
+
+/// Range of the steps kept by a raw trace, for the steps of large transactions to be fetched
+/// by several requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StepWindow {
+	/// Index of the first step.
+	pub start: u64,
+	/// Maximum number of steps, unbounded if `None`.
+	pub limit: Option<u64>,
+}
+
+impl StepWindow {
+	fn contains(&self, step: u64) -> bool {
+		step >= self.start && self.limit.map_or(true, |limit| step - self.start < limit)
+	}
+
+	/// Only keep the steps of the window of a whole raw trace, returned by a legacy runtime or
+	/// shared by several requests. As in the traces built with a window, the first step of the
+	/// window provides the storage of its context.
+	pub fn apply(&self, trace: TransactionTrace) -> TransactionTrace {
+		match trace {
+			TransactionTrace::Raw {
+				gas,
+				failed,
+				return_value,
+				struct_logs,
+				..
+			} => {
+				let total_steps = struct_logs.len() as u64;
+				let storage = self.first_step_storage(&struct_logs);
+
+				let mut struct_logs: Vec<_> = struct_logs
+					.into_iter()
+					.enumerate()
+					.filter(|(step, _)| self.contains(*step as u64))
+					.map(|(_, log)| log)
+					.collect();
+				if let (Some(first_step), Some(storage)) = (struct_logs.first_mut(), storage) {
+					first_step.storage = Some(storage);
+				}
+
+				TransactionTrace::Raw {
+					gas,
+					failed,
+					return_value,
+					struct_logs,
+					total_steps: Some(total_steps),
+				}
+			}
+			trace => trace,
+		}
+	}
+
+	/// Storage of the context of the first step of the window, the last one provided by a step
+	/// of this context. Steps of deeper contexts belong to its subcalls, and the context started
+	/// after the last step of a lower depth.
+	fn first_step_storage(&self, struct_logs: &[RawStepLog]) -> Option<BTreeMap<H256, H256>> {
+		let start = usize::try_from(self.start).ok()?;
+		let depth = struct_logs.get(start)?.depth;
+
+		struct_logs[..=start]
+			.iter()
+			.rev()
+			.take_while(|log| log.depth >= depth)
+			.filter(|log| log.depth == depth)
+			.find_map(|log| log.storage.clone())
+	}
+}
+
+impl RawTracer {
+	pub fn new(disable_storage: bool, disable_memory: bool, disable_stack: bool) -> Self {
+		Self {
+			disable_storage,
+			disable_memory,
+			disable_stack,
+
+			window: None,
+			step_count: 0,
+			step_logs: vec![],
+			return_value: vec![],
+			failed: false,
+			transaction_snapshot: None,
+
+			new_context: false,
+			context_stack: vec![],
+		}
+	}
+
+	/// Only keep the steps of the window.
+	pub fn with_window(mut self, window: StepWindow) -> Self {
+		self.window = Some(window);
+		self
+	}
+
+	pub fn into_tx_trace(self) -> TransactionTrace {
+		// Same computation as the executor, refunds being capped to half of the gas used.
+		let gas = self
+			.transaction_snapshot
+			.map(|snapshot| {
+				let used_gas = snapshot.used_gas + snapshot.memory_gas;
+				let refunded_gas = snapshot.refunded_gas.max(0) as u64;
+				used_gas - std::cmp::min(used_gas / 2, refunded_gas)
+			})
+			.unwrap_or_default();
+
+		TransactionTrace::Raw {
+			gas: gas.into(),
+			failed: self.failed,
+			return_value: self.return_value,
+			struct_logs: self.step_logs,
+			total_steps: self.window.map(|_| self.step_count),
+		}
+	}
+
+	fn record_snapshot(&mut self, snapshot: Snapshot) {
+		match self.context_stack.last_mut() {
+			Some(context) => context.refund = snapshot.refunded_gas,
+			None => self.transaction_snapshot = Some(snapshot),
+		}
+	}
+}
+
+impl Listener for RawTracer {
+	fn event(&mut self, event: Event) {
+		match event {
+			Event::Gasometer(event) => self.gasometer_event(event),
+			Event::Runtime(event) => self.runtime_event(event),
+			_ => (),
+		}
+	}
+}
+
+impl RawTracer {
+	fn gasometer_event(&mut self, event: GasometerEvent) {
+		match event {
+			GasometerEvent::RecordTransaction { snapshot, .. } => {
+				// First event of a transaction.
+				// Next step will be the first context.
+				self.new_context = true;
+				self.transaction_snapshot = Some(snapshot);
+			}
+			GasometerEvent::RecordCost { cost, snapshot } => {
+				if let Some(context) = self.context_stack.last_mut() {
+					// Register opcode cost. (ignore costs not between Step and StepResult)
+					if let Some(step) = &mut context.current_step {
+						step.gas = snapshot.gas();
+						step.gas_cost = cost;
+					}
+				}
+				self.record_snapshot(snapshot);
+			}
+			GasometerEvent::RecordDynamicCost {
+				gas_cost, snapshot, ..
+			} => {
+				if let Some(context) = self.context_stack.last_mut() {
+					// Register opcode cost. (ignore costs not between Step and StepResult)
+					if let Some(step) = &mut context.current_step {
+						step.gas = snapshot.gas();
+						step.gas_cost = gas_cost;
+					}
+				}
+				self.record_snapshot(snapshot);
+			}
+			GasometerEvent::RecordRefund { snapshot, .. }
+			| GasometerEvent::RecordStipend { snapshot, .. } => self.record_snapshot(snapshot),
+		}
+	}
+
+	fn runtime_event(&mut self, event: RuntimeEvent) {
+		match event {
+			RuntimeEvent::Step {
+				context,
+				opcode,
+				position,
+				stack,
+				memory,
+				memory_size,
+			} => {
+				// Create a context if needed.
+				if self.new_context {
+					self.new_context = false;
+
+					let parent_refund = self
+						.context_stack
+						.last()
+						.map(|parent| parent.parent_refund + parent.refund)
+						.unwrap_or_else(|| {
+							self.transaction_snapshot
+								.as_ref()
+								.map(|snapshot| snapshot.refunded_gas)
+								.unwrap_or_default()
+						});
+
+					self.context_stack.push(Context {
+						storage_cache: BTreeMap::new(),
+						storage_changed: true,
+						address: context.address,
+						current_step: None,
+						global_storage_changes: BTreeMap::new(),
+						parent_refund,
+						refund: 0,
+					});
+				}
+
+				let depth = self.context_stack.len();
+
+				// Ignore steps outside of any context (shouldn't even be possible).
+				if let Some(context) = self.context_stack.last_mut() {
+					context.current_step = Some(Step {
+						opcode: Opcode(opcode),
+						depth,
+						gas: 0,      // 0 for now, will add with gas events
+						gas_cost: 0, // 0 for now, will add with gas events
+						position: *position.as_ref().unwrap_or(&0) as usize,
+						refund: context.refund_counter(),
+						memory_size: memory_size as usize,
+						memory: if self.disable_memory { None } else { memory },
+						stack: if self.disable_stack { None } else { stack },
+					});
+				}
+			}
+			RuntimeEvent::StepResult {
+				result,
+				return_value,
+			} => {
+				// StepResult is expected to be emited after a step (in a context).
+				// Only case StepResult will occur without a Step before is in a transfer
+				// transaction to a non-contract address. However it will not contain any
+				// steps and return an empty trace, so we can ignore this edge case.
+				if let Some(context) = self.context_stack.last_mut() {
+					let current_step = context.current_step.take();
+					let step_index = self.step_count;
+					if current_step.is_some() {
+						self.step_count += 1;
+					}
+
+					// Steps outside of the window are only counted.
+					let in_window = self
+						.window
+						.map_or(true, |window| window.contains(step_index));
+
+					if let (Some(current_step), true) = (current_step, in_window) {
+						let Step {
+							opcode,
+							depth,
+							gas,
+							gas_cost,
+							position,
+							refund,
+							memory_size,
+							memory,
+							stack,
+						} = current_step;
+
+						let memory = memory.map(convert_memory);
+
+						// The first step of a window provides the storage, as the steps changing
+						// it may be out of the window.
+						let first_in_window = self
+							.window
+							.map_or(false, |window| window.start == step_index);
+						let storage = if !self.disable_storage
+							&& (context.storage_changed || first_in_window)
+						{
+							context.storage_changed = false;
+							Some(context.storage_cache.clone())
+						} else {
+							None
+						};
+
+						// Like geth, a revert is not an error of the opcode.
+						let error = match &result {
+							Err(Capture::Exit(ExitReason::Error(error))) => Some(error_message(error)),
+							Err(Capture::Exit(ExitReason::Fatal(_))) => Some(b"fatal error".to_vec()),
+							_ => None,
+						};
+
+						self.step_logs.push(RawStepLog {
+							depth: depth.into(),
+							error,
+							gas: gas.into(),
+							gas_cost: gas_cost.into(),
+							memory,
+							mem_size: memory_size.into(),
+							op: opcodes_string(opcode),
+							pc: position.into(),
+							refund: refund.into(),
+							stack,
+							storage,
+						});
+					}
+				}
+
+				// We match on the capture to handle traps/exits.
+				match &result {
+					Err(Capture::Exit(reason)) => {
+						// Exit = we exit the context (should always be some)
+						if let Some(mut context) = self.context_stack.pop() {
+							// If final context is exited, we store the outcome and return value.
+							if self.context_stack.is_empty() {
+								self.return_value = return_value;
+								self.failed = !matches!(reason, ExitReason::Succeed(_));
+							}
+
+							// The storage of the parent context is provided again by its next
+							// step.
+							if let Some(parent_context) = self.context_stack.last_mut() {
+								parent_context.storage_changed = true;
+							}
+
+							// If the context exited without revert we must keep track of the
+							// updated storage keys.
+							if !self.disable_storage && matches!(reason, &ExitReason::Succeed(_)) {
+								if let Some(parent_context) = self.context_stack.last_mut() {
+									// Add cache to storage changes.
+									context
+										.global_storage_changes
+										.insert(context.address, context.storage_cache);
+
+									// Apply storage changes to parent, either updating its cache or map of changes.
+									for (address, mut storage) in
+										context.global_storage_changes.into_iter()
+									{
+										// Same address => We update its cache (only tracked keys)
+										if parent_context.address == address {
+											for (cached_key, cached_value) in
+												parent_context.storage_cache.iter_mut()
+											{
+												if let Some(value) = storage.remove(cached_key) {
+													*cached_value = value;
+												}
+											}
+										}
+										// Otherwise, update the storage changes.
+										else {
+											parent_context
+												.global_storage_changes
+												.entry(address)
+												.or_insert_with(BTreeMap::new)
+												.append(&mut storage);
+										}
+									}
+								}
+							}
+						}
+					}
+					Err(Capture::Trap(opcode)) if ContextType::from(Opcode(*opcode)).is_some() => {
+						self.new_context = true;
+					}
+					_ => (),
+				}
+			}
+			RuntimeEvent::SLoad {
+				address: _,
+				index,
+				value,
+			}
+			| RuntimeEvent::SStore {
+				address: _,
+				index,
+				value,
+			} => {
+				if let Some(context) = self.context_stack.last_mut() {
+					if !self.disable_storage && context.storage_cache.insert(index, value) != Some(value)
+					{
+						context.storage_changed = true;
+					}
+				}
+			}
+		}
+	}
+}
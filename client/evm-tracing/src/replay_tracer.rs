@@ -0,0 +1,122 @@
+// Copyright 2019-2021 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{util::*, CallListTracer, PrestateTracer, VmTracer};
+use hedgeware_rpc_primitives_debug::{
+	block,
+	replay::{ReplayTraceType, TransactionReplay},
+	single, CallResult, CreateResult,
+};
+
+/// Listen to the events sent by the runtime to provide the outputs of OpenEthereum's
+/// `trace_replay*` RPCs.
+///
+/// The runtime sends the state of the touched accounts for the state diff, then the events of
+/// the execution, which provide both the calls and the VM trace.
+#[derive(Debug)]
+pub struct ReplayTracer {
+	trace_type: ReplayTraceType,
+
+	calls: CallListTracer,
+	state: Option<PrestateTracer>,
+	vm: Option<VmTracer>,
+}
+
+impl ReplayTracer {
+	pub fn new(trace_type: ReplayTraceType) -> Self {
+		Self {
+			trace_type,
+
+			calls: CallListTracer::new(),
+			state: if trace_type.state_diff {
+				Some(PrestateTracer::new(true))
+			} else {
+				None
+			},
+			vm: if trace_type.vm_trace {
+				Some(VmTracer::new())
+			} else {
+				None
+			},
+		}
+	}
+
+	pub fn into_replay(self) -> TransactionReplay {
+		let calls = match self.calls.into_tx_trace() {
+			single::TransactionTrace::CallList(calls) => calls,
+			_ => vec![],
+		};
+
+		// The root call comes first.
+		let output = match calls.first().map(|call| &call.inner) {
+			Some(single::CallInner::Call {
+				res: CallResult::Output { output },
+				..
+			})
+			| Some(single::CallInner::Call {
+				res: CallResult::Error { output, .. },
+				..
+			})
+			| Some(single::CallInner::Create {
+				res: CreateResult::Error { output, .. },
+				..
+			}) => output.clone(),
+			Some(single::CallInner::Create {
+				res: CreateResult::Success {
+					created_contract_code,
+					..
+				},
+				..
+			}) => created_contract_code.clone(),
+			_ => vec![],
+		};
+
+		let trace = if self.trace_type.trace {
+			calls
+				.into_iter()
+				.map(|call| block::TransactionTrace::from_call(call, 0).into())
+				.collect()
+		} else {
+			vec![]
+		};
+
+		TransactionReplay {
+			output,
+			state_diff: self.state.map(PrestateTracer::into_state_diff),
+			trace,
+			vm_trace: self.vm.map(VmTracer::into_vm_trace),
+			transaction_hash: None,
+		}
+	}
+}
+
+impl Listener for ReplayTracer {
+	fn event(&mut self, event: Event) {
+		match event {
+			Event::PreState(_) | Event::PostState(_) => {
+				if let Some(state) = &mut self.state {
+					state.event(event);
+				}
+			}
+			event => {
+				if let Some(vm) = &mut self.vm {
+					vm.event(event.clone());
+				}
+				self.calls.event(event);
+			}
+		}
+	}
+}
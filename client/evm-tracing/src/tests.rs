@@ -0,0 +1,887 @@
+// Copyright 2019-2021 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Traces built by the tracers of this crate from known sequences of events.
+
+use crate::{
+	call_tracer::nest_calls,
+	util::{Capture, ExitReason, GasometerEvent, RuntimeEvent, Snapshot},
+	BlockTracer, CallListTracer, CallTracer, Event, Listener, PrestateTracer, RawTracer,
+	ReplayTracer, StepWindow, TransactionOrigin, VmTracer,
+};
+use ethereum_types::{H160, H256, U256};
+use evm::{ExitError, ExitRevert, ExitSucceed};
+use hedgeware_evm_tracing_events::{evm::Context, AccountState, EvmEvent};
+use hedgeware_rpc_primitives_debug::{
+	replay::{AccountDiff, Diff, ReplayTraceType, VmStorageDiff},
+	single::{Call, CallFrame, CallInner, Log, PrestateAccount, RawStepLog, TransactionTrace},
+	CallResult, CallType,
+};
+use std::collections::BTreeMap;
+
+fn address(value: u64) -> H160 {
+	H160::from_low_u64_be(value)
+}
+
+fn call(trace_address: Vec<u32>, to: u64) -> Call {
+	Call {
+		from: address(1),
+		trace_address,
+		subtraces: 0,
+		value: U256::zero(),
+		gas: 100_000.into(),
+		gas_used: 21_000.into(),
+		inner: CallInner::Call {
+			call_type: CallType::Call,
+			to: address(to),
+			input: vec![],
+			res: CallResult::Output { output: vec![] },
+		},
+		logs: vec![],
+	}
+}
+
+fn callees(frame: &CallFrame) -> Vec<Option<H160>> {
+	frame.calls.iter().map(|call| call.to).collect()
+}
+
+fn root_frame(trace: TransactionTrace) -> CallFrame {
+	match trace {
+		TransactionTrace::CallTracer(root) => root,
+		other => panic!("expected call frames, got {:?}", other),
+	}
+}
+
+#[test]
+fn call_tracer_nests_calls_by_trace_address() {
+	let root = root_frame(nest_calls(
+		vec![
+			call(vec![], 2),
+			call(vec![0], 3),
+			call(vec![0, 0], 4),
+			call(vec![1], 5),
+		],
+		false,
+	));
+
+	assert_eq!(root.to, Some(address(2)));
+	assert_eq!(callees(&root), vec![Some(address(3)), Some(address(5))]);
+	assert_eq!(callees(&root.calls[0]), vec![Some(address(4))]);
+	assert!(root.calls[1].calls.is_empty());
+}
+
+#[test]
+fn call_tracer_attaches_orphan_calls_to_the_root() {
+	let root = root_frame(nest_calls(
+		vec![
+			call(vec![], 2),
+			call(vec![0], 3),
+			// There is no call at trace address [5].
+			call(vec![5, 0], 4),
+			call(vec![1], 5),
+			call(vec![1, 0], 6),
+		],
+		false,
+	));
+
+	// The orphan comes last so that the following calls still find their parent.
+	assert_eq!(
+		callees(&root),
+		vec![Some(address(3)), Some(address(5)), Some(address(4))]
+	);
+	assert_eq!(callees(&root.calls[1]), vec![Some(address(6))]);
+}
+
+#[test]
+fn call_tracer_without_root_call_is_empty() {
+	assert_eq!(
+		nest_calls(vec![call(vec![0], 3)], false),
+		TransactionTrace::CallList(vec![]),
+	);
+	assert_eq!(nest_calls(vec![], false), TransactionTrace::CallList(vec![]));
+}
+
+/// Listener counting the events it receives.
+#[derive(Default)]
+struct EventCount(usize);
+
+impl Listener for EventCount {
+	fn event(&mut self, _event: Event) {
+		self.0 += 1;
+	}
+}
+
+#[test]
+fn block_tracer_only_keeps_extrinsics_executing_evm_code() {
+	let substrate = |extrinsic_index| TransactionOrigin::Substrate {
+		extrinsic_index,
+		extrinsic_hash: H256::repeat_byte(extrinsic_index as u8),
+	};
+
+	let mut tracer = BlockTracer::new(EventCount::default);
+	for event in vec![
+		// Timestamp, without EVM execution.
+		Event::NewTransaction(substrate(0)),
+		// Ethereum transactions are kept even without events, their traces being matched
+		// with the Ethereum transactions of the block by position.
+		Event::NewTransaction(TransactionOrigin::Ethereum),
+		Event::NewTransaction(substrate(2)),
+		Event::CallCode(vec![]),
+		Event::CallCode(vec![]),
+		Event::NewTransaction(TransactionOrigin::Ethereum),
+		Event::CallCode(vec![]),
+	] {
+		tracer.event(event);
+	}
+
+	let transactions: Vec<_> = tracer
+		.into_transactions()
+		.into_iter()
+		.map(|(origin, count)| (origin, count.0))
+		.collect();
+	assert_eq!(
+		transactions,
+		vec![
+			(TransactionOrigin::Ethereum, 0),
+			(substrate(2), 2),
+			(TransactionOrigin::Ethereum, 1),
+		]
+	);
+}
+
+fn word(value: u64) -> H256 {
+	H256::from_low_u64_be(value)
+}
+
+fn snapshot(gas_limit: u64, used_gas: u64) -> Snapshot {
+	Snapshot {
+		gas_limit,
+		memory_gas: 0,
+		used_gas,
+		refunded_gas: 0,
+	}
+}
+
+fn record_cost(cost: u64, gas_limit: u64, used_gas: u64) -> Event {
+	Event::Gasometer(GasometerEvent::RecordCost {
+		cost,
+		snapshot: snapshot(gas_limit, used_gas),
+	})
+}
+
+fn record_dynamic_cost(gas_cost: u64, gas_limit: u64, used_gas: u64) -> Event {
+	Event::Gasometer(GasometerEvent::RecordDynamicCost {
+		gas_cost,
+		memory_gas: 0,
+		gas_refund: 0,
+		snapshot: snapshot(gas_limit, used_gas),
+	})
+}
+
+fn enter_call(caller: u64, to: u64, input: Vec<u8>) -> Event {
+	Event::Evm(EvmEvent::Call {
+		code_address: address(to),
+		transfer: None,
+		input,
+		target_gas: None,
+		is_static: false,
+		context: Context {
+			address: address(to),
+			caller: address(caller),
+			apparent_value: U256::zero(),
+		},
+	})
+}
+
+fn step(at: u64, opcode: u8, pc: u64, stack: Vec<H256>, memory: Vec<u8>) -> Event {
+	Event::Runtime(RuntimeEvent::Step {
+		context: Context {
+			address: address(at),
+			caller: H160::zero(),
+			apparent_value: U256::zero(),
+		},
+		opcode,
+		position: Ok(pc),
+		memory_size: memory.len() as u64,
+		stack: Some(stack),
+		memory: Some(memory),
+	})
+}
+
+fn step_result(result: Result<(), Capture<ExitReason, u8>>, return_value: Vec<u8>) -> Event {
+	Event::Runtime(RuntimeEvent::StepResult {
+		result,
+		return_value,
+	})
+}
+
+fn feed<T: Listener>(mut tracer: T, events: Vec<Event>) -> T {
+	for event in events {
+		tracer.event(event);
+	}
+	tracer
+}
+
+/// Transaction from account 1 calling account 2, which executes
+/// `PUSH1 0x2a PUSH1 0x01 SSTORE STOP`.
+fn storing_call() -> Vec<Event> {
+	vec![
+		Event::Gasometer(GasometerEvent::RecordTransaction {
+			cost: 21_000,
+			snapshot: snapshot(100_000, 0),
+		}),
+		enter_call(1, 2, vec![0xaa]),
+		Event::CallCode(vec![0x60, 0x2a, 0x60, 0x01, 0x55, 0x00]),
+		step(2, 0x60, 0, vec![], vec![]),
+		record_cost(3, 79_000, 0),
+		step_result(Ok(()), vec![]),
+		step(2, 0x60, 2, vec![word(0x2a)], vec![]),
+		record_cost(3, 79_000, 3),
+		step_result(Ok(()), vec![]),
+		step(2, 0x55, 4, vec![word(0x2a), word(1)], vec![]),
+		record_dynamic_cost(20_000, 79_000, 6),
+		Event::Runtime(RuntimeEvent::SStore {
+			address: address(2),
+			index: word(1),
+			value: word(0x2a),
+		}),
+		step_result(Ok(()), vec![]),
+		step(2, 0x00, 5, vec![], vec![]),
+		record_cost(0, 79_000, 20_006),
+		step_result(
+			Err(Capture::Exit(ExitReason::Succeed(ExitSucceed::Stopped))),
+			vec![],
+		),
+		// Refunds of the transaction, recorded once the call exited.
+		Event::Gasometer(GasometerEvent::RecordRefund {
+			refund: 15_000,
+			snapshot: Snapshot {
+				gas_limit: 100_000,
+				memory_gas: 0,
+				used_gas: 41_006,
+				refunded_gas: 15_000,
+			},
+		}),
+	]
+}
+
+/// Transaction from account 1 calling account 2, which calls account 3 that emits a log and
+/// reverts, then emits a log and returns `0x2a`.
+fn reverting_subcall() -> Vec<Event> {
+	// `LOG0` reading one byte at offset 0, the top of the stack being the last item.
+	let log_stack = vec![word(1), word(0)];
+
+	vec![
+		Event::Gasometer(GasometerEvent::RecordTransaction {
+			cost: 21_000,
+			snapshot: snapshot(100_000, 0),
+		}),
+		enter_call(1, 2, vec![0xaa]),
+		step(2, 0xf1, 0, vec![H256::zero(); 7], vec![]),
+		record_cost(700, 79_000, 0),
+		step_result(Err(Capture::Trap(0xf1)), vec![]),
+		enter_call(2, 3, vec![0xbb]),
+		step(3, 0xa0, 0, log_stack.clone(), vec![0x11]),
+		record_dynamic_cost(383, 50_000, 0),
+		step_result(Ok(()), vec![]),
+		step(3, 0xfd, 1, vec![word(0), word(0)], vec![0x11]),
+		record_cost(0, 50_000, 383),
+		step_result(
+			Err(Capture::Exit(ExitReason::Revert(ExitRevert::Reverted))),
+			vec![],
+		),
+		step(2, 0xa0, 1, log_stack, vec![0x22]),
+		record_dynamic_cost(383, 79_000, 700),
+		step_result(Ok(()), vec![]),
+		step(2, 0xf3, 2, vec![word(1), word(0)], vec![0x22]),
+		record_cost(0, 79_000, 1_083),
+		step_result(
+			Err(Capture::Exit(ExitReason::Succeed(ExitSucceed::Returned))),
+			vec![0x2a],
+		),
+	]
+}
+
+fn log(at: u64, data: Vec<u8>, position: u32, reverted: bool) -> Log {
+	Log {
+		address: address(at),
+		topics: vec![],
+		data,
+		position,
+		reverted,
+	}
+}
+
+/// Step of the first context, without memory nor stack.
+fn raw_step(
+	op: &[u8],
+	pc: u64,
+	gas: u64,
+	gas_cost: u64,
+	storage: Option<Vec<(u64, u64)>>,
+) -> RawStepLog {
+	RawStepLog {
+		depth: U256::one(),
+		error: None,
+		gas: gas.into(),
+		gas_cost: gas_cost.into(),
+		memory: None,
+		mem_size: U256::zero(),
+		op: op.to_vec(),
+		pc: pc.into(),
+		refund: U256::zero(),
+		stack: None,
+		storage: storage.map(|storage| {
+			storage
+				.into_iter()
+				.map(|(index, value)| (word(index), word(value)))
+				.collect()
+		}),
+	}
+}
+
+#[test]
+fn raw_tracer_records_each_step() {
+	let trace = feed(RawTracer::new(false, true, true), storing_call()).into_tx_trace();
+
+	let (gas, failed, return_value, struct_logs, total_steps) = match trace {
+		TransactionTrace::Raw {
+			gas,
+			failed,
+			return_value,
+			struct_logs,
+			total_steps,
+		} => (gas, failed, return_value, struct_logs, total_steps),
+		other => panic!("expected a raw trace, got {:?}", other),
+	};
+
+	// Refunds are capped to half of the gas used.
+	assert_eq!(gas, U256::from(41_006 - 15_000));
+	assert!(!failed);
+	assert!(return_value.is_empty());
+	assert_eq!(total_steps, None);
+
+	assert_eq!(
+		struct_logs,
+		vec![
+			// The first step of a context provides its storage.
+			raw_step(b"Push1", 0, 79_000, 3, Some(vec![])),
+			raw_step(b"Push1", 2, 78_997, 3, None),
+			raw_step(b"SStore", 4, 78_994, 20_000, Some(vec![(1, 0x2a)])),
+			raw_step(b"Stop", 5, 58_994, 0, None),
+		]
+	);
+}
+
+#[test]
+fn raw_tracer_names_self_balance_and_undefined_opcodes() {
+	let events = vec![
+		Event::Gasometer(GasometerEvent::RecordTransaction {
+			cost: 21_000,
+			snapshot: snapshot(100_000, 0),
+		}),
+		enter_call(1, 2, vec![]),
+		step(2, 0x47, 0, vec![], vec![]),
+		record_cost(5, 79_000, 0),
+		step_result(Ok(()), vec![]),
+		step(2, 0x0c, 1, vec![word(0)], vec![]),
+		record_cost(0, 79_000, 5),
+		step_result(
+			Err(Capture::Exit(ExitReason::Error(ExitError::DesignatedInvalid))),
+			vec![],
+		),
+	];
+
+	let trace = feed(RawTracer::new(true, true, true), events).into_tx_trace();
+	let ops: Vec<_> = raw_steps(trace).0.into_iter().map(|(op, _, _)| op).collect();
+	assert_eq!(ops, vec!["SelfBalance", "opcode 0xc not defined"]);
+}
+
+/// Opcode, depth and provided storage of a step of a raw trace.
+type RawStep = (String, u64, Option<Vec<(u64, u64)>>);
+
+/// Steps of a raw trace, and its number of steps.
+fn raw_steps(trace: TransactionTrace) -> (Vec<RawStep>, Option<u64>) {
+	match trace {
+		TransactionTrace::Raw {
+			struct_logs,
+			total_steps,
+			..
+		} => (
+			struct_logs
+				.into_iter()
+				.map(|log| {
+					(
+						String::from_utf8(log.op).unwrap(),
+						log.depth.as_u64(),
+						log.storage.map(|storage| {
+							storage
+								.into_iter()
+								.map(|(index, value)| {
+									(index.to_low_u64_be(), value.to_low_u64_be())
+								})
+								.collect()
+						}),
+					)
+				})
+				.collect(),
+			total_steps,
+		),
+		other => panic!("expected a raw trace, got {:?}", other),
+	}
+}
+
+fn windowed_trace(start: u64, limit: Option<u64>) -> TransactionTrace {
+	feed(
+		RawTracer::new(false, true, true).with_window(StepWindow { start, limit }),
+		reverting_subcall(),
+	)
+	.into_tx_trace()
+}
+
+#[test]
+fn raw_tracer_only_keeps_the_steps_of_its_window() {
+	let step = |op: &str, depth: u64, storage: Option<Vec<(u64, u64)>>| -> RawStep {
+		(op.to_string(), depth, storage)
+	};
+
+	// The window spans the subcall and the following step of its parent, whose storage is
+	// provided again.
+	assert_eq!(
+		raw_steps(windowed_trace(1, Some(3))),
+		(
+			vec![
+				step("Log0", 2, Some(vec![])),
+				step("Revert", 2, None),
+				step("Log0", 1, Some(vec![])),
+			],
+			Some(5)
+		)
+	);
+
+	// The first step of a window provides the storage of its context, even if unchanged.
+	assert_eq!(
+		raw_steps(windowed_trace(2, Some(1))),
+		(vec![step("Revert", 2, Some(vec![]))], Some(5))
+	);
+
+	// An unbounded window keeps the remaining steps.
+	assert_eq!(
+		raw_steps(windowed_trace(4, None)),
+		(vec![step("Return", 1, Some(vec![]))], Some(5))
+	);
+
+	// Empty windows still provide the number of steps.
+	assert_eq!(raw_steps(windowed_trace(5, None)), (vec![], Some(5)));
+	assert_eq!(raw_steps(windowed_trace(100, Some(10))), (vec![], Some(5)));
+	assert_eq!(raw_steps(windowed_trace(0, Some(0))), (vec![], Some(5)));
+
+	// The outcome of the transaction doesn't depend on the window.
+	match windowed_trace(100, Some(0)) {
+		TransactionTrace::Raw {
+			failed,
+			return_value,
+			..
+		} => {
+			assert!(!failed);
+			assert_eq!(return_value, vec![0x2a]);
+		}
+		other => panic!("expected a raw trace, got {:?}", other),
+	}
+}
+
+#[test]
+fn step_window_applies_to_whole_raw_traces() {
+	let trace = feed(RawTracer::new(false, true, true), reverting_subcall()).into_tx_trace();
+	let (steps, total_steps) = raw_steps(trace.clone());
+	assert_eq!(steps.len(), 5);
+	assert_eq!(total_steps, None);
+
+	// The window of a whole trace provides the same steps as a trace built with it, the first
+	// step providing the storage of its context.
+	let windows = vec![
+		(0, None),
+		(1, Some(3)),
+		(2, Some(1)),
+		(3, None),
+		(4, None),
+		(0, Some(100)),
+		(5, None),
+		(100, Some(1)),
+		(0, Some(0)),
+	];
+	for (start, limit) in windows {
+		assert_eq!(
+			raw_steps(StepWindow { start, limit }.apply(trace.clone())),
+			raw_steps(windowed_trace(start, limit)),
+			"window starting at {} limited to {:?}",
+			start,
+			limit
+		);
+	}
+
+	// Traces without storage are only filtered.
+	let trace = feed(RawTracer::new(true, true, true), reverting_subcall()).into_tx_trace();
+	let (steps, _) = raw_steps(StepWindow {
+		start: 2,
+		limit: Some(2),
+	}
+	.apply(trace));
+	assert_eq!(
+		steps,
+		vec![
+			("Revert".to_string(), 2, None),
+			("Log0".to_string(), 1, None),
+		]
+	);
+
+	// Other traces are not changed.
+	let call_list = TransactionTrace::CallList(vec![call(vec![], 2)]);
+	assert_eq!(
+		StepWindow {
+			start: 1,
+			limit: Some(1)
+		}
+		.apply(call_list.clone()),
+		call_list
+	);
+}
+
+fn storage_access(at: u64, opcode: u8, pc: u64, index: u64, value: u64) -> Vec<Event> {
+	let access = if opcode == 0x54 {
+		RuntimeEvent::SLoad {
+			address: address(at),
+			index: word(index),
+			value: word(value),
+		}
+	} else {
+		RuntimeEvent::SStore {
+			address: address(at),
+			index: word(index),
+			value: word(value),
+		}
+	};
+
+	vec![
+		step(at, opcode, pc, vec![], vec![]),
+		record_dynamic_cost(800, 79_000, 0),
+		Event::Runtime(access),
+		step_result(Ok(()), vec![]),
+	]
+}
+
+#[test]
+fn raw_tracer_only_provides_the_storage_when_it_changes() {
+	let mut events = vec![
+		Event::Gasometer(GasometerEvent::RecordTransaction {
+			cost: 21_000,
+			snapshot: snapshot(100_000, 0),
+		}),
+		enter_call(1, 2, vec![]),
+	];
+	// Loading a slot, loading it again, storing its value, then storing a new value.
+	events.extend(storage_access(2, 0x54, 0, 1, 5));
+	events.extend(storage_access(2, 0x54, 1, 1, 5));
+	events.extend(storage_access(2, 0x55, 2, 1, 5));
+	events.extend(storage_access(2, 0x55, 3, 1, 7));
+	events.extend(storage_access(2, 0x54, 4, 2, 0));
+	events.extend(vec![
+		step(2, 0x00, 5, vec![], vec![]),
+		record_cost(0, 79_000, 0),
+		step_result(
+			Err(Capture::Exit(ExitReason::Succeed(ExitSucceed::Stopped))),
+			vec![],
+		),
+	]);
+
+	let trace = feed(RawTracer::new(false, true, true), events.clone()).into_tx_trace();
+	let (steps, _) = raw_steps(trace);
+	let storage: Vec<_> = steps.into_iter().map(|(_, _, storage)| storage).collect();
+	assert_eq!(
+		storage,
+		vec![
+			Some(vec![(1, 5)]),
+			None,
+			None,
+			Some(vec![(1, 7)]),
+			Some(vec![(1, 7), (2, 0)]),
+			None,
+		]
+	);
+
+	// No storage is provided when it is disabled.
+	let (steps, _) = raw_steps(feed(RawTracer::new(true, true, true), events).into_tx_trace());
+	assert!(steps.iter().all(|(_, _, storage)| storage.is_none()));
+}
+
+#[test]
+fn call_list_tracer_builds_the_calls_and_their_logs() {
+	let calls = match feed(CallListTracer::new(), reverting_subcall()).into_tx_trace() {
+		TransactionTrace::CallList(calls) => calls,
+		other => panic!("expected a call list, got {:?}", other),
+	};
+
+	assert_eq!(
+		calls,
+		vec![
+			Call {
+				from: address(1),
+				trace_address: vec![],
+				subtraces: 1,
+				value: U256::zero(),
+				gas: 77_917.into(),
+				// The cost of the transaction is part of the root call.
+				gas_used: (79_000 - 77_917 + 21_000).into(),
+				inner: CallInner::Call {
+					call_type: CallType::Call,
+					to: address(2),
+					input: vec![0xaa],
+					res: CallResult::Output {
+						output: vec![0x2a],
+					},
+				},
+				logs: vec![log(2, vec![0x22], 1, false)],
+			},
+			Call {
+				from: address(2),
+				trace_address: vec![0],
+				subtraces: 0,
+				value: U256::zero(),
+				gas: 49_617.into(),
+				gas_used: 383.into(),
+				inner: CallInner::Call {
+					call_type: CallType::Call,
+					to: address(3),
+					input: vec![0xbb],
+					res: CallResult::Error {
+						error: b"execution reverted".to_vec(),
+						revert_reason: None,
+						output: vec![],
+					},
+				},
+				logs: vec![log(3, vec![0x11], 0, true)],
+			},
+		]
+	);
+}
+
+#[test]
+fn call_tracer_nests_the_frames_and_drops_reverted_logs() {
+	let root = root_frame(feed(CallTracer::new(true), reverting_subcall()).into_tx_trace());
+
+	assert_eq!(root.to, Some(address(2)));
+	assert_eq!(root.output, Some(vec![0x2a]));
+	assert_eq!(root.error, None);
+	assert_eq!(root.logs, vec![log(2, vec![0x22], 1, false)]);
+
+	assert_eq!(callees(&root), vec![Some(address(3))]);
+	let subcall = &root.calls[0];
+	assert_eq!(subcall.error, Some(b"execution reverted".to_vec()));
+	assert_eq!(subcall.output, None);
+	assert!(subcall.logs.is_empty());
+
+	// Logs are only provided on demand.
+	let root = root_frame(feed(CallTracer::new(false), reverting_subcall()).into_tx_trace());
+	assert!(root.logs.is_empty());
+}
+
+fn account_state(at: u64, balance: u64, nonce: u64, storage: Vec<(u64, u64)>) -> AccountState {
+	AccountState {
+		address: address(at),
+		balance: balance.into(),
+		nonce: nonce.into(),
+		code: vec![],
+		storage: storage
+			.into_iter()
+			.map(|(index, value)| (word(index), word(value)))
+			.collect(),
+	}
+}
+
+fn state_events() -> Vec<Event> {
+	vec![
+		Event::PreState(account_state(1, 10, 1, vec![(1, 5), (2, 7)])),
+		Event::PreState(account_state(4, 3, 0, vec![])),
+		Event::PostState(account_state(1, 7, 2, vec![(1, 6), (2, 7)])),
+		Event::PostState(account_state(4, 3, 0, vec![])),
+	]
+}
+
+#[test]
+fn prestate_tracer_provides_the_state_of_the_touched_accounts() {
+	let storage = |slots: Vec<(u64, u64)>| -> BTreeMap<H256, H256> {
+		slots
+			.into_iter()
+			.map(|(index, value)| (word(index), word(value)))
+			.collect()
+	};
+	let trace = feed(PrestateTracer::new(false), state_events()).into_tx_trace();
+	assert_eq!(
+		trace,
+		TransactionTrace::Prestate(
+			vec![
+				(
+					address(1),
+					PrestateAccount {
+						balance: Some(10.into()),
+						nonce: Some(1.into()),
+						code: None,
+						storage: storage(vec![(1, 5), (2, 7)]),
+					}
+				),
+				(
+					address(4),
+					PrestateAccount {
+						balance: Some(3.into()),
+						nonce: None,
+						code: None,
+						storage: BTreeMap::new(),
+					}
+				),
+			]
+			.into_iter()
+			.collect()
+		)
+	);
+
+	// Only the modified accounts and fields are part of the diff.
+	let trace = feed(PrestateTracer::new(true), state_events()).into_tx_trace();
+	assert_eq!(
+		trace,
+		TransactionTrace::PrestateDiff {
+			pre: vec![(
+				address(1),
+				PrestateAccount {
+					balance: Some(10.into()),
+					nonce: Some(1.into()),
+					code: None,
+					storage: storage(vec![(1, 5)]),
+				}
+			)]
+			.into_iter()
+			.collect(),
+			post: vec![(
+				address(1),
+				PrestateAccount {
+					balance: Some(7.into()),
+					nonce: Some(2.into()),
+					code: None,
+					storage: storage(vec![(1, 6)]),
+				}
+			)]
+			.into_iter()
+			.collect(),
+		}
+	);
+}
+
+#[test]
+fn vm_tracer_records_the_outcome_of_each_operation() {
+	let vm_trace = feed(VmTracer::new(), storing_call()).into_vm_trace();
+
+	assert_eq!(vm_trace.code, vec![0x60, 0x2a, 0x60, 0x01, 0x55, 0x00]);
+	assert_eq!(
+		vm_trace.ops.iter().map(|op| (op.pc, op.cost)).collect::<Vec<_>>(),
+		vec![(0, 3), (2, 3), (4, 20_000), (5, 0)]
+	);
+	assert!(vm_trace.ops.iter().all(|op| op.sub.is_none()));
+
+	let ex = |index: usize| vm_trace.ops[index].ex.clone().unwrap();
+	assert_eq!(ex(0).push, vec![U256::from(0x2a)]);
+	assert_eq!(ex(0).used, 78_997);
+	assert_eq!(ex(1).push, vec![U256::one()]);
+	assert!(ex(2).push.is_empty());
+	assert_eq!(
+		ex(2).store,
+		Some(VmStorageDiff {
+			key: U256::one(),
+			val: U256::from(0x2a),
+		})
+	);
+	assert_eq!(ex(2).used, 58_994);
+}
+
+#[test]
+fn replay_tracer_combines_the_calls_state_diff_and_vm_trace() {
+	let mut events = vec![Event::PreState(account_state(2, 0, 1, vec![(1, 0)]))];
+	events.extend(storing_call());
+	events.push(Event::PostState(account_state(2, 0, 1, vec![(1, 0x2a)])));
+
+	let replay = feed(
+		ReplayTracer::new(ReplayTraceType {
+			trace: true,
+			state_diff: true,
+			vm_trace: true,
+		}),
+		events.clone(),
+	)
+	.into_replay();
+
+	assert!(replay.output.is_empty());
+	assert_eq!(replay.trace.len(), 1);
+	assert_eq!(replay.vm_trace.map(|vm_trace| vm_trace.ops.len()), Some(4));
+	assert_eq!(
+		replay.state_diff,
+		Some(
+			vec![(
+				address(2),
+				AccountDiff {
+					balance: Diff::Same,
+					nonce: Diff::Same,
+					code: Diff::Same,
+					storage: vec![(
+						word(1),
+						Diff::Changed {
+							from: word(0),
+							to: word(0x2a),
+						}
+					)]
+					.into_iter()
+					.collect(),
+				}
+			)]
+			.into_iter()
+			.collect()
+		)
+	);
+
+	// Only the requested outputs are built.
+	let replay = feed(ReplayTracer::new(ReplayTraceType::default()), events).into_replay();
+	assert!(replay.trace.is_empty());
+	assert_eq!(replay.state_diff, None);
+	assert_eq!(replay.vm_trace, None);
+}
+
+#[test]
+fn tracing_runtimes_replace_the_spec_versions_of_their_file_name() {
+	use crate::overrides::spec_versions;
+	use sp_api::RuntimeVersion;
+	use std::path::Path;
+
+	let version = RuntimeVersion {
+		spec_version: 19,
+		..Default::default()
+	};
+	let spec_versions = |name: &str| spec_versions(Path::new(name), &version);
+
+	assert_eq!(spec_versions("tracing/hedgeware-tracing-17-18.wasm"), Ok(17..=18));
+	assert_eq!(spec_versions("hedgeware-tracing-20-20.wasm"), Ok(20..=20));
+	// Without a range, the runtime replaces its own spec version.
+	assert_eq!(spec_versions("hedgeware-tracing.wasm"), Ok(19..=19));
+	assert_eq!(spec_versions("hedgeware-tracing-17.wasm"), Ok(19..=19));
+	assert_eq!(spec_versions("hedgeware-tracing-v17-18.wasm"), Ok(19..=19));
+	assert!(spec_versions("hedgeware-tracing-18-17.wasm").is_err());
+}
@@ -0,0 +1,382 @@
+// Copyright 2019-2020 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::util::*;
+
+use ethereum_types::{H256, U256};
+use hedgeware_rpc_primitives_debug::replay::{
+	VmExecutedOperation, VmMemoryDiff, VmOperation, VmStorageDiff, VmTrace,
+};
+
+/// Listen to the EVM events sent by the runtime to provide the executed opcodes in the format of OpenEthereum's
+/// `vmTrace`.
+///
+/// The outcome of an operation (written stack items and memory) is only known at the
+/// next step of the same call, the trace of a subcall being attached to the operation
+/// which triggered it. The runtime must send the memory, the stack and the code of the
+/// called accounts.
+///
+/// # Output example
+///
+/// ```json
+/// {
+///   "code": "0x6080604052...",
+///   "ops": [
+///     {
+///       "pc": 0,
+///       "cost": 3,
+///       "ex": {
+///         "used": 78642,
+///         "push": ["0x80"],
+///         "mem": null,
+///         "store": null
+///       },
+///       "sub": null
+///     }
+///   ]
+/// }
+/// ```
+#[derive(Debug)]
+pub struct VmTracer {
+	// Stack of calls being executed.
+	frames: Vec<Frame>,
+	// Trace of the root call, once exited.
+	result: Option<VmTrace>,
+}
+
+#[derive(Debug)]
+struct Frame {
+	trace: VmTrace,
+	// Precompiles are executed without any step, a call which didn't step yet is thus
+	// closed if a step of its parent is received.
+	started: bool,
+	// Operation of the current step, whose cost is being recorded.
+	current: Option<usize>,
+	// Whether a cost has already been recorded during the current step.
+	cost_recorded: bool,
+	// Last operation, whose outcome is known at the next step.
+	pending: Option<PendingOperation>,
+}
+
+#[derive(Debug)]
+struct PendingOperation {
+	index: usize,
+	// Number of stack items written.
+	push: usize,
+	// Offset and size of the memory written.
+	mem: Option<(usize, usize)>,
+	store: Option<VmStorageDiff>,
+}
+
+impl Frame {
+	fn new(code: Vec<u8>) -> Self {
+		Self {
+			trace: VmTrace {
+				code,
+				ops: vec![],
+			},
+			started: false,
+			current: None,
+			cost_recorded: false,
+			pending: None,
+		}
+	}
+}
+
+impl Default for VmTracer {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl VmTracer {
+	pub fn new() -> Self {
+		Self {
+			frames: vec![],
+			result: None,
+		}
+	}
+
+	pub fn into_vm_trace(mut self) -> VmTrace {
+		// Calls to precompiles can be left open.
+		while !self.frames.is_empty() {
+			self.exit(None);
+		}
+		self.result.unwrap_or_default()
+	}
+
+	fn enter(&mut self, code: Vec<u8>) {
+		// Gas forwarded to the subcall is not part of the cost of the operation.
+		if let Some(parent) = self.frames.last_mut() {
+			parent.current = None;
+		}
+		self.frames.push(Frame::new(code));
+	}
+
+	/// Close the current call, its last operation having no outcome if it failed.
+	fn exit(&mut self, reason: Option<&ExitReason>) {
+		let mut frame = match self.frames.pop() {
+			Some(frame) => frame,
+			None => return,
+		};
+
+		if matches!(reason, Some(ExitReason::Error(_)) | Some(ExitReason::Fatal(_))) {
+			if let Some(op) = frame.trace.ops.last_mut() {
+				op.ex = None;
+			}
+		}
+
+		match self.frames.last_mut() {
+			Some(parent) => {
+				if let Some(op) = parent.trace.ops.last_mut() {
+					op.sub = Some(frame.trace);
+				}
+			}
+			None => self.result = Some(frame.trace),
+		}
+	}
+
+	fn record_cost(&mut self, cost: u64, gas: u64) {
+		if let Some(frame) = self.frames.last_mut() {
+			if let Some(index) = frame.current {
+				// The gas left before this operation is the gas left after the previous one,
+				// including the gas given back by its subcall if any.
+				if !frame.cost_recorded && index > 0 {
+					if let Some(ex) = &mut frame.trace.ops[index - 1].ex {
+						ex.used = gas;
+					}
+				}
+				frame.cost_recorded = true;
+
+				let op = &mut frame.trace.ops[index];
+				op.cost += cost;
+				if let Some(ex) = &mut op.ex {
+					ex.used = gas.saturating_sub(cost);
+				}
+			}
+		}
+	}
+}
+
+/// Complete the outcome of an operation with the state at the next step.
+fn complete_operation(
+	trace: &mut VmTrace,
+	pending: PendingOperation,
+	stack: &[H256],
+	memory: &[u8],
+	memory_len: usize,
+) {
+	let ex = match trace.ops.get_mut(pending.index).and_then(|op| op.ex.as_mut()) {
+		Some(ex) => ex,
+		None => return,
+	};
+
+	let push = pending.push.min(stack.len());
+	ex.push = stack[stack.len() - push..]
+		.iter()
+		.map(|item| U256::from_big_endian(item.as_bytes()))
+		.collect();
+
+	ex.mem = pending.mem.and_then(|(offset, size)| {
+		let end = offset.checked_add(size)?;
+		if end > memory_len {
+			return None;
+		}
+		// Memory is only allocated when written.
+		let mut data = vec![0u8; size];
+		if offset < memory.len() {
+			let available = &memory[offset..end.min(memory.len())];
+			data[..available.len()].copy_from_slice(available);
+		}
+		Some(VmMemoryDiff {
+			off: offset as u64,
+			data,
+		})
+	});
+
+	ex.store = pending.store;
+}
+
+/// Number of stack items written by an opcode.
+fn stack_pushes(opcode: Opcode) -> usize {
+	match opcode.0 {
+		// DUPn and SWAPn write the n+1 top items.
+		0x80..=0x8f => (opcode.0 - 0x80 + 2) as usize,
+		0x90..=0x9f => (opcode.0 - 0x90 + 2) as usize,
+		0x00 | 0x37 | 0x39 | 0x3c | 0x3e | 0x50 | 0x52 | 0x53 | 0x55 | 0x56 | 0x57 | 0x5b
+		| 0xa0..=0xa4 | 0xf3 | 0xfd | 0xfe | 0xff => 0,
+		_ => 1,
+	}
+}
+
+/// Offset and size of the memory written by an opcode, read from the stack before its
+/// execution.
+fn memory_written(opcode: Opcode, stack: &[H256]) -> Option<(usize, usize)> {
+	let peek = |n: usize| {
+		stack
+			.len()
+			.checked_sub(n + 1)
+			.and_then(|index| stack_usize(&stack[index]))
+	};
+
+	let (offset, size) = match opcode.0 {
+		// MLOAD, MSTORE
+		0x51 | 0x52 => (peek(0)?, 32),
+		// MSTORE8
+		0x53 => (peek(0)?, 1),
+		// CALLDATACOPY, CODECOPY, RETURNDATACOPY
+		0x37 | 0x39 | 0x3e => (peek(0)?, peek(2)?),
+		// EXTCODECOPY
+		0x3c => (peek(1)?, peek(3)?),
+		// CALL, CALLCODE
+		0xf1 | 0xf2 => (peek(5)?, peek(6)?),
+		// DELEGATECALL, STATICCALL
+		0xf4 | 0xfa => (peek(4)?, peek(5)?),
+		_ => return None,
+	};
+
+	Some((offset, size)).filter(|(_, size)| *size > 0)
+}
+
+/// Storage written by an opcode, read from the stack before its execution.
+fn storage_written(opcode: Opcode, stack: &[H256]) -> Option<VmStorageDiff> {
+	// SSTORE
+	if opcode.0 != 0x55 || stack.len() < 2 {
+		return None;
+	}
+	Some(VmStorageDiff {
+		key: U256::from_big_endian(stack[stack.len() - 1].as_bytes()),
+		val: U256::from_big_endian(stack[stack.len() - 2].as_bytes()),
+	})
+}
+
+impl Listener for VmTracer {
+	fn event(&mut self, event: Event) {
+		match event {
+			Event::Evm(event) => self.evm_event(event),
+			Event::Gasometer(event) => self.gasometer_event(event),
+			Event::Runtime(event) => self.runtime_event(event),
+			// The code of a call is sent right after it started.
+			Event::CallCode(code) => {
+				if let Some(frame) = self.frames.last_mut() {
+					frame.trace.code = code;
+				}
+			}
+			_ => (),
+		}
+	}
+}
+
+impl VmTracer {
+	fn gasometer_event(&mut self, event: GasometerEvent) {
+		match event {
+			GasometerEvent::RecordCost { cost, snapshot } => {
+				self.record_cost(cost, snapshot.gas());
+			}
+			GasometerEvent::RecordDynamicCost {
+				gas_cost,
+				memory_gas,
+				snapshot,
+				..
+			} => {
+				// Memory expansion is part of the cost of the operation.
+				let cost = gas_cost + memory_gas.saturating_sub(snapshot.memory_gas);
+				self.record_cost(cost, snapshot.gas());
+			}
+			GasometerEvent::RecordRefund { .. }
+			| GasometerEvent::RecordStipend { .. }
+			| GasometerEvent::RecordTransaction { .. } => (),
+		}
+	}
+
+	fn runtime_event(&mut self, event: RuntimeEvent) {
+		match event {
+			RuntimeEvent::Step {
+				opcode,
+				position,
+				stack,
+				memory,
+				memory_size,
+				..
+			} => {
+				let position = *position.as_ref().unwrap_or(&0);
+				let opcode = Opcode(opcode);
+				let stack = stack.unwrap_or_default();
+				let memory = memory.unwrap_or_default();
+
+				// The first step of a call is always at position 0, and the step following a
+				// subcall never is.
+				if matches!(self.frames.last(), Some(frame) if !frame.started && position != 0) {
+					self.exit(None);
+				}
+
+				if let Some(frame) = self.frames.last_mut() {
+					frame.started = true;
+
+					if let Some(pending) = frame.pending.take() {
+						complete_operation(
+							&mut frame.trace,
+							pending,
+							&stack,
+							&memory,
+							memory_size as usize,
+						);
+					}
+
+					let index = frame.trace.ops.len();
+					frame.trace.ops.push(VmOperation {
+						pc: position,
+						cost: 0,
+						ex: Some(VmExecutedOperation::default()),
+						sub: None,
+					});
+					frame.current = Some(index);
+					frame.cost_recorded = false;
+					frame.pending = Some(PendingOperation {
+						index,
+						push: stack_pushes(opcode),
+						mem: memory_written(opcode, &stack),
+						store: storage_written(opcode, &stack),
+					});
+				}
+			}
+			RuntimeEvent::StepResult { result: Ok(()), .. } => {
+				if let Some(frame) = self.frames.last_mut() {
+					frame.current = None;
+				}
+			}
+			RuntimeEvent::StepResult {
+				result: Err(Capture::Exit(reason)),
+				..
+			} => self.exit(Some(&reason)),
+			RuntimeEvent::StepResult {
+				result: Err(Capture::Trap(_)),
+				..
+			}
+			| RuntimeEvent::SLoad { .. }
+			| RuntimeEvent::SStore { .. } => (),
+		}
+	}
+
+	fn evm_event(&mut self, event: EvmEvent) {
+		match event {
+			EvmEvent::Call { .. } => self.enter(vec![]),
+			EvmEvent::Create { init_code, .. } => self.enter(init_code),
+			// Self destructs execute no code, and transactions are traced from their root call.
+			_ => (),
+		}
+	}
+}
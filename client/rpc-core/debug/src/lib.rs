@@ -13,11 +13,15 @@
 
 // You should have received a copy of the GNU General Public License
 // along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
-use ethereum_types::H256;
+use ethereum_types::{H160, H256};
 use futures::{compat::Compat, future::BoxFuture};
 use jsonrpc_core::Result as RpcResult;
 use jsonrpc_derive::rpc;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+pub use fc_rpc_core::types::CallRequest;
+pub use hedgeware_rpc_core_types::{RequestBlockId, RequestBlockTag, StateOverride};
+use std::collections::BTreeMap;
 
 pub use rpc_impl_Debug::gen_server::Debug as DebugServer;
 pub mod types {
@@ -26,15 +30,61 @@ pub mod types {
 
 use crate::types::single;
 
+#[cfg(test)]
+mod tests;
+
 #[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TraceParams {
 	pub disable_storage: Option<bool>,
 	pub disable_memory: Option<bool>,
 	pub disable_stack: Option<bool>,
-	/// Javascript tracer (we just check if it's Blockscout tracer string)
+	/// Name of a built-in tracer (`callTracer`, `prestateTracer`) or javascript tracer (we
+	/// just check if it's Blockscout tracer string).
 	pub tracer: Option<String>,
+	/// Options of the built-in tracer.
+	pub tracer_config: Option<TracerConfig>,
 	pub timeout: Option<String>,
+	/// Index of the first step returned by the default tracer, for the steps of large
+	/// transactions to be fetched by several requests.
+	pub start_step: Option<u64>,
+	/// Maximum number of steps returned by the default tracer.
+	pub limit: Option<u64>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TracerConfig {
+	/// Return the state before and after the transaction (`prestateTracer`).
+	///
+	/// The state before the transaction is read by rolling it back, so tracing the transactions
+	/// of a block with `prestateTracer` executes each of them twice.
+	pub diff_mode: Option<bool>,
+	/// Return the logs emitted by each call (`callTracer`).
+	pub with_log: Option<bool>,
+}
+
+/// Parameters of `debug_traceCall`: the ones of `debug_traceTransaction` and state overrides.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceCallParams {
+	#[serde(flatten)]
+	pub trace: TraceParams,
+	/// State of accounts to override before executing the call.
+	pub state_overrides: Option<BTreeMap<H160, StateOverride>>,
+}
+
+/// Trace of a transaction as part of a block trace.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockTransactionTrace {
+	/// Hash of the Ethereum transaction, or of the extrinsic for Substrate-originated traces.
+	pub tx_hash: H256,
+	/// Position of the extrinsic in the Substrate block, only set for Substrate-originated
+	/// traces.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub extrinsic_index: Option<u32>,
+	pub result: single::TransactionTrace,
 }
 
 #[rpc(server)]
@@ -45,4 +95,26 @@ pub trait Debug {
 		transaction_hash: H256,
 		params: Option<TraceParams>,
 	) -> Compat<BoxFuture<'static, RpcResult<single::TransactionTrace>>>;
+
+	#[rpc(name = "debug_traceCall")]
+	fn trace_call(
+		&self,
+		call: CallRequest,
+		block: Option<RequestBlockId>,
+		params: Option<TraceCallParams>,
+	) -> Compat<BoxFuture<'static, RpcResult<single::TransactionTrace>>>;
+
+	#[rpc(name = "debug_traceBlockByNumber")]
+	fn trace_block_by_number(
+		&self,
+		block_number: RequestBlockId,
+		params: Option<TraceParams>,
+	) -> Compat<BoxFuture<'static, RpcResult<Vec<BlockTransactionTrace>>>>;
+
+	#[rpc(name = "debug_traceBlockByHash")]
+	fn trace_block_by_hash(
+		&self,
+		block_hash: H256,
+		params: Option<TraceParams>,
+	) -> Compat<BoxFuture<'static, RpcResult<Vec<BlockTransactionTrace>>>>;
 }
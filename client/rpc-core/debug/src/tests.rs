@@ -0,0 +1,67 @@
+// Copyright 2019-2020 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::*;
+use ethereum_types::{H160, U256};
+use serde_json::json;
+
+#[test]
+fn trace_call_params_take_trace_params_and_state_overrides() {
+	let params: TraceCallParams = serde_json::from_value(json!({
+		"tracer": "callTracer",
+		"tracerConfig": { "withLog": true },
+		"timeout": "10s",
+		"stateOverrides": {
+			"0x1111111111111111111111111111111111111111": {
+				"balance": "0x10",
+				"nonce": "0x2"
+			}
+		}
+	}))
+	.unwrap();
+
+	assert_eq!(params.trace.tracer.as_deref(), Some("callTracer"));
+	assert_eq!(
+		params.trace.tracer_config,
+		Some(TracerConfig {
+			diff_mode: None,
+			with_log: Some(true),
+		})
+	);
+	assert_eq!(params.trace.timeout.as_deref(), Some("10s"));
+	assert_eq!(params.trace.disable_storage, None);
+
+	let state_overrides = params.state_overrides.unwrap();
+	let state_override = &state_overrides[&H160::repeat_byte(0x11)];
+	assert_eq!(state_override.balance, Some(U256::from(0x10)));
+	assert_eq!(state_override.nonce, Some(U256::from(2)));
+	assert_eq!(state_override.code, None);
+}
+
+#[test]
+fn trace_call_params_without_state_overrides() {
+	let params: TraceCallParams = serde_json::from_value(json!({
+		"disableStorage": true,
+		"startStep": 5,
+		"limit": 10
+	}))
+	.unwrap();
+
+	assert_eq!(params.trace.disable_storage, Some(true));
+	assert_eq!(params.trace.start_step, Some(5));
+	assert_eq!(params.trace.limit, Some(10));
+	assert_eq!(params.state_overrides, None);
+}
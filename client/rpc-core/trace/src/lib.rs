@@ -14,21 +14,81 @@
 // You should have received a copy of the GNU General Public License
 // along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
 
-use ethereum_types::H160;
+use ethereum_types::{H160, H256};
+pub use fc_rpc_core::types::CallRequest;
 use futures::{compat::Compat, future::BoxFuture};
 use jsonrpc_derive::rpc;
-pub use hedgeware_rpc_primitives_debug::block::TransactionTrace;
-use serde::{de::Error, Deserialize, Deserializer};
+pub use hedgeware_rpc_primitives_debug::{block::TransactionTrace, replay::TransactionReplay};
+use hedgeware_rpc_core_types::deserialize_u32_0x;
+pub use hedgeware_rpc_core_types::{RequestBlockId, RequestBlockTag, StateOverride};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
 
 pub use rpc_impl_Trace::gen_server::Trace as TraceServer;
 
+#[cfg(test)]
+mod tests;
+
 #[rpc(server)]
 pub trait Trace {
+	/// Returns an array of traces, or a page of traces with a cursor if the request contains a
+	/// cursor.
 	#[rpc(name = "trace_filter")]
 	fn filter(
 		&self,
 		filter: FilterRequest,
+	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<FilterResponse>>>;
+
+	#[rpc(name = "trace_block")]
+	fn block(
+		&self,
+		block: RequestBlockId,
 	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<Vec<TransactionTrace>>>>;
+
+	#[rpc(name = "trace_transaction")]
+	fn transaction(
+		&self,
+		transaction_hash: H256,
+	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<Option<Vec<TransactionTrace>>>>>;
+
+	#[rpc(name = "trace_get")]
+	fn get(
+		&self,
+		transaction_hash: H256,
+		indices: Vec<TraceIndex>,
+	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<Option<TransactionTrace>>>>;
+
+	#[rpc(name = "trace_replayTransaction")]
+	fn replay_transaction(
+		&self,
+		transaction_hash: H256,
+		trace_types: Vec<RequestTraceType>,
+	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<TransactionReplay>>>;
+
+	#[rpc(name = "trace_replayBlockTransactions")]
+	fn replay_block_transactions(
+		&self,
+		block: RequestBlockId,
+		trace_types: Vec<RequestTraceType>,
+	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<Vec<TransactionReplay>>>>;
+
+	#[rpc(name = "trace_call")]
+	fn call(
+		&self,
+		call: CallRequest,
+		trace_types: Vec<RequestTraceType>,
+		block: Option<RequestBlockId>,
+		state_overrides: Option<BTreeMap<H160, StateOverride>>,
+	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<TransactionReplay>>>;
+
+	/// Calls are executed one after the other, each one on top of the state left by the
+	/// previous ones.
+	#[rpc(name = "trace_callMany")]
+	fn call_many(
+		&self,
+		calls: Vec<(CallRequest, Vec<RequestTraceType>)>,
+		block: Option<RequestBlockId>,
+	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<Vec<TransactionReplay>>>>;
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
@@ -51,33 +111,149 @@ pub struct FilterRequest {
 
 	/// (optional) Integer number of traces to display in a batch.
 	pub count: Option<u32>,
+
+	/// (optional) Whether a trace must match both `fromAddress` and `toAddress`
+	/// (`intersection`, the default) or any of them (`union`).
+	pub mode: Option<FilterMode>,
+
+	/// (optional) Only traces of these kinds.
+	pub call_type: Option<Vec<FilterCallType>>,
+
+	/// (optional) Only traces of failed calls.
+	pub errors_only: Option<bool>,
+
+	/// (optional) Paginate the traces with a cursor instead of `after`. An empty cursor starts
+	/// from the beginning of the range, the cursor of the previous page continues from it.
+	pub cursor: Option<TraceCursor>,
 }
 
+/// How `fromAddress` and `toAddress` are combined.
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize)]
-#[serde(rename_all = "camelCase", untagged)]
-pub enum RequestBlockId {
-	Number(#[serde(deserialize_with = "deserialize_u32_0x")] u32),
-	Tag(RequestBlockTag),
+#[serde(rename_all = "lowercase")]
+pub enum FilterMode {
+	Union,
+	Intersection,
+}
+
+impl Default for FilterMode {
+	fn default() -> Self {
+		FilterMode::Intersection
+	}
 }
 
+/// Kind of trace, as filtered by `callType`.
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub enum RequestBlockTag {
-	Earliest,
-	Latest,
-	Pending,
+#[serde(rename_all = "lowercase")]
+pub enum FilterCallType {
+	Call,
+	CallCode,
+	DelegateCall,
+	StaticCall,
+	Create,
+	Suicide,
+}
+
+/// Position of the next trace of a paginated `trace_filter`, serialized as an opaque hexadecimal
+/// string.
+///
+/// It contains the hash of the block of the trace, so that a page following a reorganization
+/// of this block fails instead of silently skipping or repeating traces.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum TraceCursor {
+	/// Beginning of the range.
+	Start,
+	/// Trace at `trace_index` in the (unfiltered) traces of a block.
+	Trace {
+		block_number: u32,
+		block_hash: H256,
+		trace_index: u32,
+	},
+}
+
+impl Serialize for TraceCursor {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		let bytes = match self {
+			TraceCursor::Start => vec![],
+			TraceCursor::Trace {
+				block_number,
+				block_hash,
+				trace_index,
+			} => {
+				let mut bytes = block_number.to_be_bytes().to_vec();
+				bytes.extend_from_slice(block_hash.as_bytes());
+				bytes.extend_from_slice(&trace_index.to_be_bytes());
+				bytes
+			}
+		};
+		serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+	}
+}
+
+impl<'de> Deserialize<'de> for TraceCursor {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		let buf = String::deserialize(deserializer)?;
+		let bytes = hex::decode(buf.strip_prefix("0x").unwrap_or(&buf))
+			.map_err(|e| Error::custom(format!("invalid cursor: {:?}", e)))?;
+
+		match bytes.len() {
+			0 => Ok(TraceCursor::Start),
+			40 => {
+				let mut block_number = [0u8; 4];
+				let mut trace_index = [0u8; 4];
+				block_number.copy_from_slice(&bytes[..4]);
+				trace_index.copy_from_slice(&bytes[36..]);
+				Ok(TraceCursor::Trace {
+					block_number: u32::from_be_bytes(block_number),
+					block_hash: H256::from_slice(&bytes[4..36]),
+					trace_index: u32::from_be_bytes(trace_index),
+				})
+			}
+			_ => Err(Error::custom("invalid cursor length")),
+		}
+	}
 }
 
-fn deserialize_u32_0x<'de, D>(deserializer: D) -> Result<u32, D::Error>
-where
-	D: Deserializer<'de>,
-{
-	let buf = String::deserialize(deserializer)?;
+/// Response of `trace_filter`.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize)]
+#[serde(untagged)]
+pub enum FilterResponse {
+	/// Traces of a request without cursor, as in OpenEthereum.
+	Traces(Vec<TransactionTrace>),
+	/// Traces of a request with a cursor, and the cursor of the next page if any.
+	Page {
+		traces: Vec<TransactionTrace>,
+		cursor: Option<TraceCursor>,
+	},
+}
 
-	let parsed = match buf.strip_prefix("0x") {
-		Some(buf) => u32::from_str_radix(&buf, 16),
-		None => u32::from_str_radix(&buf, 10),
-	};
+/// Position of a trace in the `traceAddress` of a transaction, as a number or an hexadecimal
+/// string.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum TraceIndex {
+	Number(u32),
+	Hex(#[serde(deserialize_with = "deserialize_u32_0x")] u32),
+}
 
-	parsed.map_err(|e| Error::custom(format!("parsing error: {:?} from '{}'", e, buf)))
+impl TraceIndex {
+	pub fn value(self) -> u32 {
+		match self {
+			TraceIndex::Number(index) | TraceIndex::Hex(index) => index,
+		}
+	}
+}
+
+/// Outputs of a transaction replay.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RequestTraceType {
+	Trace,
+	StateDiff,
+	VmTrace,
 }
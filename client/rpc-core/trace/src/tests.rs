@@ -0,0 +1,100 @@
+// Copyright 2019-2020 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::*;
+use serde_json::json;
+
+fn cursor(value: serde_json::Value) -> Result<TraceCursor, serde_json::Error> {
+	serde_json::from_value(value)
+}
+
+#[test]
+fn trace_cursor_round_trip() {
+	let trace = TraceCursor::Trace {
+		block_number: 0x0102_0304,
+		block_hash: H256::repeat_byte(0xab),
+		trace_index: 7,
+	};
+
+	let serialized = serde_json::to_value(&trace).unwrap();
+	assert_eq!(
+		serialized,
+		json!(format!("0x01020304{}00000007", "ab".repeat(32)))
+	);
+	assert_eq!(cursor(serialized).unwrap(), trace);
+
+	let start = serde_json::to_value(&TraceCursor::Start).unwrap();
+	assert_eq!(start, json!("0x"));
+	assert_eq!(cursor(start).unwrap(), TraceCursor::Start);
+	assert_eq!(cursor(json!("")).unwrap(), TraceCursor::Start);
+}
+
+#[test]
+fn trace_cursor_without_prefix_is_accepted() {
+	let hex = format!("00000001{}00000002", "cd".repeat(32));
+
+	assert_eq!(
+		cursor(json!(hex)).unwrap(),
+		TraceCursor::Trace {
+			block_number: 1,
+			block_hash: H256::repeat_byte(0xcd),
+			trace_index: 2,
+		}
+	);
+}
+
+#[test]
+fn malformed_trace_cursors_are_rejected() {
+	let valid = format!("0x00000001{}00000002", "cd".repeat(32));
+	assert!(cursor(json!(valid)).is_ok());
+
+	// 40 characters instead of 40 bytes.
+	assert!(cursor(json!(format!("0x{}", "00".repeat(20)))).is_err());
+	// One byte missing or in excess.
+	assert!(cursor(json!(format!("0x{}", "00".repeat(39)))).is_err());
+	assert!(cursor(json!(format!("0x{}", "00".repeat(41)))).is_err());
+	// Odd number of digits.
+	assert!(cursor(json!(&valid[..valid.len() - 1])).is_err());
+	// A digit which isn't hexadecimal in an otherwise valid cursor.
+	assert!(cursor(json!(valid.replacen("cd", "zz", 1))).is_err());
+	// Not a string.
+	assert!(cursor(json!(42)).is_err());
+	assert!(cursor(json!({ "blockNumber": 1 })).is_err());
+}
+
+#[test]
+fn filter_request_takes_cursor_and_filters() {
+	let request: FilterRequest = serde_json::from_value(json!({
+		"fromBlock": "0x1",
+		"toBlock": "latest",
+		"fromAddress": ["0x1111111111111111111111111111111111111111"],
+		"mode": "union",
+		"callType": ["delegatecall", "create"],
+		"errorsOnly": true,
+		"cursor": "0x",
+	}))
+	.unwrap();
+
+	assert_eq!(request.from_address, Some(vec![H160::repeat_byte(0x11)]));
+	assert_eq!(request.to_address, None);
+	assert_eq!(request.mode, Some(FilterMode::Union));
+	assert_eq!(
+		request.call_type,
+		Some(vec![FilterCallType::DelegateCall, FilterCallType::Create])
+	);
+	assert_eq!(request.errors_only, Some(true));
+	assert_eq!(request.cursor, Some(TraceCursor::Start));
+}
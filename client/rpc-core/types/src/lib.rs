@@ -0,0 +1,284 @@
+// Copyright 2019-2020 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Types shared by the RPC interfaces.
+
+use ethereum_types::{H160, H256, U256};
+use fc_rpc_core::types::{Bytes, CallRequest};
+use hedgeware_rpc_primitives_debug::call::{AccountOverride, TraceCall};
+use serde::{de::Error, Deserialize, Deserializer};
+use std::{collections::BTreeMap, convert::TryFrom, fmt, time::Duration};
+
+#[cfg(test)]
+mod tests;
+
+/// Code of the error returned when a request exceeds its timeout.
+pub const TIMEOUT_ERROR_CODE: i64 = -32005;
+/// Code of the error returned when a block is not known to the node ("resource not found" of
+/// EIP-1474).
+pub const BLOCK_NOT_FOUND_ERROR_CODE: i64 = -32001;
+/// Code of the error returned when the state needed to trace a block has been pruned
+/// ("resource unavailable" of EIP-1474).
+pub const STATE_PRUNED_ERROR_CODE: i64 = -32002;
+/// Code of the error returned when the runtime of a block cannot trace the request ("method
+/// not supported" of EIP-1474).
+pub const UNSUPPORTED_RUNTIME_ERROR_CODE: i64 = -32004;
+/// Code of the error returned when a transaction is not in the Ethereum mapping of the node.
+pub const TRANSACTION_NOT_INDEXED_ERROR_CODE: i64 = -32010;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize)]
+#[serde(rename_all = "camelCase", untagged)]
+pub enum RequestBlockId {
+	Number(#[serde(deserialize_with = "deserialize_u32_0x")] u32),
+	Tag(RequestBlockTag),
+	/// Block from its number, in the object form specified by EIP-1898.
+	#[serde(rename_all = "camelCase")]
+	BlockNumber {
+		#[serde(deserialize_with = "deserialize_u32_0x")]
+		block_number: u32,
+	},
+	/// Block from its Ethereum hash, as specified by EIP-1898. If `requireCanonical` is set, the
+	/// request fails when the block isn't part of the canonical chain.
+	#[serde(rename_all = "camelCase")]
+	Hash {
+		block_hash: H256,
+		#[serde(default)]
+		require_canonical: bool,
+	},
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RequestBlockTag {
+	Earliest,
+	Latest,
+	/// The ready transactions of the pool, on top of the best block.
+	Pending,
+	/// The last finalized block. Parachain blocks are finalized along with the relay chain
+	/// block including them.
+	Finalized,
+	/// Same as `Finalized`: there is no weaker form of finality.
+	Safe,
+}
+
+/// State of an account overridden before tracing a call, as in geth's `debug_traceCall`.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateOverride {
+	pub balance: Option<U256>,
+	pub nonce: Option<U256>,
+	pub code: Option<Bytes>,
+	/// Replaces the whole storage of the account.
+	pub state: Option<BTreeMap<H256, H256>>,
+	/// Replaces some slots of the storage of the account.
+	pub state_diff: Option<BTreeMap<H256, H256>>,
+}
+
+impl TryFrom<StateOverride> for AccountOverride {
+	type Error = String;
+
+	/// Balances and nonces are rejected when they don't fit the `u128` balances and `u32`
+	/// nonces of the runtime, rather than being truncated.
+	fn try_from(state_override: StateOverride) -> Result<Self, Self::Error> {
+		if let Some(balance) = state_override.balance {
+			if balance > U256::from(u128::MAX) {
+				return Err(format!("balance override {} does not fit in 128 bits", balance));
+			}
+		}
+		if let Some(nonce) = state_override.nonce {
+			if nonce > U256::from(u32::MAX) {
+				return Err(format!("nonce override {} does not fit in 32 bits", nonce));
+			}
+		}
+
+		Ok(AccountOverride {
+			balance: state_override.balance,
+			nonce: state_override.nonce,
+			code: state_override.code.map(|code| code.into_vec()),
+			state: state_override.state,
+			state_diff: state_override.state_diff.unwrap_or_default(),
+		})
+	}
+}
+
+/// Convert the overrides of a request into their runtime representation.
+pub fn account_overrides(
+	state_overrides: Option<BTreeMap<H160, StateOverride>>,
+) -> jsonrpc_core::Result<BTreeMap<H160, AccountOverride>> {
+	state_overrides
+		.unwrap_or_default()
+		.into_iter()
+		.map(|(address, state_override)| {
+			AccountOverride::try_from(state_override)
+				.map(|account_override| (address, account_override))
+				.map_err(|e| {
+					jsonrpc_core::Error::invalid_params(format!("account {:?}: {}", address, e))
+				})
+		})
+		.collect()
+}
+
+/// Convert an `eth_call` request into a call to trace, the gas limit defaulting to the one of
+/// the block. A gas limit that doesn't fit the `u64` of the EVM is rejected.
+pub fn trace_call(request: CallRequest, block_gas_limit: U256) -> jsonrpc_core::Result<TraceCall> {
+	let gas_limit = request.gas.unwrap_or(block_gas_limit);
+	if gas_limit > U256::from(u64::MAX) {
+		return Err(jsonrpc_core::Error::invalid_params(format!(
+			"gas limit {} does not fit in 64 bits",
+			gas_limit
+		)));
+	}
+
+	Ok(TraceCall {
+		from: request.from.unwrap_or_default(),
+		to: request.to,
+		data: request.data.map(|data| data.into_vec()).unwrap_or_default(),
+		value: request.value.unwrap_or_default(),
+		gas_limit,
+		gas_price: request.gas_price,
+		nonce: request.nonce,
+	})
+}
+
+/// Error returned when a request exceeds its timeout.
+pub fn timeout_err() -> jsonrpc_core::Error {
+	jsonrpc_core::Error {
+		code: jsonrpc_core::ErrorCode::ServerError(TIMEOUT_ERROR_CODE),
+		message: "execution timeout".to_string(),
+		data: None,
+	}
+}
+
+/// Error of a tracing request lacking data, or whose block cannot be traced by its runtime.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TraceRpcError {
+	/// The state the block is replayed on, the one of its parent, has been pruned. The node
+	/// only keeps the state of all blocks in archive mode.
+	StatePruned { block: String, archive: bool },
+	/// The block is not known to the node.
+	BlockNotFound(String),
+	/// The transaction is not in the Ethereum mapping of the node, which may still be syncing.
+	TransactionNotIndexed(H256),
+	/// The runtime of the block does not implement the tracing API the request requires.
+	RuntimeApiUnsupported(String),
+}
+
+impl TraceRpcError {
+	/// Code of the JSON-RPC error.
+	pub fn code(&self) -> i64 {
+		match self {
+			Self::StatePruned { .. } => STATE_PRUNED_ERROR_CODE,
+			Self::BlockNotFound(_) => BLOCK_NOT_FOUND_ERROR_CODE,
+			Self::TransactionNotIndexed(_) => TRANSACTION_NOT_INDEXED_ERROR_CODE,
+			Self::RuntimeApiUnsupported(_) => UNSUPPORTED_RUNTIME_ERROR_CODE,
+		}
+	}
+}
+
+impl fmt::Display for TraceRpcError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::StatePruned { block, archive } => {
+				write!(f, "state of block {} is pruned", block)?;
+				if !archive {
+					f.write_str(
+						" (the node is not running in archive mode, start it with \
+						 `--pruning archive` to trace old blocks)",
+					)?;
+				}
+				Ok(())
+			}
+			Self::BlockNotFound(block) => write!(f, "block {} not found", block),
+			Self::TransactionNotIndexed(hash) => {
+				write!(f, "transaction {:?} is not indexed by the node", hash)
+			}
+			Self::RuntimeApiUnsupported(e) => write!(f, "unsupported by the runtime : {}", e),
+		}
+	}
+}
+
+impl From<TraceRpcError> for jsonrpc_core::Error {
+	fn from(error: TraceRpcError) -> Self {
+		jsonrpc_core::Error {
+			code: jsonrpc_core::ErrorCode::ServerError(error.code()),
+			message: error.to_string(),
+			data: None,
+		}
+	}
+}
+
+/// Parse a duration in the format of Go's `time.ParseDuration` (`"300ms"`, `"1.5s"`,
+/// `"1h10m"`), used by the `timeout` of geth's tracing requests. Negative durations are
+/// rejected.
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+	let invalid = || format!("invalid duration '{}'", duration);
+
+	let mut rest = duration.strip_prefix('+').unwrap_or(duration);
+	if rest == "0" {
+		return Ok(Duration::from_secs(0));
+	}
+	if rest.is_empty() {
+		return Err(invalid());
+	}
+
+	let mut nanos = 0f64;
+	while !rest.is_empty() {
+		let number_len = rest
+			.find(|c: char| !c.is_ascii_digit() && c != '.')
+			.unwrap_or_else(|| rest.len());
+		let (number, tail) = rest.split_at(number_len);
+		if number.is_empty() || number == "." {
+			return Err(invalid());
+		}
+		let number = number.parse::<f64>().map_err(|_| invalid())?;
+
+		let unit_len = tail
+			.find(|c: char| c.is_ascii_digit() || c == '.')
+			.unwrap_or_else(|| tail.len());
+		let (unit, tail) = tail.split_at(unit_len);
+		let unit_nanos = match unit {
+			"ns" => 1f64,
+			"us" | "µs" | "μs" => 1e3,
+			"ms" => 1e6,
+			"s" => 1e9,
+			"m" => 60e9,
+			"h" => 3600e9,
+			_ => return Err(invalid()),
+		};
+
+		nanos += number * unit_nanos;
+		rest = tail;
+	}
+
+	if nanos > u64::MAX as f64 {
+		return Err(invalid());
+	}
+	Ok(Duration::from_nanos(nanos as u64))
+}
+
+pub fn deserialize_u32_0x<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let buf = String::deserialize(deserializer)?;
+
+	let parsed = match buf.strip_prefix("0x") {
+		Some(buf) => u32::from_str_radix(&buf, 16),
+		None => u32::from_str_radix(&buf, 10),
+	};
+
+	parsed.map_err(|e| Error::custom(format!("parsing error: {:?} from '{}'", e, buf)))
+}
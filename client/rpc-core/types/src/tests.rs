@@ -0,0 +1,279 @@
+// Copyright 2019-2020 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::*;
+use serde_json::json;
+use std::time::Duration;
+
+fn state_override(value: serde_json::Value) -> StateOverride {
+	serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn state_override_deserializes_every_field() {
+	let parsed = state_override(json!({
+		"balance": "0x10",
+		"nonce": "0x2",
+		"code": "0x6000",
+		"state": {
+			"0x0000000000000000000000000000000000000000000000000000000000000001":
+				"0x000000000000000000000000000000000000000000000000000000000000002a"
+		},
+		"stateDiff": {
+			"0x0000000000000000000000000000000000000000000000000000000000000002":
+				"0x0000000000000000000000000000000000000000000000000000000000000007"
+		}
+	}));
+
+	assert_eq!(parsed.balance, Some(U256::from(0x10)));
+	assert_eq!(parsed.nonce, Some(U256::from(2)));
+	assert_eq!(parsed.code, Some(Bytes(vec![0x60, 0x00])));
+	assert_eq!(
+		parsed.state,
+		Some(
+			vec![(H256::from_low_u64_be(1), H256::from_low_u64_be(0x2a))]
+				.into_iter()
+				.collect()
+		)
+	);
+	assert_eq!(
+		parsed.state_diff,
+		Some(
+			vec![(H256::from_low_u64_be(2), H256::from_low_u64_be(7))]
+				.into_iter()
+				.collect()
+		)
+	);
+}
+
+#[test]
+fn state_override_fields_are_optional() {
+	let parsed = state_override(json!({}));
+
+	assert_eq!(parsed.balance, None);
+	assert_eq!(parsed.nonce, None);
+	assert_eq!(parsed.code, None);
+	assert_eq!(parsed.state, None);
+	assert_eq!(parsed.state_diff, None);
+}
+
+#[test]
+fn state_overrides_are_converted() {
+	let address = H160::repeat_byte(0x11);
+	let overrides = vec![(
+		address,
+		state_override(json!({
+			"balance": "0x10",
+			"nonce": "0x2",
+			"code": "0x6000",
+		})),
+	)]
+	.into_iter()
+	.collect();
+
+	let converted = account_overrides(Some(overrides)).unwrap();
+	assert_eq!(
+		converted.get(&address),
+		Some(&AccountOverride {
+			balance: Some(U256::from(0x10)),
+			nonce: Some(U256::from(2)),
+			code: Some(vec![0x60, 0x00]),
+			state: None,
+			state_diff: BTreeMap::new(),
+		})
+	);
+	assert!(account_overrides(None).unwrap().is_empty());
+}
+
+#[test]
+fn overflowing_state_overrides_are_rejected() {
+	let overrides = |value: serde_json::Value| {
+		Some(
+			vec![(H160::repeat_byte(0x11), state_override(value))]
+				.into_iter()
+				.collect(),
+		)
+	};
+
+	// The largest values of the runtime are accepted.
+	assert!(account_overrides(overrides(json!({
+		"balance": "0xffffffffffffffffffffffffffffffff",
+		"nonce": "0xffffffff",
+	})))
+	.is_ok());
+
+	let error = account_overrides(overrides(json!({
+		"balance": "0x100000000000000000000000000000000",
+	})))
+	.unwrap_err();
+	assert_eq!(error.code, jsonrpc_core::ErrorCode::InvalidParams);
+
+	let error = account_overrides(overrides(json!({ "nonce": "0x100000000" }))).unwrap_err();
+	assert_eq!(error.code, jsonrpc_core::ErrorCode::InvalidParams);
+}
+
+#[test]
+fn call_gas_limit_defaults_to_the_block_one_and_must_fit_64_bits() {
+	let request = |value: serde_json::Value| -> CallRequest { serde_json::from_value(value).unwrap() };
+
+	let call = trace_call(
+		request(json!({ "to": "0x2222222222222222222222222222222222222222" })),
+		U256::from(15_000_000),
+	)
+	.unwrap();
+	assert_eq!(call.gas_limit, U256::from(15_000_000));
+	assert_eq!(call.to, Some(H160::repeat_byte(0x22)));
+	assert_eq!(call.from, H160::zero());
+
+	let call = trace_call(request(json!({ "gas": "0xffffffffffffffff" })), U256::zero()).unwrap();
+	assert_eq!(call.gas_limit, U256::from(u64::MAX));
+	assert_eq!(call.to, None);
+
+	let error = trace_call(request(json!({ "gas": "0x10000000000000000" })), U256::zero())
+		.unwrap_err();
+	assert_eq!(error.code, jsonrpc_core::ErrorCode::InvalidParams);
+}
+
+#[test]
+fn block_ids_deserialize_numbers_tags_and_eip_1898_objects() {
+	let block_id = |value: serde_json::Value| -> RequestBlockId {
+		serde_json::from_value(value).unwrap()
+	};
+
+	assert_eq!(block_id(json!("0x10")), RequestBlockId::Number(16));
+	assert_eq!(block_id(json!("16")), RequestBlockId::Number(16));
+	assert_eq!(
+		block_id(json!("latest")),
+		RequestBlockId::Tag(RequestBlockTag::Latest)
+	);
+	assert_eq!(
+		block_id(json!("finalized")),
+		RequestBlockId::Tag(RequestBlockTag::Finalized)
+	);
+	assert_eq!(
+		block_id(json!("safe")),
+		RequestBlockId::Tag(RequestBlockTag::Safe)
+	);
+	assert_eq!(
+		block_id(json!({ "blockNumber": "0x10" })),
+		RequestBlockId::BlockNumber { block_number: 16 }
+	);
+
+	let block_hash = "0x3333333333333333333333333333333333333333333333333333333333333333";
+	assert_eq!(
+		block_id(json!({ "blockHash": block_hash })),
+		RequestBlockId::Hash {
+			block_hash: H256::repeat_byte(0x33),
+			require_canonical: false,
+		}
+	);
+	assert_eq!(
+		block_id(json!({ "blockHash": block_hash, "requireCanonical": true })),
+		RequestBlockId::Hash {
+			block_hash: H256::repeat_byte(0x33),
+			require_canonical: true,
+		}
+	);
+
+	assert!(serde_json::from_value::<RequestBlockId>(json!("unsafe")).is_err());
+	assert!(serde_json::from_value::<RequestBlockId>(json!({ "blockNumber": "0xzz" })).is_err());
+}
+
+#[test]
+fn durations_are_parsed_like_go_does() {
+	assert_eq!(parse_duration("1h10m"), Ok(Duration::from_secs(4200)));
+	assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+	assert_eq!(parse_duration("300ms"), Ok(Duration::from_millis(300)));
+	assert_eq!(parse_duration("+2m"), Ok(Duration::from_secs(120)));
+	assert_eq!(parse_duration("0"), Ok(Duration::from_secs(0)));
+
+	// Negative durations, missing units and empty strings are rejected.
+	assert!(parse_duration("-1s").is_err());
+	assert!(parse_duration("10").is_err());
+	assert!(parse_duration("").is_err());
+	assert!(parse_duration("1.5").is_err());
+	assert!(parse_duration("s").is_err());
+	assert!(parse_duration("1d").is_err());
+}
+
+#[test]
+fn trace_rpc_errors_have_their_own_code_and_message() {
+	let error = |error: TraceRpcError| -> (i64, String) {
+		let rpc_error = jsonrpc_core::Error::from(error.clone());
+		assert_eq!(
+			rpc_error.code,
+			jsonrpc_core::ErrorCode::ServerError(error.code())
+		);
+		assert_eq!(rpc_error.message, error.to_string());
+		assert_eq!(rpc_error.data, None);
+		(error.code(), error.to_string())
+	};
+
+	assert_eq!(
+		error(TraceRpcError::StatePruned {
+			block: "0x10".to_string(),
+			archive: false,
+		}),
+		(
+			STATE_PRUNED_ERROR_CODE,
+			"state of block 0x10 is pruned (the node is not running in archive mode, start it \
+			 with `--pruning archive` to trace old blocks)"
+				.to_string()
+		)
+	);
+	// Nodes in archive mode don't hint at it.
+	assert_eq!(
+		error(TraceRpcError::StatePruned {
+			block: "0x10".to_string(),
+			archive: true,
+		}),
+		(
+			STATE_PRUNED_ERROR_CODE,
+			"state of block 0x10 is pruned".to_string()
+		)
+	);
+	assert_eq!(
+		error(TraceRpcError::BlockNotFound("0x10".to_string())),
+		(BLOCK_NOT_FOUND_ERROR_CODE, "block 0x10 not found".to_string())
+	);
+	assert_eq!(
+		error(TraceRpcError::TransactionNotIndexed(H256::repeat_byte(0x33))),
+		(
+			TRANSACTION_NOT_INDEXED_ERROR_CODE,
+			format!(
+				"transaction {:?} is not indexed by the node",
+				H256::repeat_byte(0x33)
+			)
+		)
+	);
+	assert_eq!(
+		error(TraceRpcError::RuntimeApiUnsupported(
+			"`trace_call` is not supported".to_string()
+		)),
+		(
+			UNSUPPORTED_RUNTIME_ERROR_CODE,
+			"unsupported by the runtime : `trace_call` is not supported".to_string()
+		)
+	);
+
+	// Timeouts have their own code too.
+	let timeout = timeout_err();
+	assert_eq!(
+		timeout.code,
+		jsonrpc_core::ErrorCode::ServerError(TIMEOUT_ERROR_CODE)
+	);
+	assert_eq!(timeout.message, "execution timeout");
+}
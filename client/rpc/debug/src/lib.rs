@@ -15,33 +15,82 @@
 // along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
 use futures::{
 	compat::Compat,
-	future::{BoxFuture, TryFutureExt},
-	FutureExt, SinkExt, StreamExt,
+	future::{BoxFuture, Shared, TryFutureExt},
+	pin_mut, FutureExt, SinkExt, StreamExt,
 };
 use jsonrpc_core::Result as RpcResult;
-pub use hedgeware_rpc_core_debug::{Debug as DebugT, DebugServer, TraceParams};
+pub use hedgeware_rpc_core_debug::{
+	BlockTransactionTrace, CallRequest, Debug as DebugT, DebugServer, RequestBlockId,
+	RequestBlockTag, StateOverride, TraceCallParams, TraceParams,
+};
 
 use tokio::{
 	self,
-	sync::{oneshot, Semaphore},
+	sync::{oneshot, OwnedSemaphorePermit, Semaphore},
+	time::{delay_for, timeout_at},
 };
 
-use ethereum_types::{H128, H256};
+use codec::Encode;
+use ethereum_types::{H128, H160, H256};
 use fc_rpc::{frontier_backend_client, internal_err};
 use fp_rpc::EthereumRuntimeRPCApi;
+use hedgeware_client_evm_tracing::{
+	check_events_api, check_state, DebugApi, Deadline, PendingBlock, SingleTracer, StepWindow,
+	TraceError, Tracer, TracingRuntimes, TransactionOrigin,
+};
+use hedgeware_rpc_core_types::{parse_duration, timeout_err, TraceRpcError};
 use hedgeware_rpc_primitives_debug::{single, DebugRuntimeApi};
+use hedgeware_rpc_primitives_txpool::TxPoolRuntimeApi;
 use sc_client_api::backend::Backend;
-use sp_api::{BlockId, HeaderT, ProvideRuntimeApi};
+use sc_transaction_graph::{ChainApi, Pool};
+use sp_api::{BlockId, CallApiAt, HeaderT, ProvideRuntimeApi};
 use sp_block_builder::BlockBuilder;
 use sp_blockchain::{
 	Backend as BlockchainBackend, Error as BlockChainError, HeaderBackend, HeaderMetadata,
 };
-use sp_runtime::traits::Block as BlockT;
+use sp_runtime::traits::{Block as BlockT, Zero};
 use sp_utils::mpsc::TracingUnboundedSender;
-use std::{future::Future, marker::PhantomData, str::FromStr, sync::Arc};
+use std::{
+	collections::{BTreeMap, HashMap},
+	future::Future,
+	marker::PhantomData,
+	str::FromStr,
+	mem,
+	pin::Pin,
+	sync::{Arc, Mutex, MutexGuard, PoisonError},
+	task::{Context, Poll},
+	time::{Duration, Instant},
+};
 
-pub type Responder = oneshot::Sender<RpcResult<single::TransactionTrace>>;
-pub type DebugRequester = TracingUnboundedSender<((H256, Option<TraceParams>), Responder)>;
+#[cfg(test)]
+mod tests;
+
+/// What to trace.
+#[derive(Clone, Debug)]
+pub enum RequesterInput {
+	/// A transaction, from its Ethereum hash.
+	Transaction(H256),
+	/// An unsigned call, executed on top of the state of a block (the latest one by default).
+	Call {
+		call: CallRequest,
+		block: Option<RequestBlockId>,
+		state_overrides: Option<BTreeMap<H160, StateOverride>>,
+	},
+	/// All the transactions of a block, from its number or tag.
+	BlockNumber(RequestBlockId),
+	/// All the transactions of a block, from its Ethereum hash.
+	BlockHash(H256),
+}
+
+/// Traces, depending on the `RequesterInput`.
+pub enum Response {
+	Single(single::TransactionTrace),
+	Block(Vec<BlockTransactionTrace>),
+}
+
+pub type Responder = oneshot::Sender<RpcResult<Response>>;
+pub type DebugRequester =
+	TracingUnboundedSender<((RequesterInput, Option<TraceParams>), Responder)>;
 
 pub struct Debug {
 	pub requester: DebugRequester,
@@ -51,6 +100,41 @@ impl Debug {
 	pub fn new(requester: DebugRequester) -> Self {
 		Self { requester }
 	}
+
+	/// Communicates with the service-defined task using channels.
+	async fn request(
+		mut requester: DebugRequester,
+		input: RequesterInput,
+		params: Option<TraceParams>,
+	) -> RpcResult<Response> {
+		let (tx, rx) = oneshot::channel();
+		// Send a message from the rpc handler to the service level task.
+		requester
+			.send(((input, params), tx))
+			.await
+			.map_err(|err| {
+				internal_err(format!(
+					"failed to send request to debug service : {:?}",
+					err
+				))
+			})?;
+
+		// Receive a message from the service level task and send the rpc response.
+		rx.await.map_err(|err| {
+			internal_err(format!("debug service dropped the channel : {:?}", err))
+		})?
+	}
+
+	async fn trace_block(
+		requester: DebugRequester,
+		input: RequesterInput,
+		params: Option<TraceParams>,
+	) -> RpcResult<Vec<BlockTransactionTrace>> {
+		match Self::request(requester, input, params).await? {
+			Response::Block(traces) => Ok(traces),
+			_ => Err(internal_err("debug service sent an unexpected response".to_string())),
+		}
+	}
 }
 
 impl DebugT for Debug {
@@ -61,62 +145,407 @@ impl DebugT for Debug {
 		transaction_hash: H256,
 		params: Option<TraceParams>,
 	) -> Compat<BoxFuture<'static, RpcResult<single::TransactionTrace>>> {
-		let mut requester = self.requester.clone();
+		let requester = self.requester.clone();
 
 		async move {
-			let (tx, rx) = oneshot::channel();
-			// Send a message from the rpc handler to the service level task.
-			requester
-				.send(((transaction_hash, params), tx))
-				.await
-				.map_err(|err| {
-					internal_err(format!(
-						"failed to send request to debug service : {:?}",
-						err
-					))
-				})?;
-
-			// Receive a message from the service level task and send the rpc response.
-			rx.await.map_err(|err| {
-				internal_err(format!("debug service dropped the channel : {:?}", err))
-			})?
+			let input = RequesterInput::Transaction(transaction_hash);
+			match Self::request(requester, input, params).await? {
+				Response::Single(trace) => Ok(trace),
+				_ => Err(internal_err(
+					"debug service sent an unexpected response".to_string(),
+				)),
+			}
 		}
 		.boxed()
 		.compat()
 	}
+
+	/// Handler for `debug_traceCall` request. The call is executed on top of the state of the
+	/// requested block, without being broadcasted.
+	fn trace_call(
+		&self,
+		call: CallRequest,
+		block: Option<RequestBlockId>,
+		params: Option<TraceCallParams>,
+	) -> Compat<BoxFuture<'static, RpcResult<single::TransactionTrace>>> {
+		let requester = self.requester.clone();
+		let (params, state_overrides) = match params {
+			Some(params) => (Some(params.trace), params.state_overrides),
+			None => (None, None),
+		};
+
+		async move {
+			let input = RequesterInput::Call {
+				call,
+				block,
+				state_overrides,
+			};
+			match Self::request(requester, input, params).await? {
+				Response::Single(trace) => Ok(trace),
+				_ => Err(internal_err(
+					"debug service sent an unexpected response".to_string(),
+				)),
+			}
+		}
+		.boxed()
+		.compat()
+	}
+
+	/// Handler for `debug_traceBlockByNumber` request. The block is replayed once, all its
+	/// Ethereum transactions being traced.
+	fn trace_block_by_number(
+		&self,
+		block_number: RequestBlockId,
+		params: Option<TraceParams>,
+	) -> Compat<BoxFuture<'static, RpcResult<Vec<BlockTransactionTrace>>>> {
+		let input = RequesterInput::BlockNumber(block_number);
+		Self::trace_block(self.requester.clone(), input, params)
+			.boxed()
+			.compat()
+	}
+
+	/// Handler for `debug_traceBlockByHash` request. The block is replayed once, all its
+	/// Ethereum transactions being traced.
+	fn trace_block_by_hash(
+		&self,
+		block_hash: H256,
+		params: Option<TraceParams>,
+	) -> Compat<BoxFuture<'static, RpcResult<Vec<BlockTransactionTrace>>>> {
+		let input = RequesterInput::BlockHash(block_hash);
+		Self::trace_block(self.requester.clone(), input, params)
+			.boxed()
+			.compat()
+	}
 }
 
-pub struct DebugHandler<B: BlockT, C, BE>(PhantomData<(B, C, BE)>);
+/// Block on top of which a request is traced.
+enum TracedBlock<B: BlockT> {
+	/// An imported block.
+	Imported(BlockId<B>),
+	/// The pending block, made of the ready transactions of the pool on top of the best block.
+	Pending,
+}
+
+/// Block replay with a given tracer configuration. Raw traces are not replayed for the whole
+/// block, as their steps would take too much memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct ReplayKey {
+	block_hash: H256,
+	trace_type: single::TraceType,
+}
+
+/// Traces of a block replay.
+enum BlockReplay {
+	/// Traces of the Ethereum transactions of the block and of the EVM executions started by
+	/// other extrinsics, in order.
+	Transactions(Vec<(TransactionOrigin, single::TransactionTrace)>),
+	/// The runtime of the block only traces transactions one by one.
+	Legacy,
+}
+
+impl BlockReplay {
+	/// Approximate size in memory of the traces, estimated from their encoding.
+	fn size(&self) -> usize {
+		let traces = match self {
+			Self::Transactions(traces) => traces,
+			Self::Legacy => return 0,
+		};
+
+		traces
+			.iter()
+			.map(|(_, trace)| {
+				mem::size_of::<(TransactionOrigin, single::TransactionTrace)>()
+					+ trace.encoded_size()
+			})
+			.sum()
+	}
+}
+
+type SharedReplay = Shared<BoxFuture<'static, RpcResult<Arc<BlockReplay>>>>;
+
+/// Block replays running or recently finished. Concurrent requests for the transactions of a
+/// block wait for the same replay, which traces all of them at once. A running replay is
+/// aborted at the latest deadline of the requests waiting for it, or as soon as none is left.
+/// Replays are kept for `cache_duration` once finished, failed ones being discarded at once,
+/// and the least recently used ones are evicted when the finished replays take more than
+/// `max_bytes`.
+struct ReplayCache {
+	state: Mutex<ReplayCacheState>,
+	cache_duration: Duration,
+	max_bytes: usize,
+}
 
-impl<B, C, BE> DebugHandler<B, C, BE>
+#[derive(Default)]
+struct ReplayCacheState {
+	replays: HashMap<ReplayKey, CachedReplay>,
+	/// Approximate size of the finished replays.
+	bytes: usize,
+	/// Incremented each time a replay is requested, to identify replays and find the least
+	/// recently used ones.
+	usage_tick: u64,
+}
+
+struct CachedReplay {
+	/// Distinguishes the replay from later ones of the same block once it has been evicted.
+	id: u64,
+	replay: SharedReplay,
+	/// Approximate size of the traces, once the replay is finished.
+	size: Option<usize>,
+	/// Value of `usage_tick` when the replay was last requested.
+	last_used: u64,
+	/// Deadline of the replay, the latest one of the requests waiting for it.
+	deadline: Deadline,
+	/// Deadlines of the requests waiting for the replay, by the `usage_tick` of their request.
+	waiters: HashMap<u64, Instant>,
+}
+
+impl ReplayCacheState {
+	/// Remove a replay, unless it has been replaced by another one.
+	fn remove(&mut self, key: &ReplayKey, id: u64) {
+		if self.replays.get(key).map_or(false, |cached| cached.id == id) {
+			if let Some(size) = self.replays.remove(key).and_then(|cached| cached.size) {
+				self.bytes -= size;
+			}
+		}
+	}
+
+	/// Remove a request waiting for a replay. A running replay gets the latest deadline of the
+	/// requests still waiting for it, or is aborted and removed once none is left.
+	fn leave(&mut self, key: &ReplayKey, id: u64, waiter: u64) {
+		let cached = match self.replays.get_mut(key) {
+			Some(cached) if cached.id == id => cached,
+			_ => return,
+		};
+		cached.waiters.remove(&waiter);
+		if cached.size.is_some() {
+			return;
+		}
+
+		match cached.waiters.values().max().copied() {
+			Some(deadline) => cached.deadline.set(deadline),
+			None => {
+				cached.deadline.set(Instant::now());
+				self.remove(key, id);
+			}
+		}
+	}
+
+	/// Record the size of a finished replay, then evict the least recently used finished
+	/// replays until the cache size is below its limit. Running replays are never evicted.
+	fn finished(&mut self, key: &ReplayKey, id: u64, size: usize, max_bytes: usize) {
+		match self.replays.get_mut(key) {
+			Some(cached) if cached.id == id && cached.size.is_none() => {
+				cached.size = Some(size);
+				self.bytes += size;
+			}
+			_ => return,
+		}
+
+		if self.bytes <= max_bytes {
+			return;
+		}
+		let mut evictable: Vec<_> = self
+			.replays
+			.iter()
+			.filter(|(_, cached)| cached.size.is_some())
+			.map(|(key, cached)| (cached.last_used, *key, cached.id))
+			.collect();
+		evictable.sort_unstable_by_key(|(last_used, _, _)| *last_used);
+
+		for (_, key, id) in evictable {
+			if self.bytes <= max_bytes {
+				break;
+			}
+			self.remove(&key, id);
+		}
+	}
+}
+
+impl ReplayCache {
+	fn new(cache_duration: Duration, max_bytes: usize) -> Self {
+		Self {
+			state: Mutex::new(ReplayCacheState::default()),
+			cache_duration,
+			max_bytes,
+		}
+	}
+
+	fn state(&self) -> MutexGuard<ReplayCacheState> {
+		// The state is only changed by operations which cannot panic, so it is still
+		// consistent if another thread panicked while holding the lock.
+		self.state.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Wait for the replay of the block with the given tracer configuration, started by
+	/// `replay` with the deadline of the request unless it is already running or cached. The
+	/// deadline of a running replay is pushed back to the one of the request if it is later.
+	fn get_or_start(
+		cache: &Arc<Self>,
+		key: ReplayKey,
+		deadline: Instant,
+		replay: impl FnOnce(Deadline) -> BoxFuture<'static, RpcResult<Arc<BlockReplay>>>,
+	) -> ReplayWaiter {
+		let mut state = cache.state();
+		state.usage_tick += 1;
+		let usage_tick = state.usage_tick;
+		if let Some(cached) = state.replays.get_mut(&key) {
+			cached.last_used = usage_tick;
+			cached.waiters.insert(usage_tick, deadline);
+			if cached.size.is_none() && cached.deadline.instant() < deadline {
+				cached.deadline.set(deadline);
+			}
+			return ReplayWaiter {
+				cache: Arc::clone(cache),
+				key,
+				id: cached.id,
+				waiter: usage_tick,
+				replay: cached.replay.clone(),
+			};
+		}
+
+		// The size of the replay is recorded before any request gets its traces.
+		let id = usage_tick;
+		let replay_deadline = Deadline::new(deadline);
+		let recorder = Arc::clone(cache);
+		let shared = replay(replay_deadline.clone())
+			.map(move |result| {
+				let mut state = recorder.state();
+				match &result {
+					Ok(replay) => state.finished(&key, id, replay.size(), recorder.max_bytes),
+					Err(_) => state.remove(&key, id),
+				}
+				result
+			})
+			.boxed()
+			.shared();
+		state.replays.insert(
+			key,
+			CachedReplay {
+				id,
+				replay: shared.clone(),
+				size: None,
+				last_used: usage_tick,
+				deadline: replay_deadline,
+				waiters: vec![(usage_tick, deadline)].into_iter().collect(),
+			},
+		);
+		drop(state);
+
+		let expiration = Arc::clone(cache);
+		let finished = shared.clone();
+		tokio::spawn(async move {
+			if finished.await.is_ok() {
+				delay_for(expiration.cache_duration).await;
+			}
+			expiration.state().remove(&key, id);
+		});
+
+		ReplayWaiter {
+			cache: Arc::clone(cache),
+			key,
+			id,
+			waiter: usage_tick,
+			replay: shared,
+		}
+	}
+}
+
+/// Request waiting for a block replay, which it leaves when dropped.
+struct ReplayWaiter {
+	cache: Arc<ReplayCache>,
+	key: ReplayKey,
+	id: u64,
+	waiter: u64,
+	replay: SharedReplay,
+}
+
+impl Future for ReplayWaiter {
+	type Output = RpcResult<Arc<BlockReplay>>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		self.replay.poll_unpin(cx)
+	}
+}
+
+impl Drop for ReplayWaiter {
+	fn drop(&mut self) {
+		self.cache.state().leave(&self.key, self.id, self.waiter);
+	}
+}
+
+pub struct DebugHandler<B: BlockT, C, BE, A>(PhantomData<(B, C, BE, A)>);
+
+impl<B, C, BE, A> DebugHandler<B, C, BE, A>
 where
 	BE: Backend<B> + 'static,
-	C: ProvideRuntimeApi<B>,
+	C: ProvideRuntimeApi<B> + CallApiAt<B>,
 	C: HeaderMetadata<B, Error = BlockChainError> + HeaderBackend<B>,
 	C: Send + Sync + 'static,
 	B: BlockT<Hash = H256> + Send + Sync + 'static,
+	A: ChainApi<Block = B> + 'static,
 	C::Api: BlockBuilder<B>,
 	C::Api: DebugRuntimeApi<B>,
 	C::Api: EthereumRuntimeRPCApi<B>,
+	C::Api: TxPoolRuntimeApi<B>,
 {
 	/// Task spawned at service level that listens for messages on the rpc channel and spawns
 	/// blocking tasks using a permit pool.
+	///
+	/// Imported blocks are traced with the `tracing_runtimes` replacing their on-chain runtime,
+	/// if any.
+	///
+	/// A request is aborted after its `timeout`, waiting for a permit included. Otherwise
+	/// `debug_traceTransaction` and `debug_traceCall` are aborted after `transaction_timeout`,
+	/// and the tracing of whole blocks after `block_timeout`.
+	///
+	/// Transactions are traced by replaying their whole block once, the traces of all its
+	/// Ethereum transactions being kept for `cache_duration`, as long as the replays take less
+	/// than `max_cached_bytes`. Raw traces are built for the requested transaction only.
+	///
+	/// Errors on pruned states hint at running the node in archive mode, unless `state_archive`
+	/// is set.
 	pub fn task(
 		client: Arc<C>,
 		backend: Arc<BE>,
 		frontier_backend: Arc<fc_db::Backend<B>>,
+		graph: Arc<Pool<A>>,
 		permit_pool: Arc<Semaphore>,
+		tracing_runtimes: Option<Arc<TracingRuntimes<B, BE>>>,
+		transaction_timeout: Duration,
+		block_timeout: Duration,
+		cache_duration: Duration,
+		max_cached_bytes: usize,
+		state_archive: bool,
 	) -> (impl Future<Output = ()>, DebugRequester) {
 		let (tx, mut rx): (DebugRequester, _) =
 			sp_utils::mpsc::tracing_unbounded("debug-requester");
+		let replay_cache = Arc::new(ReplayCache::new(cache_duration, max_cached_bytes));
 
 		let fut = async move {
 			loop {
-				if let Some(((transaction_hash, params), response_tx)) = rx.next().await {
+				if let Some(((input, params), response_tx)) = rx.next().await {
+					let default_timeout = match input {
+						RequesterInput::Transaction(_) | RequesterInput::Call { .. } => {
+							transaction_timeout
+						}
+						RequesterInput::BlockNumber(_) | RequesterInput::BlockHash(_) => {
+							block_timeout
+						}
+					};
+					let deadline = match Self::deadline(params.as_ref(), default_timeout) {
+						Ok(deadline) => deadline,
+						Err(e) => {
+							let _ = response_tx.send(Err(e));
+							continue;
+						}
+					};
 					let client = client.clone();
 					let backend = backend.clone();
 					let frontier_backend = frontier_backend.clone();
+					let graph = graph.clone();
 					let permit_pool = permit_pool.clone();
+					let tracing_runtimes = tracing_runtimes.clone();
+					let replay_cache = replay_cache.clone();
 					// Note on spawned tasks https://tokio.rs/tokio/tutorial/spawning#tasks.
 					//
 					// Substrate uses the default value for `core_threads` (number of cores of the
@@ -127,75 +556,346 @@ where
 					// blocking tasks use a more restrictive permit pool shared by trace modules.
 					// https://docs.rs/tokio/0.2.23/tokio/sync/struct.Semaphore.html
 					tokio::task::spawn(async move {
-						let _ = response_tx.send(
-							async {
-								let _permit = permit_pool.acquire().await;
-								tokio::task::spawn_blocking(move || {
-									Self::handle_request(
-										client.clone(),
-										backend.clone(),
-										frontier_backend.clone(),
-										transaction_hash,
-										params,
-									)
-								})
+						let response = match input {
+							RequesterInput::Transaction(transaction_hash) => {
+								Self::trace_transaction(
+									client,
+									backend,
+									frontier_backend,
+									permit_pool,
+									tracing_runtimes,
+									replay_cache,
+									transaction_hash,
+									params,
+									deadline,
+									state_archive,
+								)
 								.await
-								.map_err(|e| {
-									internal_err(format!(
-										"Internal error on spawned task : {:?}",
-										e
-									))
-								})?
+								.map(Response::Single)
 							}
-							.await,
-						);
+							RequesterInput::Call {
+								call,
+								block,
+								state_overrides,
+							} => Self::blocking(&permit_pool, deadline, move || {
+								Self::handle_call_request(
+									client,
+									backend,
+									frontier_backend,
+									graph,
+									call,
+									block,
+									state_overrides,
+									params,
+									deadline,
+									state_archive,
+								)
+							})
+							.await
+							.map(Response::Single),
+							input => Self::blocking(&permit_pool, deadline, move || {
+								Self::handle_block_request(
+									client,
+									backend,
+									frontier_backend,
+									graph,
+									tracing_runtimes.as_deref(),
+									input,
+									params,
+									deadline,
+									state_archive,
+								)
+							})
+							.await
+							.map(Response::Block),
+						};
+						let _ = response_tx.send(response);
 					});
 				}
 			}
 		};
 		(fut, tx)
 	}
-	/// Replays a transaction in the Runtime at a given block height.
+
+	/// Run a handler in a blocking task once it got a permit, answering with a timeout error
+	/// at the deadline.
 	///
-	/// In order to succesfully reproduce the result of the original transaction we need a correct
-	/// state to replay over.
+	/// The runtime is only told to abort when it sends an event, so a late blocking task is left
+	/// to finish, its result being dropped.
+	async fn blocking<R: Send + 'static>(
+		permit_pool: &Arc<Semaphore>,
+		deadline: Instant,
+		handler: impl FnOnce() -> RpcResult<R> + Send + 'static,
+	) -> RpcResult<R> {
+		timeout_at(deadline.into(), Self::run_blocking(permit_pool, handler))
+			.await
+			.map_err(|_| timeout_err())?
+	}
+
+	/// Run a handler in a blocking task once it got a permit, which the task keeps until it
+	/// finishes.
+	async fn run_blocking<R: Send + 'static>(
+		permit_pool: &Arc<Semaphore>,
+		handler: impl FnOnce() -> RpcResult<R> + Send + 'static,
+	) -> RpcResult<R> {
+		let permit = Arc::clone(permit_pool).acquire_owned().await;
+		Self::spawn_blocking(permit, handler).await
+	}
+
+	/// Same as `run_blocking`, unless the deadline, which may be moved while waiting for the
+	/// permit, is reached first. The handler is left to apply the deadline itself.
+	async fn run_until<R: Send + 'static>(
+		permit_pool: &Arc<Semaphore>,
+		deadline: &Deadline,
+		handler: impl FnOnce() -> RpcResult<R> + Send + 'static,
+	) -> RpcResult<R> {
+		let acquire = Arc::clone(permit_pool).acquire_owned();
+		pin_mut!(acquire);
+		let permit = loop {
+			let instant = deadline.instant();
+			match timeout_at(instant.into(), &mut acquire).await {
+				Ok(permit) => break permit,
+				Err(_) if deadline.instant() > instant => continue,
+				Err(_) => return Err(timeout_err()),
+			}
+		};
+		if deadline.expired() {
+			return Err(timeout_err());
+		}
+		Self::spawn_blocking(permit, handler).await
+	}
+
+	async fn spawn_blocking<R: Send + 'static>(
+		permit: OwnedSemaphorePermit,
+		handler: impl FnOnce() -> RpcResult<R> + Send + 'static,
+	) -> RpcResult<R> {
+		let handler = move || {
+			let _permit = permit;
+			handler()
+		};
+		tokio::task::spawn_blocking(handler)
+			.await
+			.map_err(|e| internal_err(format!("Internal error on spawned task : {:?}", e)))?
+	}
+
+	/// Trace a transaction from the replay of its block, shared with the concurrent requests
+	/// for the transactions of the block with the same tracer. The shared replay runs until the
+	/// latest deadline of its requests, and is aborted once they all left.
 	///
-	/// Substrate allows to apply extrinsics in the Runtime and thus creating an overlayed state.
-	/// This overlayed changes will live in-memory for the lifetime of the ApiRef.
-	fn handle_request(
+	/// Raw traces, and the transactions of blocks whose runtime only traces transactions one by
+	/// one, are traced on their own instead, the block being replayed up to the transaction.
+	async fn trace_transaction(
 		client: Arc<C>,
 		backend: Arc<BE>,
 		frontier_backend: Arc<fc_db::Backend<B>>,
+		permit_pool: Arc<Semaphore>,
+		tracing_runtimes: Option<Arc<TracingRuntimes<B, BE>>>,
+		replay_cache: Arc<ReplayCache>,
 		transaction_hash: H256,
 		params: Option<TraceParams>,
+		deadline: Instant,
+		state_archive: bool,
 	) -> RpcResult<single::TransactionTrace> {
-		let (hash, index) = match frontier_backend_client::load_transactions::<B, C>(
+		let step_window = Self::step_window(params.as_ref())?;
+		let trace_type = Self::trace_type(params)?;
+		let (block_hash, index) = Self::transaction_position(
 			client.as_ref(),
 			frontier_backend.as_ref(),
 			transaction_hash,
+		)?;
+		let trace_alone = {
+			let client = client.clone();
+			let backend = backend.clone();
+			let permit_pool = permit_pool.clone();
+			let tracing_runtimes = tracing_runtimes.clone();
+			async move {
+				Self::blocking(&permit_pool, deadline, move || {
+					Self::handle_transaction_request(
+						client,
+						backend,
+						tracing_runtimes.as_deref(),
+						BlockId::Hash(block_hash),
+						index,
+						trace_type,
+						step_window,
+						deadline,
+						state_archive,
+					)
+				})
+				.await
+			}
+		};
+
+		if let single::TraceType::Raw { .. } = trace_type {
+			return trace_alone.await;
+		}
+
+		let key = ReplayKey {
+			block_hash,
+			trace_type,
+		};
+		let replay = ReplayCache::get_or_start(&replay_cache, key, deadline, |replay_deadline| {
+			let client = client.clone();
+			let backend = backend.clone();
+			let permit_pool = permit_pool.clone();
+			let tracing_runtimes = tracing_runtimes.clone();
+			async move {
+				let handler_deadline = replay_deadline.clone();
+				Self::run_until(&permit_pool, &replay_deadline, move || {
+					Self::replay_block(
+						client,
+						backend,
+						tracing_runtimes.as_deref(),
+						key,
+						handler_deadline,
+						state_archive,
+					)
+				})
+				.await
+				.map(Arc::new)
+			}
+			.boxed()
+		});
+		let replay = timeout_at(deadline.into(), replay)
+			.await
+			.map_err(|_| timeout_err())??;
+
+		match &*replay {
+			BlockReplay::Transactions(traces) => traces
+				.iter()
+				.filter(|(origin, _)| *origin == TransactionOrigin::Ethereum)
+				.nth(index)
+				.map(|(_, trace)| trace.clone())
+				.ok_or_else(|| {
+					internal_err("Transaction not found in the replay of its block".to_string())
+				}),
+			BlockReplay::Legacy => trace_alone.await,
+		}
+	}
+
+	/// Substrate block of an Ethereum transaction, and the index of the transaction in the
+	/// Ethereum block.
+	fn transaction_position(
+		client: &C,
+		frontier_backend: &fc_db::Backend<B>,
+		transaction_hash: H256,
+	) -> RpcResult<(H256, usize)> {
+		let (hash, index) = match frontier_backend_client::load_transactions::<B, C>(
+			client,
+			frontier_backend,
+			transaction_hash,
 		) {
 			Ok(Some((hash, index))) => (hash, index as usize),
-			Ok(None) => return Err(internal_err("Transaction hash not found".to_string())),
+			Ok(None) => return Err(TraceRpcError::TransactionNotIndexed(transaction_hash).into()),
 			Err(e) => return Err(e),
 		};
 
-		let reference_id =
-			match frontier_backend_client::load_hash::<B>(frontier_backend.as_ref(), hash) {
-				Ok(Some(hash)) => hash,
-				Ok(_) => return Err(internal_err("Block hash not found".to_string())),
-				Err(e) => return Err(e),
-			};
+		let reference_id = match frontier_backend_client::load_hash::<B>(frontier_backend, hash) {
+			Ok(Some(hash)) => hash,
+			Ok(_) => return Err(TraceRpcError::BlockNotFound(format!("{:?}", hash)).into()),
+			Err(e) => return Err(e),
+		};
+		let block_hash = client
+			.expect_block_hash_from_id(&reference_id)
+			.map_err(|_| TraceRpcError::BlockNotFound(format!("{:?}", hash)))?;
+
+		Ok((block_hash, index))
+	}
+
+	/// Replays a block in the Runtime, tracing all its Ethereum transactions with the tracer
+	/// of the key. The replay is shared by several requests, which move its deadline.
+	fn replay_block(
+		client: Arc<C>,
+		backend: Arc<BE>,
+		tracing_runtimes: Option<&TracingRuntimes<B, BE>>,
+		key: ReplayKey,
+		deadline: Deadline,
+		state_archive: bool,
+	) -> RpcResult<BlockReplay> {
+		let (header, ext) = Self::block_data(
+			client.as_ref(),
+			backend.as_ref(),
+			BlockId::Hash(key.block_hash),
+		)?;
+
+		// The block is traced by the runtime it has been built with, or the tracing runtime
+		// replacing it.
+		let api = DebugApi::new(
+			client.as_ref(),
+			backend.as_ref(),
+			tracing_runtimes,
+			BlockId::Hash(*header.parent_hash()),
+		)
+		.map_err(|e| trace_err(e, state_archive))?;
+		if !api.sends_events() {
+			return Ok(BlockReplay::Legacy);
+		}
+
+		api.with_shared_deadline(deadline)
+			.trace_block_transactions(ext, key.trace_type)
+			.map_err(|e| trace_err(e, state_archive))?
+			.map(BlockReplay::Transactions)
+			.map_err(|e| internal_err(format!("DispatchError: {:?}", e)))
+	}
+
+	/// Header and extrinsics of an imported block.
+	fn block_data(
+		client: &C,
+		backend: &BE,
+		reference_id: BlockId<B>,
+	) -> RpcResult<(B::Header, Vec<B::Extrinsic>)> {
+		let header = match client.header(reference_id) {
+			Ok(Some(header)) => header,
+			Ok(None) => return Err(TraceRpcError::BlockNotFound(reference_id.to_string()).into()),
+			Err(e) => return Err(internal_err(format!("Block header not found : {:?}", e))),
+		};
+		let ext = match backend.blockchain().body(reference_id) {
+			Ok(Some(ext)) => ext,
+			Ok(None) => return Err(TraceRpcError::BlockNotFound(reference_id.to_string()).into()),
+			Err(e) => {
+				return Err(internal_err(format!(
+					"Block extrinsics not found : {:?}",
+					e
+				)))
+			}
+		};
+		Ok((header, ext))
+	}
+
+	/// Deadline of a request received now, from its geth-style `timeout` (`"10s"`, `"1m30s"`).
+	fn deadline(params: Option<&TraceParams>, default_timeout: Duration) -> RpcResult<Instant> {
+		let timeout = match params.and_then(|params| params.timeout.as_ref()) {
+			Some(timeout) => parse_duration(timeout).map_err(internal_err)?,
+			None => default_timeout,
+		};
+		Ok(Instant::now() + timeout)
+	}
+
+	/// Replays a transaction in the Runtime at a given block height.
+	///
+	/// In order to succesfully reproduce the result of the original transaction we need a correct
+	/// state to replay over.
+	///
+	/// Substrate allows to apply extrinsics in the Runtime and thus creating an overlayed state.
+	/// This overlayed changes will live in-memory for the lifetime of the ApiRef.
+	fn handle_transaction_request(
+		client: Arc<C>,
+		backend: Arc<BE>,
+		tracing_runtimes: Option<&TracingRuntimes<B, BE>>,
+		reference_id: BlockId<B>,
+		index: usize,
+		trace_type: single::TraceType,
+		step_window: Option<StepWindow>,
+		deadline: Instant,
+		state_archive: bool,
+	) -> RpcResult<single::TransactionTrace> {
 		// Get ApiRef. This handle allow to keep changes between txs in an internal buffer.
 		let api = client.runtime_api();
-		// Get Blockchain backend
-		let blockchain = backend.blockchain();
-		// Get the header I want to work with.
-		let header = client.header(reference_id).unwrap().unwrap();
+		// Get the header I want to work with, and the extrinsics.
+		let (header, ext) = Self::block_data(client.as_ref(), backend.as_ref(), reference_id)?;
 		// Get parent blockid.
 		let parent_block_id = BlockId::Hash(*header.parent_hash());
-
-		// Get the extrinsics.
-		let ext = blockchain.body(reference_id).unwrap().unwrap();
+		check_state(backend.as_ref(), parent_block_id).map_err(|e| trace_err(e, state_archive))?;
 
 		// Get the block that contains the requested transaction.
 		let reference_block = match api.current_block(&reference_id) {
@@ -203,8 +903,73 @@ where
 			Err(e) => return Err(internal_err(format!("Runtime block call failed: {:?}", e))),
 		};
 
-		// Set trace type
-		let trace_type = match params {
+		// Get the actual ethereum transaction.
+		if let Some(block) = reference_block {
+			let transactions = block.transactions;
+			if let Some(transaction) = transactions.get(index) {
+				// The block is traced by the runtime it has been built with, or the tracing
+				// runtime replacing it.
+				return DebugApi::new(
+					client.as_ref(),
+					backend.as_ref(),
+					tracing_runtimes,
+					parent_block_id,
+				)
+				.and_then(|api| {
+					api.with_deadline(Some(deadline))
+						.with_step_window(step_window)
+						.trace_transaction(ext, &transaction, trace_type)
+				})
+				.map_err(|e| trace_err(e, state_archive))?
+				.map_err(|e| internal_err(format!("DispatchError: {:?}", e)));
+			}
+		}
+		return Err(internal_err("Runtime block call failed".to_string()));
+	}
+
+	/// Steps of the raw traces requested by the parameters of the request, all of them by
+	/// default.
+	fn step_window(params: Option<&TraceParams>) -> RpcResult<Option<StepWindow>> {
+		match params {
+			Some(TraceParams {
+				start_step: None,
+				limit: None,
+				..
+			})
+			| None => Ok(None),
+			Some(TraceParams {
+				tracer: Some(_), ..
+			}) => Err(internal_err(
+				"startStep and limit are only supported by the default tracer".to_string(),
+			)),
+			Some(params) => Ok(Some(StepWindow {
+				start: params.start_step.unwrap_or(0),
+				limit: params.limit,
+			})),
+		}
+	}
+
+	/// Tracer requested by the parameters of the request.
+	fn trace_type(params: Option<TraceParams>) -> RpcResult<single::TraceType> {
+		Ok(match params {
+			Some(TraceParams {
+				tracer: Some(tracer),
+				tracer_config,
+				..
+			}) if tracer == "callTracer" => single::TraceType::CallTracer {
+				with_log: tracer_config
+					.and_then(|config| config.with_log)
+					.unwrap_or(false),
+			},
+			Some(TraceParams {
+				tracer: Some(tracer),
+				tracer_config,
+				..
+			}) if tracer == "prestateTracer" => single::TraceType::Prestate {
+				diff_mode: tracer_config
+					.and_then(|config| config.diff_mode)
+					.unwrap_or(false),
+			},
 			Some(TraceParams {
 				tracer: Some(tracer),
 				..
@@ -230,19 +995,264 @@ where
 				disable_memory: false,
 				disable_stack: false,
 			},
+		})
+	}
+
+	/// Resolve a block number, tag or hash.
+	fn traced_block(
+		client: &C,
+		frontier_backend: &fc_db::Backend<B>,
+		block: RequestBlockId,
+	) -> RpcResult<TracedBlock<B>> {
+		Ok(TracedBlock::Imported(match block {
+			RequestBlockId::Number(n) | RequestBlockId::BlockNumber { block_number: n } => {
+				BlockId::Number(n.into())
+			}
+			RequestBlockId::Tag(RequestBlockTag::Latest) => {
+				BlockId::Number(client.info().best_number)
+			}
+			RequestBlockId::Tag(RequestBlockTag::Earliest) => BlockId::Number(0u32.into()),
+			RequestBlockId::Tag(RequestBlockTag::Finalized)
+			| RequestBlockId::Tag(RequestBlockTag::Safe) => {
+				BlockId::Hash(client.info().finalized_hash)
+			}
+			RequestBlockId::Tag(RequestBlockTag::Pending) => return Ok(TracedBlock::Pending),
+			RequestBlockId::Hash {
+				block_hash,
+				require_canonical,
+			} => {
+				let block_id = frontier_backend_client::load_hash::<B>(frontier_backend, block_hash)?
+					.ok_or_else(|| TraceRpcError::BlockNotFound(format!("{:?}", block_hash)))?;
+
+				if require_canonical {
+					let header = match client.header(block_id) {
+						Ok(Some(header)) => header,
+						_ => {
+							return Err(
+								TraceRpcError::BlockNotFound(format!("{:?}", block_hash)).into()
+							)
+						}
+					};
+					let canonical_hash = client
+						.hash(*header.number())
+						.map_err(|e| internal_err(format!("Block hash not found: {:?}", e)))?;
+					if canonical_hash != Some(header.hash()) {
+						return Err(internal_err(format!(
+							"Block {} is not canonical",
+							block_hash
+						)));
+					}
+				}
+
+				block_id
+			}
+		}))
+	}
+
+	/// The pending block, made of the ready extrinsics of the pool in the order they would be
+	/// included in the next block.
+	fn pending_block(client: &C, graph: &Pool<A>) -> RpcResult<PendingBlock<B>> {
+		let extrinsics = graph
+			.validated_pool()
+			.ready()
+			.map(|in_pool_tx| in_pool_tx.data().clone())
+			.collect();
+
+		PendingBlock::new(client, extrinsics).map_err(internal_err)
+	}
+
+	/// Executes an unsigned call in the Runtime on top of the state of a block, like
+	/// `eth_call` does. Nothing is persisted.
+	fn handle_call_request(
+		client: Arc<C>,
+		backend: Arc<BE>,
+		frontier_backend: Arc<fc_db::Backend<B>>,
+		graph: Arc<Pool<A>>,
+		call: CallRequest,
+		block: Option<RequestBlockId>,
+		state_overrides: Option<BTreeMap<H160, StateOverride>>,
+		params: Option<TraceParams>,
+		deadline: Instant,
+		state_archive: bool,
+	) -> RpcResult<single::TransactionTrace> {
+		let step_window = Self::step_window(params.as_ref())?;
+		let trace_type = Self::trace_type(params)?;
+
+		let api = client.runtime_api();
+
+		let reference_id = match Self::traced_block(
+			client.as_ref(),
+			frontier_backend.as_ref(),
+			block.unwrap_or(RequestBlockId::Tag(RequestBlockTag::Latest)),
+		)? {
+			TracedBlock::Imported(reference_id) => reference_id,
+			TracedBlock::Pending => {
+				let pending = Self::pending_block(client.as_ref(), graph.as_ref())?;
+				// The ApiRef keeps the changes of the pending extrinsics for the traced call.
+				for ext in pending.extrinsics {
+					let _ = api.apply_extrinsic(&pending.parent, ext);
+				}
+				pending.parent
+			}
 		};
 
-		// Get the actual ethereum transaction.
-		if let Some(block) = reference_block {
-			let transactions = block.transactions;
-			if let Some(transaction) = transactions.get(index) {
-				return client
-					.runtime_api()
-					.trace_transaction(&parent_block_id, ext, &transaction, trace_type)
-					.map_err(|e| internal_err(format!("Runtime api access error: {:?}", e)))?
-					.map_err(|e| internal_err(format!("DispatchError: {:?}", e)));
+		check_state(backend.as_ref(), reference_id)
+			.and_then(|()| check_events_api(&*api, &reference_id))
+			.map_err(|e| trace_err(e, state_archive))?;
+
+		// The gas limit of the call defaults to the one of the block.
+		let block_gas_limit = match api.current_block(&reference_id) {
+			Ok(Some(block)) => block.header.gas_limit,
+			Ok(None) => return Err(internal_err("Ethereum block not found".to_string())),
+			Err(e) => return Err(internal_err(format!("Runtime block call failed: {:?}", e))),
+		};
+
+		let call = hedgeware_rpc_core_types::trace_call(call, block_gas_limit)?;
+		let account_overrides = hedgeware_rpc_core_types::account_overrides(state_overrides)?;
+
+		let (tracer, result) = SingleTracer::new(trace_type)
+			.with_step_window(step_window)
+			.trace_until(Some(Deadline::new(deadline)), || {
+				api.trace_call(&reference_id, call, account_overrides, trace_type)
+			});
+		result
+			.map_err(|_| timeout_err())?
+			.map_err(|e| internal_err(format!("Runtime api access error: {:?}", e)))?
+			.map_err(|e| internal_err(format!("DispatchError: {:?}", e)))?;
+
+		Ok(tracer.into_tx_trace())
+	}
+
+	/// Replays a whole block in the Runtime, tracing each Ethereum transaction.
+	///
+	/// The Runtime is called a single time, the state of each transaction being the one left
+	/// by the preceding extrinsics.
+	fn handle_block_request(
+		client: Arc<C>,
+		backend: Arc<BE>,
+		frontier_backend: Arc<fc_db::Backend<B>>,
+		graph: Arc<Pool<A>>,
+		tracing_runtimes: Option<&TracingRuntimes<B, BE>>,
+		input: RequesterInput,
+		params: Option<TraceParams>,
+		deadline: Instant,
+		state_archive: bool,
+	) -> RpcResult<Vec<BlockTransactionTrace>> {
+		let traced_block = match input {
+			RequesterInput::BlockNumber(block) => {
+				Self::traced_block(client.as_ref(), frontier_backend.as_ref(), block)?
+			}
+			RequesterInput::BlockHash(eth_block_hash) => {
+				match frontier_backend_client::load_hash::<B>(
+					frontier_backend.as_ref(),
+					eth_block_hash,
+				) {
+					Ok(Some(hash)) => TracedBlock::Imported(hash),
+					Ok(_) => {
+						return Err(
+							TraceRpcError::BlockNotFound(format!("{:?}", eth_block_hash)).into(),
+						)
+					}
+					Err(e) => return Err(e),
+				}
 			}
+			RequesterInput::Transaction(_) | RequesterInput::Call { .. } => {
+				return Err(internal_err("Expected a block request".to_string()))
+			}
+		};
+
+		let step_window = Self::step_window(params.as_ref())?;
+		let trace_type = Self::trace_type(params)?;
+
+		let api = client.runtime_api();
+
+		let (parent_block_id, ext, transaction_hashes) = match traced_block {
+			TracedBlock::Imported(reference_id) => {
+				// Get the header I want to work with, and the extrinsics.
+				let (header, ext) =
+					Self::block_data(client.as_ref(), backend.as_ref(), reference_id)?;
+				// The genesis block has no transactions to trace.
+				if header.number().is_zero() {
+					return Ok(vec![]);
+				}
+				let parent_block_id = BlockId::Hash(*header.parent_hash());
+				check_state(backend.as_ref(), parent_block_id)
+					.map_err(|e| trace_err(e, state_archive))?;
+
+				// Get the hashes of the Ethereum transactions.
+				let statuses = match api.current_transaction_statuses(&reference_id) {
+					Ok(Some(statuses)) => statuses,
+					Ok(None) => return Err(internal_err("Ethereum block not found".to_string())),
+					Err(e) => {
+						return Err(internal_err(format!("Runtime block call failed: {:?}", e)))
+					}
+				};
+
+				(
+					parent_block_id,
+					ext,
+					statuses
+						.into_iter()
+						.map(|status| status.transaction_hash)
+						.collect(),
+				)
+			}
+			TracedBlock::Pending => {
+				let pending = Self::pending_block(client.as_ref(), graph.as_ref())?;
+				(pending.parent, pending.extrinsics, pending.transaction_hashes)
+			}
+		};
+
+		let traces = DebugApi::new(
+			client.as_ref(),
+			backend.as_ref(),
+			tracing_runtimes,
+			parent_block_id,
+		)
+		.and_then(|api| {
+			api.with_deadline(Some(deadline))
+				.with_step_window(step_window)
+				.trace_block_transactions(ext, trace_type)
+		})
+		.map_err(|e| trace_err(e, state_archive))?
+		.map_err(|e| internal_err(format!("DispatchError: {:?}", e)))?;
+
+		// Substrate-originated traces are identified by their extrinsic.
+		let mut transaction_hashes = transaction_hashes.into_iter();
+		Ok(traces
+			.into_iter()
+			.filter_map(|(origin, result)| match origin {
+				TransactionOrigin::Ethereum => {
+					transaction_hashes.next().map(|tx_hash| BlockTransactionTrace {
+						tx_hash,
+						extrinsic_index: None,
+						result,
+					})
+				}
+				TransactionOrigin::Substrate {
+					extrinsic_index,
+					extrinsic_hash,
+				} => Some(BlockTransactionTrace {
+					tx_hash: extrinsic_hash,
+					extrinsic_index: Some(extrinsic_index),
+					result,
+				}),
+			})
+			.collect())
+	}
+}
+
+/// Convert an error of `DebugApi`, timeouts, pruned states and unsupported runtimes being
+/// reported with their own error code.
+fn trace_err(e: TraceError, state_archive: bool) -> jsonrpc_core::Error {
+	match e {
+		TraceError::TimedOut => timeout_err(),
+		TraceError::Api(e) => internal_err(e),
+		TraceError::StatePruned(block) => TraceRpcError::StatePruned {
+			block,
+			archive: state_archive,
 		}
-		return Err(internal_err("Runtime block call failed".to_string()));
+		.into(),
+		TraceError::Unsupported(e) => TraceRpcError::RuntimeApiUnsupported(e).into(),
 	}
 }
@@ -0,0 +1,187 @@
+// Copyright 2019-2020 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{BlockReplay, ReplayCache, ReplayKey, ReplayWaiter};
+use ethereum_types::H256;
+use futures::{
+	future::{self, BoxFuture},
+	FutureExt,
+};
+use hedgeware_client_evm_tracing::{Deadline, TransactionOrigin};
+use hedgeware_rpc_primitives_debug::single::{TraceType, TransactionTrace};
+use jsonrpc_core::Result as RpcResult;
+use std::{
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, Mutex,
+	},
+	time::{Duration, Instant},
+};
+use tokio::{runtime::Runtime, sync::oneshot, time::timeout_at};
+
+fn runtime() -> Runtime {
+	tokio::runtime::Builder::new()
+		.basic_scheduler()
+		.enable_time()
+		.build()
+		.unwrap()
+}
+
+fn key(block: u64, trace_type: TraceType) -> ReplayKey {
+	ReplayKey {
+		block_hash: H256::from_low_u64_be(block),
+		trace_type,
+	}
+}
+
+fn call_list_replay() -> Arc<BlockReplay> {
+	Arc::new(BlockReplay::Transactions(vec![(
+		TransactionOrigin::Ethereum,
+		TransactionTrace::CallList(vec![]),
+	)]))
+}
+
+/// Request the replay of a block, counting the replays started by the cache.
+fn request(
+	cache: &Arc<ReplayCache>,
+	replays: &Arc<AtomicUsize>,
+	key: ReplayKey,
+	replay: BoxFuture<'static, RpcResult<Arc<BlockReplay>>>,
+) -> ReplayWaiter {
+	let replays = Arc::clone(replays);
+	let deadline = Instant::now() + Duration::from_secs(60);
+	ReplayCache::get_or_start(cache, key, deadline, move |_| {
+		replays.fetch_add(1, Ordering::SeqCst);
+		replay
+	})
+}
+
+#[test]
+fn concurrent_requests_for_a_block_share_a_single_replay() {
+	runtime().block_on(async {
+		let cache = Arc::new(ReplayCache::new(Duration::from_secs(60), usize::MAX));
+		let replays = Arc::new(AtomicUsize::new(0));
+		let (finish, finished) = oneshot::channel::<()>();
+		let finished = finished.shared();
+		let replay = || {
+			let finished = finished.clone();
+			async move {
+				let _ = finished.await;
+				Ok(call_list_replay())
+			}
+			.boxed()
+		};
+
+		let first = request(&cache, &replays, key(1, TraceType::CallList), replay());
+		let second = request(&cache, &replays, key(1, TraceType::CallList), replay());
+
+		// A request timing out doesn't abort the replay waited for by the other ones.
+		let timed_out = request(&cache, &replays, key(1, TraceType::CallList), replay());
+		assert!(timeout_at(tokio::time::Instant::now(), timed_out)
+			.await
+			.is_err());
+
+		let _ = finish.send(());
+		let (first, second) = future::join(first, second).await;
+		assert!(Arc::ptr_eq(&first.unwrap(), &second.unwrap()));
+		assert_eq!(replays.load(Ordering::SeqCst), 1);
+
+		// The finished replay is cached, while another tracer needs a replay of its own.
+		request(&cache, &replays, key(1, TraceType::CallList), replay())
+			.await
+			.unwrap();
+		assert_eq!(replays.load(Ordering::SeqCst), 1);
+		request(
+			&cache,
+			&replays,
+			key(1, TraceType::CallTracer { with_log: false }),
+			replay(),
+		)
+		.await
+		.unwrap();
+		assert_eq!(replays.load(Ordering::SeqCst), 2);
+	});
+}
+
+#[test]
+fn least_recently_used_replays_are_evicted_above_the_size_limit() {
+	runtime().block_on(async {
+		let replay_size = call_list_replay().size();
+		let cache = Arc::new(ReplayCache::new(Duration::from_secs(60), replay_size));
+		let replays = Arc::new(AtomicUsize::new(0));
+		let replay = || future::ready(Ok(call_list_replay())).boxed();
+		let call_list = |block| key(block, TraceType::CallList);
+
+		request(&cache, &replays, call_list(1), replay()).await.unwrap();
+		request(&cache, &replays, call_list(2), replay()).await.unwrap();
+		assert_eq!(replays.load(Ordering::SeqCst), 2);
+		assert_eq!(cache.state().bytes, replay_size);
+
+		// The replay of block 2 is still cached, the one of block 1 has been evicted.
+		request(&cache, &replays, call_list(2), replay()).await.unwrap();
+		assert_eq!(replays.load(Ordering::SeqCst), 2);
+		request(&cache, &replays, call_list(1), replay()).await.unwrap();
+		assert_eq!(replays.load(Ordering::SeqCst), 3);
+
+		// Failed replays are discarded at once.
+		let failed = || future::ready(Err(jsonrpc_core::Error::internal_error())).boxed();
+		assert!(request(&cache, &replays, call_list(3), failed())
+			.await
+			.is_err());
+		request(&cache, &replays, call_list(3), replay()).await.unwrap();
+		assert_eq!(replays.load(Ordering::SeqCst), 5);
+	});
+}
+
+/// Replay which never finishes, recording its deadline once started.
+fn pending_replay(
+	started: &Arc<Mutex<Option<Deadline>>>,
+) -> impl FnOnce(Deadline) -> BoxFuture<'static, RpcResult<Arc<BlockReplay>>> {
+	let started = Arc::clone(started);
+	move |deadline| {
+		*started.lock().unwrap() = Some(deadline);
+		future::pending().boxed()
+	}
+}
+
+#[test]
+fn replays_run_until_the_latest_deadline_of_their_requests() {
+	runtime().block_on(async {
+		let cache = Arc::new(ReplayCache::new(Duration::from_secs(60), usize::MAX));
+		let started = Arc::new(Mutex::new(None));
+		let call_list = key(1, TraceType::CallList);
+		let now = Instant::now();
+		let in_secs = |secs| now + Duration::from_secs(secs);
+
+		let first =
+			ReplayCache::get_or_start(&cache, call_list, in_secs(10), pending_replay(&started));
+		let second =
+			ReplayCache::get_or_start(&cache, call_list, in_secs(20), pending_replay(&started));
+		let deadline = started.lock().unwrap().take().unwrap();
+		assert_eq!(deadline.instant(), in_secs(20));
+
+		// The replay keeps the latest deadline of the requests still waiting for it.
+		drop(second);
+		assert_eq!(deadline.instant(), in_secs(10));
+
+		// It is aborted once no request waits for it, a later request starting another replay.
+		drop(first);
+		assert!(deadline.expired());
+		let _third =
+			ReplayCache::get_or_start(&cache, call_list, in_secs(10), pending_replay(&started));
+		assert!(started.lock().unwrap().is_some());
+	});
+}
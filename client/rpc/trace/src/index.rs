@@ -0,0 +1,584 @@
+// Copyright 2019-2020 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Persistent index of the traces of the canonical chain.
+//!
+//! The `TraceIndexTask` traces each new block with the same runtime API as the `CacheTask` and
+//! stores the result in its own RocksDB database, alongside indexes of the addresses found in the
+//! traces. `trace_filter` then reads the indexed blocks instead of replaying them, and only
+//! considers the blocks in which the requested addresses appear.
+//!
+//! The indexed blocks always form a contiguous range of the canonical chain. The range grows
+//! upward as blocks are imported (or finalized), and downward when backfilling historical blocks.
+//! When the canonical chain is reorganized, the blocks of the retracted branch are replaced.
+//!
+//! A database written with another version of its content is cleared when opened, and the
+//! range it covered is indexed again by backfilling.
+
+use codec::{Decode, Encode};
+use futures::{stream::BoxStream, FutureExt, StreamExt};
+use kvdb::{DBTransaction, KeyValueDB};
+use std::{collections::BTreeSet, marker::PhantomData, ops::RangeInclusive, path::Path, sync::Arc};
+use tokio::sync::Semaphore;
+use tracing::instrument;
+
+use sc_client_api::{backend::Backend, BlockchainEvents};
+use sp_api::{BlockId, CallApiAt, HeaderT, ProvideRuntimeApi};
+use sp_block_builder::BlockBuilder;
+use sp_blockchain::{Error as BlockChainError, HeaderBackend, HeaderMetadata};
+use sp_runtime::traits::Block as BlockT;
+
+use ethereum_types::{H160, H256};
+use fp_rpc::EthereumRuntimeRPCApi;
+
+use crate::{CacheTask, TransactionTrace};
+use hedgeware_client_evm_tracing::TracingRuntimes;
+use hedgeware_rpc_primitives_debug::{block, DebugRuntimeApi};
+use hedgeware_rpc_primitives_txpool::TxPoolRuntimeApi;
+
+/// Layout of the trace database.
+mod columns {
+	pub const NUM_COLUMNS: u32 = 5;
+
+	/// Bounds of the indexed range and backfill progress.
+	pub const META: u32 = 0;
+	/// Block number (big endian) to the hash of the indexed canonical block.
+	pub const CANONICAL: u32 = 1;
+	/// Block hash to the traces of the block.
+	pub const TRACES: u32 = 2;
+	/// Address followed by a block number (big endian), for each block in which the address
+	/// is the sender of a trace.
+	pub const FROM_ADDRESS: u32 = 3;
+	/// Address followed by a block number (big endian), for each block in which the address
+	/// is the recipient of a trace.
+	pub const TO_ADDRESS: u32 = 4;
+}
+
+/// Lowest indexed block number, in the `META` column.
+const FIRST_BLOCK: &[u8] = b"FIRST_BLOCK";
+/// Highest indexed block number, in the `META` column.
+const LAST_BLOCK: &[u8] = b"LAST_BLOCK";
+/// Version of the content of the database, in the `META` column.
+const VERSION: &[u8] = b"VERSION";
+/// Lowest block number indexed by a cleared database, in the `META` column.
+const REINDEX_TARGET: &[u8] = b"REINDEX_TARGET";
+
+/// Current version of the content of the database.
+///
+/// 1. Initial version, which didn't store its version.
+/// 2. Created contracts and refund addresses are indexed as recipients, and traces have an
+///    optional Ethereum position and an extrinsic index.
+const SCHEMA_VERSION: u32 = 2;
+
+/// When the index is extended with newly imported or finalized blocks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TraceIndexMode {
+	/// Index each new best block. Blocks of retracted forks are replaced.
+	Imported,
+	/// Only index finalized blocks.
+	Finalized,
+}
+
+/// Database of the traces of the canonical chain, with indexes of their addresses.
+pub struct TraceDb {
+	db: Arc<dyn KeyValueDB>,
+}
+
+impl TraceDb {
+	/// Open the database, creating it if needed. It is cleared if written with another version.
+	pub fn open(path: &Path) -> Result<Self, String> {
+		let db = kvdb_rocksdb::Database::open(
+			&kvdb_rocksdb::DatabaseConfig::with_columns(columns::NUM_COLUMNS),
+			&path.to_string_lossy(),
+		)
+		.map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+		let trace_db = Self { db: Arc::new(db) };
+		trace_db.check_version()?;
+		Ok(trace_db)
+	}
+
+	/// Lowest block number to index again after the database has been cleared, if any.
+	pub fn reindex_target(&self) -> Result<Option<u32>, String> {
+		self.meta(REINDEX_TARGET)
+	}
+
+	/// Range of indexed blocks, if any.
+	pub fn indexed_range(&self) -> Result<Option<RangeInclusive<u32>>, String> {
+		match (self.meta(FIRST_BLOCK)?, self.meta(LAST_BLOCK)?) {
+			(Some(first), Some(last)) => Ok(Some(first..=last)),
+			_ => Ok(None),
+		}
+	}
+
+	/// Hash of the canonical block indexed at this height.
+	pub fn indexed_hash(&self, block_number: u32) -> Result<Option<H256>, String> {
+		self.db
+			.get(columns::CANONICAL, &block_number.to_be_bytes())
+			.map_err(|e| format!("Failed to read the trace database: {}", e))?
+			.map(|value| decode(&value))
+			.transpose()
+	}
+
+	/// Traces of an indexed block, `None` if it isn't indexed. The traces of a block don't
+	/// depend on the chain it belongs to, so they are read by hash whatever the index became.
+	pub fn traces(&self, block_hash: H256) -> Result<Option<Vec<TransactionTrace>>, String> {
+		self.db
+			.get(columns::TRACES, block_hash.as_bytes())
+			.map_err(|e| format!("Failed to read the trace database: {}", e))?
+			.map(|value| decode(&value))
+			.transpose()
+	}
+
+	/// Block numbers within `range` in which one of the addresses is the sender (or the recipient
+	/// if `recipient` is set) of a trace.
+	pub fn blocks_with_addresses(
+		&self,
+		addresses: &[H160],
+		recipient: bool,
+		range: &RangeInclusive<u32>,
+	) -> BTreeSet<u32> {
+		let column = if recipient {
+			columns::TO_ADDRESS
+		} else {
+			columns::FROM_ADDRESS
+		};
+
+		let mut blocks = BTreeSet::new();
+		for address in addresses {
+			for (key, _) in self.db.iter_with_prefix(column, address.as_bytes()) {
+				let mut number = [0u8; 4];
+				number.copy_from_slice(&key[H160::len_bytes()..]);
+				let number = u32::from_be_bytes(number);
+
+				if range.contains(&number) {
+					blocks.insert(number);
+				}
+			}
+		}
+		blocks
+	}
+
+	/// Blocks to index for the chain ending at `tip`, from the highest: the ones above the
+	/// last block of this chain already indexed, and at most down to the start of the indexed
+	/// range. Only the tip is indexed when the index is empty.
+	pub(crate) fn blocks_to_index(
+		&self,
+		tip_number: u32,
+		tip: H256,
+		parent_hash: impl Fn(H256) -> Result<H256, String>,
+	) -> Result<Vec<(u32, H256)>, String> {
+		let first_indexed = self.indexed_range()?.map(|range| *range.start());
+		let mut blocks = vec![];
+		let (mut number, mut hash) = (tip_number, tip);
+		while number > 0 {
+			if self.indexed_hash(number)? == Some(hash) {
+				break;
+			}
+			blocks.push((number, hash));
+
+			// An empty index starts at the first block seen.
+			if first_indexed.map_or(true, |first| number <= first) {
+				break;
+			}
+
+			hash = parent_hash(hash)?;
+			number -= 1;
+		}
+		Ok(blocks)
+	}
+
+	/// Block below the indexed range to backfill, if the range doesn't reach `target` yet.
+	pub(crate) fn next_backfill_block(&self, target: u32) -> Result<Option<u32>, String> {
+		Ok(match self.indexed_range()? {
+			Some(range) if *range.start() > target => Some(*range.start() - 1),
+			_ => None,
+		})
+	}
+
+	/// Clear a database written with another version, keeping the lowest block it indexed for
+	/// the same range to be indexed again.
+	fn check_version(&self) -> Result<(), String> {
+		let version = self.meta(VERSION)?;
+		if version == Some(SCHEMA_VERSION) {
+			return Ok(());
+		}
+
+		let reindex_target = match (self.meta(FIRST_BLOCK)?, self.reindex_target()?) {
+			(Some(first), Some(target)) => Some(first.min(target)),
+			(first, target) => first.or(target),
+		};
+		if let Some(target) = reindex_target {
+			tracing::info!(
+				"Trace database version {} is outdated, indexing again down to block {}",
+				version.unwrap_or(1),
+				target
+			);
+		}
+
+		let mut transaction = DBTransaction::new();
+		for column in 0..columns::NUM_COLUMNS {
+			for (key, _) in self.db.iter(column) {
+				transaction.delete(column, &key);
+			}
+		}
+		if let Some(target) = reindex_target {
+			transaction.put_vec(columns::META, REINDEX_TARGET, target.encode());
+		}
+		transaction.put_vec(columns::META, VERSION, SCHEMA_VERSION.encode());
+
+		self.write(transaction)
+	}
+
+	fn meta(&self, key: &[u8]) -> Result<Option<u32>, String> {
+		self.db
+			.get(columns::META, key)
+			.map_err(|e| format!("Failed to read the trace database: {}", e))?
+			.map(|value| decode(&value))
+			.transpose()
+	}
+
+	/// Store the traces of a canonical block, replacing the block previously indexed at this
+	/// height if any.
+	pub(crate) fn insert_block(
+		&self,
+		block_number: u32,
+		block_hash: H256,
+		traces: &[TransactionTrace],
+	) -> Result<(), String> {
+		let mut transaction = DBTransaction::new();
+
+		if let Some(retracted) = self.indexed_hash(block_number)? {
+			self.remove_block(&mut transaction, block_number, retracted)?;
+		}
+
+		transaction.put_vec(
+			columns::CANONICAL,
+			&block_number.to_be_bytes(),
+			block_hash.encode(),
+		);
+		transaction.put_vec(columns::TRACES, block_hash.as_bytes(), traces.encode());
+		for (column, address) in addresses(traces) {
+			transaction.put(column, &address_key(address, block_number), &[]);
+		}
+
+		let first = match self.meta(FIRST_BLOCK)? {
+			Some(first) if first <= block_number => first,
+			_ => block_number,
+		};
+		let last = match self.meta(LAST_BLOCK)? {
+			Some(last) if last >= block_number => last,
+			_ => block_number,
+		};
+		transaction.put_vec(columns::META, FIRST_BLOCK, first.encode());
+		transaction.put_vec(columns::META, LAST_BLOCK, last.encode());
+
+		self.write(transaction)
+	}
+
+	/// Remove the indexed blocks above this height, after a reorganization to a shorter chain.
+	pub(crate) fn truncate(&self, block_number: u32) -> Result<(), String> {
+		let range = match self.indexed_range()? {
+			Some(range) if *range.end() > block_number => range,
+			_ => return Ok(()),
+		};
+
+		let mut transaction = DBTransaction::new();
+		for number in (block_number + 1).max(*range.start())..=*range.end() {
+			if let Some(hash) = self.indexed_hash(number)? {
+				self.remove_block(&mut transaction, number, hash)?;
+			}
+		}
+
+		if block_number < *range.start() {
+			transaction.delete(columns::META, FIRST_BLOCK);
+			transaction.delete(columns::META, LAST_BLOCK);
+		} else {
+			transaction.put_vec(columns::META, LAST_BLOCK, block_number.encode());
+		}
+
+		self.write(transaction)
+	}
+
+	fn remove_block(
+		&self,
+		transaction: &mut DBTransaction,
+		block_number: u32,
+		block_hash: H256,
+	) -> Result<(), String> {
+		if let Some(traces) = self.traces(block_hash)? {
+			for (column, address) in addresses(&traces) {
+				transaction.delete(column, &address_key(address, block_number));
+			}
+		}
+		transaction.delete(columns::TRACES, block_hash.as_bytes());
+		transaction.delete(columns::CANONICAL, &block_number.to_be_bytes());
+		Ok(())
+	}
+
+	fn write(&self, transaction: DBTransaction) -> Result<(), String> {
+		self.db
+			.write(transaction)
+			.map_err(|e| format!("Failed to write the trace database: {}", e))
+	}
+}
+
+fn decode<T: Decode>(value: &[u8]) -> Result<T, String> {
+	T::decode(&mut &value[..]).map_err(|e| format!("Corrupted trace database: {:?}", e))
+}
+
+fn address_key(address: H160, block_number: u32) -> Vec<u8> {
+	let mut key = address.as_bytes().to_vec();
+	key.extend_from_slice(&block_number.to_be_bytes());
+	key
+}
+
+/// Addresses indexed for the given traces, matching the way `trace_filter` filters them: the
+/// recipient of a creation is the created contract, and the one of a suicide is the refund
+/// address.
+fn addresses(traces: &[TransactionTrace]) -> BTreeSet<(u32, H160)> {
+	traces
+		.iter()
+		.flat_map(|trace| match trace.action {
+			block::TransactionTraceAction::Call { from, to, .. } => vec![
+				(columns::FROM_ADDRESS, from),
+				(columns::TO_ADDRESS, to),
+			],
+			block::TransactionTraceAction::Create { from, .. } => match trace.output {
+				block::TransactionTraceOutput::Result {
+					result: block::TransactionTraceResult::Create { address, .. },
+				} => vec![
+					(columns::FROM_ADDRESS, from),
+					(columns::TO_ADDRESS, address),
+				],
+				_ => vec![(columns::FROM_ADDRESS, from)],
+			},
+			block::TransactionTraceAction::Suicide {
+				address,
+				refund_address,
+				..
+			} => vec![
+				(columns::FROM_ADDRESS, address),
+				(columns::TO_ADDRESS, refund_address),
+			],
+		})
+		.collect()
+}
+
+/// Task extending the trace database as blocks are imported or finalized.
+pub struct TraceIndexTask<B, C, BE> {
+	client: Arc<C>,
+	backend: Arc<BE>,
+	trace_db: Arc<TraceDb>,
+	/// Tracing runtimes replacing the on-chain runtimes, if any.
+	tracing_runtimes: Option<Arc<TracingRuntimes<B, BE>>>,
+	blocking_permits: Arc<Semaphore>,
+	/// Lowest block to index when backfilling, if any.
+	backfill_target: Option<u32>,
+	/// Whether the node keeps the state of all blocks, which old blocks need to be traced.
+	state_archive: bool,
+	_phantom: PhantomData<B>,
+}
+
+impl<B, C, BE> TraceIndexTask<B, C, BE>
+where
+	BE: Backend<B> + 'static,
+	C: ProvideRuntimeApi<B> + CallApiAt<B> + BlockchainEvents<B>,
+	C: HeaderMetadata<B, Error = BlockChainError> + HeaderBackend<B>,
+	C: Send + Sync + 'static,
+	B: BlockT<Hash = H256> + Send + Sync + 'static,
+	B::Header: HeaderT<Number = u32>,
+	C::Api: BlockBuilder<B>,
+	C::Api: DebugRuntimeApi<B>,
+	C::Api: EthereumRuntimeRPCApi<B>,
+	C::Api: TxPoolRuntimeApi<B>,
+{
+	/// Create the indexing task, sharing the blocking permits of the other tracing tasks.
+	///
+	/// With a `backfill_target`, the blocks below the indexed range are indexed down to it
+	/// while no new block is waiting to be indexed.
+	pub async fn create(
+		client: Arc<C>,
+		backend: Arc<BE>,
+		trace_db: Arc<TraceDb>,
+		tracing_runtimes: Option<Arc<TracingRuntimes<B, BE>>>,
+		blocking_permits: Arc<Semaphore>,
+		mode: TraceIndexMode,
+		backfill_target: Option<u32>,
+		state_archive: bool,
+	) {
+		let mut notifications: BoxStream<H256> = match mode {
+			TraceIndexMode::Imported => client
+				.import_notification_stream()
+				.filter_map(|notification| async move {
+					Some(notification.hash).filter(|_| notification.is_new_best)
+				})
+				.boxed(),
+			TraceIndexMode::Finalized => client
+				.finality_notification_stream()
+				.map(|notification| notification.hash)
+				.boxed(),
+		};
+
+		// The range covered by an outdated database is indexed again.
+		let reindex_target = trace_db.reindex_target().unwrap_or_else(|e| {
+			tracing::warn!("Failed to read the trace database : {}", e);
+			None
+		});
+		let backfill_target = match (backfill_target, reindex_target) {
+			(Some(target), Some(reindex_target)) => Some(target.min(reindex_target)),
+			(target, reindex_target) => target.or(reindex_target),
+		};
+
+		let task = Self {
+			client,
+			backend,
+			trace_db,
+			tracing_runtimes,
+			blocking_permits,
+			backfill_target,
+			state_archive,
+			_phantom: PhantomData,
+		};
+
+		// Catch up with the blocks imported while the node was stopped.
+		let info = task.client.info();
+		let tip = match mode {
+			TraceIndexMode::Imported => info.best_hash,
+			TraceIndexMode::Finalized => info.finalized_hash,
+		};
+		task.index_chain(tip).await;
+
+		loop {
+			let block_hash = match notifications.next().now_or_never() {
+				Some(block_hash) => block_hash,
+				// Backfill while no new block is waiting.
+				None => {
+					if task.backfill().await {
+						continue;
+					}
+					notifications.next().await
+				}
+			};
+
+			match block_hash {
+				Some(block_hash) => task.index_chain(block_hash).await,
+				// The client is shutting down.
+				None => return,
+			}
+		}
+	}
+
+	/// Index the canonical chain up to `tip`, replacing the blocks of a retracted fork.
+	#[instrument(skip(self))]
+	async fn index_chain(&self, tip: H256) {
+		if let Err(e) = self.try_index_chain(tip).await {
+			tracing::warn!("Failed to index traces up to block {} : {}", tip, e);
+		}
+	}
+
+	async fn try_index_chain(&self, tip: H256) -> Result<(), String> {
+		let header = self
+			.client
+			.header(BlockId::Hash(tip))
+			.map_err(|e| format!("{:?}", e))?
+			.ok_or_else(|| format!("Block {} not found", tip))?;
+		let tip_number = *header.number();
+
+		// Walk back the new chain until reaching a block already indexed, the start of the
+		// indexed range or the genesis.
+		let blocks = self.trace_db.blocks_to_index(tip_number, tip, |hash| {
+			Ok(*self
+				.client
+				.header(BlockId::Hash(hash))
+				.map_err(|e| format!("{:?}", e))?
+				.ok_or_else(|| format!("Block {} not found", hash))?
+				.parent_hash())
+		})?;
+
+		self.trace_db.truncate(tip_number)?;
+
+		for (number, hash) in blocks.into_iter().rev() {
+			self.index_block(number, hash).await?;
+		}
+
+		Ok(())
+	}
+
+	/// Index the block below the indexed range. Returns false if there is nothing to backfill.
+	async fn backfill(&self) -> bool {
+		let target = match self.backfill_target {
+			Some(target) => target.max(1),
+			None => return false,
+		};
+
+		let number = match self.trace_db.next_backfill_block(target) {
+			Ok(Some(number)) => number,
+			Ok(None) => return false,
+			Err(e) => {
+				tracing::warn!("Failed to backfill traces : {}", e);
+				return false;
+			}
+		};
+
+		let result = match self.client.hash(number) {
+			Ok(Some(hash)) => self.index_block(number, hash).await,
+			Ok(None) => Err(format!("Block {} not found", number)),
+			Err(e) => Err(format!("{:?}", e)),
+		};
+
+		match result {
+			Ok(()) => {
+				if number == target {
+					tracing::info!("Finished backfilling traces down to block {}", target);
+				}
+				true
+			}
+			Err(e) => {
+				tracing::warn!("Failed to backfill traces of block {} : {}", number, e);
+				false
+			}
+		}
+	}
+
+	/// Trace a block once a permit is available, then store its traces.
+	async fn index_block(&self, block_number: u32, block_hash: H256) -> Result<(), String> {
+		let traces = {
+			let _permit = self.blocking_permits.acquire().await;
+
+			let client = Arc::clone(&self.client);
+			let backend = Arc::clone(&self.backend);
+			let tracing_runtimes = self.tracing_runtimes.clone();
+			let state_archive = self.state_archive;
+			// Indexing has no deadline, a block being indexed only once.
+			tokio::task::spawn_blocking(move || {
+				CacheTask::<B, C, BE>::cache_block(
+					client,
+					backend,
+					tracing_runtimes.as_deref(),
+					block_hash,
+					None,
+					state_archive,
+				)
+			})
+			.await
+			.map_err(|e| format!("Tracing of block {} panicked : {:?}", block_hash, e))?
+			.map_err(|e| e.message)?
+		};
+
+		self.trace_db.insert_block(block_number, block_hash, &traces)
+	}
+}
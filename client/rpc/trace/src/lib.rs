@@ -14,15 +14,22 @@
 // You should have received a copy of the GNU General Public License
 // along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
 
-//! `trace_filter` RPC handler and its associated service task.
+//! `trace_filter`, `trace_block`, `trace_transaction`, `trace_get` and `trace_replay*` RPC
+//! handlers and their associated service task.
 //! The RPC handler rely on `CacheTask` which provides a future that must be run inside a tokio
 //! executor.
 //!
 //! The implementation is composed of multiple tasks :
-//! - Many calls the the RPC handler `Trace::filter`, communicating with the main task.
+//! - Many calls the the RPC handlers `Trace::filter`, `Trace::block`, `Trace::transaction` and
+//!   `Trace::get`, communicating with the main task.
 //! - A main `CacheTask` managing the cache and the communication between tasks.
 //! - For each traced block an async task responsible to wait for a permit, spawn a blocking
 //!   task and waiting for the result, then send it to the main `CacheTask`.
+//! - For each replay an async task responsible to wait for a permit, spawn a blocking task and
+//!   send the result directly to the RPC handler. Replays are not cached as their output
+//!   depends on the requested trace types.
+//! - Optionally a `TraceIndexTask` storing the traces of the canonical chain in a `TraceDb`,
+//!   read by `Trace::filter` instead of tracing the indexed blocks (see the `index` module).
 
 use futures::{
 	compat::Compat,
@@ -31,47 +38,83 @@ use futures::{
 	stream::FuturesUnordered,
 	FutureExt, SinkExt, StreamExt,
 };
-use std::{collections::BTreeMap, future::Future, marker::PhantomData, sync::Arc, time::Duration};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	future::Future,
+	marker::PhantomData,
+	mem,
+	ops::RangeInclusive,
+	sync::Arc,
+	time::{Duration, Instant},
+};
 use tokio::{
 	sync::{mpsc, oneshot, Semaphore},
-	time::delay_for,
+	time::{delay_for, timeout_at},
 };
 use tracing::{instrument, Instrument};
 
-use jsonrpc_core::Result;
+use jsonrpc_core::{ErrorCode, Result};
 use sc_client_api::backend::Backend;
-use sp_api::{BlockId, HeaderT, ProvideRuntimeApi};
+use sp_api::{BlockId, CallApiAt, HeaderT, ProvideRuntimeApi};
 use sp_block_builder::BlockBuilder;
 use sp_blockchain::{
 	Backend as BlockchainBackend, Error as BlockChainError, HeaderBackend, HeaderMetadata,
 };
 use sp_runtime::traits::Block as BlockT;
 use sp_utils::mpsc::TracingUnboundedSender;
+use substrate_prometheus_endpoint::{
+	register, Counter, Gauge, PrometheusError, Registry, U64,
+};
 
-use ethereum_types::H256;
-use fc_rpc::internal_err;
+use ethereum_types::{H160, H256};
+use fc_rpc::{frontier_backend_client, internal_err};
 use fp_rpc::EthereumRuntimeRPCApi;
+use sc_transaction_graph::{ChainApi, Pool};
 
 pub use hedgeware_rpc_core_trace::{
-	FilterRequest, RequestBlockId, RequestBlockTag, Trace as TraceT, TraceServer, TransactionTrace,
+	CallRequest, FilterCallType, FilterMode, FilterRequest, FilterResponse, RequestBlockId,
+	RequestBlockTag, RequestTraceType, StateOverride, Trace as TraceT, TraceCursor, TraceIndex,
+	TraceServer, TransactionReplay, TransactionTrace,
+};
+use hedgeware_client_evm_tracing::{
+	check_events_api, check_state, BlockTracer, DebugApi, Deadline, PendingBlock, ReplayTracer,
+	TraceError, Tracer, TracingRuntimes,
 };
-use hedgeware_rpc_primitives_debug::{block, DebugRuntimeApi};
+use hedgeware_rpc_core_types::{timeout_err, TraceRpcError, TIMEOUT_ERROR_CODE};
+use hedgeware_rpc_primitives_debug::{block, replay, single::Log, CallType, DebugRuntimeApi};
+use hedgeware_rpc_primitives_txpool::TxPoolRuntimeApi;
+
+mod index;
+pub use index::{TraceDb, TraceIndexMode, TraceIndexTask};
+
+#[cfg(test)]
+mod tests;
 
 /// RPC handler. Will communicate with a `CacheTask` through a `CacheRequester`.
-pub struct Trace<B, C> {
+pub struct Trace<B: BlockT, C> {
 	_phantom: PhantomData<B>,
 	client: Arc<C>,
+	frontier_backend: Arc<fc_db::Backend<B>>,
 	requester: CacheRequester,
+	trace_db: Option<Arc<TraceDb>>,
 	max_count: u32,
+	/// Time after which a request is aborted.
+	timeout: Duration,
+	/// Time after which a `trace_filter` request, which may trace many blocks, is aborted.
+	filter_timeout: Duration,
 }
 
-impl<B, C> Clone for Trace<B, C> {
+impl<B: BlockT, C> Clone for Trace<B, C> {
 	fn clone(&self) -> Self {
 		Self {
 			_phantom: PhantomData::default(),
 			client: Arc::clone(&self.client),
+			frontier_backend: Arc::clone(&self.frontier_backend),
 			requester: self.requester.clone(),
+			trace_db: self.trace_db.clone(),
 			max_count: self.max_count,
+			timeout: self.timeout,
+			filter_timeout: self.filter_timeout,
 		}
 	}
 }
@@ -84,33 +127,231 @@ where
 	C: Send + Sync + 'static,
 {
 	/// Create a new RPC handler.
-	pub fn new(client: Arc<C>, requester: CacheRequester, max_count: u32) -> Self {
+	pub fn new(
+		client: Arc<C>,
+		frontier_backend: Arc<fc_db::Backend<B>>,
+		requester: CacheRequester,
+		trace_db: Option<Arc<TraceDb>>,
+		max_count: u32,
+		timeout: Duration,
+		filter_timeout: Duration,
+	) -> Self {
 		Self {
 			client,
+			frontier_backend,
 			requester,
+			trace_db,
 			max_count,
+			timeout,
+			filter_timeout,
 			_phantom: PhantomData::default(),
 		}
 	}
 
-	/// Convert an optional block ID (number or tag) to a block height.
+	/// Convert an optional block ID (number, tag or hash) to a block height. The pending block
+	/// isn't part of the chain yet, it is resolved as the best block.
 	fn block_id(&self, id: Option<RequestBlockId>) -> Result<u32> {
 		match id {
-			Some(RequestBlockId::Number(n)) => Ok(n),
-			None | Some(RequestBlockId::Tag(RequestBlockTag::Latest)) => {
+			Some(RequestBlockId::Number(n))
+			| Some(RequestBlockId::BlockNumber { block_number: n }) => Ok(n),
+			None
+			| Some(RequestBlockId::Tag(RequestBlockTag::Latest))
+			| Some(RequestBlockId::Tag(RequestBlockTag::Pending)) => {
 				Ok(self.client.info().best_number)
 			}
 			Some(RequestBlockId::Tag(RequestBlockTag::Earliest)) => Ok(0),
-			Some(RequestBlockId::Tag(RequestBlockTag::Pending)) => {
-				Err(internal_err("'pending' is not supported"))
+			Some(RequestBlockId::Tag(RequestBlockTag::Finalized))
+			| Some(RequestBlockId::Tag(RequestBlockTag::Safe)) => {
+				Ok(self.client.info().finalized_number)
+			}
+			Some(RequestBlockId::Hash {
+				block_hash,
+				require_canonical,
+			}) => Ok(self.eth_block(block_hash, require_canonical)?.0),
+		}
+	}
+
+	/// Convert an optional block ID to the imported block or the pending block it designates.
+	fn requested_block(&self, id: Option<RequestBlockId>) -> Result<RequestedBlock> {
+		match id {
+			Some(RequestBlockId::Tag(RequestBlockTag::Pending)) => Ok(RequestedBlock::Pending),
+			Some(RequestBlockId::Hash {
+				block_hash,
+				require_canonical,
+			}) => {
+				let (number, hash) = self.eth_block(block_hash, require_canonical)?;
+				Ok(RequestedBlock::Imported { number, hash })
+			}
+			id => {
+				let number = self.block_id(id)?;
+				Ok(RequestedBlock::Imported {
+					number,
+					hash: self.block_hash(number)?,
+				})
 			}
 		}
 	}
 
+	/// Height and Substrate hash of the block with the given Ethereum hash.
+	fn eth_block(&self, eth_block_hash: H256, require_canonical: bool) -> Result<(u32, H256)> {
+		let block_id =
+			frontier_backend_client::load_hash::<B>(self.frontier_backend.as_ref(), eth_block_hash)?
+				.ok_or_else(|| TraceRpcError::BlockNotFound(format!("{:?}", eth_block_hash)))?;
+
+		let block_header = self
+			.client
+			.header(block_id)
+			.map_err(|e| {
+				internal_err(format!(
+					"Error when fetching block {} header : {:?}",
+					eth_block_hash, e
+				))
+			})?
+			.ok_or_else(|| TraceRpcError::BlockNotFound(format!("{:?}", eth_block_hash)))?;
+
+		let block_height = *block_header.number();
+		let block_hash = block_header.hash();
+
+		if require_canonical && self.block_hash(block_height)? != block_hash {
+			return Err(internal_err(format!(
+				"Block {} is not canonical",
+				eth_block_hash
+			)));
+		}
+
+		Ok((block_height, block_hash))
+	}
+
+	/// Substrate hash of the block at the given height.
+	fn block_hash(&self, block_height: u32) -> Result<H256> {
+		let block_header = self
+			.client
+			.header(BlockId::<B>::Number(block_height))
+			.map_err(|e| {
+				internal_err(format!(
+					"Error when fetching block {} header : {:?}",
+					block_height, e
+				))
+			})?
+			.ok_or_else(|| TraceRpcError::BlockNotFound(block_height.to_string()))?;
+
+		Ok(block_header.hash())
+	}
+
+	/// Substrate hash of the block containing the given Ethereum transaction, if known.
+	fn transaction_block_hash(&self, transaction_hash: H256) -> Result<Option<H256>> {
+		let eth_block_hash = match frontier_backend_client::load_transactions::<B, C>(
+			self.client.as_ref(),
+			self.frontier_backend.as_ref(),
+			transaction_hash,
+		)? {
+			Some((eth_block_hash, _)) => eth_block_hash,
+			None => return Ok(None),
+		};
+
+		match frontier_backend_client::load_hash::<B>(
+			self.frontier_backend.as_ref(),
+			eth_block_hash,
+		)? {
+			Some(block_id) => self
+				.client
+				.expect_block_hash_from_id(&block_id)
+				.map(Some)
+				.map_err(|_| TraceRpcError::BlockNotFound(format!("{:?}", eth_block_hash)).into()),
+			None => Ok(None),
+		}
+	}
+
+	/// Deadline of a request received now.
+	fn deadline(&self) -> Instant {
+		Instant::now() + self.timeout
+	}
+
+	/// Fetch the traces of a block through a batch of its own, which hits the cache if the
+	/// block has recently been traced.
+	async fn block_traces(&self, block_hash: H256) -> Result<Vec<TransactionTrace>> {
+		let deadline = self.deadline();
+		let batch_id = self.requester.start_batch(vec![block_hash], deadline).await?;
+		let res = timeout_at(deadline.into(), self.requester.get_traces(block_hash))
+			.await
+			.unwrap_or_else(|_| Err(timeout_err()));
+		// Always stop the batch for the cache cleanup to work properly.
+		self.requester.stop_batch(batch_id).await;
+
+		res
+	}
+
+	/// `trace_block` endpoint (wrapped in the trait implementation with futures compatibilty)
+	async fn block(self, block: RequestBlockId) -> Result<Vec<TransactionTrace>> {
+		match self.requested_block(Some(block))? {
+			RequestedBlock::Imported { number: 0, .. } => Ok(vec![]), // no traces for genesis block.
+			RequestedBlock::Imported { hash, .. } => self.block_traces(hash).await,
+			RequestedBlock::Pending => self.requester.pending_traces(self.deadline()).await,
+		}
+	}
+
+	/// `trace_transaction` endpoint (wrapped in the trait implementation with futures
+	/// compatibilty)
+	async fn transaction(self, transaction_hash: H256) -> Result<Option<Vec<TransactionTrace>>> {
+		let block_hash = match self.transaction_block_hash(transaction_hash)? {
+			Some(block_hash) => block_hash,
+			None => return Ok(None),
+		};
+
+		let traces = self
+			.block_traces(block_hash)
+			.await?
+			.into_iter()
+			.filter(|trace| trace.transaction_hash == transaction_hash)
+			.collect();
+
+		Ok(Some(traces))
+	}
+
+	/// `trace_get` endpoint (wrapped in the trait implementation with futures compatibilty)
+	async fn get(
+		self,
+		transaction_hash: H256,
+		indices: Vec<TraceIndex>,
+	) -> Result<Option<TransactionTrace>> {
+		let trace_address: Vec<u32> = indices.into_iter().map(TraceIndex::value).collect();
+
+		Ok(self
+			.transaction(transaction_hash)
+			.await?
+			.unwrap_or_default()
+			.into_iter()
+			.find(|trace| trace.trace_address == trace_address))
+	}
+
 	/// `trace_filter` endpoint (wrapped in the trait implementation with futures compatibilty)
-	async fn filter(self, req: FilterRequest) -> Result<Vec<TransactionTrace>> {
-		let from_block = self.block_id(req.from_block)?;
+	async fn filter(self, req: FilterRequest) -> Result<FilterResponse> {
+		let deadline = Instant::now() + self.filter_timeout;
+		let mut from_block = self.block_id(req.from_block)?;
 		let to_block = self.block_id(req.to_block)?;
+
+		// A cursor continues from the block of its trace, which must still be canonical.
+		if let Some(cursor) = req.cursor {
+			if req.after.is_some() {
+				return Err(internal_err("'after' can't be used with a cursor"));
+			}
+
+			if let TraceCursor::Trace {
+				block_number,
+				block_hash,
+				..
+			} = cursor
+			{
+				if block_number > to_block || self.block_hash(block_number)? != block_hash {
+					return Err(internal_err(
+						"the cursor doesn't match the canonical chain anymore, the pagination \
+						must be restarted",
+					));
+				}
+				from_block = from_block.max(block_number);
+			}
+		}
+
 		let block_heights = from_block..=to_block;
 
 		let count = req.count.unwrap_or(self.max_count);
@@ -121,99 +362,243 @@ where
 			)));
 		}
 
-		// Build a list of all the Substrate block hashes that need to be traced.
-		let mut block_hashes = vec![];
-		for block_height in block_heights {
+		// Substrate block hashes of the range, and whether they are indexed.
+		let mut heights = vec![];
+		for block_height in block_heights.clone() {
 			if block_height == 0 {
 				continue; // no traces for genesis block.
 			}
 
-			let block_id = BlockId::<B>::Number(block_height);
-			let block_header = self
-				.client
-				.header(block_id)
-				.map_err(|e| {
-					internal_err(format!(
-						"Error when fetching block {} header : {:?}",
-						block_height, e
-					))
-				})?
-				.ok_or_else(|| {
-					internal_err(format!("Block with height {} don't exist", block_height))
-				})?;
+			let block_hash = self.block_hash(block_height)?;
+			let indexed = self.is_indexed(block_height, block_hash)?;
+			heights.push((block_height, block_hash, indexed));
+		}
 
-			let block_hash = block_header.hash();
+		// Indexed blocks in which the requested addresses appear, if any address is requested.
+		let indexed_candidates = match &self.trace_db {
+			Some(trace_db) => Self::indexed_candidates(trace_db, &req, &block_heights),
+			None => None,
+		};
 
-			block_hashes.push(block_hash);
+		// Build a list of all the Substrate block hashes that need to be traced, and of the ones
+		// already indexed. The index may have been reorganized while reading the candidates,
+		// which are only used for the blocks that are still indexed after it.
+		let mut blocks = vec![];
+		let mut not_indexed = vec![];
+		for (block_height, block_hash, indexed) in heights {
+			let indexed = indexed && self.is_indexed(block_height, block_hash)?;
+
+			if !indexed {
+				not_indexed.push(block_hash);
+				blocks.push((block_height, block_hash));
+			} else if indexed_candidates
+				.as_ref()
+				.map_or(true, |candidates| candidates.contains(&block_height))
+			{
+				blocks.push((block_height, block_hash));
+			}
 		}
 
-		// Start a batch with these blocks.
-		let batch_id = self.requester.start_batch(block_hashes.clone()).await?;
+		// Everything is indexed, no need to trace anything.
+		if not_indexed.is_empty() {
+			return self.fetch_traces(req, &blocks, count as usize).await;
+		}
+
+		// Start a batch with the blocks which are not indexed, all of them being traced before
+		// the deadline of the request.
+		let batch_id = self.requester.start_batch(not_indexed, deadline).await?;
 		// Fetch all the traces. It is done in another function to simplify error handling and allow
 		// to call the following `stop_batch` regardless of the result. This is important for the
 		// cache cleanup to work properly.
-		let res = self.fetch_traces(req, &block_hashes, count as usize).await;
+		let res = timeout_at(
+			deadline.into(),
+			self.fetch_traces(req, &blocks, count as usize),
+		)
+		.await
+		.unwrap_or_else(|_| Err(timeout_err()));
 		// Stop the batch, allowing the cache task to remove useless non-started block traces and
-		// start the expiration delay.
+		// start the expiration delay. On timeout it aborts the tracing of the remaining blocks.
 		self.requester.stop_batch(batch_id).await;
 
 		res
 	}
 
+	/// Whether this canonical block is the one indexed at its height.
+	fn is_indexed(&self, block_height: u32, block_hash: H256) -> Result<bool> {
+		match &self.trace_db {
+			Some(trace_db) => Ok(trace_db
+				.indexed_hash(block_height)
+				.map_err(internal_err)?
+				== Some(block_hash)),
+			None => Ok(false),
+		}
+	}
+
+	/// Indexed blocks of the range in which the requested addresses appear, or `None` if no
+	/// address is requested.
+	fn indexed_candidates(
+		trace_db: &TraceDb,
+		req: &FilterRequest,
+		block_heights: &RangeInclusive<u32>,
+	) -> Option<BTreeSet<u32>> {
+		let from_candidates = req
+			.from_address
+			.as_ref()
+			.filter(|addresses| !addresses.is_empty())
+			.map(|addresses| trace_db.blocks_with_addresses(addresses, false, block_heights));
+		let to_candidates = req
+			.to_address
+			.as_ref()
+			.filter(|addresses| !addresses.is_empty())
+			.map(|addresses| trace_db.blocks_with_addresses(addresses, true, block_heights));
+
+		match (from_candidates, to_candidates) {
+			(Some(from), Some(to)) => match req.mode.unwrap_or_default() {
+				FilterMode::Intersection => Some(from.intersection(&to).cloned().collect()),
+				FilterMode::Union => Some(from.union(&to).cloned().collect()),
+			},
+			(Some(candidates), None) | (None, Some(candidates)) => Some(candidates),
+			(None, None) => None,
+		}
+	}
+
+	/// `trace_replayTransaction` endpoint (wrapped in the trait implementation with futures
+	/// compatibilty)
+	async fn replay_transaction(
+		self,
+		transaction_hash: H256,
+		trace_types: Vec<RequestTraceType>,
+	) -> Result<TransactionReplay> {
+		self.requester
+			.replay(
+				ReplayTarget::Transaction(transaction_hash),
+				replay_trace_type(&trace_types),
+				self.deadline(),
+			)
+			.await?
+			.pop()
+			.ok_or_else(|| internal_err("Transaction hash not found".to_string()))
+	}
+
+	/// `trace_replayBlockTransactions` endpoint (wrapped in the trait implementation with
+	/// futures compatibilty)
+	async fn replay_block_transactions(
+		self,
+		block: RequestBlockId,
+		trace_types: Vec<RequestTraceType>,
+	) -> Result<Vec<TransactionReplay>> {
+		let target = match self.requested_block(Some(block))? {
+			RequestedBlock::Imported { number: 0, .. } => return Ok(vec![]), // no traces for genesis block.
+			RequestedBlock::Imported { hash, .. } => ReplayTarget::Block(hash),
+			RequestedBlock::Pending => ReplayTarget::PendingBlock,
+		};
+
+		self.requester
+			.replay(target, replay_trace_type(&trace_types), self.deadline())
+			.await
+	}
+
+	/// `trace_call` endpoint (wrapped in the trait implementation with futures compatibilty)
+	async fn call(
+		self,
+		call: CallRequest,
+		trace_types: Vec<RequestTraceType>,
+		block: Option<RequestBlockId>,
+		state_overrides: Option<BTreeMap<H160, StateOverride>>,
+	) -> Result<TransactionReplay> {
+		let block = self.requested_block(block)?;
+
+		self.requester
+			.replay_calls(
+				block,
+				vec![(call, replay_trace_type(&trace_types))],
+				state_overrides,
+				self.deadline(),
+			)
+			.await?
+			.pop()
+			.ok_or_else(|| internal_err("Call was not replayed".to_string()))
+	}
+
+	/// `trace_callMany` endpoint (wrapped in the trait implementation with futures
+	/// compatibilty)
+	async fn call_many(
+		self,
+		calls: Vec<(CallRequest, Vec<RequestTraceType>)>,
+		block: Option<RequestBlockId>,
+	) -> Result<Vec<TransactionReplay>> {
+		let block = self.requested_block(block)?;
+
+		let calls = calls
+			.into_iter()
+			.map(|(call, trace_types)| (call, replay_trace_type(&trace_types)))
+			.collect();
+
+		self.requester
+			.replay_calls(block, calls, None, self.deadline())
+			.await
+	}
+
 	async fn fetch_traces(
 		&self,
 		req: FilterRequest,
-		block_hashes: &[H256],
+		blocks: &[(u32, H256)],
 		count: usize,
-	) -> Result<Vec<TransactionTrace>> {
-		let from_address = req.from_address.unwrap_or_default();
-		let to_address = req.to_address.unwrap_or_default();
+	) -> Result<FilterResponse> {
+		let filter = TraceFilter::new(&req);
+		let paginated = req.cursor.is_some();
 
-		let mut traces_amount: i64 = -(req.after.unwrap_or(0) as i64);
+		let mut skip = req.after.unwrap_or(0) as usize;
 		let mut traces = vec![];
 
-		for &block_hash in block_hashes {
-			// Request the traces of this block to the cache service.
+		for &(block_number, block_hash) in blocks {
+			// Read the traces of an indexed block, or request them to the cache service.
 			// This will resolve quickly if the block is already cached, or wait until the block
 			// has finished tracing.
-			let block_traces = self.requester.get_traces(block_hash).await?;
+			let indexed_traces = match &self.trace_db {
+				Some(trace_db) => trace_db.traces(block_hash).map_err(internal_err)?,
+				None => None,
+			};
+			let block_traces = match indexed_traces {
+				Some(block_traces) => block_traces,
+				None => self.requester.get_traces(block_hash).await?,
+			};
 
-			// Filter addresses.
-			let mut block_traces: Vec<_> = block_traces
-				.iter()
-				.filter(|trace| match trace.action {
-					block::TransactionTraceAction::Call { from, to, .. } => {
-						(from_address.is_empty() || from_address.contains(&from))
-							&& (to_address.is_empty() || to_address.contains(&to))
-					}
-					block::TransactionTraceAction::Create { from, .. } => {
-						(from_address.is_empty() || from_address.contains(&from))
-							&& to_address.is_empty()
-					}
-					block::TransactionTraceAction::Suicide { address, .. } => {
-						(from_address.is_empty() || from_address.contains(&address))
-							&& to_address.is_empty()
-					}
-				})
-				.cloned()
-				.collect();
-
-			// Don't insert anything if we're still before "after"
-			traces_amount += block_traces.len() as i64;
-			if traces_amount > 0 {
-				let traces_amount = traces_amount as usize;
-				// If the current Vec of traces is across the "after" marker,
-				// we skip some elements of it.
-				if traces_amount < block_traces.len() {
-					let skip = block_traces.len() - traces_amount;
-					block_traces = block_traces.into_iter().skip(skip).collect();
+			// A cursor points into the traces of its block.
+			let first_index = match req.cursor {
+				Some(TraceCursor::Trace {
+					block_number: cursor_block,
+					trace_index,
+					..
+				}) if cursor_block == block_number => trace_index as usize,
+				_ => 0,
+			};
+
+			for (trace_index, trace) in block_traces.into_iter().enumerate().skip(first_index) {
+				if !filter.matches(&trace) {
+					continue;
 				}
 
-				traces.append(&mut block_traces);
+				// Don't insert anything if we're still before "after"
+				if skip > 0 {
+					skip -= 1;
+					continue;
+				}
+
+				// There are more traces than requested.
+				if traces.len() == count {
+					if paginated {
+						return Ok(FilterResponse::Page {
+							traces,
+							cursor: Some(TraceCursor::Trace {
+								block_number,
+								block_hash,
+								trace_index: trace_index as u32,
+							}),
+						});
+					}
 
-				// If we go over "count" (the limit), we trim and exit the loop,
-				// unless we used the default maximum, in which case we return an error.
-				if traces_amount >= count {
+					// Unless we used the default maximum, in which case we return an error.
 					if req.count.is_none() {
 						return Err(internal_err(format!(
 							"the amount of traces goes over the maximum ({}), please use 'after' \
@@ -222,13 +607,21 @@ where
 						)));
 					}
 
-					traces = traces.into_iter().take(count).collect();
-					break;
+					return Ok(FilterResponse::Traces(traces));
 				}
+
+				traces.push(trace);
 			}
 		}
 
-		Ok(traces)
+		Ok(if paginated {
+			FilterResponse::Page {
+				traces,
+				cursor: None,
+			}
+		} else {
+			FilterResponse::Traces(traces)
+		})
 	}
 }
 
@@ -242,12 +635,233 @@ where
 	fn filter(
 		&self,
 		filter: FilterRequest,
-	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<Vec<TransactionTrace>>>> {
+	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<FilterResponse>>> {
 		// Wraps the async function into futures compatibility layer.
 		self.clone().filter(filter).boxed().compat()
 	}
+
+	fn block(
+		&self,
+		block: RequestBlockId,
+	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<Vec<TransactionTrace>>>> {
+		self.clone().block(block).boxed().compat()
+	}
+
+	fn transaction(
+		&self,
+		transaction_hash: H256,
+	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<Option<Vec<TransactionTrace>>>>> {
+		self.clone().transaction(transaction_hash).boxed().compat()
+	}
+
+	fn get(
+		&self,
+		transaction_hash: H256,
+		indices: Vec<TraceIndex>,
+	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<Option<TransactionTrace>>>> {
+		self.clone().get(transaction_hash, indices).boxed().compat()
+	}
+
+	fn replay_transaction(
+		&self,
+		transaction_hash: H256,
+		trace_types: Vec<RequestTraceType>,
+	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<TransactionReplay>>> {
+		self.clone()
+			.replay_transaction(transaction_hash, trace_types)
+			.boxed()
+			.compat()
+	}
+
+	fn replay_block_transactions(
+		&self,
+		block: RequestBlockId,
+		trace_types: Vec<RequestTraceType>,
+	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<Vec<TransactionReplay>>>> {
+		self.clone()
+			.replay_block_transactions(block, trace_types)
+			.boxed()
+			.compat()
+	}
+
+	fn call(
+		&self,
+		call: CallRequest,
+		trace_types: Vec<RequestTraceType>,
+		block: Option<RequestBlockId>,
+		state_overrides: Option<BTreeMap<H160, StateOverride>>,
+	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<TransactionReplay>>> {
+		self.clone()
+			.call(call, trace_types, block, state_overrides)
+			.boxed()
+			.compat()
+	}
+
+	fn call_many(
+		&self,
+		calls: Vec<(CallRequest, Vec<RequestTraceType>)>,
+		block: Option<RequestBlockId>,
+	) -> Compat<BoxFuture<'static, jsonrpc_core::Result<Vec<TransactionReplay>>>> {
+		self.clone().call_many(calls, block).boxed().compat()
+	}
+}
+
+/// Matching of traces against the filters of a `trace_filter` request.
+struct TraceFilter {
+	from_address: Vec<H160>,
+	to_address: Vec<H160>,
+	mode: FilterMode,
+	call_type: Option<Vec<FilterCallType>>,
+	errors_only: bool,
+}
+
+impl TraceFilter {
+	fn new(req: &FilterRequest) -> Self {
+		Self {
+			from_address: req.from_address.clone().unwrap_or_default(),
+			to_address: req.to_address.clone().unwrap_or_default(),
+			mode: req.mode.unwrap_or_default(),
+			call_type: req.call_type.clone(),
+			errors_only: req.errors_only.unwrap_or(false),
+		}
+	}
+
+	fn matches(&self, trace: &TransactionTrace) -> bool {
+		// The recipient of a creation is the created contract, and the one of a suicide is the
+		// refund address.
+		let (from, to, call_type) = match trace.action {
+			block::TransactionTraceAction::Call {
+				from, to, call_type, ..
+			} => (
+				from,
+				Some(to),
+				match call_type {
+					CallType::Call => FilterCallType::Call,
+					CallType::CallCode => FilterCallType::CallCode,
+					CallType::DelegateCall => FilterCallType::DelegateCall,
+					CallType::StaticCall => FilterCallType::StaticCall,
+				},
+			),
+			block::TransactionTraceAction::Create { from, .. } => (
+				from,
+				match trace.output {
+					block::TransactionTraceOutput::Result {
+						result: block::TransactionTraceResult::Create { address, .. },
+					} => Some(address),
+					_ => None,
+				},
+				FilterCallType::Create,
+			),
+			block::TransactionTraceAction::Suicide {
+				address,
+				refund_address,
+				..
+			} => (address, Some(refund_address), FilterCallType::Suicide),
+		};
+
+		if let Some(call_types) = &self.call_type {
+			if !call_types.contains(&call_type) {
+				return false;
+			}
+		}
+
+		if self.errors_only
+			&& !matches!(trace.output, block::TransactionTraceOutput::Error { .. })
+		{
+			return false;
+		}
+
+		let from_matches = self.from_address.contains(&from);
+		let to_matches = to.map_or(false, |to| self.to_address.contains(&to));
+
+		// An empty list of addresses matches any address.
+		match (self.from_address.is_empty(), self.to_address.is_empty()) {
+			(true, true) => true,
+			(false, true) => from_matches,
+			(true, false) => to_matches,
+			(false, false) => match self.mode {
+				FilterMode::Intersection => from_matches && to_matches,
+				FilterMode::Union => from_matches || to_matches,
+			},
+		}
+	}
+}
+
+fn replay_trace_type(trace_types: &[RequestTraceType]) -> replay::ReplayTraceType {
+	replay::ReplayTraceType {
+		trace: trace_types.contains(&RequestTraceType::Trace),
+		state_diff: trace_types.contains(&RequestTraceType::StateDiff),
+		vm_trace: trace_types.contains(&RequestTraceType::VmTrace),
+	}
+}
+
+/// Replays built from the events of several transactions, in the order they were executed.
+fn into_replays(tracer: BlockTracer<ReplayTracer>) -> Vec<TransactionReplay> {
+	tracer
+		.into_transactions()
+		.into_iter()
+		.map(|(_, tracer)| tracer.into_replay())
+		.collect()
+}
+
+/// Convert an error of `DebugApi`, timeouts, pruned states and unsupported runtimes being
+/// reported with their own error code.
+fn trace_err(
+	e: TraceError,
+	state_archive: bool,
+	context: impl FnOnce() -> String,
+) -> jsonrpc_core::Error {
+	match e {
+		TraceError::TimedOut => timeout_err(),
+		TraceError::Api(e) => internal_err(format!("{} : {}", context(), e)),
+		TraceError::StatePruned(block) => TraceRpcError::StatePruned {
+			block,
+			archive: state_archive,
+		}
+		.into(),
+		TraceError::Unsupported(e) => TraceRpcError::RuntimeApiUnsupported(e).into(),
+	}
+}
+
+/// Reformat error messages in the format of OpenEthereum.
+fn reformat_trace_error(trace: &mut TransactionTrace) {
+	if let block::TransactionTraceOutput::Error { ref mut error, .. } = trace.output {
+		if error.as_slice() == b"execution reverted" {
+			*error = b"Reverted".to_vec();
+		}
+	}
+}
+
+/// Reformat error messages of replays in the format of OpenEthereum.
+fn reformat_replay_errors(replays: &mut [TransactionReplay]) {
+	for trace in replays.iter_mut().flat_map(|replay| replay.trace.iter_mut()) {
+		reformat_trace_error(trace);
+	}
 }
 
+/// Block designated by a request.
+#[derive(Copy, Clone, Debug)]
+enum RequestedBlock {
+	/// An imported block, from its height and Substrate hash.
+	Imported { number: u32, hash: H256 },
+	/// The pending block, made of the ready transactions of the pool on top of the best block.
+	Pending,
+}
+
+/// Transactions to replay.
+#[derive(Copy, Clone, Debug)]
+enum ReplayTarget {
+	/// A single transaction, from its Ethereum hash.
+	Transaction(H256),
+	/// All the transactions of a block, from its Substrate hash.
+	Block(H256),
+	/// All the transactions of the pending block.
+	PendingBlock,
+}
+
+/// Provides the ready extrinsics of the transaction pool, which form the pending block.
+type PendingExtrinsics<B> = Arc<dyn Fn() -> Vec<<B as BlockT>::Extrinsic> + Send + Sync>;
+
 /// An opaque batch ID.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct CacheBatchId(u64);
@@ -261,6 +875,8 @@ enum CacheRequest {
 		sender: oneshot::Sender<CacheBatchId>,
 		/// List of block hash to trace.
 		blocks: Vec<H256>,
+		/// Time after which the tracing of the blocks is aborted.
+		deadline: Instant,
 	},
 	/// Fetch the traces for given block hash.
 	/// The task will answer only when it has processed this block.
@@ -273,6 +889,35 @@ enum CacheRequest {
 	/// Notify the cache that it can stop the batch with that ID. Any block contained only in
 	/// this batch and still not started will be discarded.
 	StopBatch { batch_id: CacheBatchId },
+	/// Replay transactions with the requested outputs.
+	/// The task will answer once it got a permit and the replay is finished.
+	Replay {
+		/// Returns the array of replays or an error.
+		sender: oneshot::Sender<Result<Vec<TransactionReplay>>>,
+		target: ReplayTarget,
+		trace_type: replay::ReplayTraceType,
+		/// Time after which the replay is aborted.
+		deadline: Instant,
+	},
+	/// Replay unsigned calls on top of the state of a block.
+	/// The task will answer once it got a permit and the replay is finished.
+	ReplayCalls {
+		/// Returns the array of replays or an error.
+		sender: oneshot::Sender<Result<Vec<TransactionReplay>>>,
+		block: RequestedBlock,
+		calls: Vec<(CallRequest, replay::ReplayTraceType)>,
+		state_overrides: Option<BTreeMap<H160, StateOverride>>,
+		/// Time after which the replay is aborted.
+		deadline: Instant,
+	},
+	/// Trace the pending block, which is never cached as it changes with the pool.
+	/// The task will answer once it got a permit and the tracing is finished.
+	PendingTraces {
+		/// Returns the array of traces or an error.
+		sender: oneshot::Sender<Result<Vec<TransactionTrace>>>,
+		/// Time after which the tracing is aborted.
+		deadline: Instant,
+	},
 }
 
 /// Allows to interact with the cache task.
@@ -280,10 +925,11 @@ enum CacheRequest {
 pub struct CacheRequester(TracingUnboundedSender<CacheRequest>);
 
 impl CacheRequester {
-	/// Request to start caching the provided range of blocks.
+	/// Request to start caching the provided range of blocks, whose tracing is aborted once
+	/// the deadline of the request is reached.
 	/// The task will add to blocks to its pool and immediately return the batch ID.
 	#[instrument(skip(self))]
-	pub async fn start_batch(&self, blocks: Vec<H256>) -> Result<CacheBatchId> {
+	pub async fn start_batch(&self, blocks: Vec<H256>, deadline: Instant) -> Result<CacheBatchId> {
 		let (response_tx, response_rx) = oneshot::channel();
 		let mut sender = self.0.clone();
 
@@ -291,6 +937,7 @@ impl CacheRequester {
 			.send(CacheRequest::StartBatch {
 				sender: response_tx,
 				blocks,
+				deadline,
 			})
 			.await
 			.map_err(|e| {
@@ -338,7 +985,115 @@ impl CacheRequester {
 					e
 				))
 			})?
-			.map_err(|e| internal_err(format!("Failed to replay block. Error : {:?}", e)))
+			.map_err(|e| match e.code {
+				// Timeouts and missing data keep their own code.
+				ErrorCode::ServerError(_) => e,
+				_ => internal_err(format!("Failed to replay block. Error : {:?}", e)),
+			})
+	}
+
+	/// Replay transactions with the requested outputs.
+	/// The task will answer once it got a permit and the replay is finished, or with a timeout
+	/// error once the deadline is reached.
+	#[instrument(skip(self))]
+	async fn replay(
+		&self,
+		target: ReplayTarget,
+		trace_type: replay::ReplayTraceType,
+		deadline: Instant,
+	) -> Result<Vec<TransactionReplay>> {
+		let (response_tx, response_rx) = oneshot::channel();
+		let mut sender = self.0.clone();
+
+		sender
+			.send(CacheRequest::Replay {
+				sender: response_tx,
+				target,
+				trace_type,
+				deadline,
+			})
+			.await
+			.map_err(|e| {
+				internal_err(format!(
+					"Failed to send request to the trace cache task. Error : {:?}",
+					e
+				))
+			})?;
+
+		response_rx.await.map_err(|e| {
+			internal_err(format!(
+				"Trace cache task closed the response channel. Error : {:?}",
+				e
+			))
+		})?
+	}
+
+	/// Replay unsigned calls on top of the state of a block, each call seeing the changes of the
+	/// previous ones.
+	/// The task will answer once it got a permit and the replay is finished, or with a timeout
+	/// error once the deadline is reached.
+	#[instrument(skip(self, calls, state_overrides))]
+	async fn replay_calls(
+		&self,
+		block: RequestedBlock,
+		calls: Vec<(CallRequest, replay::ReplayTraceType)>,
+		state_overrides: Option<BTreeMap<H160, StateOverride>>,
+		deadline: Instant,
+	) -> Result<Vec<TransactionReplay>> {
+		let (response_tx, response_rx) = oneshot::channel();
+		let mut sender = self.0.clone();
+
+		sender
+			.send(CacheRequest::ReplayCalls {
+				sender: response_tx,
+				block,
+				calls,
+				state_overrides,
+				deadline,
+			})
+			.await
+			.map_err(|e| {
+				internal_err(format!(
+					"Failed to send request to the trace cache task. Error : {:?}",
+					e
+				))
+			})?;
+
+		response_rx.await.map_err(|e| {
+			internal_err(format!(
+				"Trace cache task closed the response channel. Error : {:?}",
+				e
+			))
+		})?
+	}
+
+	/// Trace the pending block.
+	/// The task will answer once it got a permit and the tracing is finished, or with a timeout
+	/// error once the deadline is reached.
+	#[instrument(skip(self))]
+	async fn pending_traces(&self, deadline: Instant) -> Result<Vec<TransactionTrace>> {
+		let (response_tx, response_rx) = oneshot::channel();
+		let mut sender = self.0.clone();
+
+		sender
+			.send(CacheRequest::PendingTraces {
+				sender: response_tx,
+				deadline,
+			})
+			.await
+			.map_err(|e| {
+				internal_err(format!(
+					"Failed to send request to the trace cache task. Error : {:?}",
+					e
+				))
+			})?;
+
+		response_rx.await.map_err(|e| {
+			internal_err(format!(
+				"Trace cache task closed the response channel. Error : {:?}",
+				e
+			))
+		})?
 	}
 
 	/// Notify the cache that it can stop the batch with that ID. Any block contained only in
@@ -367,33 +1122,214 @@ impl CacheRequester {
 /// decrease only after the batch ends and its expiration delay passes.
 /// It allows to keep the data in the cache for following requests that would use
 /// this block, which is important to handle pagination efficiently.
+/// `running_batch_count` is the number of batches using this block which are not
+/// stopped yet. A block not used by any running batch can be evicted before the expiration
+/// of its batches when the cache goes over its size limit, least recently used first.
 struct CacheBlock {
 	active_batch_count: usize,
+	running_batch_count: usize,
+	/// Value of `CacheTask::usage_tick` when the block was last requested.
+	last_used: u64,
 	state: CacheBlockState,
 }
 
-/// State of a cached block. It can either be polled to be traced or cached.
-enum CacheBlockState {
-	/// Block has been added to the pool blocks to be replayed.
-	/// It may be currently waiting to be replayed or being replayed.
-	Pooled {
-		started: bool,
-		/// Multiple requests might query the same block while it is pooled to be
-		/// traced. They response channel is stored here, and the result will be
-		/// sent in all of them when the tracing is finished.
-		waiting_requests: Vec<oneshot::Sender<Result<Vec<TransactionTrace>>>>,
-		/// Channel used to unqueue a tracing that has not yet started.
-		/// A tracing will be unqueued if it has not yet been started and the last batch
-		/// needing this block is ended (ignoring the expiration delay).
-		/// It is not used directly, but dropping will wake up the receiver.
-		#[allow(dead_code)]
-		unqueue_sender: oneshot::Sender<()>,
-	},
-	/// Tracing has completed and the result is available. No Runtime API call
-	/// will be needed until this block cache is removed.
-	Cached {
-		traces: Result<Vec<TransactionTrace>>,
-	},
+/// State of a cached block. It can either be polled to be traced or cached.
+enum CacheBlockState {
+	/// Block has been added to the pool blocks to be replayed.
+	/// It may be currently waiting to be replayed or being replayed.
+	Pooled {
+		started: bool,
+		/// Latest deadline of the batches using this block. If the tracing times out before it,
+		/// the block is pooled again for the batches which can still wait for it.
+		deadline: Instant,
+		/// Multiple requests might query the same block while it is pooled to be
+		/// traced. They response channel is stored here, and the result will be
+		/// sent in all of them when the tracing is finished.
+		waiting_requests: Vec<oneshot::Sender<Result<Vec<TransactionTrace>>>>,
+		/// Channel used to unqueue a tracing that has not yet started.
+		/// A tracing will be unqueued if it has not yet been started and the last batch
+		/// needing this block is ended (ignoring the expiration delay).
+		/// It is not used directly, but dropping will wake up the receiver.
+		#[allow(dead_code)]
+		unqueue_sender: oneshot::Sender<()>,
+	},
+	/// Tracing has completed and the result is available. No Runtime API call
+	/// will be needed until this block cache is removed.
+	Cached {
+		traces: Result<Vec<TransactionTrace>>,
+		/// Approximate size of the traces in memory.
+		size: usize,
+	},
+}
+
+/// Blocks of the cache and the batches using them, the traced blocks being evicted when they
+/// use too much memory.
+struct BlockCache {
+	blocks: BTreeMap<H256, CacheBlock>,
+	batches: BTreeMap<u64, Vec<H256>>,
+	/// Approximate size of the cached traces.
+	bytes: usize,
+	/// Size above which blocks not used by a running batch are evicted.
+	max_bytes: usize,
+}
+
+impl BlockCache {
+	fn new(max_bytes: usize) -> Self {
+		Self {
+			blocks: BTreeMap::new(),
+			batches: BTreeMap::new(),
+			bytes: 0,
+			max_bytes,
+		}
+	}
+
+	/// Remove a block from the cache, keeping track of the size of the cached traces.
+	fn remove_block(&mut self, block: &H256) {
+		if let Some(CacheBlock {
+			state: CacheBlockState::Cached { size, .. },
+			..
+		}) = self.blocks.remove(block)
+		{
+			self.bytes -= size;
+		}
+	}
+
+	/// Evict the least recently used blocks which are not used by a running batch, until the
+	/// cache size is below its limit. Blocks of running batches are never evicted, so the limit
+	/// can be temporarily exceeded by large requests.
+	fn evict(&mut self) {
+		if self.bytes <= self.max_bytes {
+			return;
+		}
+
+		let mut evictable: Vec<_> = self
+			.blocks
+			.iter()
+			.filter(|(_, block_cache)| {
+				block_cache.running_batch_count == 0
+					&& matches!(block_cache.state, CacheBlockState::Cached { .. })
+			})
+			.map(|(block, block_cache)| (block_cache.last_used, *block))
+			.collect();
+		evictable.sort_unstable();
+
+		let mut evicted = BTreeSet::new();
+		for (_, block) in evictable {
+			if self.bytes <= self.max_bytes {
+				break;
+			}
+
+			tracing::trace!("Evicting block {} from the cache.", block);
+			self.remove_block(&block);
+			evicted.insert(block);
+		}
+
+		// The expiration of the batches using the evicted blocks must not affect them if they
+		// are requested again.
+		if !evicted.is_empty() {
+			for blocks in self.batches.values_mut() {
+				blocks.retain(|block| !evicted.contains(block));
+			}
+		}
+	}
+}
+
+/// Approximate size in memory of traces, which their SCALE encoding understates: each trace
+/// takes the size of its type, and its buffers their whole capacity.
+fn traces_size(traces: &[TransactionTrace]) -> usize {
+	traces
+		.iter()
+		.map(|trace| {
+			let action = match &trace.action {
+				block::TransactionTraceAction::Call { input, .. } => input.capacity(),
+				block::TransactionTraceAction::Create { init, .. } => init.capacity(),
+				block::TransactionTraceAction::Suicide { .. } => 0,
+			};
+			let output = match &trace.output {
+				block::TransactionTraceOutput::Result {
+					result: block::TransactionTraceResult::Call { output, .. },
+				} => output.capacity(),
+				block::TransactionTraceOutput::Result {
+					result: block::TransactionTraceResult::Create { code, .. },
+				} => code.capacity(),
+				block::TransactionTraceOutput::Result {
+					result: block::TransactionTraceResult::Suicide,
+				} => 0,
+				block::TransactionTraceOutput::Error {
+					error,
+					revert_reason,
+					output,
+				} => {
+					error.capacity()
+						+ revert_reason.as_ref().map_or(0, Vec::capacity)
+						+ output.capacity()
+				}
+			};
+			let logs: usize = trace
+				.logs
+				.iter()
+				.map(|log| log.topics.capacity() * mem::size_of::<H256>() + log.data.capacity())
+				.sum();
+
+			mem::size_of::<TransactionTrace>()
+				+ action
+				+ output
+				+ trace.trace_address.capacity() * mem::size_of::<u32>()
+				+ trace.logs.capacity() * mem::size_of::<Log>()
+				+ logs
+		})
+		.sum()
+}
+
+/// Prometheus metrics of the cache task.
+struct CacheMetrics {
+	cached_bytes: Gauge<U64>,
+	cached_blocks: Gauge<U64>,
+	hits: Counter<U64>,
+	misses: Counter<U64>,
+	tracing_blocks: Gauge<U64>,
+}
+
+impl CacheMetrics {
+	fn register(registry: &Registry) -> std::result::Result<Self, PrometheusError> {
+		Ok(Self {
+			cached_bytes: register(
+				Gauge::new(
+					"trace_filter_cache_bytes",
+					"Approximate size of the traces in the trace_filter cache",
+				)?,
+				registry,
+			)?,
+			cached_blocks: register(
+				Gauge::new(
+					"trace_filter_cache_blocks",
+					"Number of blocks in the trace_filter cache, traced or pooled",
+				)?,
+				registry,
+			)?,
+			hits: register(
+				Counter::new(
+					"trace_filter_cache_hits",
+					"Number of blocks requested by a trace_filter batch found in the cache",
+				)?,
+				registry,
+			)?,
+			misses: register(
+				Counter::new(
+					"trace_filter_cache_misses",
+					"Number of blocks requested by a trace_filter batch missing from the cache",
+				)?,
+				registry,
+			)?,
+			tracing_blocks: register(
+				Gauge::new(
+					"trace_filter_cache_tracing_blocks",
+					"Number of blocks currently being traced for the trace_filter cache",
+				)?,
+				registry,
+			)?,
+		})
+	}
 }
 
 /// Tracing a block is done in a separate tokio blocking task to avoid clogging the async threads.
@@ -412,20 +1348,29 @@ enum BlockingTaskMessage {
 }
 
 /// Type wrapper for the cache task, generic over the Client, Block and Backend types.
-pub struct CacheTask<B, C, BE> {
+pub struct CacheTask<B: BlockT, C, BE> {
 	client: Arc<C>,
 	backend: Arc<BE>,
+	frontier_backend: Arc<fc_db::Backend<B>>,
+	pending_extrinsics: PendingExtrinsics<B>,
+	/// Tracing runtimes replacing the on-chain runtimes of imported blocks, if any.
+	tracing_runtimes: Option<Arc<TracingRuntimes<B, BE>>>,
 	blocking_permits: Arc<Semaphore>,
-	cached_blocks: BTreeMap<H256, CacheBlock>,
-	batches: BTreeMap<u64, Vec<H256>>,
+	/// Whether the node keeps the state of all blocks.
+	state_archive: bool,
+	cache: BlockCache,
 	next_batch_id: u64,
+	/// Incremented each time blocks are requested, to find the least recently used ones.
+	usage_tick: u64,
+	tracing_blocks: u64,
+	metrics: Option<CacheMetrics>,
 	_phantom: PhantomData<B>,
 }
 
 impl<B, C, BE> CacheTask<B, C, BE>
 where
 	BE: Backend<B> + 'static,
-	C: ProvideRuntimeApi<B>,
+	C: ProvideRuntimeApi<B> + CallApiAt<B>,
 	C: HeaderMetadata<B, Error = BlockChainError> + HeaderBackend<B>,
 	C: Send + Sync + 'static,
 	B: BlockT<Hash = H256> + Send + Sync + 'static,
@@ -433,17 +1378,38 @@ where
 	C::Api: BlockBuilder<B>,
 	C::Api: DebugRuntimeApi<B>,
 	C::Api: EthereumRuntimeRPCApi<B>,
+	C::Api: TxPoolRuntimeApi<B>,
 {
 	/// Create a new cache task.
 	///
 	/// Returns a Future that needs to be added to a tokio executor, and an handle allowing to
 	/// send requests to the task.
-	pub fn create(
+	pub fn create<A: ChainApi<Block = B> + 'static>(
 		client: Arc<C>,
 		backend: Arc<BE>,
+		frontier_backend: Arc<fc_db::Backend<B>>,
+		graph: Arc<Pool<A>>,
+		tracing_runtimes: Option<Arc<TracingRuntimes<B, BE>>>,
 		cache_duration: Duration,
+		max_cached_bytes: usize,
+		state_archive: bool,
 		blocking_permits: Arc<Semaphore>,
+		prometheus_registry: Option<Registry>,
 	) -> (impl Future<Output = ()>, CacheRequester) {
+		let metrics = prometheus_registry.and_then(|registry| {
+			CacheMetrics::register(&registry)
+				.map_err(|e| tracing::warn!("Failed to register trace_filter cache metrics : {}", e))
+				.ok()
+		});
+
+		let pending_extrinsics: PendingExtrinsics<B> = Arc::new(move || {
+			graph
+				.validated_pool()
+				.ready()
+				.map(|in_pool_tx| in_pool_tx.data().clone())
+				.collect()
+		});
+
 		// Communication with the outside world :
 		let (requester_tx, mut requester_rx) =
 			sp_utils::mpsc::tracing_unbounded("trace-filter-cache");
@@ -463,10 +1429,16 @@ where
 			let mut inner = Self {
 				client,
 				backend,
+				frontier_backend,
+				pending_extrinsics,
+				tracing_runtimes,
 				blocking_permits,
-				cached_blocks: BTreeMap::new(),
-				batches: BTreeMap::new(),
+				state_archive,
+				cache: BlockCache::new(max_cached_bytes),
 				next_batch_id: 0,
+				usage_tick: 0,
+				tracing_blocks: 0,
+				metrics,
 				_phantom: Default::default(),
 			};
 
@@ -477,8 +1449,8 @@ where
 					request = requester_rx.next() => {
 						match request {
 							None => break,
-							Some(CacheRequest::StartBatch {sender, blocks})
-								=> inner.request_start_batch(&blocking_tx, sender, blocks),
+							Some(CacheRequest::StartBatch {sender, blocks, deadline}) => inner
+								.request_start_batch(&blocking_tx, sender, blocks, deadline),
 							Some(CacheRequest::GetTraces {sender, block})
 								=> inner.request_get_traces(sender, block),
 							Some(CacheRequest::StopBatch {batch_id}) => {
@@ -491,6 +1463,23 @@ where
 
 								inner.request_stop_batch(batch_id);
 							},
+							Some(CacheRequest::Replay {sender, target, trace_type, deadline})
+								=> inner.request_replay(sender, target, trace_type, deadline),
+							Some(CacheRequest::ReplayCalls {
+								sender,
+								block,
+								calls,
+								state_overrides,
+								deadline,
+							}) => inner.request_replay_calls(
+								sender,
+								block,
+								calls,
+								state_overrides,
+								deadline,
+							),
+							Some(CacheRequest::PendingTraces {sender, deadline})
+								=> inner.request_pending_traces(sender, deadline),
 						}
 					},
 					message = blocking_rx.next() => {
@@ -499,7 +1488,7 @@ where
 							Some(BlockingTaskMessage::Started { block_hash })
 								=> inner.blocking_started(block_hash),
 							Some(BlockingTaskMessage::Finished { block_hash, result })
-								=> inner.blocking_finished(block_hash, result),
+								=> inner.blocking_finished(&blocking_tx, block_hash, result),
 						}
 					},
 					batch_id = batch_expirations.next() => {
@@ -517,21 +1506,37 @@ where
 	}
 
 	/// Handle the creation of a batch.
-	/// Will start the tracing process for blocks that are not already in the cache.
+	/// Will start the tracing process for blocks that are not already in the cache, aborting it
+	/// at the deadline of the batch. A block also requested by later batches while it is pooled
+	/// keeps the latest of their deadlines.
 	#[instrument(skip(self, blocking_tx, sender, blocks))]
 	fn request_start_batch(
 		&mut self,
 		blocking_tx: &mpsc::Sender<BlockingTaskMessage>,
 		sender: oneshot::Sender<CacheBatchId>,
 		blocks: Vec<H256>,
+		deadline: Instant,
 	) {
 		tracing::trace!("Starting batch {}", self.next_batch_id);
-		self.batches.insert(self.next_batch_id, blocks.clone());
+		self.cache.batches.insert(self.next_batch_id, blocks.clone());
+		self.usage_tick += 1;
 
 		for block in blocks {
 			// The block is already in the cache, awesome !
-			if let Some(block_cache) = self.cached_blocks.get_mut(&block) {
+			if let Some(block_cache) = self.cache.blocks.get_mut(&block) {
 				block_cache.active_batch_count += 1;
+				block_cache.running_batch_count += 1;
+				block_cache.last_used = self.usage_tick;
+				if let CacheBlockState::Pooled {
+					deadline: ref mut pooled_deadline,
+					..
+				} = block_cache.state
+				{
+					*pooled_deadline = std::cmp::max(*pooled_deadline, deadline);
+				}
+				if let Some(metrics) = &self.metrics {
+					metrics.hits.inc();
+				}
 				tracing::trace!(
 					"Cache hit for block {}, now used by {} batches.",
 					block,
@@ -541,71 +1546,22 @@ where
 			// Otherwise we need to queue this block for tracing.
 			else {
 				tracing::trace!("Cache miss for block {}, pooling it for tracing.", block);
+				if let Some(metrics) = &self.metrics {
+					metrics.misses.inc();
+				}
 
-				let blocking_permits = Arc::clone(&self.blocking_permits);
-				let (unqueue_sender, unqueue_receiver) = oneshot::channel();
-				let client = Arc::clone(&self.client);
-				let backend = Arc::clone(&self.backend);
-				let mut blocking_tx = blocking_tx.clone();
-
-				// Spawn all block caching asynchronously.
-				// It will wait to obtain a permit, then spawn a blocking task.
-				// When the blocking task returns its result, it is send
-				// thought a channel to the main task loop.
-				tokio::spawn(
-					async move {
-						tracing::trace!("Waiting for blocking permit or task cancellation");
-						let _permit = select!(
-							_ = unqueue_receiver.fuse() => {
-							tracing::trace!("Tracing of the block has been cancelled.");
-								return;
-							},
-							permit = blocking_permits.acquire().fuse() => permit,
-						);
-
-						// Warn the main task that block tracing as started, and
-						// this block cache entry should not be removed.
-						let _ = blocking_tx
-							.send(BlockingTaskMessage::Started { block_hash: block })
-							.await;
-
-						tracing::trace!("Start block tracing in a blocking task.");
-
-						// Perform block tracing in a tokio blocking task.
-						let result = async {
-							tokio::task::spawn_blocking(move || {
-								Self::cache_block(client, backend, block)
-							})
-							.await
-							.map_err(|e| {
-								internal_err(format!(
-									"Tracing Substrate block {} panicked : {:?}",
-									block, e
-								))
-							})?
-						}
-						.await;
-
-						tracing::trace!("Block tracing finished, sending result to main task.");
-
-						// Send response to main task.
-						let _ = blocking_tx
-							.send(BlockingTaskMessage::Finished {
-								block_hash: block,
-								result,
-							})
-							.await;
-					}
-					.instrument(tracing::trace_span!("Block tracing", block = %block)),
-				);
+				let unqueue_sender = self.pool_block(blocking_tx, block, deadline);
 
 				// Insert the block in the cache.
-				self.cached_blocks.insert(
+				self.cache.blocks.insert(
 					block,
 					CacheBlock {
 						active_batch_count: 1,
+						running_batch_count: 1,
+						last_used: self.usage_tick,
 						state: CacheBlockState::Pooled {
 							started: false,
+							deadline,
 							waiting_requests: vec![],
 							unqueue_sender,
 						},
@@ -614,6 +1570,8 @@ where
 			}
 		}
 
+		self.update_metrics();
+
 		// Respond with the batch ID.
 		let _ = sender.send(CacheBatchId(self.next_batch_id));
 
@@ -621,6 +1579,89 @@ where
 		self.next_batch_id = self.next_batch_id.overflowing_add(1).0;
 	}
 
+	/// Spawn the tracing of a block, which waits for a permit then traces the block in a
+	/// blocking task until the deadline. Returns the sender unqueuing the tracing when dropped
+	/// before it starts.
+	fn pool_block(
+		&self,
+		blocking_tx: &mpsc::Sender<BlockingTaskMessage>,
+		block: H256,
+		deadline: Instant,
+	) -> oneshot::Sender<()> {
+		let blocking_permits = Arc::clone(&self.blocking_permits);
+		let (unqueue_sender, unqueue_receiver) = oneshot::channel();
+		let client = Arc::clone(&self.client);
+		let backend = Arc::clone(&self.backend);
+		let tracing_runtimes = self.tracing_runtimes.clone();
+		let state_archive = self.state_archive;
+		let mut blocking_tx = blocking_tx.clone();
+
+		// Spawn all block caching asynchronously.
+		// It will wait to obtain a permit, then spawn a blocking task.
+		// When the blocking task returns its result, it is send
+		// thought a channel to the main task loop.
+		tokio::spawn(
+			async move {
+				tracing::trace!("Waiting for blocking permit or task cancellation");
+				let permit = select!(
+					_ = unqueue_receiver.fuse() => {
+					tracing::trace!("Tracing of the block has been cancelled.");
+						return;
+					},
+					permit = blocking_permits.acquire_owned().fuse() => permit,
+				);
+
+				// Warn the main task that block tracing as started, and
+				// this block cache entry should not be removed.
+				let _ = blocking_tx
+					.send(BlockingTaskMessage::Started { block_hash: block })
+					.await;
+
+				tracing::trace!("Start block tracing in a blocking task.");
+
+				// Perform block tracing in a tokio blocking task, which keeps the permit
+				// until it finishes. Its result is dropped if it is still running at the
+				// deadline, the runtime only being told to abort when it sends an event.
+				let result = async {
+					let blocking = tokio::task::spawn_blocking(move || {
+						let _permit = permit;
+						Self::cache_block(
+							client,
+							backend,
+							tracing_runtimes.as_deref(),
+							block,
+							Some(deadline),
+							state_archive,
+						)
+					});
+					timeout_at(deadline.into(), blocking)
+						.await
+						.map_err(|_| timeout_err())?
+						.map_err(|e| {
+							internal_err(format!(
+								"Tracing Substrate block {} panicked : {:?}",
+								block, e
+							))
+						})?
+				}
+				.await;
+
+				tracing::trace!("Block tracing finished, sending result to main task.");
+
+				// Send response to main task.
+				let _ = blocking_tx
+					.send(BlockingTaskMessage::Finished {
+						block_hash: block,
+						result,
+					})
+					.await;
+			}
+			.instrument(tracing::trace_span!("Block tracing", block = %block)),
+		);
+
+		unqueue_sender
+	}
+
 	/// Handle a request to get the traces of the provided block.
 	/// - If the result is stored in the cache, it sends it immediatly.
 	/// - If the block is currently being pooled, it is added in this block cache waiting list,
@@ -634,7 +1675,10 @@ where
 		sender: oneshot::Sender<Result<Vec<TransactionTrace>>>,
 		block: H256,
 	) {
-		if let Some(block_cache) = self.cached_blocks.get_mut(&block) {
+		if let Some(block_cache) = self.cache.blocks.get_mut(&block) {
+			self.usage_tick += 1;
+			block_cache.last_used = self.usage_tick;
+
 			match &mut block_cache.state {
 				CacheBlockState::Pooled {
 					ref mut waiting_requests,
@@ -676,13 +1720,16 @@ where
 	#[instrument(skip(self))]
 	fn request_stop_batch(&mut self, batch_id: CacheBatchId) {
 		tracing::trace!("Stopping batch {}", batch_id.0);
-		if let Some(blocks) = self.batches.get(&batch_id.0) {
+		if let Some(blocks) = self.cache.batches.get(&batch_id.0) {
 			for block in blocks {
 				let mut remove = false;
 
 				// We remove early the block cache if this batch is the last
 				// pooling this block.
-				if let Some(block_cache) = self.cached_blocks.get_mut(block) {
+				if let Some(block_cache) = self.cache.blocks.get_mut(block) {
+					block_cache.running_batch_count =
+						block_cache.running_batch_count.saturating_sub(1);
+
 					if block_cache.active_batch_count == 1
 						&& matches!(
 							block_cache.state,
@@ -696,17 +1743,204 @@ where
 					tracing::trace!("Pooled block {} is no longer requested.", block);
 					// Remove block from the cache. Drops the value,
 					// closing all the channels contained in it.
-					let _ = self.cached_blocks.remove(&block);
+					let _ = self.cache.blocks.remove(&block);
 				}
 			}
 		}
+
+		// The blocks of this batch may now be evicted.
+		self.cache.evict();
+		self.update_metrics();
+	}
+
+	/// Handle a request to replay transactions.
+	/// The replay waits for a permit in its own task like block tracing does, but its result is
+	/// sent directly to the requester without being cached.
+	#[instrument(skip(self, sender))]
+	fn request_replay(
+		&mut self,
+		sender: oneshot::Sender<Result<Vec<TransactionReplay>>>,
+		target: ReplayTarget,
+		trace_type: replay::ReplayTraceType,
+		deadline: Instant,
+	) {
+		let blocking_permits = Arc::clone(&self.blocking_permits);
+		let client = Arc::clone(&self.client);
+		let backend = Arc::clone(&self.backend);
+		let frontier_backend = Arc::clone(&self.frontier_backend);
+		let pending_extrinsics = Arc::clone(&self.pending_extrinsics);
+		let tracing_runtimes = self.tracing_runtimes.clone();
+		let state_archive = self.state_archive;
+
+		tokio::spawn(
+			async move {
+				tracing::trace!("Waiting for blocking permit");
+				let permit =
+					match timeout_at(deadline.into(), blocking_permits.acquire_owned()).await {
+					Ok(permit) => permit,
+					Err(_) => {
+						let _ = sender.send(Err(timeout_err()));
+						return;
+					}
+				};
+
+				tracing::trace!("Start replay in a blocking task.");
+
+				let result = async {
+					let blocking = tokio::task::spawn_blocking(move || {
+						let _permit = permit;
+						Self::replay(
+							client,
+							backend,
+							frontier_backend,
+							pending_extrinsics,
+							tracing_runtimes.as_deref(),
+							target,
+							trace_type,
+							deadline,
+							state_archive,
+						)
+					});
+					timeout_at(deadline.into(), blocking)
+						.await
+						.map_err(|_| timeout_err())?
+						.map_err(|e| {
+							internal_err(format!("Replay of {:?} panicked : {:?}", target, e))
+						})?
+				}
+				.await;
+
+				let _ = sender.send(result);
+			}
+			.instrument(tracing::trace_span!("Replay", target = ?target)),
+		);
+	}
+
+	/// Handle a request to replay unsigned calls, in the same way as transaction replays.
+	#[instrument(skip(self, sender, calls, state_overrides))]
+	fn request_replay_calls(
+		&mut self,
+		sender: oneshot::Sender<Result<Vec<TransactionReplay>>>,
+		block: RequestedBlock,
+		calls: Vec<(CallRequest, replay::ReplayTraceType)>,
+		state_overrides: Option<BTreeMap<H160, StateOverride>>,
+		deadline: Instant,
+	) {
+		let blocking_permits = Arc::clone(&self.blocking_permits);
+		let client = Arc::clone(&self.client);
+		let backend = Arc::clone(&self.backend);
+		let pending_extrinsics = Arc::clone(&self.pending_extrinsics);
+		let state_archive = self.state_archive;
+
+		tokio::spawn(
+			async move {
+				tracing::trace!("Waiting for blocking permit");
+				let permit =
+					match timeout_at(deadline.into(), blocking_permits.acquire_owned()).await {
+					Ok(permit) => permit,
+					Err(_) => {
+						let _ = sender.send(Err(timeout_err()));
+						return;
+					}
+				};
+
+				tracing::trace!("Start replay in a blocking task.");
+
+				let result = async {
+					let blocking = tokio::task::spawn_blocking(move || {
+						let _permit = permit;
+						Self::replay_calls(
+							client,
+							backend,
+							pending_extrinsics,
+							block,
+							calls,
+							state_overrides,
+							deadline,
+							state_archive,
+						)
+					});
+					timeout_at(deadline.into(), blocking)
+						.await
+						.map_err(|_| timeout_err())?
+						.map_err(|e| {
+							internal_err(format!(
+								"Replay of calls in block {:?} panicked : {:?}",
+								block, e
+							))
+						})?
+				}
+				.await;
+
+				let _ = sender.send(result);
+			}
+			.instrument(tracing::trace_span!("ReplayCalls", block = ?block)),
+		);
+	}
+
+	/// Handle a request to trace the pending block, in the same way as replays.
+	#[instrument(skip(self, sender))]
+	fn request_pending_traces(
+		&mut self,
+		sender: oneshot::Sender<Result<Vec<TransactionTrace>>>,
+		deadline: Instant,
+	) {
+		let blocking_permits = Arc::clone(&self.blocking_permits);
+		let client = Arc::clone(&self.client);
+		let backend = Arc::clone(&self.backend);
+		let pending_extrinsics = Arc::clone(&self.pending_extrinsics);
+		let tracing_runtimes = self.tracing_runtimes.clone();
+		let state_archive = self.state_archive;
+
+		tokio::spawn(
+			async move {
+				tracing::trace!("Waiting for blocking permit");
+				let permit =
+					match timeout_at(deadline.into(), blocking_permits.acquire_owned()).await {
+					Ok(permit) => permit,
+					Err(_) => {
+						let _ = sender.send(Err(timeout_err()));
+						return;
+					}
+				};
+
+				tracing::trace!("Start tracing the pending block in a blocking task.");
+
+				let result = async {
+					let blocking = tokio::task::spawn_blocking(move || {
+						let _permit = permit;
+						Self::trace_pending_block(
+							client,
+							backend,
+							tracing_runtimes.as_deref(),
+							pending_extrinsics,
+							deadline,
+							state_archive,
+						)
+					});
+					timeout_at(deadline.into(), blocking)
+						.await
+						.map_err(|_| timeout_err())?
+						.map_err(|e| {
+							internal_err(format!("Tracing of the pending block panicked : {:?}", e))
+						})?
+				}
+				.await;
+
+				let _ = sender.send(result);
+			}
+			.instrument(tracing::trace_span!("PendingTraces")),
+		);
 	}
 
 	/// A tracing blocking task notifies it got a permit and is starting the tracing.
 	/// This started status is stored to avoid removing this block entry.
 	#[instrument(skip(self))]
 	fn blocking_started(&mut self, block_hash: H256) {
-		if let Some(block_cache) = self.cached_blocks.get_mut(&block_hash) {
+		self.tracing_blocks += 1;
+		self.update_metrics();
+
+		if let Some(block_cache) = self.cache.blocks.get_mut(&block_hash) {
 			if let CacheBlockState::Pooled {
 				ref mut started, ..
 			} = block_cache.state
@@ -717,15 +1951,56 @@ where
 	}
 
 	/// A tracing blocking task notifies it has finished the tracing and provide the result.
-	#[instrument(skip(self, result))]
-	fn blocking_finished(&mut self, block_hash: H256, result: Result<Vec<TransactionTrace>>) {
+	/// A block which timed out before the deadline of a batch which joined it later is pooled
+	/// again for this batch.
+	#[instrument(skip(self, blocking_tx, result))]
+	fn blocking_finished(
+		&mut self,
+		blocking_tx: &mpsc::Sender<BlockingTaskMessage>,
+		block_hash: H256,
+		result: Result<Vec<TransactionTrace>>,
+	) {
+		self.tracing_blocks = self.tracing_blocks.saturating_sub(1);
+
+		let timed_out =
+			matches!(&result, Err(e) if e.code == ErrorCode::ServerError(TIMEOUT_ERROR_CODE));
+		let repool_deadline = match self.cache.blocks.get(&block_hash) {
+			Some(CacheBlock {
+				state: CacheBlockState::Pooled { deadline, .. },
+				..
+			}) if timed_out && *deadline > Instant::now() => Some(*deadline),
+			_ => None,
+		};
+		if let Some(deadline) = repool_deadline {
+			tracing::trace!(
+				"Block {} timed out before the deadline of a later batch, pooling it again.",
+				block_hash
+			);
+			let new_unqueue_sender = self.pool_block(blocking_tx, block_hash, deadline);
+			if let Some(CacheBlock {
+				state:
+					CacheBlockState::Pooled {
+						ref mut started,
+						ref mut unqueue_sender,
+						..
+					},
+				..
+			}) = self.cache.blocks.get_mut(&block_hash)
+			{
+				*started = false;
+				*unqueue_sender = new_unqueue_sender;
+			}
+			self.update_metrics();
+			return;
+		}
+
 		// In some cases it might be possible to receive traces of a block
 		// that has no entry in the cache because it was removed of the pool
 		// and received a permit concurrently. We just ignore it.
 		//
 		// TODO : Should we add it back ? Should it have an active_batch_count
 		// of 1 then ?
-		if let Some(block_cache) = self.cached_blocks.get_mut(&block_hash) {
+		if let Some(block_cache) = self.cache.blocks.get_mut(&block_hash) {
 			if let CacheBlockState::Pooled {
 				ref mut waiting_requests,
 				..
@@ -742,22 +2017,45 @@ where
 					let _ = channel.send(result.clone());
 				}
 
+				// A block which timed out is not cached, so that it can be traced again. The
+				// expiration of the batches using it must not affect it if it is pooled again.
+				if timed_out {
+					self.cache.blocks.remove(&block_hash);
+					for blocks in self.cache.batches.values_mut() {
+						blocks.retain(|block| *block != block_hash);
+					}
+					self.cache.evict();
+					self.update_metrics();
+					return;
+				}
+
 				// Update cache entry
-				block_cache.state = CacheBlockState::Cached { traces: result };
+				let size = match &result {
+					Ok(traces) => traces_size(traces),
+					Err(_) => 0,
+				};
+				self.cache.bytes += size;
+				block_cache.state = CacheBlockState::Cached {
+					traces: result,
+					size,
+				};
 			}
 		}
+
+		self.cache.evict();
+		self.update_metrics();
 	}
 
 	/// A batch expiration delay timer has completed. It performs the cache cleaning for blocks
 	/// not longer used by other batches.
 	#[instrument(skip(self))]
 	fn expired_batch(&mut self, batch_id: CacheBatchId) {
-		if let Some(batch) = self.batches.remove(&batch_id.0) {
+		if let Some(batch) = self.cache.batches.remove(&batch_id.0) {
 			for block in batch {
 				// For each block of the batch, we remove it if it was the
 				// last batch containing it.
 				let mut remove = false;
-				if let Some(block_cache) = self.cached_blocks.get_mut(&block) {
+				if let Some(block_cache) = self.cache.blocks.get_mut(&block) {
 					block_cache.active_batch_count -= 1;
 
 					if block_cache.active_batch_count == 0 {
@@ -766,22 +2064,73 @@ where
 				}
 
 				if remove {
-					let _ = self.cached_blocks.remove(&block);
+					self.cache.remove_block(&block);
 				}
 			}
 		}
+
+		self.update_metrics();
 	}
 
-	/// (In blocking task) Use the Runtime API to trace the block.
-	#[instrument(skip(client, backend))]
-	fn cache_block(
+	fn update_metrics(&self) {
+		if let Some(metrics) = &self.metrics {
+			metrics.cached_bytes.set(self.cache.bytes as u64);
+			metrics.cached_blocks.set(self.cache.blocks.len() as u64);
+			metrics.tracing_blocks.set(self.tracing_blocks);
+		}
+	}
+
+	/// (In blocking task) Use the Runtime API to replay a transaction or a block.
+	#[instrument(skip(client, backend, frontier_backend, pending_extrinsics, tracing_runtimes))]
+	fn replay(
 		client: Arc<C>,
 		backend: Arc<BE>,
-		substrate_hash: H256,
-	) -> Result<Vec<TransactionTrace>> {
+		frontier_backend: Arc<fc_db::Backend<B>>,
+		pending_extrinsics: PendingExtrinsics<B>,
+		tracing_runtimes: Option<&TracingRuntimes<B, BE>>,
+		target: ReplayTarget,
+		trace_type: replay::ReplayTraceType,
+		deadline: Instant,
+		state_archive: bool,
+	) -> Result<Vec<TransactionReplay>> {
+		// Find the Substrate block, and the index of the transaction in the Ethereum block.
+		let (substrate_hash, index) = match target {
+			ReplayTarget::Transaction(transaction_hash) => {
+				let (eth_block_hash, index) = frontier_backend_client::load_transactions::<B, C>(
+					client.as_ref(),
+					frontier_backend.as_ref(),
+					transaction_hash,
+				)?
+				.ok_or_else(|| TraceRpcError::TransactionNotIndexed(transaction_hash))?;
+
+				let substrate_block_id = frontier_backend_client::load_hash::<B>(
+					frontier_backend.as_ref(),
+					eth_block_hash,
+				)?
+				.ok_or_else(|| TraceRpcError::BlockNotFound(format!("{:?}", eth_block_hash)))?;
+
+				let substrate_hash = client
+					.expect_block_hash_from_id(&substrate_block_id)
+					.map_err(|_| TraceRpcError::BlockNotFound(format!("{:?}", eth_block_hash)))?;
+
+				(substrate_hash, Some(index as usize))
+			}
+			ReplayTarget::Block(substrate_hash) => (substrate_hash, None),
+			ReplayTarget::PendingBlock => {
+				return Self::replay_pending_block(
+					client,
+					backend,
+					tracing_runtimes,
+					pending_extrinsics,
+					trace_type,
+					deadline,
+					state_archive,
+				)
+			}
+		};
+
 		let substrate_block_id = BlockId::Hash(substrate_hash);
 
-		// Get Subtrate block data.
 		let api = client.runtime_api();
 		let block_header = client
 			.header(substrate_block_id)
@@ -791,16 +2140,19 @@ where
 					substrate_hash, e
 				))
 			})?
-			.ok_or_else(|| {
-				internal_err(format!("Subtrate block {} don't exist", substrate_block_id))
-			})?;
+			.ok_or_else(|| TraceRpcError::BlockNotFound(format!("{:?}", substrate_hash)))?;
 
 		let height = *block_header.number();
 		let substrate_parent_id = BlockId::<B>::Hash(*block_header.parent_hash());
+		check_state(backend.as_ref(), substrate_parent_id).map_err(|e| {
+			trace_err(e, state_archive, || {
+				format!("Blockchain error when replaying block {}", height)
+			})
+		})?;
 
 		// Get Ethereum block data.
 		let (eth_block, _, eth_transactions) = api
-			.current_all(&BlockId::Hash(substrate_hash))
+			.current_all(&substrate_block_id)
 			.map_err(|e| {
 				internal_err(format!(
 					"Failed to get Ethereum block data for Substrate block {} : {:?}",
@@ -818,8 +2170,6 @@ where
 			}
 		};
 
-		let eth_block_hash = eth_block.header.hash();
-
 		// Get extrinsics (containing Ethereum ones)
 		let extrinsics = backend
 			.blockchain()
@@ -830,35 +2180,388 @@ where
 					height, e
 				))
 			})?
+			.ok_or_else(|| TraceRpcError::BlockNotFound(format!("{:?}", substrate_hash)))?;
+
+		// The block is replayed by the runtime it has been built with, or the tracing runtime
+		// replacing it.
+		let debug_api = DebugApi::new(
+			client.as_ref(),
+			backend.as_ref(),
+			tracing_runtimes,
+			substrate_parent_id,
+		)
+		.map_err(|e| {
+			trace_err(e, state_archive, || {
+				format!("Blockchain error when replaying block {}", height)
+			})
+		})?
+		.with_deadline(Some(deadline));
+
+		let mut replays = match index {
+			Some(index) => {
+				let transaction = eth_block.transactions.get(index).ok_or_else(|| {
+					internal_err(format!(
+						"Transaction {} not found in block {}",
+						index, height
+					))
+				})?;
+
+				let replay = debug_api
+					.replay_transaction(extrinsics, transaction, trace_type)
+					.map_err(|e| {
+						trace_err(e, state_archive, || {
+							format!(
+								"Blockchain error when replaying transaction in block {}",
+								height
+							)
+						})
+					})?
+					.map_err(|e| {
+						internal_err(format!(
+							"Internal runtime error when replaying transaction in block {} : {:?}",
+							height, e
+						))
+					})?;
+
+				vec![replay]
+			}
+			None => {
+				let mut replays = debug_api
+					.replay_block(extrinsics, trace_type)
+					.map_err(|e| {
+						trace_err(e, state_archive, || {
+							format!("Blockchain error when replaying block {}", height)
+						})
+					})?
+					.map_err(|e| {
+						internal_err(format!(
+							"Internal runtime error when replaying block {} : {:?}",
+							height, e
+						))
+					})?;
+
+				// Only replays of a whole block include the transaction hash.
+				for (replay, status) in replays.iter_mut().zip(eth_transactions.iter()) {
+					replay.transaction_hash = Some(status.transaction_hash);
+				}
+
+				replays
+			}
+		};
+
+		reformat_replay_errors(&mut replays);
+
+		Ok(replays)
+	}
+
+	/// (In blocking task) Use the Runtime API to replay unsigned calls on top of the state of
+	/// a block.
+	#[instrument(skip(client, backend, pending_extrinsics, calls, state_overrides))]
+	fn replay_calls(
+		client: Arc<C>,
+		backend: Arc<BE>,
+		pending_extrinsics: PendingExtrinsics<B>,
+		block: RequestedBlock,
+		calls: Vec<(CallRequest, replay::ReplayTraceType)>,
+		state_overrides: Option<BTreeMap<H160, StateOverride>>,
+		deadline: Instant,
+		state_archive: bool,
+	) -> Result<Vec<TransactionReplay>> {
+		let api = client.runtime_api();
+
+		let substrate_block_id = match block {
+			RequestedBlock::Imported { hash, .. } => BlockId::<B>::Hash(hash),
+			RequestedBlock::Pending => {
+				let pending = PendingBlock::new(client.as_ref(), pending_extrinsics())
+					.map_err(internal_err)?;
+				// The ApiRef keeps the changes of the pending extrinsics for the replayed calls.
+				for ext in pending.extrinsics {
+					let _ = api.apply_extrinsic(&pending.parent, ext);
+				}
+				pending.parent
+			}
+		};
+
+		check_state(backend.as_ref(), substrate_block_id)
+			.and_then(|()| check_events_api(&*api, &substrate_block_id))
+			.map_err(|e| {
+				trace_err(e, state_archive, || {
+					format!(
+						"Blockchain error when replaying calls in block {}",
+						substrate_block_id
+					)
+				})
+			})?;
+
+		// The gas limit of the calls defaults to the one of the block.
+		let block_gas_limit = api
+			.current_block(&substrate_block_id)
+			.map_err(|e| {
+				internal_err(format!(
+					"Failed to get Ethereum block data for Substrate block {} : {:?}",
+					substrate_block_id, e
+				))
+			})?
 			.ok_or_else(|| {
 				internal_err(format!(
-					"Could not find block {} when fetching extrinsics.",
-					height
+					"Failed to get Ethereum block data for Substrate block {}",
+					substrate_block_id
+				))
+			})?
+			.header
+			.gas_limit;
+
+		// Each call is replayed by its own tracer, depending on its trace type.
+		let mut trace_types = calls
+			.iter()
+			.map(|(_, trace_type)| *trace_type)
+			.collect::<Vec<_>>()
+			.into_iter();
+
+		let calls = calls
+			.into_iter()
+			.map(|(call, trace_type)| {
+				hedgeware_rpc_core_types::trace_call(call, block_gas_limit)
+					.map(|call| (call, trace_type))
+			})
+			.collect::<Result<Vec<_>>>()?;
+		let account_overrides = hedgeware_rpc_core_types::account_overrides(state_overrides)?;
+
+		let (tracer, result) = BlockTracer::new(move || {
+			ReplayTracer::new(trace_types.next().unwrap_or_default())
+		})
+		.trace_until(Some(Deadline::new(deadline)), || {
+			api.replay_calls(&substrate_block_id, calls, account_overrides)
+		});
+		result
+			.map_err(|_| timeout_err())?
+			.map_err(|e| {
+				internal_err(format!(
+					"Blockchain error when replaying calls in block {} : {:?}",
+					substrate_block_id, e
+				))
+			})?
+			.map_err(|e| {
+				internal_err(format!(
+					"Internal runtime error when replaying calls in block {} : {:?}",
+					substrate_block_id, e
 				))
 			})?;
+		let mut replays = into_replays(tracer);
+
+		reformat_replay_errors(&mut replays);
+
+		Ok(replays)
+	}
+
+	/// (In blocking task) Use the Runtime API to replay the transactions of the pending block.
+	fn replay_pending_block(
+		client: Arc<C>,
+		backend: Arc<BE>,
+		tracing_runtimes: Option<&TracingRuntimes<B, BE>>,
+		pending_extrinsics: PendingExtrinsics<B>,
+		trace_type: replay::ReplayTraceType,
+		deadline: Instant,
+		state_archive: bool,
+	) -> Result<Vec<TransactionReplay>> {
+		let pending =
+			PendingBlock::new(client.as_ref(), pending_extrinsics()).map_err(internal_err)?;
+
+		// The pending block is replayed by the runtime of the best block, or the tracing
+		// runtime replacing it.
+		let mut replays = DebugApi::new(
+			client.as_ref(),
+			backend.as_ref(),
+			tracing_runtimes,
+			pending.parent,
+		)
+		.and_then(|debug_api| {
+			debug_api
+				.with_deadline(Some(deadline))
+				.replay_block(pending.extrinsics, trace_type)
+		})
+		.map_err(|e| {
+			trace_err(e, state_archive, || {
+				"Blockchain error when replaying the pending block".to_string()
+			})
+		})?
+		.map_err(|e| {
+			internal_err(format!(
+				"Internal runtime error when replaying the pending block : {:?}",
+				e
+			))
+		})?;
+
+		for (replay, transaction_hash) in replays.iter_mut().zip(pending.transaction_hashes) {
+			replay.transaction_hash = Some(transaction_hash);
+		}
+
+		reformat_replay_errors(&mut replays);
+
+		Ok(replays)
+	}
+
+	/// (In blocking task) Use the Runtime API to trace the pending block. Its traces have no
+	/// block hash.
+	fn trace_pending_block(
+		client: Arc<C>,
+		backend: Arc<BE>,
+		tracing_runtimes: Option<&TracingRuntimes<B, BE>>,
+		pending_extrinsics: PendingExtrinsics<B>,
+		deadline: Instant,
+		state_archive: bool,
+	) -> Result<Vec<TransactionTrace>> {
+		let pending =
+			PendingBlock::new(client.as_ref(), pending_extrinsics()).map_err(internal_err)?;
+
+		// The pending block is traced by the runtime of the best block, or the tracing runtime
+		// replacing it.
+		let mut traces = DebugApi::new(
+			client.as_ref(),
+			backend.as_ref(),
+			tracing_runtimes,
+			pending.parent,
+		)
+		.and_then(|debug_api| {
+			debug_api
+				.with_deadline(Some(deadline))
+				.trace_block(pending.extrinsics)
+		})
+		.map_err(|e| {
+			trace_err(e, state_archive, || {
+				"Blockchain error when tracing the pending block".to_string()
+			})
+		})?
+		.map_err(|e| {
+			internal_err(format!(
+				"Internal runtime error when tracing the pending block : {:?}",
+				e
+			))
+		})?;
+
+		for trace in traces.iter_mut() {
+			trace.block_number = pending.parent_number + 1;
+			reformat_trace_error(trace);
+
+			// Substrate-originated traces are identified by their extrinsic in the runtime.
+			let position = match trace.transaction_position {
+				Some(position) => position,
+				None => continue,
+			};
 
-		// Trace the block.
-		let mut traces: Vec<_> = api
-			.trace_block(&substrate_parent_id, extrinsics)
+			trace.transaction_hash = *pending
+				.transaction_hashes
+				.get(position as usize)
+				.ok_or_else(|| {
+					internal_err("Pending transaction not found in the pool".to_string())
+				})?;
+		}
+
+		Ok(traces)
+	}
+
+	/// (In blocking task) Use the Runtime API to trace the block, aborting once the deadline,
+	/// if any, is reached.
+	#[instrument(skip(client, backend, tracing_runtimes))]
+	fn cache_block(
+		client: Arc<C>,
+		backend: Arc<BE>,
+		tracing_runtimes: Option<&TracingRuntimes<B, BE>>,
+		substrate_hash: H256,
+		deadline: Option<Instant>,
+		state_archive: bool,
+	) -> Result<Vec<TransactionTrace>> {
+		let substrate_block_id = BlockId::Hash(substrate_hash);
+
+		// Get Subtrate block data.
+		let api = client.runtime_api();
+		let block_header = client
+			.header(substrate_block_id)
 			.map_err(|e| {
 				internal_err(format!(
-					"Blockchain error when replaying block {} : {:?}",
-					height, e
+					"Error when fetching substrate block {} header : {:?}",
+					substrate_hash, e
 				))
 			})?
+			.ok_or_else(|| TraceRpcError::BlockNotFound(format!("{:?}", substrate_hash)))?;
+
+		let height = *block_header.number();
+		let substrate_parent_id = BlockId::<B>::Hash(*block_header.parent_hash());
+		check_state(backend.as_ref(), substrate_parent_id).map_err(|e| {
+			trace_err(e, state_archive, || {
+				format!("Blockchain error when replaying block {}", height)
+			})
+		})?;
+
+		// Get Ethereum block data.
+		let (eth_block, _, eth_transactions) = api
+			.current_all(&BlockId::Hash(substrate_hash))
 			.map_err(|e| {
 				internal_err(format!(
-					"Internal runtime error when replaying block {} : {:?}",
-					height, e
+					"Failed to get Ethereum block data for Substrate block {} : {:?}",
+					substrate_hash, e
 				))
 			})?;
 
+		let (eth_block, eth_transactions) = match (eth_block, eth_transactions) {
+			(Some(a), Some(b)) => (a, b),
+			_ => {
+				return Err(internal_err(format!(
+					"Failed to get Ethereum block data for Substrate block {}",
+					substrate_hash
+				)))
+			}
+		};
+
+		let eth_block_hash = eth_block.header.hash();
+
+		// Get extrinsics (containing Ethereum ones)
+		let extrinsics = backend
+			.blockchain()
+			.body(substrate_block_id)
+			.map_err(|e| {
+				internal_err(format!(
+					"Blockchain error when fetching extrinsics of block {} : {:?}",
+					height, e
+				))
+			})?
+			.ok_or_else(|| TraceRpcError::BlockNotFound(format!("{:?}", substrate_hash)))?;
+
+		// Trace the block with the runtime it has been built with, or the tracing runtime
+		// replacing it.
+		let mut traces = DebugApi::new(
+			client.as_ref(),
+			backend.as_ref(),
+			tracing_runtimes,
+			substrate_parent_id,
+		)
+		.and_then(|debug_api| debug_api.with_deadline(deadline).trace_block(extrinsics))
+		.map_err(|e| {
+			trace_err(e, state_archive, || {
+				format!("Blockchain error when replaying block {}", height)
+			})
+		})?
+		.map_err(|e| {
+			internal_err(format!(
+				"Internal runtime error when replaying block {} : {:?}",
+				height, e
+			))
+		})?;
+
 		// Fill missing data.
 		for trace in traces.iter_mut() {
 			trace.block_hash = eth_block_hash;
 			trace.block_number = height;
+			reformat_trace_error(trace);
+
+			// Substrate-originated traces are identified by their extrinsic in the runtime.
+			let position = match trace.transaction_position {
+				Some(position) => position,
+				None => continue,
+			};
+
 			trace.transaction_hash = eth_transactions
-				.get(trace.transaction_position as usize)
+				.get(position as usize)
 				.ok_or_else(|| {
 					tracing::warn!(
 						"Bug: A transaction has been replayed while it shouldn't (in block {}).",
@@ -871,13 +2574,6 @@ where
 					))
 				})?
 				.transaction_hash;
-
-			// Reformat error messages.
-			if let block::TransactionTraceOutput::Error(ref mut error) = trace.output {
-				if error.as_slice() == b"execution reverted" {
-					*error = b"Reverted".to_vec();
-				}
-			}
 		}
 
 		Ok(traces)
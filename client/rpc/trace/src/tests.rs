@@ -0,0 +1,526 @@
+// Copyright 2019-2020 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{
+	traces_size, BlockCache, CacheBlock, CacheBlockState, FilterCallType, FilterMode,
+	FilterRequest, TraceDb, TraceFilter, TransactionTrace,
+};
+use codec::Encode;
+use ethereum_types::{H160, H256, U256};
+use hedgeware_rpc_primitives_debug::{block, CallType};
+use kvdb::{DBTransaction, KeyValueDB};
+use std::{collections::HashMap, path::Path};
+
+fn address(value: u64) -> H160 {
+	H160::from_low_u64_be(value)
+}
+
+/// Hash of the block at this height on the given fork.
+fn hash(fork: u64, number: u32) -> H256 {
+	H256::from_low_u64_be(fork * 1_000 + number as u64)
+}
+
+/// Call from `from` to `to`.
+fn call_trace(from: H160, to: H160) -> TransactionTrace {
+	TransactionTrace {
+		action: block::TransactionTraceAction::Call {
+			call_type: CallType::Call,
+			from,
+			gas: 100_000.into(),
+			input: vec![],
+			to,
+			value: U256::zero(),
+		},
+		block_hash: H256::zero(),
+		block_number: 0,
+		output: block::TransactionTraceOutput::Result {
+			result: block::TransactionTraceResult::Call {
+				gas_used: 21_000.into(),
+				output: vec![],
+			},
+		},
+		subtraces: 0,
+		trace_address: vec![],
+		transaction_hash: H256::zero(),
+		transaction_position: Some(0),
+		extrinsic_index: None,
+		logs: vec![],
+		origin: block::TraceOrigin::Ethereum,
+	}
+}
+
+/// Index the blocks of a fork in the given range, the block at height `n` containing a call
+/// from the address `n` to the address `100 + n`.
+fn index_fork(trace_db: &TraceDb, fork: u64, numbers: std::ops::RangeInclusive<u32>) {
+	for number in numbers {
+		let trace = call_trace(address(number as u64), address(100 + number as u64));
+		trace_db
+			.insert_block(number, hash(fork, number), &[trace])
+			.unwrap();
+	}
+}
+
+fn senders_in(trace_db: &TraceDb, senders: &[u64]) -> Vec<u32> {
+	let senders: Vec<_> = senders.iter().map(|sender| address(*sender)).collect();
+	trace_db
+		.blocks_with_addresses(&senders, false, &(0..=u32::MAX))
+		.into_iter()
+		.collect()
+}
+
+fn open(path: &Path) -> TraceDb {
+	TraceDb::open(path).unwrap()
+}
+
+#[test]
+fn trace_db_truncate_removes_the_blocks_above() {
+	let dir = tempfile::tempdir().unwrap();
+	let trace_db = open(dir.path());
+	index_fork(&trace_db, 0, 3..=6);
+
+	trace_db.truncate(4).unwrap();
+
+	assert_eq!(trace_db.indexed_range().unwrap(), Some(3..=4));
+	assert_eq!(trace_db.indexed_hash(4).unwrap(), Some(hash(0, 4)));
+	assert_eq!(trace_db.indexed_hash(5).unwrap(), None);
+	assert_eq!(trace_db.traces(hash(0, 6)).unwrap(), None);
+	assert!(trace_db.traces(hash(0, 4)).unwrap().is_some());
+	assert_eq!(senders_in(&trace_db, &[3, 4, 5, 6]), vec![3, 4]);
+
+	// Nothing to remove at or above the end of the range.
+	trace_db.truncate(4).unwrap();
+	trace_db.truncate(10).unwrap();
+	assert_eq!(trace_db.indexed_range().unwrap(), Some(3..=4));
+}
+
+#[test]
+fn trace_db_truncate_below_the_range_empties_it() {
+	let dir = tempfile::tempdir().unwrap();
+	let trace_db = open(dir.path());
+	index_fork(&trace_db, 0, 3..=6);
+
+	trace_db.truncate(2).unwrap();
+
+	assert_eq!(trace_db.indexed_range().unwrap(), None);
+	assert_eq!(trace_db.indexed_hash(3).unwrap(), None);
+	assert!(senders_in(&trace_db, &[3, 4, 5, 6]).is_empty());
+}
+
+#[test]
+fn trace_db_replaces_retracted_blocks() {
+	let dir = tempfile::tempdir().unwrap();
+	let trace_db = open(dir.path());
+	index_fork(&trace_db, 0, 1..=3);
+
+	trace_db
+		.insert_block(3, hash(1, 3), &[call_trace(address(42), address(43))])
+		.unwrap();
+
+	assert_eq!(trace_db.indexed_range().unwrap(), Some(1..=3));
+	assert_eq!(trace_db.indexed_hash(3).unwrap(), Some(hash(1, 3)));
+	assert_eq!(trace_db.traces(hash(0, 3)).unwrap(), None);
+	assert_eq!(senders_in(&trace_db, &[3]), Vec::<u32>::new());
+	assert_eq!(senders_in(&trace_db, &[42]), vec![3]);
+}
+
+#[test]
+fn trace_db_walks_back_to_the_indexed_ancestor() {
+	let dir = tempfile::tempdir().unwrap();
+	let trace_db = open(dir.path());
+	index_fork(&trace_db, 0, 2..=5);
+
+	// Fork 1 branches off after block 3 of fork 0, fork 0 continues up to block 7.
+	let parents: HashMap<H256, H256> = vec![
+		(hash(1, 4), hash(0, 3)),
+		(hash(1, 5), hash(1, 4)),
+		(hash(1, 6), hash(1, 5)),
+		(hash(0, 6), hash(0, 5)),
+		(hash(0, 7), hash(0, 6)),
+	]
+	.into_iter()
+	.collect();
+	let parent_hash = |block: H256| {
+		parents
+			.get(&block)
+			.cloned()
+			.ok_or_else(|| format!("Block {} not found", block))
+	};
+
+	assert_eq!(
+		trace_db
+			.blocks_to_index(6, hash(1, 6), &parent_hash)
+			.unwrap(),
+		vec![(6, hash(1, 6)), (5, hash(1, 5)), (4, hash(1, 4))]
+	);
+	assert_eq!(
+		trace_db
+			.blocks_to_index(7, hash(0, 7), &parent_hash)
+			.unwrap(),
+		vec![(7, hash(0, 7)), (6, hash(0, 6))]
+	);
+	// The tip is already indexed.
+	assert!(trace_db
+		.blocks_to_index(5, hash(0, 5), &parent_hash)
+		.unwrap()
+		.is_empty());
+}
+
+#[test]
+fn trace_db_walk_back_stops_at_the_start_of_the_range() {
+	let dir = tempfile::tempdir().unwrap();
+	let trace_db = open(dir.path());
+	let no_parent = |block: H256| Err(format!("Block {} not found", block));
+
+	// An empty index starts at the tip.
+	assert_eq!(
+		trace_db.blocks_to_index(9, hash(0, 9), no_parent).unwrap(),
+		vec![(9, hash(0, 9))]
+	);
+
+	// A fork replacing the whole range isn't walked back below it.
+	index_fork(&trace_db, 0, 5..=6);
+	let parent_hash = |block: H256| {
+		if block == hash(1, 6) {
+			Ok(hash(1, 5))
+		} else {
+			no_parent(block)
+		}
+	};
+	assert_eq!(
+		trace_db.blocks_to_index(6, hash(1, 6), parent_hash).unwrap(),
+		vec![(6, hash(1, 6)), (5, hash(1, 5))]
+	);
+}
+
+#[test]
+fn trace_db_backfills_down_to_the_target() {
+	let dir = tempfile::tempdir().unwrap();
+	let trace_db = open(dir.path());
+
+	// Nothing to backfill below an empty index.
+	assert_eq!(trace_db.next_backfill_block(1).unwrap(), None);
+
+	index_fork(&trace_db, 0, 5..=8);
+	assert_eq!(trace_db.next_backfill_block(3).unwrap(), Some(4));
+
+	index_fork(&trace_db, 0, 4..=4);
+	assert_eq!(trace_db.indexed_range().unwrap(), Some(4..=8));
+	assert_eq!(trace_db.next_backfill_block(3).unwrap(), Some(3));
+
+	index_fork(&trace_db, 0, 3..=3);
+	assert_eq!(trace_db.next_backfill_block(3).unwrap(), None);
+	assert_eq!(trace_db.next_backfill_block(5).unwrap(), None);
+}
+
+#[test]
+fn trace_db_with_another_version_is_cleared() {
+	let dir = tempfile::tempdir().unwrap();
+	{
+		let trace_db = open(dir.path());
+		index_fork(&trace_db, 0, 5..=8);
+		assert_eq!(trace_db.reindex_target().unwrap(), None);
+	}
+
+	// Reopening with the same version keeps the index.
+	{
+		let trace_db = open(dir.path());
+		assert_eq!(trace_db.indexed_range().unwrap(), Some(5..=8));
+	}
+
+	// Databases of the first version don't have a version key.
+	{
+		let db = kvdb_rocksdb::Database::open(
+			&kvdb_rocksdb::DatabaseConfig::with_columns(5),
+			&dir.path().to_string_lossy(),
+		)
+		.unwrap();
+		let mut transaction = DBTransaction::new();
+		transaction.delete(0, b"VERSION");
+		db.write(transaction).unwrap();
+	}
+
+	let trace_db = open(dir.path());
+	assert_eq!(trace_db.indexed_range().unwrap(), None);
+	assert_eq!(trace_db.indexed_hash(5).unwrap(), None);
+	assert_eq!(trace_db.traces(hash(0, 5)).unwrap(), None);
+	assert!(senders_in(&trace_db, &[5, 6, 7, 8]).is_empty());
+	assert_eq!(trace_db.reindex_target().unwrap(), Some(5));
+
+	// The target is kept until the blocks are indexed again.
+	drop(trace_db);
+	let trace_db = open(dir.path());
+	assert_eq!(trace_db.reindex_target().unwrap(), Some(5));
+}
+
+/// Traced block of the cache, used by `running_batch_count` running batches and last used at
+/// `last_used`.
+fn cached_block(
+	traces: Vec<TransactionTrace>,
+	running_batch_count: usize,
+	last_used: u64,
+) -> CacheBlock {
+	let size = traces_size(&traces);
+	CacheBlock {
+		active_batch_count: running_batch_count.max(1),
+		running_batch_count,
+		last_used,
+		state: CacheBlockState::Cached {
+			traces: Ok(traces),
+			size,
+		},
+	}
+}
+
+#[test]
+fn traces_size_accounts_for_the_memory_of_the_traces() {
+	let mut trace = call_trace(address(1), address(2));
+	if let block::TransactionTraceAction::Call { ref mut input, .. } = trace.action {
+		*input = Vec::with_capacity(1024);
+		input.push(0x60);
+	}
+	let traces = vec![trace];
+
+	assert!(traces_size(&traces) >= std::mem::size_of::<TransactionTrace>() + 1024);
+	assert!(traces_size(&traces) > traces.encoded_size());
+}
+
+#[test]
+fn cache_evicts_least_recently_used_blocks_outside_running_batches() {
+	let traces = || vec![call_trace(address(1), address(2))];
+	let block_size = traces_size(&traces());
+	let mut cache = BlockCache::new(2 * block_size);
+
+	// Block 1 is the least recently used but still needed by a running batch.
+	let blocks = vec![
+		(hash(0, 1), cached_block(traces(), 1, 1)),
+		(hash(0, 2), cached_block(traces(), 0, 2)),
+		(hash(0, 3), cached_block(traces(), 0, 4)),
+		(hash(0, 4), cached_block(traces(), 0, 3)),
+	];
+	for (block, cache_block) in blocks {
+		cache.bytes += block_size;
+		cache.blocks.insert(block, cache_block);
+	}
+	cache.batches.insert(0, vec![hash(0, 1)]);
+	cache.batches.insert(1, vec![hash(0, 2), hash(0, 3), hash(0, 4)]);
+
+	cache.evict();
+
+	assert_eq!(cache.bytes, 2 * block_size);
+	assert_eq!(
+		cache.blocks.keys().cloned().collect::<Vec<_>>(),
+		vec![hash(0, 1), hash(0, 3)]
+	);
+	// The expiration of the batch must not remove the blocks if they are cached again.
+	assert_eq!(cache.batches[&1], vec![hash(0, 3)]);
+
+	// The blocks of running batches are kept even above the limit.
+	cache.max_bytes = 0;
+	cache.evict();
+	assert_eq!(cache.bytes, block_size);
+	assert_eq!(cache.blocks.keys().cloned().collect::<Vec<_>>(), vec![hash(0, 1)]);
+	assert_eq!(cache.batches[&0], vec![hash(0, 1)]);
+}
+
+fn filter(
+	from_address: &[u64],
+	to_address: &[u64],
+	mode: Option<FilterMode>,
+	call_type: Option<Vec<FilterCallType>>,
+	errors_only: Option<bool>,
+) -> TraceFilter {
+	let addresses = |addresses: &[u64]| -> Option<Vec<H160>> {
+		if addresses.is_empty() {
+			None
+		} else {
+			Some(addresses.iter().map(|value| address(*value)).collect())
+		}
+	};
+	TraceFilter::new(&FilterRequest {
+		from_block: None,
+		to_block: None,
+		from_address: addresses(from_address),
+		to_address: addresses(to_address),
+		after: None,
+		count: None,
+		mode,
+		call_type,
+		errors_only,
+		cursor: None,
+	})
+}
+
+fn with_call_type(mut trace: TransactionTrace, call_type: CallType) -> TransactionTrace {
+	if let block::TransactionTraceAction::Call {
+		call_type: ref mut trace_call_type,
+		..
+	} = trace.action
+	{
+		*trace_call_type = call_type;
+	}
+	trace
+}
+
+fn failed(mut trace: TransactionTrace) -> TransactionTrace {
+	trace.output = block::TransactionTraceOutput::Error {
+		error: b"Reverted".to_vec(),
+		revert_reason: None,
+		output: vec![],
+	};
+	trace
+}
+
+/// Creation of `created` by `from`, `None` if it failed.
+fn create_trace(from: H160, created: Option<H160>) -> TransactionTrace {
+	let trace = TransactionTrace {
+		action: block::TransactionTraceAction::Create {
+			creation_method: hedgeware_rpc_primitives_debug::CreateType::Create,
+			from,
+			gas: 100_000.into(),
+			init: vec![],
+			value: U256::zero(),
+		},
+		..call_trace(from, H160::zero())
+	};
+	match created {
+		Some(address) => TransactionTrace {
+			output: block::TransactionTraceOutput::Result {
+				result: block::TransactionTraceResult::Create {
+					address,
+					code: vec![],
+					gas_used: 21_000.into(),
+				},
+			},
+			..trace
+		},
+		None => failed(trace),
+	}
+}
+
+fn suicide_trace(address: H160, refund_address: H160) -> TransactionTrace {
+	TransactionTrace {
+		action: block::TransactionTraceAction::Suicide {
+			address,
+			balance: U256::zero(),
+			refund_address,
+		},
+		output: block::TransactionTraceOutput::Result {
+			result: block::TransactionTraceResult::Suicide,
+		},
+		..call_trace(address, H160::zero())
+	}
+}
+
+#[test]
+fn trace_filter_without_addresses_matches_everything() {
+	let filter = filter(&[], &[], None, None, None);
+
+	assert!(filter.matches(&call_trace(address(1), address(2))));
+	assert!(filter.matches(&create_trace(address(1), None)));
+	assert!(filter.matches(&suicide_trace(address(1), address(2))));
+}
+
+#[test]
+fn trace_filter_modes_combine_the_addresses() {
+	let from_1 = call_trace(address(1), address(3));
+	let to_2 = call_trace(address(3), address(2));
+	let from_1_to_2 = call_trace(address(1), address(2));
+	let other = call_trace(address(3), address(4));
+
+	// The intersection is the default.
+	for mode in vec![None, Some(FilterMode::Intersection)] {
+		let intersection = filter(&[1], &[2], mode, None, None);
+		assert!(intersection.matches(&from_1_to_2));
+		assert!(!intersection.matches(&from_1));
+		assert!(!intersection.matches(&to_2));
+		assert!(!intersection.matches(&other));
+	}
+
+	let union = filter(&[1], &[2], Some(FilterMode::Union), None, None);
+	assert!(union.matches(&from_1_to_2));
+	assert!(union.matches(&from_1));
+	assert!(union.matches(&to_2));
+	assert!(!union.matches(&other));
+
+	// A single list of addresses is used whatever the mode.
+	for mode in vec![Some(FilterMode::Intersection), Some(FilterMode::Union)] {
+		let senders = filter(&[1], &[], mode, None, None);
+		assert!(senders.matches(&from_1));
+		assert!(!senders.matches(&to_2));
+
+		let recipients = filter(&[], &[2], mode, None, None);
+		assert!(recipients.matches(&to_2));
+		assert!(!recipients.matches(&from_1));
+	}
+}
+
+#[test]
+fn trace_filter_recipients_of_creations_and_suicides() {
+	let recipients = filter(&[], &[2], None, None, None);
+
+	// The created contract and the refund address are the recipients.
+	assert!(recipients.matches(&create_trace(address(1), Some(address(2)))));
+	assert!(!recipients.matches(&create_trace(address(2), Some(address(3)))));
+	assert!(recipients.matches(&suicide_trace(address(1), address(2))));
+	// A failed creation has no recipient.
+	assert!(!recipients.matches(&create_trace(address(1), None)));
+	assert!(!filter(&[], &[0], None, None, None).matches(&create_trace(address(1), None)));
+
+	// The destroyed contract is the sender of a suicide.
+	assert!(filter(&[1], &[], None, None, None).matches(&suicide_trace(address(1), address(2))));
+}
+
+#[test]
+fn trace_filter_call_types() {
+	let call_types = filter(
+		&[],
+		&[],
+		None,
+		Some(vec![FilterCallType::DelegateCall, FilterCallType::Create]),
+		None,
+	);
+
+	assert!(call_types.matches(&with_call_type(
+		call_trace(address(1), address(2)),
+		CallType::DelegateCall
+	)));
+	assert!(call_types.matches(&create_trace(address(1), Some(address(2)))));
+	assert!(!call_types.matches(&call_trace(address(1), address(2))));
+	assert!(!call_types.matches(&with_call_type(
+		call_trace(address(1), address(2)),
+		CallType::StaticCall
+	)));
+	assert!(!call_types.matches(&suicide_trace(address(1), address(2))));
+
+	// No call type matches nothing.
+	let none = filter(&[], &[], None, Some(vec![]), None);
+	assert!(!none.matches(&call_trace(address(1), address(2))));
+}
+
+#[test]
+fn trace_filter_errors_only() {
+	let errors = filter(&[1], &[], None, None, Some(true));
+
+	assert!(errors.matches(&failed(call_trace(address(1), address(2)))));
+	assert!(errors.matches(&create_trace(address(1), None)));
+	assert!(!errors.matches(&call_trace(address(1), address(2))));
+	// Other filters still apply.
+	assert!(!errors.matches(&failed(call_trace(address(3), address(2)))));
+
+	let all = filter(&[1], &[], None, None, Some(false));
+	assert!(all.matches(&failed(call_trace(address(1), address(2)))));
+	assert!(all.matches(&call_trace(address(1), address(2))));
+}
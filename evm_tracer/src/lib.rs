@@ -19,13 +19,17 @@
 //! The purpose of this crate is enable tracing the EVM opcode execution and will be used by
 //! both Dapp developers - to get a granular view on their transactions - and indexers to access
 //! the EVM callstack (internal transactions).
+//!
+//! This is the runtime side of the tracing: the events of the EVM are forwarded to the client
+//! through the host functions of `hedgeware-primitives-ext`, the traces being built natively
+//! by `hedgeware-client-evm-tracing`. It is only meant to be part of tracing runtimes.
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
-mod call_list;
-mod raw;
+mod state_tracer;
+mod tracer;
 mod util;
 
-pub use call_list::CallListTracer;
-pub use raw::RawTracer;
-pub use util::EvmListener;
+pub use hedgeware_evm_tracing_events::{EventFilter, TransactionOrigin};
+pub use state_tracer::StateTracer;
+pub use tracer::EvmTracer;
@@ -0,0 +1,169 @@
+// Copyright 2019-2021 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::util::*;
+
+use ethereum_types::{H160, H256};
+use hedgeware_evm_tracing_events::{AccountState, Event};
+use sp_std::{
+	collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+	marker::PhantomData,
+};
+
+/// Send the state of every account touched by a transaction to the client, for the
+/// prestate tracer and the state diff of the replays.
+///
+/// Accounts are collected from the call, create and self destruct events, and storage slots
+/// from the `SLoad` and `SStore` events. The traced closure is executed inside a storage
+/// transaction which is rolled back afterwards, so that the state from before the
+/// transaction can be read back. **The changes made by the closure are thus discarded.**
+///
+/// The state after the transaction is only sent in diff mode, before the one from before it.
+pub struct StateTracer<T> {
+	diff_mode: bool,
+
+	// Touched addresses, with their accessed storage slots.
+	touched: BTreeMap<H160, BTreeSet<H256>>,
+
+	_marker: PhantomData<T>,
+}
+
+impl<T: pallet_evm::Config> StateTracer<T> {
+	pub fn new(diff_mode: bool) -> Self {
+		Self {
+			diff_mode,
+
+			touched: BTreeMap::new(),
+
+			_marker: PhantomData,
+		}
+	}
+
+	/// Setup event listeners and execute provided closure.
+	pub fn trace<R, F: FnOnce() -> R>(self, f: F) -> R {
+		let wrapped = Rc::new(RefCell::new(self));
+
+		sp_io::storage::start_transaction();
+
+		let result = {
+			let mut runtime = ListenerProxy(Rc::clone(&wrapped));
+			let mut evm = ListenerProxy(Rc::clone(&wrapped));
+
+			// Each line wraps the previous `f` into a `using` call.
+			// Listening to new events results in adding one new line.
+			// Order is irrelevant when registering listeners.
+			let f = || runtime_using(&mut runtime, f);
+			let f = || evm_using(&mut evm, f);
+			f()
+		};
+
+		let tracer = wrapped.borrow();
+
+		if tracer.diff_mode {
+			tracer.emit_state(Event::PostState);
+		}
+		sp_io::storage::rollback_transaction();
+		tracer.emit_state(Event::PreState);
+
+		result
+	}
+
+	/// Send the current state of the touched accounts.
+	fn emit_state(&self, event: fn(AccountState) -> Event) {
+		for (address, indices) in self.touched.iter() {
+			let account = pallet_evm::Pallet::<T>::account_basic(address);
+
+			emit(event(AccountState {
+				address: *address,
+				balance: account.balance,
+				nonce: account.nonce,
+				code: pallet_evm::AccountCodes::<T>::get(address),
+				storage: indices
+					.iter()
+					.map(|index| (*index, pallet_evm::AccountStorages::<T>::get(address, index)))
+					.collect(),
+			}));
+		}
+	}
+
+	fn touch(&mut self, address: H160) {
+		self.touched.entry(address).or_insert_with(BTreeSet::new);
+	}
+
+	fn touch_storage(&mut self, address: H160, index: H256) {
+		self.touched
+			.entry(address)
+			.or_insert_with(BTreeSet::new)
+			.insert(index);
+	}
+}
+
+impl<T: pallet_evm::Config> RuntimeListener for StateTracer<T> {
+	fn event(&mut self, event: RuntimeEvent) {
+		match event {
+			RuntimeEvent::SLoad { address, index, .. }
+			| RuntimeEvent::SStore { address, index, .. } => self.touch_storage(address, index),
+			// We ignore other kinds of message if any (new ones may be added in the future).
+			#[allow(unreachable_patterns)]
+			_ => (),
+		}
+	}
+}
+
+impl<T: pallet_evm::Config> EvmListener for StateTracer<T> {
+	fn event(&mut self, event: EvmEvent) {
+		match event {
+			EvmEvent::TransactCall {
+				caller, address, ..
+			}
+			| EvmEvent::TransactCreate {
+				caller, address, ..
+			}
+			| EvmEvent::TransactCreate2 {
+				caller, address, ..
+			}
+			| EvmEvent::Create {
+				caller, address, ..
+			} => {
+				self.touch(caller);
+				self.touch(address);
+			}
+			EvmEvent::Call {
+				code_address,
+				transfer,
+				context,
+				..
+			} => {
+				self.touch(code_address);
+				self.touch(context.address);
+				self.touch(context.caller);
+				if let Some(transfer) = transfer {
+					self.touch(transfer.source);
+					self.touch(transfer.target);
+				}
+			}
+			EvmEvent::Suicide {
+				address, target, ..
+			} => {
+				self.touch(address);
+				self.touch(target);
+			}
+			// We ignore other kinds of message if any (new ones may be added in the future).
+			#[allow(unreachable_patterns)]
+			_ => (),
+		}
+	}
+}
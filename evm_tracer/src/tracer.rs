@@ -0,0 +1,99 @@
+// Copyright 2019-2021 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::util::*;
+
+use hedgeware_evm_tracing_events::{
+	Event, EventFilter, EvmEvent as OwnedEvmEvent, GasometerEvent as OwnedGasometerEvent,
+	RuntimeEvent as OwnedRuntimeEvent, TransactionOrigin,
+};
+use sp_std::marker::PhantomData;
+
+/// Forward the events of the EVM to the client, which builds the trace.
+///
+/// The `EventFilter` restricts the data sent with the events to what the requested trace
+/// needs, the code of the called accounts being read from the state of the pallet.
+pub struct EvmTracer<T> {
+	filter: EventFilter,
+
+	_marker: PhantomData<T>,
+}
+
+impl<T: pallet_evm::Config> EvmTracer<T> {
+	pub fn new(filter: EventFilter) -> Self {
+		Self {
+			filter,
+
+			_marker: PhantomData,
+		}
+	}
+
+	/// Setup event listeners and execute provided closure.
+	pub fn trace<R, F: FnOnce() -> R>(self, f: F) -> R {
+		let wrapped = Rc::new(RefCell::new(self));
+
+		let mut gasometer = ListenerProxy(Rc::clone(&wrapped));
+		let mut runtime = ListenerProxy(Rc::clone(&wrapped));
+		let mut evm = ListenerProxy(Rc::clone(&wrapped));
+
+		// Each line wraps the previous `f` into a `using` call.
+		// Listening to new events results in adding one new line.
+		// Order is irrelevant when registering listeners.
+		let f = || runtime_using(&mut runtime, f);
+		let f = || gasometer_using(&mut gasometer, f);
+		let f = || evm_using(&mut evm, f);
+		f()
+	}
+
+	/// Notify the client that the following events belong to a new transaction.
+	pub fn emit_new_transaction(origin: TransactionOrigin) {
+		emit(Event::NewTransaction(origin));
+	}
+}
+
+impl<T: pallet_evm::Config> GasometerListener for EvmTracer<T> {
+	fn event(&mut self, event: GasometerEvent) {
+		emit(Event::Gasometer(OwnedGasometerEvent::from(event)));
+	}
+}
+
+impl<T: pallet_evm::Config> RuntimeListener for EvmTracer<T> {
+	fn event(&mut self, event: RuntimeEvent) {
+		emit(Event::Runtime(OwnedRuntimeEvent::from_evm_event(
+			event,
+			self.filter,
+		)));
+	}
+}
+
+impl<T: pallet_evm::Config> EvmListener for EvmTracer<T> {
+	fn event(&mut self, event: EvmEvent) {
+		let event = OwnedEvmEvent::from(event);
+		let code_address = match &event {
+			OwnedEvmEvent::Call { code_address, .. } if self.filter.enable_code => {
+				Some(*code_address)
+			}
+			_ => None,
+		};
+
+		emit(Event::Evm(event));
+		if let Some(code_address) = code_address {
+			emit(Event::CallCode(pallet_evm::AccountCodes::<T>::get(
+				code_address,
+			)));
+		}
+	}
+}
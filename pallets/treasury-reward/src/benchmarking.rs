@@ -0,0 +1,95 @@
+//! Benchmarking setup for treasury-reward
+
+#![cfg(feature = "runtime-benchmarks")]
+
+use super::*;
+
+use frame_benchmarking::{account, benchmarks, impl_benchmark_test_suite};
+use frame_support::traits::OnFinalize;
+use frame_system::RawOrigin;
+use sp_runtime::traits::One;
+
+#[allow(unused)]
+use crate::Pallet as TreasuryReward;
+
+const SEED: u32 = 0;
+/// Upper bound on the recipient set used for the benchmarks. Every recipient
+/// gets 1% so the set never has to be diluted while it is being filled.
+const MAX_RECIPIENTS: u32 = 40;
+
+/// Replaces the recipient set with `r` fresh accounts holding 1% each.
+fn setup_recipients<T: Config>(r: u32) -> Vec<T::AccountId> {
+	for recipient in Recipients::<T>::get() {
+		RecipientPercentages::<T>::remove(recipient);
+	}
+	Recipients::<T>::kill();
+
+	(0..r)
+		.map(|i| {
+			let recipient: T::AccountId = account("recipient", i, SEED);
+			Pallet::<T>::add_recipient(recipient.clone(), Percent::from_percent(1), Percent::from_percent(1));
+			recipient
+		})
+		.collect()
+}
+
+benchmarks! {
+	add {
+		let r in 0 .. MAX_RECIPIENTS;
+		setup_recipients::<T>(r);
+		let recipient: T::AccountId = account("new_recipient", 0, SEED);
+	}: _(RawOrigin::Root, recipient.clone(), T::MaximumRecipientPct::get())
+	verify {
+		assert!(Recipients::<T>::get().contains(&recipient));
+	}
+
+	remove {
+		let r in 1 .. MAX_RECIPIENTS;
+		let recipients = setup_recipients::<T>(r);
+		let recipient = recipients[0].clone();
+	}: _(RawOrigin::Root, recipient.clone())
+	verify {
+		assert!(!Recipients::<T>::get().contains(&recipient));
+	}
+
+	update {
+		let r in 1 .. MAX_RECIPIENTS;
+		let recipients = setup_recipients::<T>(r);
+		let recipient = recipients[0].clone();
+	}: _(RawOrigin::Root, recipient.clone(), T::MaximumRecipientPct::get())
+	verify {
+		assert_eq!(
+			RecipientPercentages::<T>::get(recipient).map(|alloc| alloc.proposed),
+			Some(T::MaximumRecipientPct::get()),
+		);
+	}
+
+	set_minting_interval {
+		let interval: T::BlockNumber = 10u32.into();
+	}: _(RawOrigin::Root, interval)
+	verify {
+		assert_eq!(MintingInterval::<T>::get(), interval);
+	}
+
+	set_current_payout {
+		let amount: BalanceOf<T> = 1_000_000u32.into();
+	}: _(RawOrigin::Root, amount)
+	verify {
+		assert_eq!(CurrentPayout::<T>::get(), amount);
+	}
+
+	on_finalize {
+		let r in 0 .. MAX_RECIPIENTS;
+		setup_recipients::<T>(r);
+		MintingInterval::<T>::put(T::BlockNumber::one());
+		CurrentPayout::<T>::put(BalanceOf::<T>::from(1_000_000u32));
+	}: {
+		<TreasuryReward<T> as OnFinalize<T::BlockNumber>>::on_finalize(T::BlockNumber::one());
+	}
+}
+
+impl_benchmark_test_suite!(
+	TreasuryReward,
+	crate::mock::new_test_ext(None, None),
+	crate::mock::Test,
+);
@@ -23,7 +23,11 @@ pub mod mock;
 #[cfg(test)]
 pub mod tests;
 
+mod benchmarking;
+pub mod weights;
+
 pub use pallet::*;
+pub use weights::WeightInfo;
 
 #[frame_support::pallet]
 pub mod pallet {
@@ -53,6 +57,9 @@ pub mod pallet {
 		/// The default treasury reward address that will receive funds
 		#[pallet::constant]
 		type DefaultRewardAddress: Get<PalletId>;
+
+		/// Weight information for extrinsics in this pallet.
+		type WeightInfo: WeightInfo;
 	}
 
 	#[pallet::event]
@@ -70,14 +77,34 @@ pub mod pallet {
 	pub enum Error<T> {
 		FailedToAdd,
 		FailedToRemove,
+		/// The minting interval must be at least one block.
+		ZeroMintingInterval,
 	}
 
 	#[pallet::hooks]
 	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
-		
+		/// Account for the minting done in `on_finalize` up front.
+		fn on_initialize(n: T::BlockNumber) -> Weight {
+			if Self::is_minting_block(n) {
+				T::WeightInfo::on_finalize(Self::recipient_count())
+			} else {
+				T::DbWeight::get().reads(1)
+			}
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn pre_upgrade() -> Result<(), &'static str> {
+			Self::ensure_invariants()
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn post_upgrade() -> Result<(), &'static str> {
+			Self::ensure_invariants()
+		}
+
 		/// Mint money for the treasury and recipient pool!
 		fn on_finalize(_n: T::BlockNumber) {
-			if <frame_system::Pallet<T>>::block_number() % Self::minting_interval() == Zero::zero() {
+			if Self::is_minting_block(<frame_system::Pallet<T>>::block_number()) {
 				let reward = Self::current_payout();
 				// get up front treasury reward from minimum amount that is always allocated
 				let mut treasury_reward = T::MinimumTreasuryPct::get() * reward;
@@ -196,6 +223,7 @@ pub mod pallet {
 	#[pallet::genesis_build]
 	impl<T: Config> GenesisBuild<T> for GenesisConfig<T> {
 		fn build(&self) {
+			assert!(!self.minting_interval.is_zero(), "The minting interval must be at least one block");
 			MintingInterval::<T>::put(self.minting_interval);
 			CurrentPayout::<T>::put(self.current_payout);
 			// The add_extra_genesis build logic
@@ -224,7 +252,7 @@ pub mod pallet {
 		/// the submitted percentage of the leftover treasury reward.
 		/// If there is no leftover allocation, the other recipients'
 		/// reward percentages will be diluted.
-		#[pallet::weight(5_000_000)]
+		#[pallet::weight(T::WeightInfo::add(Self::recipient_count()))]
 		pub(super) fn add(origin: OriginFor<T>, recipient: T::AccountId, pct: Percent) -> DispatchResult {
 			ensure_root(origin)?;
 			ensure!(pct.deconstruct() <= T::MaximumRecipientPct::get().deconstruct(), "Invalid proposed percentage. Too large.");
@@ -252,7 +280,7 @@ pub mod pallet {
 		/// all remaining participants current percentages by that deleted amount.
 		/// Dilution should only occur up until the proposed percentages each
 		/// active participant was added to the set with.
-		#[pallet::weight(5_000_000)]
+		#[pallet::weight(T::WeightInfo::remove(Self::recipient_count()))]
 		pub(super) fn remove(origin: OriginFor<T>, recipient: T::AccountId) -> DispatchResult {
 			ensure_root(origin)?;
 			ensure!(Self::recipients().contains(&recipient), "Recipient doesn't exist");
@@ -269,7 +297,7 @@ pub mod pallet {
 		/// Updates an existing recipients allocation by removing and adding
 		/// them into the set. This will cause a dilution and inflation of the
 		/// set and does lose precision in the process.
-		#[pallet::weight(5_000_000)]
+		#[pallet::weight(T::WeightInfo::update(Self::recipient_count()))]
 		pub(super) fn update(origin: OriginFor<T>, recipient: T::AccountId, pct: Percent) -> DispatchResult {
 			ensure_root(origin.clone())?;
 			ensure!(pct.deconstruct() <= T::MaximumRecipientPct::get().deconstruct(), "Invalid proposed percentage. Too large.");
@@ -280,16 +308,17 @@ pub mod pallet {
 		}
 
 		/// Updates the minting interval of the treasury reward process
-		#[pallet::weight(5_000_000)]
+		#[pallet::weight(T::WeightInfo::set_minting_interval())]
 		pub(super) fn set_minting_interval(origin: OriginFor<T>, interval: T::BlockNumber) -> DispatchResult {
 			ensure_root(origin)?;
+			ensure!(!interval.is_zero(), Error::<T>::ZeroMintingInterval);
 			<MintingInterval<T>>::put(interval);
 			Self::deposit_event(Event::MintingIntervalUpdate(interval));
 			Ok(())
 		}
 
 		/// Updates the current payout of the treasury reward process
-		#[pallet::weight(5_000_000)]
+		#[pallet::weight(T::WeightInfo::set_current_payout())]
 		pub(super) fn set_current_payout(origin: OriginFor<T>, amount: BalanceOf<T>) -> DispatchResult {
 			ensure_root(origin)?;
 			<CurrentPayout<T>>::put(amount);
@@ -300,6 +329,54 @@ pub mod pallet {
 }
 
 impl<T: Config> Pallet<T> {
+	/// Number of recipients currently in the set, used to scale weights
+	pub(crate) fn recipient_count() -> u32 {
+		<Recipients<T>>::decode_len().unwrap_or_default() as u32
+	}
+
+	/// Whether the treasury reward is minted at block `n`. A zero interval,
+	/// which can only come from storage written outside of this pallet,
+	/// disables minting instead of panicking.
+	pub(crate) fn is_minting_block(n: T::BlockNumber) -> bool {
+		let interval = Self::minting_interval();
+		!interval.is_zero() && (n % interval).is_zero()
+	}
+
+	/// Checks the storage invariants the minting logic relies on.
+	#[cfg(any(feature = "try-runtime", test))]
+	pub fn ensure_invariants() -> Result<(), &'static str> {
+		let recipients = Self::recipients();
+		let mut sorted = recipients.clone();
+		sorted.sort();
+		sorted.dedup();
+		if sorted.len() != recipients.len() {
+			return Err("Duplicate treasury reward recipients");
+		}
+
+		let mut sum: u32 = 0;
+		for recipient in recipients.iter() {
+			let alloc = Self::recipient_percentages(recipient)
+				.ok_or("Treasury reward recipient without an allocation")?;
+			if alloc.current > alloc.proposed {
+				return Err("Current allocation above the proposed one");
+			}
+			sum += alloc.current.deconstruct() as u32;
+		}
+		if sum > 100 {
+			return Err("Recipient allocations sum to more than 100 percent");
+		}
+
+		if <RecipientPercentages<T>>::iter().count() != recipients.len() {
+			return Err("Allocation left behind for a removed recipient");
+		}
+
+		if Self::minting_interval().is_zero() {
+			return Err("Minting interval is zero");
+		}
+
+		Ok(())
+	}
+
 	/// Check whether account_id is a module account
 	pub(crate) fn get_treasury_account() -> T::AccountId {
 		T::DefaultRewardAddress::get().into_account()
@@ -96,6 +96,7 @@ impl Config for Test {
 	type MinimumTreasuryPct = MinimumTreasuryPct;
 	type MaximumRecipientPct = MaximumRecipientPct;
 	type DefaultRewardAddress = TreasuryPalletId;
+	type WeightInfo = ();
 }
 
 pub(crate) fn new_test_ext(recipients: Option<Vec<AccountId>>, pcts: Option<Vec<Percent>>) -> sp_io::TestExternalities {
@@ -1,7 +1,7 @@
 use super::*;
 use crate::mock::*;
 use frame_support::{assert_ok, assert_noop};
-use frame_support::{traits::{OnFinalize}};
+use frame_support::{traits::{OnFinalize, OnInitialize}};
 
 #[test]
 fn basic_setup_works() {
@@ -299,4 +299,54 @@ fn payout_participants_and_treasury_successfully() {
 		assert_eq!(Balances::free_balance(AccountId::new([202; 32])), 475 * DOLLARS / 100);
 		assert_eq!(Balances::free_balance(AccountId::new([203; 32])), 475 * DOLLARS / 100);
 	});
-}
\ No newline at end of file
+}
+
+#[test]
+fn invariants_hold_across_recipient_changes() {
+	new_test_ext(
+		Some(vec![AccountId::new([1; 32]), AccountId::new([2; 32]), AccountId::new([3; 32])]),
+		Some(vec![Percent::from_percent(30), Percent::from_percent(30), Percent::from_percent(30)]),
+	).execute_with(|| {
+		assert_ok!(TreasuryReward::ensure_invariants());
+		assert_ok!(add_recipient(AccountId::new([4; 32]), Percent::from_percent(40)));
+		assert_ok!(TreasuryReward::ensure_invariants());
+		assert_ok!(update(AccountId::new([2; 32]), Percent::from_percent(5)));
+		assert_ok!(TreasuryReward::ensure_invariants());
+		assert_ok!(remove_recipient(AccountId::new([1; 32])));
+		assert_ok!(TreasuryReward::ensure_invariants());
+	});
+}
+
+#[test]
+fn invariants_catch_dangling_allocation() {
+	new_test_ext(None, None).execute_with(|| {
+		<Recipients<Test>>::put(vec![AccountId::new([1; 32]), AccountId::new([2; 32])]);
+		assert_eq!(
+			TreasuryReward::ensure_invariants(),
+			Err("Allocation left behind for a removed recipient"),
+		);
+	});
+}
+
+#[test]
+fn zero_minting_interval_is_rejected() {
+	new_test_ext(None, None).execute_with(|| {
+		assert_noop!(
+			TreasuryReward::set_minting_interval(Origin::root(), 0),
+			Error::<Test>::ZeroMintingInterval,
+		);
+		assert_eq!(TreasuryReward::minting_interval(), 1);
+	});
+}
+
+#[test]
+fn zero_minting_interval_in_storage_disables_minting() {
+	new_test_ext(Some(vec![]), Some(vec![])).execute_with(|| {
+		let treasury_address: AccountId = TreasuryPalletId::get().into_account();
+		<MintingInterval<Test>>::put(0);
+		System::set_block_number(1);
+		<TreasuryReward as OnInitialize<u64>>::on_initialize(1);
+		<TreasuryReward as OnFinalize<u64>>::on_finalize(1);
+		assert_eq!(Balances::free_balance(treasury_address), 0);
+	});
+}
@@ -21,6 +21,10 @@ use crate::serialization::*;
 #[cfg(feature = "std")]
 use serde::Serialize;
 
+use crate::{
+	single::{Call, CallInner, Log},
+	CallResult, CreateResult, CreateType,
+};
 use codec::{Decode, Encode};
 use ethereum_types::{H160, H256, U256};
 use sp_std::vec::Vec;
@@ -38,9 +42,41 @@ pub struct TransactionTrace {
 	pub output: TransactionTraceOutput,
 	pub subtraces: u32,
 	pub trace_address: Vec<u32>,
+	/// Hash of the Ethereum transaction, or of the extrinsic for Substrate-originated traces.
 	#[cfg_attr(feature = "std", serde(serialize_with = "h256_0x_serialize"))]
 	pub transaction_hash: H256,
-	pub transaction_position: u32,
+	/// Position of the transaction in the Ethereum block, `null` for Substrate-originated
+	/// traces as they have no Ethereum transaction.
+	pub transaction_position: Option<u32>,
+	/// Position of the extrinsic in the Substrate block, only set for Substrate-originated
+	/// traces.
+	#[cfg_attr(feature = "std", serde(skip_serializing_if = "Option::is_none"))]
+	pub extrinsic_index: Option<u32>,
+	/// Logs emitted by the call, the ones of failed calls being marked as reverted.
+	#[cfg_attr(feature = "std", serde(skip_serializing_if = "Vec::is_empty"))]
+	pub logs: Vec<Log>,
+	/// Only part of the output for Substrate-originated traces, the other ones following the
+	/// format of OpenEthereum.
+	#[cfg_attr(feature = "std", serde(skip_serializing_if = "TraceOrigin::is_ethereum"))]
+	pub origin: TraceOrigin,
+}
+
+/// What started the traced EVM execution.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub enum TraceOrigin {
+	/// An Ethereum transaction.
+	Ethereum,
+	/// Any other extrinsic, such as `EVM::call`, `EVM::create` or an XCM `Transact`. Such an
+	/// execution has no Ethereum transaction and is identified by its extrinsic.
+	Substrate,
+}
+
+impl TraceOrigin {
+	pub fn is_ethereum(&self) -> bool {
+		*self == TraceOrigin::Ethereum
+	}
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
@@ -79,10 +115,31 @@ pub enum TransactionTraceAction {
 
 #[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
 #[cfg_attr(feature = "std", derive(Serialize))]
-#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase", untagged))]
 pub enum TransactionTraceOutput {
-	Result(TransactionTraceResult),
-	Error(#[cfg_attr(feature = "std", serde(serialize_with = "string_serialize"))] Vec<u8>),
+	Result {
+		result: TransactionTraceResult,
+	},
+	#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+	Error {
+		#[cfg_attr(feature = "std", serde(serialize_with = "string_serialize"))]
+		error: Vec<u8>,
+		/// Decoded revert data, see `crate::decode_revert_reason`.
+		#[cfg_attr(
+			feature = "std",
+			serde(
+				serialize_with = "option_string_serialize",
+				skip_serializing_if = "Option::is_none"
+			)
+		)]
+		revert_reason: Option<Vec<u8>>,
+		/// Raw revert data, kept for custom errors.
+		#[cfg_attr(
+			feature = "std",
+			serde(serialize_with = "bytes_0x_serialize", skip_serializing_if = "Vec::is_empty")
+		)]
+		output: Vec<u8>,
+	},
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
@@ -104,3 +161,117 @@ pub enum TransactionTraceResult {
 	},
 	Suicide,
 }
+
+impl TransactionTrace {
+	/// Convert a call of the "single" format into the "block" format.
+	///
+	/// The block and transaction hashes and the block number can't be known from the
+	/// runtime and must be inserted upstream.
+	pub fn from_call(call: Call, transaction_position: u32) -> Self {
+		let (action, output) = match call.inner {
+			CallInner::Call {
+				call_type,
+				to,
+				input,
+				res,
+			} => (
+				TransactionTraceAction::Call {
+					call_type,
+					from: call.from,
+					gas: call.gas,
+					input,
+					to,
+					value: call.value,
+				},
+				match res {
+					CallResult::Output { output } => TransactionTraceOutput::Result {
+						result: TransactionTraceResult::Call {
+							gas_used: call.gas_used,
+							output,
+						},
+					},
+					CallResult::Error {
+						error,
+						revert_reason,
+						output,
+					} => TransactionTraceOutput::Error {
+						error,
+						revert_reason,
+						output,
+					},
+				},
+			),
+			CallInner::Create { init, res } => (
+				TransactionTraceAction::Create {
+					creation_method: CreateType::Create,
+					from: call.from,
+					gas: call.gas,
+					init,
+					value: call.value,
+				},
+				match res {
+					CreateResult::Success {
+						created_contract_address_hash,
+						created_contract_code,
+					} => TransactionTraceOutput::Result {
+						result: TransactionTraceResult::Create {
+							address: created_contract_address_hash,
+							code: created_contract_code,
+							gas_used: call.gas_used,
+						},
+					},
+					CreateResult::Error {
+						error,
+						revert_reason,
+						output,
+					} => TransactionTraceOutput::Error {
+						error,
+						revert_reason,
+						output,
+					},
+				},
+			),
+			CallInner::SelfDestruct {
+				balance,
+				refund_address,
+			} => (
+				TransactionTraceAction::Suicide {
+					address: call.from,
+					balance,
+					refund_address,
+				},
+				TransactionTraceOutput::Result {
+					result: TransactionTraceResult::Suicide,
+				},
+			),
+		};
+
+		Self {
+			action,
+			block_hash: H256::default(),
+			block_number: 0,
+			output,
+			subtraces: call.subtraces,
+			trace_address: call.trace_address,
+			transaction_hash: H256::default(),
+			transaction_position: Some(transaction_position),
+			extrinsic_index: None,
+			logs: call.logs,
+			origin: TraceOrigin::Ethereum,
+		}
+	}
+
+	/// Convert a call of the "single" format, started by an extrinsic which isn't an Ethereum
+	/// transaction, into the "block" format.
+	///
+	/// The block hash and number must be inserted upstream.
+	pub fn from_substrate_call(call: Call, extrinsic_index: u32, extrinsic_hash: H256) -> Self {
+		Self {
+			transaction_hash: extrinsic_hash,
+			transaction_position: None,
+			extrinsic_index: Some(extrinsic_index),
+			origin: TraceOrigin::Substrate,
+			..Self::from_call(call, 0)
+		}
+	}
+}
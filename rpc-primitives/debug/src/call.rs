@@ -0,0 +1,46 @@
+// Copyright 2019-2020 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Types for the tracing of unsigned calls, executed on top of the state of a block.
+
+use codec::{Decode, Encode};
+use ethereum_types::{H160, H256, U256};
+use sp_std::{collections::btree_map::BTreeMap, vec::Vec};
+
+/// Unsigned call, with the same parameters as `EthereumRuntimeRPCApi::call`.
+#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+pub struct TraceCall {
+	pub from: H160,
+	/// Contract creation if `None`.
+	pub to: Option<H160>,
+	pub data: Vec<u8>,
+	pub value: U256,
+	pub gas_limit: U256,
+	pub gas_price: Option<U256>,
+	pub nonce: Option<U256>,
+}
+
+/// State of an account overridden before executing the calls.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Encode, Decode)]
+pub struct AccountOverride {
+	pub balance: Option<U256>,
+	pub nonce: Option<U256>,
+	pub code: Option<Vec<u8>>,
+	/// Replaces the whole storage of the account.
+	pub state: Option<BTreeMap<H256, H256>>,
+	/// Replaces some slots of the storage of the account.
+	pub state_diff: BTreeMap<H256, H256>,
+}
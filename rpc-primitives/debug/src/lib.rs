@@ -18,23 +18,71 @@
 
 use codec::{Decode, Encode};
 use ethereum::Transaction;
-use ethereum_types::H160;
-use sp_std::vec::Vec;
+use ethereum_types::{H160, U256};
+use sp_std::{collections::btree_map::BTreeMap, vec::Vec};
 
 #[cfg(feature = "std")]
 use serde::Serialize;
 
 sp_api::decl_runtime_apis! {
+	/// Tracing of EVM executions. The traces are not returned: the events of the executions
+	/// are sent to the client through the host functions of `hedgeware-primitives-ext`, while
+	/// the methods are running, and the trace types only select what the runtime sends.
+	///
+	/// Only tracing runtimes implement these methods, which fail otherwise.
+	///
+	/// Version 1 runtimes only provide `trace_transaction` and `trace_block`, which return the
+	/// traces built by the runtime itself.
+	#[api_version(2)]
 	pub trait DebugRuntimeApi {
+		#[changed_in(2)]
+		fn trace_transaction(
+			extrinsics: Vec<Block::Extrinsic>,
+			transaction: &Transaction,
+			trace_type: v1::single::TraceType,
+		) -> Result<v1::single::TransactionTrace, sp_runtime::DispatchError>;
+
 		fn trace_transaction(
 			extrinsics: Vec<Block::Extrinsic>,
 			transaction: &Transaction,
 			trace_type: single::TraceType,
-		) -> Result<single::TransactionTrace, sp_runtime::DispatchError>;
+		) -> Result<(), sp_runtime::DispatchError>;
+
+		#[changed_in(2)]
+		fn trace_block(
+			extrinsics: Vec<Block::Extrinsic>,
+		) -> Result<Vec<v1::block::TransactionTrace>, sp_runtime::DispatchError>;
 
 		fn trace_block(
 			extrinsics: Vec<Block::Extrinsic>,
-		) -> Result<Vec<block::TransactionTrace>, sp_runtime::DispatchError>;
+		) -> Result<(), sp_runtime::DispatchError>;
+
+		fn trace_block_transactions(
+			extrinsics: Vec<Block::Extrinsic>,
+			trace_type: single::TraceType,
+		) -> Result<(), sp_runtime::DispatchError>;
+
+		fn trace_call(
+			call: call::TraceCall,
+			state_overrides: BTreeMap<H160, call::AccountOverride>,
+			trace_type: single::TraceType,
+		) -> Result<(), sp_runtime::DispatchError>;
+
+		fn replay_calls(
+			calls: Vec<(call::TraceCall, replay::ReplayTraceType)>,
+			state_overrides: BTreeMap<H160, call::AccountOverride>,
+		) -> Result<(), sp_runtime::DispatchError>;
+
+		fn replay_transaction(
+			extrinsics: Vec<Block::Extrinsic>,
+			transaction: &Transaction,
+			trace_type: replay::ReplayTraceType,
+		) -> Result<(), sp_runtime::DispatchError>;
+
+		fn replay_block(
+			extrinsics: Vec<Block::Extrinsic>,
+			trace_type: replay::ReplayTraceType,
+		) -> Result<(), sp_runtime::DispatchError>;
 	}
 }
 
@@ -45,24 +93,67 @@ pub mod serialization;
 use crate::serialization::*;
 
 pub mod block;
+pub mod call;
+pub mod replay;
 pub mod single;
+pub mod v1;
+
+#[cfg(test)]
+mod tests;
 
 #[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
 #[cfg_attr(feature = "std", derive(Serialize))]
-#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase", untagged))]
 pub enum CallResult {
-	Output(#[cfg_attr(feature = "std", serde(serialize_with = "bytes_0x_serialize"))] Vec<u8>),
-	// field "error"
-	Error(#[cfg_attr(feature = "std", serde(serialize_with = "string_serialize"))] Vec<u8>),
+	Output {
+		#[cfg_attr(feature = "std", serde(serialize_with = "bytes_0x_serialize"))]
+		output: Vec<u8>,
+	},
+	#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+	Error {
+		#[cfg_attr(feature = "std", serde(serialize_with = "string_serialize"))]
+		error: Vec<u8>,
+		/// Decoded revert data, see `decode_revert_reason`.
+		#[cfg_attr(
+			feature = "std",
+			serde(
+				serialize_with = "option_string_serialize",
+				skip_serializing_if = "Option::is_none"
+			)
+		)]
+		revert_reason: Option<Vec<u8>>,
+		/// Raw revert data, kept for custom errors.
+		#[cfg_attr(
+			feature = "std",
+			serde(serialize_with = "bytes_0x_serialize", skip_serializing_if = "Vec::is_empty")
+		)]
+		output: Vec<u8>,
+	},
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
 #[cfg_attr(feature = "std", derive(Serialize))]
 #[cfg_attr(feature = "std", serde(rename_all = "camelCase", untagged))]
 pub enum CreateResult {
+	#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
 	Error {
 		#[cfg_attr(feature = "std", serde(serialize_with = "string_serialize"))]
 		error: Vec<u8>,
+		/// Decoded revert data, see `decode_revert_reason`.
+		#[cfg_attr(
+			feature = "std",
+			serde(
+				serialize_with = "option_string_serialize",
+				skip_serializing_if = "Option::is_none"
+			)
+		)]
+		revert_reason: Option<Vec<u8>>,
+		/// Raw revert data, kept for custom errors.
+		#[cfg_attr(
+			feature = "std",
+			serde(serialize_with = "bytes_0x_serialize", skip_serializing_if = "Vec::is_empty")
+		)]
+		output: Vec<u8>,
 	},
 	Success {
 		#[cfg_attr(feature = "std", serde(rename = "createdContractAddressHash"))]
@@ -91,3 +182,57 @@ pub enum CallType {
 pub enum CreateType {
 	Create,
 }
+
+/// Selector of `Error(string)`, the revert data of `revert("...")` and `require(.., "...")`.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of `Panic(uint256)`, the revert data of failed assertions and checked arithmetic.
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Decode the revert data of a call into a human-readable message.
+///
+/// Only the standard `Error(string)` and `Panic(uint256)` payloads can be decoded, using the
+/// same messages as geth for the panic codes. `None` is returned for anything else, such as
+/// custom errors.
+pub fn decode_revert_reason(data: &[u8]) -> Option<Vec<u8>> {
+	if data.len() < 4 {
+		return None;
+	}
+	let (selector, payload) = data.split_at(4);
+
+	if selector == ERROR_SELECTOR {
+		let offset = abi_usize(payload.get(0..32)?)?;
+		let length = abi_usize(payload.get(offset..offset.checked_add(32)?)?)?;
+		let start = offset.checked_add(32)?;
+		let reason = payload.get(start..start.checked_add(length)?)?;
+
+		// Invalid UTF-8 cannot be serialized as a message, the raw data is still available.
+		sp_std::str::from_utf8(reason).ok()?;
+		Some(reason.to_vec())
+	} else if selector == PANIC_SELECTOR {
+		let reason = match abi_usize(payload.get(0..32)?)? {
+			0x00 => "generic panic",
+			0x01 => "assert(false)",
+			0x11 => "arithmetic underflow or overflow",
+			0x12 => "division or modulo by zero",
+			0x21 => "enum overflow",
+			0x22 => "invalid encoded storage byte array accessed",
+			0x31 => "out-of-bounds array access; popping on an empty array",
+			0x32 => "out-of-bounds access of an array or bytesN",
+			0x41 => "out of memory",
+			0x51 => "uninitialized function",
+			_ => return None,
+		};
+		Some(reason.as_bytes().to_vec())
+	} else {
+		None
+	}
+}
+
+/// Decode an ABI encoded `uint256` used as an offset, a length or a code.
+fn abi_usize(word: &[u8]) -> Option<usize> {
+	let value = U256::from_big_endian(word);
+	if value > U256::from(u32::MAX) {
+		return None;
+	}
+	Some(value.low_u32() as usize)
+}
@@ -0,0 +1,178 @@
+// Copyright 2019-2020 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Types for the replay of Ethereum transactions, matching the output of OpenEthereum's
+//! `trace_replayTransaction` and `trace_replayBlockTransactions`.
+
+#[cfg(feature = "std")]
+use crate::serialization::*;
+#[cfg(feature = "std")]
+use serde::Serialize;
+
+use crate::block::{self, TransactionTraceAction, TransactionTraceOutput};
+use codec::{Decode, Encode};
+use ethereum_types::{H160, H256, U256};
+use sp_std::{collections::btree_map::BTreeMap, vec::Vec};
+
+/// Outputs requested when replaying a transaction.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default, Encode, Decode)]
+pub struct ReplayTraceType {
+	/// Flat list of calls and subcalls.
+	pub trace: bool,
+	/// Balance, nonce, code and storage changes of the touched accounts.
+	pub state_diff: bool,
+	/// Executed opcodes, nested by call.
+	pub vm_trace: bool,
+}
+
+/// Replay of a single transaction.
+#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct TransactionReplay {
+	#[cfg_attr(feature = "std", serde(serialize_with = "bytes_0x_serialize"))]
+	pub output: Vec<u8>,
+	pub state_diff: Option<BTreeMap<H160, AccountDiff>>,
+	pub trace: Vec<TransactionTrace>,
+	pub vm_trace: Option<VmTrace>,
+	/// Only known upstream, and only part of the output when replaying a whole block.
+	#[cfg_attr(
+		feature = "std",
+		serde(
+			serialize_with = "option_h256_0x_serialize",
+			skip_serializing_if = "Option::is_none"
+		)
+	)]
+	pub transaction_hash: Option<H256>,
+}
+
+/// Same as `block::TransactionTrace`, without the fields related to the block or the
+/// transaction which are implied by the request.
+#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct TransactionTrace {
+	#[cfg_attr(feature = "std", serde(flatten))]
+	pub action: TransactionTraceAction,
+	#[cfg_attr(feature = "std", serde(flatten))]
+	pub output: TransactionTraceOutput,
+	pub subtraces: u32,
+	pub trace_address: Vec<u32>,
+}
+
+impl From<block::TransactionTrace> for TransactionTrace {
+	fn from(trace: block::TransactionTrace) -> Self {
+		Self {
+			action: trace.action,
+			output: trace.output,
+			subtraces: trace.subtraces,
+			trace_address: trace.trace_address,
+		}
+	}
+}
+
+/// Change of a value during the transaction.
+#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+pub enum Diff<T> {
+	/// Unchanged value.
+	#[cfg_attr(feature = "std", serde(rename = "="))]
+	Same,
+	/// Value of an account created by the transaction.
+	#[cfg_attr(feature = "std", serde(rename = "+"))]
+	Born(T),
+	/// Value of an account destroyed by the transaction.
+	#[cfg_attr(feature = "std", serde(rename = "-"))]
+	Died(T),
+	/// Modified value.
+	#[cfg_attr(feature = "std", serde(rename = "*"))]
+	Changed { from: T, to: T },
+}
+
+impl<T: PartialEq> Diff<T> {
+	pub fn new(from: T, to: T) -> Self {
+		if from == to {
+			Diff::Same
+		} else {
+			Diff::Changed { from, to }
+		}
+	}
+}
+
+/// Bytes serialized as an hexadecimal string.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+pub struct Bytes(
+	#[cfg_attr(feature = "std", serde(serialize_with = "bytes_0x_serialize"))] pub Vec<u8>,
+);
+
+/// Changes of an account during the transaction.
+#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+pub struct AccountDiff {
+	pub balance: Diff<U256>,
+	pub nonce: Diff<U256>,
+	pub code: Diff<Bytes>,
+	pub storage: BTreeMap<H256, Diff<H256>>,
+}
+
+/// Executed opcodes of a call, the ones of the subcalls being nested in the operation
+/// which triggered them.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+pub struct VmTrace {
+	#[cfg_attr(feature = "std", serde(serialize_with = "bytes_0x_serialize"))]
+	pub code: Vec<u8>,
+	pub ops: Vec<VmOperation>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+pub struct VmOperation {
+	pub pc: u64,
+	pub cost: u64,
+	/// Outcome of the operation, missing if it failed.
+	pub ex: Option<VmExecutedOperation>,
+	pub sub: Option<VmTrace>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Default, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+pub struct VmExecutedOperation {
+	/// Remaining gas after the operation.
+	pub used: u64,
+	/// Stack items written by the operation.
+	pub push: Vec<U256>,
+	/// Memory written by the operation.
+	pub mem: Option<VmMemoryDiff>,
+	/// Storage written by the operation.
+	pub store: Option<VmStorageDiff>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+pub struct VmMemoryDiff {
+	pub off: u64,
+	#[cfg_attr(feature = "std", serde(serialize_with = "bytes_0x_serialize"))]
+	pub data: Vec<u8>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+pub struct VmStorageDiff {
+	pub key: U256,
+	pub val: U256,
+}
@@ -18,9 +18,10 @@
 
 use ethereum_types::{H256, U256};
 use serde::{
-	ser::{Error, SerializeSeq},
+	ser::{Error, SerializeMap, SerializeSeq},
 	Serializer,
 };
+use std::collections::BTreeMap;
 
 pub fn seq_h256_serialize<S>(data: &Option<Vec<H256>>, serializer: S) -> Result<S::Ok, S::Error>
 where
@@ -34,6 +35,26 @@ where
 	seq.end()
 }
 
+/// Storage as output by geth's `structLogger`: keys and values without `0x` prefix.
+pub fn option_storage_serialize<S>(
+	data: &Option<BTreeMap<H256, H256>>,
+	serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+	S: Serializer,
+{
+	match data {
+		Some(storage) => {
+			let mut map = serializer.serialize_map(Some(storage.len()))?;
+			for (key, value) in storage {
+				map.serialize_entry(&format!("{:x}", key), &format!("{:x}", value))?;
+			}
+			map.end()
+		}
+		None => serializer.serialize_none(),
+	}
+}
+
 pub fn bytes_0x_serialize<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
 where
 	S: Serializer,
@@ -41,14 +62,31 @@ where
 	serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
 }
 
+pub fn option_bytes_0x_serialize<S>(
+	bytes: &Option<Vec<u8>>,
+	serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+	S: Serializer,
+{
+	match bytes {
+		Some(bytes) => bytes_0x_serialize(bytes, serializer),
+		None => serializer.serialize_none(),
+	}
+}
+
 pub fn opcode_serialize<S>(opcode: &[u8], serializer: S) -> Result<S::Ok, S::Error>
 where
 	S: Serializer,
 {
 	let d = std::str::from_utf8(opcode)
-		.map_err(|_| S::Error::custom("Opcode serialize error."))?
-		.to_uppercase();
-	serializer.serialize_str(&d)
+		.map_err(|_| S::Error::custom("Opcode serialize error."))?;
+	// Undefined opcodes are named by a sentence, `opcode 0xc not defined`.
+	if d.starts_with("opcode ") {
+		serializer.serialize_str(d)
+	} else {
+		serializer.serialize_str(&d.to_uppercase())
+	}
 }
 
 pub fn string_serialize<S>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error>
@@ -61,6 +99,16 @@ where
 	serializer.serialize_str(&d)
 }
 
+pub fn option_string_serialize<S>(value: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+	S: Serializer,
+{
+	match value {
+		Some(value) => string_serialize(value, serializer),
+		None => serializer.serialize_none(),
+	}
+}
+
 pub fn u256_serialize<S>(data: &U256, serializer: S) -> Result<S::Ok, S::Error>
 where
 	S: Serializer,
@@ -68,6 +116,16 @@ where
 	serializer.serialize_u64(data.low_u64())
 }
 
+pub fn option_u256_serialize<S>(data: &Option<U256>, serializer: S) -> Result<S::Ok, S::Error>
+where
+	S: Serializer,
+{
+	match data {
+		Some(data) => u256_serialize(data, serializer),
+		None => serializer.serialize_none(),
+	}
+}
+
 pub fn h256_serialize<S>(data: &H256, serializer: S) -> Result<S::Ok, S::Error>
 where
 	S: Serializer,
@@ -81,3 +139,13 @@ where
 {
 	serializer.serialize_str(&format!("0x{:x}", data))
 }
+
+pub fn option_h256_0x_serialize<S>(data: &Option<H256>, serializer: S) -> Result<S::Ok, S::Error>
+where
+	S: Serializer,
+{
+	match data {
+		Some(data) => h256_0x_serialize(data, serializer),
+		None => serializer.serialize_none(),
+	}
+}
@@ -28,7 +28,7 @@ use codec::{Decode, Encode};
 use ethereum_types::{H160, H256, U256};
 use sp_std::{collections::btree_map::BTreeMap, vec::Vec};
 
-#[derive(Clone, Copy, Eq, PartialEq, Debug, Encode, Decode)]
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Encode, Decode)]
 pub enum TraceType {
 	/// Classic geth with no javascript based tracing.
 	Raw {
@@ -38,6 +38,13 @@ pub enum TraceType {
 	},
 	/// List of calls and subcalls (output Blockscout expects).
 	CallList,
+	/// Nested call frames (output of geth's built-in `callTracer`), optionally with the
+	/// logs emitted by each call.
+	CallTracer { with_log: bool },
+	/// State of the accounts touched by the transaction (output of geth's built-in
+	/// `prestateTracer`). In diff mode both the state before and after the transaction
+	/// are provided, restricted to what has been modified.
+	Prestate { diff_mode: bool },
 }
 
 /// Single transaction trace.
@@ -45,17 +52,35 @@ pub enum TraceType {
 #[cfg_attr(feature = "std", derive(Serialize))]
 #[cfg_attr(feature = "std", serde(rename_all = "camelCase", untagged))]
 pub enum TransactionTrace {
-	/// Classical output of `debug_trace`.
+	/// Classical output of `debug_trace`, matching geth's `structLogger`.
 	#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
 	Raw {
+		/// Gas used by the transaction.
+		#[cfg_attr(feature = "std", serde(serialize_with = "u256_serialize"))]
 		gas: U256,
-		#[cfg_attr(feature = "std", serde(with = "hex"))]
+		/// The transaction reverted or failed with an error.
+		failed: bool,
+		#[cfg_attr(feature = "std", serde(serialize_with = "bytes_0x_serialize"))]
 		return_value: Vec<u8>,
-		step_logs: Vec<RawStepLog>,
+		struct_logs: Vec<RawStepLog>,
+		/// Number of steps of the transaction when only a window of them is provided. Only
+		/// set by the client once the trace is built, so never encoded.
+		#[codec(skip)]
+		#[cfg_attr(feature = "std", serde(skip_serializing_if = "Option::is_none"))]
+		total_steps: Option<u64>,
 	},
 	/// Matches the formatter used by Blockscout.
 	/// Is also used to built output of OpenEthereum's `trace_filter`.
 	CallList(Vec<Call>),
+	/// Matches geth's built-in `callTracer`.
+	CallTracer(CallFrame),
+	/// Matches geth's built-in `prestateTracer`.
+	Prestate(BTreeMap<H160, PrestateAccount>),
+	/// Matches geth's built-in `prestateTracer` with `diffMode` enabled.
+	PrestateDiff {
+		pre: BTreeMap<H160, PrestateAccount>,
+		post: BTreeMap<H160, PrestateAccount>,
+	},
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
@@ -65,7 +90,16 @@ pub struct RawStepLog {
 	#[cfg_attr(feature = "std", serde(serialize_with = "u256_serialize"))]
 	pub depth: U256,
 
-	//error: TODO
+	/// Error the opcode failed with, if any.
+	#[cfg_attr(
+		feature = "std",
+		serde(
+			serialize_with = "option_string_serialize",
+			skip_serializing_if = "Option::is_none"
+		)
+	)]
+	pub error: Option<Vec<u8>>,
+
 	#[cfg_attr(feature = "std", serde(serialize_with = "u256_serialize"))]
 	pub gas: U256,
 
@@ -81,12 +115,23 @@ pub struct RawStepLog {
 	)]
 	pub memory: Option<Vec<H256>>,
 
+	/// Size of the memory in bytes, even if the memory is not captured.
+	#[cfg_attr(feature = "std", serde(serialize_with = "u256_serialize"))]
+	pub mem_size: U256,
+
 	#[cfg_attr(feature = "std", serde(serialize_with = "opcode_serialize"))]
 	pub op: Vec<u8>,
 
 	#[cfg_attr(feature = "std", serde(serialize_with = "u256_serialize"))]
 	pub pc: U256,
 
+	/// Refund counter of the transaction.
+	#[cfg_attr(
+		feature = "std",
+		serde(serialize_with = "u256_serialize", skip_serializing_if = "U256::is_zero")
+	)]
+	pub refund: U256,
+
 	#[cfg_attr(
 		feature = "std",
 		serde(
@@ -96,7 +141,13 @@ pub struct RawStepLog {
 	)]
 	pub stack: Option<Vec<H256>>,
 
-	#[cfg_attr(feature = "std", serde(skip_serializing_if = "Option::is_none"))]
+	#[cfg_attr(
+		feature = "std",
+		serde(
+			serialize_with = "option_storage_serialize",
+			skip_serializing_if = "Option::is_none"
+		)
+	)]
 	pub storage: Option<BTreeMap<H256, H256>>,
 }
 
@@ -152,4 +203,128 @@ pub struct Call {
 	pub gas_used: U256,
 	#[cfg_attr(feature = "std", serde(flatten))]
 	pub inner: CallInner,
+	/// Logs emitted by this context.
+	/// Not part of the Blockscout output, but used by `CallFrame` and `crate::block`.
+	#[cfg_attr(feature = "std", serde(skip))]
+	pub logs: Vec<Log>,
+}
+
+/// Log emitted by a call with one of the `LOG0` to `LOG4` opcodes.
+#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct Log {
+	pub address: H160,
+	pub topics: Vec<H256>,
+	#[cfg_attr(feature = "std", serde(serialize_with = "bytes_0x_serialize"))]
+	pub data: Vec<u8>,
+	/// Number of subcalls made by the call before emitting the log.
+	pub position: u32,
+	/// The log was discarded because the call, or one of its parents, failed.
+	#[cfg_attr(feature = "std", serde(skip_serializing_if = "core::ops::Not::not"))]
+	pub reverted: bool,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "UPPERCASE"))]
+pub enum CallFrameType {
+	Call,
+	CallCode,
+	DelegateCall,
+	StaticCall,
+	Create,
+	SelfDestruct,
+}
+
+impl From<crate::CallType> for CallFrameType {
+	fn from(call_type: crate::CallType) -> Self {
+		match call_type {
+			crate::CallType::Call => Self::Call,
+			crate::CallType::CallCode => Self::CallCode,
+			crate::CallType::DelegateCall => Self::DelegateCall,
+			crate::CallType::StaticCall => Self::StaticCall,
+		}
+	}
+}
+
+/// Call frame as output by geth's `callTracer`, with the subcalls nested into their parent.
+#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct CallFrame {
+	#[cfg_attr(feature = "std", serde(rename = "type"))]
+	pub call_type: CallFrameType,
+	pub from: H160,
+	/// Called address, created contract or refund address of a self destruct.
+	/// Unknown for a failed contract creation.
+	#[cfg_attr(feature = "std", serde(skip_serializing_if = "Option::is_none"))]
+	pub to: Option<H160>,
+	/// Not set for `DELEGATECALL` and `STATICCALL`, which cannot transfer funds.
+	#[cfg_attr(feature = "std", serde(skip_serializing_if = "Option::is_none"))]
+	pub value: Option<U256>,
+	pub gas: U256,
+	pub gas_used: U256,
+	#[cfg_attr(feature = "std", serde(serialize_with = "bytes_0x_serialize"))]
+	pub input: Vec<u8>,
+	#[cfg_attr(
+		feature = "std",
+		serde(
+			serialize_with = "option_bytes_0x_serialize",
+			skip_serializing_if = "Option::is_none"
+		)
+	)]
+	pub output: Option<Vec<u8>>,
+	#[cfg_attr(
+		feature = "std",
+		serde(
+			serialize_with = "option_string_serialize",
+			skip_serializing_if = "Option::is_none"
+		)
+	)]
+	pub error: Option<Vec<u8>>,
+	/// Decoded revert data, see `crate::decode_revert_reason`.
+	#[cfg_attr(
+		feature = "std",
+		serde(
+			serialize_with = "option_string_serialize",
+			skip_serializing_if = "Option::is_none"
+		)
+	)]
+	pub revert_reason: Option<Vec<u8>>,
+	/// Only provided with the `withLog` option, and never for failed calls.
+	#[cfg_attr(feature = "std", serde(skip_serializing_if = "Vec::is_empty"))]
+	pub logs: Vec<Log>,
+	#[cfg_attr(feature = "std", serde(skip_serializing_if = "Vec::is_empty"))]
+	pub calls: Vec<CallFrame>,
+}
+
+/// State of an account as output by geth's `prestateTracer`.
+///
+/// Empty fields are omitted, as well as the unchanged fields in diff mode.
+#[derive(Clone, Default, Eq, PartialEq, Debug, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct PrestateAccount {
+	#[cfg_attr(feature = "std", serde(skip_serializing_if = "Option::is_none"))]
+	pub balance: Option<U256>,
+	#[cfg_attr(
+		feature = "std",
+		serde(
+			serialize_with = "option_u256_serialize",
+			skip_serializing_if = "Option::is_none"
+		)
+	)]
+	pub nonce: Option<U256>,
+	#[cfg_attr(
+		feature = "std",
+		serde(
+			serialize_with = "option_bytes_0x_serialize",
+			skip_serializing_if = "Option::is_none"
+		)
+	)]
+	pub code: Option<Vec<u8>>,
+	/// Storage slots accessed by the transaction.
+	#[cfg_attr(feature = "std", serde(skip_serializing_if = "BTreeMap::is_empty"))]
+	pub storage: BTreeMap<H256, H256>,
 }
@@ -0,0 +1,606 @@
+// Copyright 2019-2020 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Serialization of the traces, and decoding of revert data.
+//!
+//! The expected outputs in `expected/` are written by hand following the format of geth's
+//! `structLogger` (field names, opcode names, encoding of the numbers). They were not
+//! captured from a geth node, and only guard against changes of our own format until the
+//! output of `scripts/capture_geth_traces.sh` is checked in and compared against.
+
+use crate::single::{RawStepLog, TransactionTrace};
+use ethereum_types::{H160, H256, U256};
+use std::collections::BTreeMap;
+
+fn word(value: u64) -> H256 {
+	H256::from_low_u64_be(value)
+}
+
+fn step(pc: u64, op: &str, gas: u64, gas_cost: u64, stack: Vec<H256>) -> RawStepLog {
+	RawStepLog {
+		depth: U256::one(),
+		error: None,
+		gas: gas.into(),
+		gas_cost: gas_cost.into(),
+		memory: Some(vec![]),
+		mem_size: U256::zero(),
+		op: op.as_bytes().to_vec(),
+		pc: pc.into(),
+		refund: U256::zero(),
+		stack: Some(stack),
+		storage: Some(BTreeMap::new()),
+	}
+}
+
+fn with_storage(mut step: RawStepLog, key: H256, value: H256) -> RawStepLog {
+	step.storage = Some(vec![(key, value)].into_iter().collect());
+	step
+}
+
+fn assert_matches_expected(trace: TransactionTrace, expected: &str) {
+	let expected: serde_json::Value = serde_json::from_str(expected).unwrap();
+	assert_eq!(serde_json::to_value(&trace).unwrap(), expected);
+}
+
+#[test]
+fn raw_trace_of_successful_transaction() {
+	let trace = TransactionTrace::Raw {
+		gas: 41006.into(),
+		failed: false,
+		return_value: vec![],
+		struct_logs: vec![
+			step(0, "Push1", 79000, 3, vec![]),
+			step(2, "Push1", 78997, 3, vec![word(0x2a)]),
+			with_storage(
+				step(4, "SStore", 78994, 20000, vec![word(0x2a), word(0)]),
+				word(0),
+				word(0x2a),
+			),
+			with_storage(step(5, "Stop", 58994, 0, vec![]), word(0), word(0x2a)),
+		],
+		total_steps: None,
+	};
+
+	assert_matches_expected(trace, include_str!("../expected/struct_logs_success.json"));
+}
+
+#[test]
+fn raw_trace_of_reverted_transaction() {
+	let refunded = |mut step: RawStepLog| {
+		step.refund = 15000.into();
+		with_storage(step, word(0), word(0))
+	};
+
+	let trace = TransactionTrace::Raw {
+		gas: 26012.into(),
+		failed: true,
+		return_value: vec![],
+		struct_logs: vec![
+			step(0, "Push1", 79000, 3, vec![]),
+			step(2, "Push1", 78997, 3, vec![word(0)]),
+			with_storage(
+				step(4, "SStore", 78994, 5000, vec![word(0), word(0)]),
+				word(0),
+				word(0),
+			),
+			refunded(step(5, "Push1", 73994, 3, vec![])),
+			refunded(step(7, "Dup1", 73991, 3, vec![word(0)])),
+			refunded(step(8, "Revert", 73988, 0, vec![word(0), word(0)])),
+		],
+		total_steps: None,
+	};
+
+	assert_matches_expected(trace, include_str!("../expected/struct_logs_revert.json"));
+}
+
+#[test]
+fn raw_trace_of_failed_transaction_without_storage_memory_and_stack() {
+	let disabled = |mut step: RawStepLog| {
+		step.memory = None;
+		step.stack = None;
+		step.storage = None;
+		step
+	};
+	let mut out_of_gas = disabled(step(4, "SStore", 4, 20000, vec![]));
+	out_of_gas.error = Some(b"out of gas".to_vec());
+
+	let trace = TransactionTrace::Raw {
+		gas: 21010.into(),
+		failed: true,
+		return_value: vec![],
+		struct_logs: vec![
+			disabled(step(0, "Push1", 10, 3, vec![])),
+			disabled(step(2, "Push1", 7, 3, vec![])),
+			out_of_gas,
+		],
+		total_steps: None,
+	};
+
+	assert_matches_expected(trace, include_str!("../expected/struct_logs_out_of_gas.json"));
+}
+
+#[test]
+fn raw_trace_return_value_is_0x_prefixed() {
+	let trace = TransactionTrace::Raw {
+		gas: 21000.into(),
+		failed: false,
+		return_value: vec![0xde, 0xad],
+		struct_logs: vec![],
+		total_steps: None,
+	};
+
+	assert_eq!(
+		serde_json::to_value(&trace).unwrap()["returnValue"],
+		serde_json::json!("0xdead")
+	);
+}
+
+#[test]
+fn opcodes_are_named_like_geth_does() {
+	let op = |name: &str| serde_json::to_value(&step(0, name, 0, 0, vec![])).unwrap()["op"].clone();
+
+	assert_eq!(op("SelfBalance"), serde_json::json!("SELFBALANCE"));
+	assert_eq!(op("opcode 0xc not defined"), serde_json::json!("opcode 0xc not defined"));
+}
+
+#[test]
+fn raw_trace_window_provides_total_steps() {
+	let mut trace = TransactionTrace::Raw {
+		gas: 21000.into(),
+		failed: false,
+		return_value: vec![],
+		struct_logs: vec![step(2, "Push1", 78997, 3, vec![word(0x2a)])],
+		total_steps: None,
+	};
+	assert!(serde_json::to_value(&trace).unwrap().get("totalSteps").is_none());
+
+	if let TransactionTrace::Raw { total_steps, .. } = &mut trace {
+		*total_steps = Some(4);
+	}
+	assert_eq!(
+		serde_json::to_value(&trace).unwrap()["totalSteps"],
+		serde_json::json!(4)
+	);
+}
+
+#[test]
+fn raw_trace_encoding_omits_total_steps() {
+	use codec::{Decode, Encode};
+
+	let trace = TransactionTrace::Raw {
+		gas: 21000.into(),
+		failed: false,
+		return_value: vec![],
+		struct_logs: vec![step(0, "Push1", 79000, 3, vec![])],
+		total_steps: Some(1),
+	};
+	let decoded = TransactionTrace::decode(&mut &trace.encode()[..]).unwrap();
+
+	assert!(matches!(decoded, TransactionTrace::Raw { total_steps: None, .. }));
+}
+
+/// ABI encoding of `Error(string)` with the given message.
+fn error_string(message: &str) -> Vec<u8> {
+	let mut data = vec![0x08, 0xc3, 0x79, 0xa0];
+	data.extend_from_slice(H256::from_low_u64_be(32).as_bytes());
+	data.extend_from_slice(H256::from_low_u64_be(message.len() as u64).as_bytes());
+	let mut padded = message.as_bytes().to_vec();
+	padded.resize((message.len() + 31) / 32 * 32, 0);
+	data.extend_from_slice(&padded);
+	data
+}
+
+#[test]
+fn revert_reason_of_error_string_is_decoded() {
+	assert_eq!(
+		crate::decode_revert_reason(&error_string("Ownable: caller is not the owner")),
+		Some(b"Ownable: caller is not the owner".to_vec())
+	);
+}
+
+#[test]
+fn revert_reason_of_panic_is_decoded() {
+	let mut data = vec![0x4e, 0x48, 0x7b, 0x71];
+	data.extend_from_slice(H256::from_low_u64_be(0x11).as_bytes());
+
+	assert_eq!(
+		crate::decode_revert_reason(&data),
+		Some(b"arithmetic underflow or overflow".to_vec())
+	);
+}
+
+#[test]
+fn revert_reason_of_custom_or_malformed_error_is_not_decoded() {
+	// A custom error, `Unauthorized(uint256,uint256)` for instance.
+	let mut custom = vec![0xcf, 0x47, 0x91, 0x81];
+	custom.extend_from_slice(H256::from_low_u64_be(1).as_bytes());
+	custom.extend_from_slice(H256::from_low_u64_be(2).as_bytes());
+	assert_eq!(crate::decode_revert_reason(&custom), None);
+
+	let mut truncated = error_string("Ownable: caller is not the owner");
+	truncated.truncate(80);
+	assert_eq!(crate::decode_revert_reason(&truncated), None);
+
+	assert_eq!(crate::decode_revert_reason(&[]), None);
+}
+
+#[test]
+fn reverted_call_keeps_reason_and_raw_output_in_both_formats() {
+	let output = error_string("Ownable: caller is not the owner");
+	let call = crate::single::Call {
+		from: H160::repeat_byte(0x11),
+		trace_address: vec![],
+		subtraces: 0,
+		value: U256::zero(),
+		gas: 0x5208.into(),
+		gas_used: 0x1000.into(),
+		inner: crate::single::CallInner::Call {
+			call_type: crate::CallType::Call,
+			to: H160::repeat_byte(0x22),
+			input: vec![],
+			res: crate::CallResult::Error {
+				error: b"execution reverted".to_vec(),
+				revert_reason: crate::decode_revert_reason(&output),
+				output: output.clone(),
+			},
+		},
+		logs: vec![],
+	};
+	let raw_output = format!("0x{}", hex::encode(&output));
+
+	let single = serde_json::to_value(&call).unwrap();
+	assert_eq!(single["error"], "execution reverted");
+	assert_eq!(single["revertReason"], "Ownable: caller is not the owner");
+	assert_eq!(single["output"], raw_output.as_str());
+
+	let block = serde_json::to_value(&crate::block::TransactionTrace::from_call(call, 0)).unwrap();
+	assert_eq!(block["error"], "execution reverted");
+	assert_eq!(block["revertReason"], "Ownable: caller is not the owner");
+	assert_eq!(block["output"], raw_output.as_str());
+	assert!(block.get("result").is_none());
+}
+
+#[test]
+fn logs_are_part_of_block_traces() {
+	let log = |reverted| crate::single::Log {
+		address: H160::repeat_byte(0x22),
+		topics: vec![word(1)],
+		data: vec![0x2a],
+		position: 1,
+		reverted,
+	};
+	let call = crate::single::Call {
+		from: H160::repeat_byte(0x11),
+		trace_address: vec![0],
+		subtraces: 2,
+		value: U256::zero(),
+		gas: 0x5208.into(),
+		gas_used: 0x1000.into(),
+		inner: crate::single::CallInner::Call {
+			call_type: crate::CallType::Call,
+			to: H160::repeat_byte(0x22),
+			input: vec![],
+			res: crate::CallResult::Output { output: vec![] },
+		},
+		logs: vec![log(false), log(true)],
+	};
+
+	// Not part of the Blockscout output.
+	assert!(serde_json::to_value(&call).unwrap().get("logs").is_none());
+
+	let block = serde_json::to_value(&crate::block::TransactionTrace::from_call(call, 0)).unwrap();
+	assert_eq!(
+		block["logs"],
+		serde_json::json!([
+			{
+				"address": "0x2222222222222222222222222222222222222222",
+				"topics": ["0x0000000000000000000000000000000000000000000000000000000000000001"],
+				"data": "0x2a",
+				"position": 1
+			},
+			{
+				"address": "0x2222222222222222222222222222222222222222",
+				"topics": ["0x0000000000000000000000000000000000000000000000000000000000000001"],
+				"data": "0x2a",
+				"position": 1,
+				"reverted": true
+			}
+		])
+	);
+}
+
+#[test]
+fn replay_matches_openethereum_format() {
+	use crate::replay::*;
+
+	let account = AccountDiff {
+		balance: Diff::new(U256::from(0x10), U256::from(0x08)),
+		nonce: Diff::new(U256::one(), U256::one()),
+		code: Diff::Born(Bytes(vec![0x60, 0x00])),
+		storage: vec![(word(0), Diff::Died(word(7)))].into_iter().collect(),
+	};
+	let replay = TransactionReplay {
+		output: vec![],
+		state_diff: Some(vec![(H160::repeat_byte(0x11), account)].into_iter().collect()),
+		trace: vec![],
+		vm_trace: Some(VmTrace {
+			code: vec![0x60, 0x00],
+			ops: vec![VmOperation {
+				pc: 0,
+				cost: 3,
+				ex: Some(VmExecutedOperation {
+					used: 97,
+					push: vec![U256::zero()],
+					mem: None,
+					store: Some(VmStorageDiff {
+						key: U256::zero(),
+						val: U256::from(0x2a),
+					}),
+				}),
+				sub: None,
+			}],
+		}),
+		transaction_hash: None,
+	};
+
+	assert_eq!(
+		serde_json::to_value(&replay).unwrap(),
+		serde_json::json!({
+			"output": "0x",
+			"stateDiff": {
+				"0x1111111111111111111111111111111111111111": {
+					"balance": { "*": { "from": "0x10", "to": "0x8" } },
+					"nonce": "=",
+					"code": { "+": "0x6000" },
+					"storage": {
+						"0x0000000000000000000000000000000000000000000000000000000000000000": {
+							"-": "0x0000000000000000000000000000000000000000000000000000000000000007"
+						}
+					}
+				}
+			},
+			"trace": [],
+			"vmTrace": {
+				"code": "0x6000",
+				"ops": [{
+					"pc": 0,
+					"cost": 3,
+					"ex": {
+						"used": 97,
+						"push": ["0x0"],
+						"mem": null,
+						"store": { "key": "0x0", "val": "0x2a" }
+					},
+					"sub": null
+				}]
+			}
+		})
+	);
+}
+
+#[test]
+fn substrate_originated_traces_are_marked() {
+	let call = crate::single::Call {
+		from: H160::repeat_byte(0x11),
+		trace_address: vec![],
+		subtraces: 0,
+		value: U256::zero(),
+		gas: 0x5208.into(),
+		gas_used: 0x5208.into(),
+		inner: crate::single::CallInner::Call {
+			call_type: crate::CallType::Call,
+			to: H160::repeat_byte(0x22),
+			input: vec![],
+			res: crate::CallResult::Output { output: vec![] },
+		},
+		logs: vec![],
+	};
+
+	let ethereum =
+		serde_json::to_value(&crate::block::TransactionTrace::from_call(call.clone(), 1)).unwrap();
+	assert!(ethereum.get("origin").is_none());
+	assert!(ethereum.get("extrinsicIndex").is_none());
+	assert_eq!(ethereum["transactionPosition"], 1);
+
+	let substrate = serde_json::to_value(&crate::block::TransactionTrace::from_substrate_call(
+		call,
+		3,
+		word(0xab),
+	))
+	.unwrap();
+	assert_eq!(substrate["origin"], "substrate");
+	// Extrinsic indices don't share the index space of Ethereum transactions.
+	assert_eq!(substrate["transactionPosition"], serde_json::Value::Null);
+	assert_eq!(substrate["extrinsicIndex"], 3);
+	assert_eq!(
+		substrate["transactionHash"],
+		"0x00000000000000000000000000000000000000000000000000000000000000ab"
+	);
+}
+
+#[test]
+fn version_1_call_list_is_decoded_and_converted() {
+	use crate::v1;
+	use codec::{Decode, Encode};
+
+	let legacy = v1::single::TransactionTrace::CallList(vec![
+		v1::single::Call {
+			from: H160::repeat_byte(0x11),
+			trace_address: vec![],
+			subtraces: 1,
+			value: U256::from(7),
+			gas: 0x9000.into(),
+			gas_used: 0x6000.into(),
+			inner: v1::single::CallInner::Call {
+				call_type: crate::CallType::Call,
+				to: H160::repeat_byte(0x22),
+				input: vec![0xab],
+				res: v1::CallResult::Error(b"execution reverted".to_vec()),
+			},
+		},
+		v1::single::Call {
+			from: H160::repeat_byte(0x22),
+			trace_address: vec![0],
+			subtraces: 0,
+			value: U256::zero(),
+			gas: 0x4000.into(),
+			gas_used: 0x1000.into(),
+			inner: v1::single::CallInner::Create {
+				init: vec![0x60],
+				res: v1::CreateResult::Success {
+					created_contract_address_hash: H160::repeat_byte(0x33),
+					created_contract_code: vec![0x00],
+				},
+			},
+		},
+	]);
+
+	let encoded = legacy.encode();
+	let decoded = v1::single::TransactionTrace::decode(&mut &encoded[..]).unwrap();
+	let calls = match TransactionTrace::from(decoded) {
+		TransactionTrace::CallList(calls) => calls,
+		other => panic!("expected a call list, got {:?}", other),
+	};
+
+	// What a version 1 runtime returns is misread by the current types.
+	assert_ne!(
+		TransactionTrace::decode(&mut &encoded[..]).ok(),
+		Some(TransactionTrace::CallList(calls.clone())),
+	);
+
+	assert_eq!(calls.len(), 2);
+	assert_eq!(calls[0].value, U256::from(7));
+	assert!(calls[0].logs.is_empty());
+	assert_eq!(
+		calls[0].inner,
+		crate::single::CallInner::Call {
+			call_type: crate::CallType::Call,
+			to: H160::repeat_byte(0x22),
+			input: vec![0xab],
+			res: crate::CallResult::Error {
+				error: b"execution reverted".to_vec(),
+				revert_reason: None,
+				output: vec![],
+			},
+		}
+	);
+	assert_eq!(calls[1].trace_address, vec![0]);
+	assert_eq!(
+		calls[1].inner,
+		crate::single::CallInner::Create {
+			init: vec![0x60],
+			res: crate::CreateResult::Success {
+				created_contract_address_hash: H160::repeat_byte(0x33),
+				created_contract_code: vec![0x00],
+			},
+		}
+	);
+}
+
+#[test]
+fn version_1_raw_trace_is_converted() {
+	use crate::v1;
+
+	let legacy = v1::single::TransactionTrace::Raw {
+		gas: 26012.into(),
+		return_value: vec![],
+		step_logs: vec![
+			v1::single::RawStepLog {
+				depth: U256::one(),
+				gas: 79000.into(),
+				gas_cost: 3.into(),
+				memory: Some(vec![word(0), word(1)]),
+				op: b"Push1".to_vec(),
+				pc: 0.into(),
+				stack: Some(vec![]),
+				storage: None,
+			},
+			v1::single::RawStepLog {
+				depth: U256::one(),
+				gas: 78997.into(),
+				gas_cost: 0.into(),
+				memory: None,
+				op: b"Revert".to_vec(),
+				pc: 2.into(),
+				stack: None,
+				storage: None,
+			},
+		],
+	};
+
+	match TransactionTrace::from(legacy) {
+		TransactionTrace::Raw {
+			gas,
+			failed,
+			struct_logs,
+			total_steps,
+			..
+		} => {
+			assert_eq!(gas, 26012.into());
+			assert!(failed);
+			assert_eq!(total_steps, None);
+			assert_eq!(struct_logs[0].mem_size, 64.into());
+			assert_eq!(struct_logs[0].memory, Some(vec![word(0), word(1)]));
+			assert_eq!(struct_logs[1].mem_size, U256::zero());
+			assert_eq!(struct_logs[1].error, None);
+			assert_eq!(struct_logs[1].refund, U256::zero());
+		}
+		other => panic!("expected a raw trace, got {:?}", other),
+	}
+}
+
+#[test]
+fn version_1_block_trace_is_decoded_and_converted() {
+	use crate::{block, v1};
+	use codec::{Decode, Encode};
+
+	let legacy = v1::block::TransactionTrace {
+		action: block::TransactionTraceAction::Call {
+			call_type: crate::CallType::Call,
+			from: H160::repeat_byte(0x11),
+			gas: 0x5208.into(),
+			input: vec![],
+			to: H160::repeat_byte(0x22),
+			value: U256::zero(),
+		},
+		block_hash: word(0xb1),
+		block_number: 42,
+		output: v1::block::TransactionTraceOutput::Error(b"out of gas".to_vec()),
+		subtraces: 0,
+		trace_address: vec![],
+		transaction_hash: word(0x71),
+		transaction_position: 3,
+	};
+
+	let encoded = vec![legacy].encode();
+	let decoded = Vec::<v1::block::TransactionTrace>::decode(&mut &encoded[..]).unwrap();
+	let trace: block::TransactionTrace = decoded.into_iter().next().unwrap().into();
+
+	assert_eq!(trace.block_number, 42);
+	assert_eq!(trace.transaction_hash, word(0x71));
+	assert_eq!(trace.transaction_position, Some(3));
+	assert_eq!(trace.extrinsic_index, None);
+	assert_eq!(trace.origin, block::TraceOrigin::Ethereum);
+	assert!(trace.logs.is_empty());
+	assert_eq!(
+		trace.output,
+		block::TransactionTraceOutput::Error {
+			error: b"out of gas".to_vec(),
+			revert_reason: None,
+			output: vec![],
+		}
+	);
+}
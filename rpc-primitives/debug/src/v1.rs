@@ -0,0 +1,316 @@
+// Copyright 2019-2021 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Traces returned by the version 1 of `DebugRuntimeApi`.
+//!
+//! The SCALE encoding of the current types is not compatible with the one of the traces
+//! returned by version 1 runtimes: the call and create results and the block trace outputs
+//! became struct variants carrying the revert data, the step logs gained the error, memory
+//! size and refund of each step, calls and block traces gained their logs and origin, and the
+//! position of block traces became optional, next to an extrinsic index. The trace types
+//! gained the outputs of geth's `callTracer` and `prestateTracer`, which version 1 runtimes
+//! cannot build.
+//! These types are frozen copies of the version 1 types, only used to decode the traces of
+//! such runtimes before converting them into the current types. They must never change.
+
+use crate::{block::TraceOrigin, CallType};
+use codec::{Decode, Encode};
+use ethereum_types::{H160, H256, U256};
+use sp_std::{collections::btree_map::BTreeMap, vec::Vec};
+
+#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+pub enum CallResult {
+	Output(Vec<u8>),
+	Error(Vec<u8>),
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+pub enum CreateResult {
+	Error {
+		error: Vec<u8>,
+	},
+	Success {
+		created_contract_address_hash: H160,
+		created_contract_code: Vec<u8>,
+	},
+}
+
+pub mod single {
+	use super::*;
+
+	#[derive(Clone, Copy, Eq, PartialEq, Debug, Encode, Decode)]
+	pub enum TraceType {
+		Raw {
+			disable_storage: bool,
+			disable_memory: bool,
+			disable_stack: bool,
+		},
+		CallList,
+	}
+
+	impl TraceType {
+		/// Trace type of version 1 runtimes, if they can build the requested trace.
+		pub fn from_trace_type(trace_type: crate::single::TraceType) -> Option<Self> {
+			match trace_type {
+				crate::single::TraceType::Raw {
+					disable_storage,
+					disable_memory,
+					disable_stack,
+				} => Some(Self::Raw {
+					disable_storage,
+					disable_memory,
+					disable_stack,
+				}),
+				crate::single::TraceType::CallList => Some(Self::CallList),
+				crate::single::TraceType::CallTracer { .. }
+				| crate::single::TraceType::Prestate { .. } => None,
+			}
+		}
+	}
+
+	#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+	pub enum TransactionTrace {
+		Raw {
+			gas: U256,
+			return_value: Vec<u8>,
+			step_logs: Vec<RawStepLog>,
+		},
+		CallList(Vec<Call>),
+	}
+
+	#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+	pub struct RawStepLog {
+		pub depth: U256,
+		pub gas: U256,
+		pub gas_cost: U256,
+		pub memory: Option<Vec<H256>>,
+		pub op: Vec<u8>,
+		pub pc: U256,
+		pub stack: Option<Vec<H256>>,
+		pub storage: Option<BTreeMap<H256, H256>>,
+	}
+
+	#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+	pub enum CallInner {
+		Call {
+			call_type: CallType,
+			to: H160,
+			input: Vec<u8>,
+			res: CallResult,
+		},
+		Create {
+			init: Vec<u8>,
+			res: CreateResult,
+		},
+		SelfDestruct {
+			balance: U256,
+			refund_address: H160,
+		},
+	}
+
+	#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+	pub struct Call {
+		pub from: H160,
+		pub trace_address: Vec<u32>,
+		pub subtraces: u32,
+		pub value: U256,
+		pub gas: U256,
+		pub gas_used: U256,
+		pub inner: CallInner,
+	}
+}
+
+pub mod block {
+	use super::*;
+	// Unchanged since version 1.
+	pub use crate::block::{TransactionTraceAction, TransactionTraceResult};
+
+	#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+	pub struct TransactionTrace {
+		pub action: TransactionTraceAction,
+		pub block_hash: H256,
+		pub block_number: u32,
+		pub output: TransactionTraceOutput,
+		pub subtraces: u32,
+		pub trace_address: Vec<u32>,
+		pub transaction_hash: H256,
+		pub transaction_position: u32,
+	}
+
+	#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+	pub enum TransactionTraceOutput {
+		Result(TransactionTraceResult),
+		Error(Vec<u8>),
+	}
+}
+
+impl From<CallResult> for crate::CallResult {
+	fn from(res: CallResult) -> Self {
+		match res {
+			CallResult::Output(output) => Self::Output { output },
+			// Version 1 runtimes did not keep the revert data.
+			CallResult::Error(error) => Self::Error {
+				error,
+				revert_reason: None,
+				output: Vec::new(),
+			},
+		}
+	}
+}
+
+impl From<CreateResult> for crate::CreateResult {
+	fn from(res: CreateResult) -> Self {
+		match res {
+			CreateResult::Error { error } => Self::Error {
+				error,
+				revert_reason: None,
+				output: Vec::new(),
+			},
+			CreateResult::Success {
+				created_contract_address_hash,
+				created_contract_code,
+			} => Self::Success {
+				created_contract_address_hash,
+				created_contract_code,
+			},
+		}
+	}
+}
+
+impl From<single::TransactionTrace> for crate::single::TransactionTrace {
+	/// Version 1 runtimes did not report whether the transaction failed, so `failed` is only
+	/// set when the last step is a `REVERT`. Other failures can't be told apart.
+	fn from(trace: single::TransactionTrace) -> Self {
+		match trace {
+			single::TransactionTrace::Raw {
+				gas,
+				return_value,
+				step_logs,
+			} => Self::Raw {
+				gas,
+				failed: step_logs
+					.last()
+					.map(|step| step.op.eq_ignore_ascii_case(b"revert"))
+					.unwrap_or(false),
+				return_value,
+				struct_logs: step_logs.into_iter().map(Into::into).collect(),
+				total_steps: None,
+			},
+			single::TransactionTrace::CallList(calls) => {
+				Self::CallList(calls.into_iter().map(Into::into).collect())
+			}
+		}
+	}
+}
+
+impl From<single::RawStepLog> for crate::single::RawStepLog {
+	fn from(step: single::RawStepLog) -> Self {
+		Self {
+			depth: step.depth,
+			error: None,
+			gas: step.gas,
+			gas_cost: step.gas_cost,
+			// Memory is captured by words, the size is only known when it is.
+			mem_size: step
+				.memory
+				.as_ref()
+				.map(|memory| U256::from(memory.len() * 32))
+				.unwrap_or_default(),
+			memory: step.memory,
+			op: step.op,
+			pc: step.pc,
+			refund: U256::zero(),
+			stack: step.stack,
+			storage: step.storage,
+		}
+	}
+}
+
+impl From<single::CallInner> for crate::single::CallInner {
+	fn from(inner: single::CallInner) -> Self {
+		match inner {
+			single::CallInner::Call {
+				call_type,
+				to,
+				input,
+				res,
+			} => Self::Call {
+				call_type,
+				to,
+				input,
+				res: res.into(),
+			},
+			single::CallInner::Create { init, res } => Self::Create {
+				init,
+				res: res.into(),
+			},
+			single::CallInner::SelfDestruct {
+				balance,
+				refund_address,
+			} => Self::SelfDestruct {
+				balance,
+				refund_address,
+			},
+		}
+	}
+}
+
+impl From<single::Call> for crate::single::Call {
+	fn from(call: single::Call) -> Self {
+		Self {
+			from: call.from,
+			trace_address: call.trace_address,
+			subtraces: call.subtraces,
+			value: call.value,
+			gas: call.gas,
+			gas_used: call.gas_used,
+			inner: call.inner.into(),
+			logs: Vec::new(),
+		}
+	}
+}
+
+impl From<block::TransactionTraceOutput> for crate::block::TransactionTraceOutput {
+	fn from(output: block::TransactionTraceOutput) -> Self {
+		match output {
+			block::TransactionTraceOutput::Result(result) => Self::Result { result },
+			block::TransactionTraceOutput::Error(error) => Self::Error {
+				error,
+				revert_reason: None,
+				output: Vec::new(),
+			},
+		}
+	}
+}
+
+impl From<block::TransactionTrace> for crate::block::TransactionTrace {
+	/// Version 1 runtimes only traced Ethereum transactions.
+	fn from(trace: block::TransactionTrace) -> Self {
+		Self {
+			action: trace.action,
+			block_hash: trace.block_hash,
+			block_number: trace.block_number,
+			output: trace.output.into(),
+			subtraces: trace.subtraces,
+			trace_address: trace.trace_address,
+			transaction_hash: trace.transaction_hash,
+			transaction_position: Some(trace.transaction_position),
+			extrinsic_index: None,
+			logs: Vec::new(),
+			origin: TraceOrigin::Ethereum,
+		}
+	}
+}
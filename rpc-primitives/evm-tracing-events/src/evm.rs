@@ -0,0 +1,225 @@
+// Copyright 2019-2021 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use codec::{Decode, Encode};
+use ethereum_types::{H160, H256, U256};
+use sp_std::vec::Vec;
+
+/// Owned version of `evm::Transfer`.
+#[derive(Clone, Copy, Debug, Encode, Decode, PartialEq, Eq)]
+pub struct Transfer {
+	pub source: H160,
+	pub target: H160,
+	pub value: U256,
+}
+
+impl From<::evm::Transfer> for Transfer {
+	fn from(i: ::evm::Transfer) -> Self {
+		Self {
+			source: i.source,
+			target: i.target,
+			value: i.value,
+		}
+	}
+}
+
+/// Owned version of `evm::Context`.
+#[derive(Clone, Copy, Debug, Encode, Decode, PartialEq, Eq)]
+pub struct Context {
+	pub address: H160,
+	pub caller: H160,
+	pub apparent_value: U256,
+}
+
+impl From<::evm::Context> for Context {
+	fn from(i: ::evm::Context) -> Self {
+		Self {
+			address: i.address,
+			caller: i.caller,
+			apparent_value: i.apparent_value,
+		}
+	}
+}
+
+/// Owned version of `evm::CreateScheme`.
+#[derive(Clone, Copy, Debug, Encode, Decode, PartialEq, Eq)]
+pub enum CreateScheme {
+	Legacy {
+		caller: H160,
+	},
+	Create2 {
+		caller: H160,
+		code_hash: H256,
+		salt: H256,
+	},
+	Fixed(H160),
+}
+
+impl From<::evm::CreateScheme> for CreateScheme {
+	fn from(i: ::evm::CreateScheme) -> Self {
+		match i {
+			::evm::CreateScheme::Legacy { caller } => Self::Legacy { caller },
+			::evm::CreateScheme::Create2 {
+				caller,
+				code_hash,
+				salt,
+			} => Self::Create2 {
+				caller,
+				code_hash,
+				salt,
+			},
+			::evm::CreateScheme::Fixed(address) => Self::Fixed(address),
+		}
+	}
+}
+
+/// Owned version of `evm::tracing::Event`.
+#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq)]
+pub enum EvmEvent {
+	Call {
+		code_address: H160,
+		transfer: Option<Transfer>,
+		input: Vec<u8>,
+		target_gas: Option<u64>,
+		is_static: bool,
+		context: Context,
+	},
+	Create {
+		caller: H160,
+		address: H160,
+		scheme: CreateScheme,
+		value: U256,
+		init_code: Vec<u8>,
+		target_gas: Option<u64>,
+	},
+	Suicide {
+		address: H160,
+		target: H160,
+		balance: U256,
+	},
+	TransactCall {
+		caller: H160,
+		address: H160,
+		value: U256,
+		data: Vec<u8>,
+		gas_limit: u64,
+	},
+	TransactCreate {
+		caller: H160,
+		value: U256,
+		init_code: Vec<u8>,
+		gas_limit: u64,
+		address: H160,
+	},
+	TransactCreate2 {
+		caller: H160,
+		value: U256,
+		init_code: Vec<u8>,
+		salt: H256,
+		gas_limit: u64,
+		address: H160,
+	},
+}
+
+#[cfg(feature = "evm-tracing")]
+impl<'a> From<::evm::tracing::Event<'a>> for EvmEvent {
+	fn from(i: ::evm::tracing::Event<'a>) -> Self {
+		use ::evm::tracing::Event;
+
+		match i {
+			Event::Call {
+				code_address,
+				transfer,
+				input,
+				target_gas,
+				is_static,
+				context,
+			} => Self::Call {
+				code_address,
+				transfer: transfer.clone().map(Into::into),
+				input: input.to_vec(),
+				target_gas,
+				is_static,
+				context: context.clone().into(),
+			},
+			Event::Create {
+				caller,
+				address,
+				scheme,
+				value,
+				init_code,
+				target_gas,
+			} => Self::Create {
+				caller,
+				address,
+				scheme: scheme.into(),
+				value,
+				init_code: init_code.to_vec(),
+				target_gas,
+			},
+			Event::Suicide {
+				address,
+				target,
+				balance,
+			} => Self::Suicide {
+				address,
+				target,
+				balance,
+			},
+			Event::TransactCall {
+				caller,
+				address,
+				value,
+				data,
+				gas_limit,
+			} => Self::TransactCall {
+				caller,
+				address,
+				value,
+				data: data.to_vec(),
+				gas_limit,
+			},
+			Event::TransactCreate {
+				caller,
+				value,
+				init_code,
+				gas_limit,
+				address,
+			} => Self::TransactCreate {
+				caller,
+				value,
+				init_code: init_code.to_vec(),
+				gas_limit,
+				address,
+			},
+			Event::TransactCreate2 {
+				caller,
+				value,
+				init_code,
+				salt,
+				gas_limit,
+				address,
+			} => Self::TransactCreate2 {
+				caller,
+				value,
+				init_code: init_code.to_vec(),
+				salt,
+				gas_limit,
+				address,
+			},
+		}
+	}
+}
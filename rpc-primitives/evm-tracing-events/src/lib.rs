@@ -0,0 +1,119 @@
+// Copyright 2019-2021 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Events of a traced EVM execution.
+//!
+//! The runtime forwards the events of the EVM to the client through host functions, as
+//! compact SCALE-encoded values. The client builds the requested trace from them, which
+//! avoids returning the (potentially huge) trace across the WASM boundary.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub mod evm;
+pub mod gasometer;
+pub mod runtime;
+
+pub use self::evm::EvmEvent;
+pub use gasometer::GasometerEvent;
+pub use runtime::RuntimeEvent;
+
+use codec::{Decode, Encode};
+use ethereum_types::{H160, H256, U256};
+use sp_std::vec::Vec;
+
+/// Event sent by the runtime during a traced execution.
+#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq)]
+pub enum Event {
+	Evm(EvmEvent),
+	Gasometer(GasometerEvent),
+	Runtime(RuntimeEvent),
+	/// Code of the account called by the preceding `EvmEvent::Call`. Only sent if enabled
+	/// by the `EventFilter`.
+	CallCode(Vec<u8>),
+	/// Start of the next traced transaction, when several of them are traced at once.
+	NewTransaction(TransactionOrigin),
+	/// State of an account touched by the traced transaction, before its execution.
+	PreState(AccountState),
+	/// State of an account touched by the traced transaction, after its execution.
+	PostState(AccountState),
+}
+
+/// Origin of a traced transaction.
+#[derive(Clone, Copy, Debug, Encode, Decode, PartialEq, Eq)]
+pub enum TransactionOrigin {
+	/// An Ethereum transaction, or an unsigned call.
+	Ethereum,
+	/// EVM executions started by another extrinsic.
+	Substrate {
+		extrinsic_index: u32,
+		extrinsic_hash: H256,
+	},
+}
+
+/// State of an account, restricted to the storage slots accessed by the traced transaction.
+#[derive(Clone, Debug, Default, Encode, Decode, PartialEq, Eq)]
+pub struct AccountState {
+	pub address: H160,
+	pub balance: U256,
+	pub nonce: U256,
+	pub code: Vec<u8>,
+	pub storage: Vec<(H256, H256)>,
+}
+
+/// Data which is expensive to send and only needed by some tracers. The memory and stack of
+/// `LOG0` to `LOG4` steps are always sent, logs being read from them.
+#[derive(Clone, Copy, Debug, Default, Encode, Decode, PartialEq, Eq)]
+pub struct EventFilter {
+	/// Send the memory of each step.
+	pub enable_memory: bool,
+	/// Send the stack of each step.
+	pub enable_stack: bool,
+	/// Send the code of each called account.
+	pub enable_code: bool,
+}
+
+/// Client-side receiver of the events.
+pub trait Listener {
+	fn event(&mut self, event: Event);
+
+	/// Whether the traced execution must be aborted, which the runtime does after sending the
+	/// current event.
+	fn aborted(&mut self) -> bool {
+		false
+	}
+}
+
+#[cfg(feature = "std")]
+environmental::environmental!(listener: dyn Listener + 'static);
+
+/// Execute the closure with the given listener receiving the events.
+#[cfg(feature = "std")]
+pub fn using<R, F: FnOnce() -> R>(new: &mut (dyn Listener + 'static), f: F) -> R {
+	listener::using(new, f)
+}
+
+#[cfg(feature = "std")]
+impl Event {
+	/// Send the event to the current listener, if any, and return whether the traced execution
+	/// must be aborted.
+	pub fn emit(self) -> bool {
+		listener::with(|listener| {
+			listener.event(self);
+			listener.aborted()
+		})
+		.unwrap_or(false)
+	}
+}
@@ -0,0 +1,126 @@
+// Copyright 2019-2021 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::evm::Context;
+use codec::{Decode, Encode};
+use ethereum_types::{H160, H256};
+pub use evm::ExitReason;
+use sp_std::vec::Vec;
+
+/// Owned version of `evm::Capture`, traps being the opcode starting a subcall.
+#[derive(Clone, Copy, Debug, Encode, Decode, PartialEq, Eq)]
+pub enum Capture<E, T> {
+	Exit(E),
+	Trap(T),
+}
+
+/// Owned version of `evm_runtime::tracing::Event`, opcodes being sent as bytes.
+#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq)]
+pub enum RuntimeEvent {
+	Step {
+		context: Context,
+		opcode: u8,
+		position: Result<u64, ExitReason>,
+		/// Stack before the execution of the opcode, if enabled by the `EventFilter`.
+		stack: Option<Vec<H256>>,
+		/// Memory before the execution of the opcode, if enabled by the `EventFilter`.
+		memory: Option<Vec<u8>>,
+		/// Size of the memory, which is sent even if the memory isn't.
+		memory_size: u64,
+	},
+	StepResult {
+		result: Result<(), Capture<ExitReason, u8>>,
+		return_value: Vec<u8>,
+	},
+	SLoad {
+		address: H160,
+		index: H256,
+		value: H256,
+	},
+	SStore {
+		address: H160,
+		index: H256,
+		value: H256,
+	},
+}
+
+#[cfg(feature = "evm-tracing")]
+impl RuntimeEvent {
+	/// Convert an event of the EVM, the parts of the machine state disabled by the filter
+	/// being omitted.
+	pub fn from_evm_event(i: evm_runtime::tracing::Event, filter: crate::EventFilter) -> Self {
+		use evm_runtime::tracing::Event;
+
+		match i {
+			Event::Step {
+				context,
+				opcode,
+				position,
+				stack,
+				memory,
+			} => {
+				// Logs are read from the stack and memory of their step.
+				let is_log = (0xa0..=0xa4).contains(&opcode.0);
+
+				Self::Step {
+					context: context.clone().into(),
+					opcode: opcode.0,
+					position: position.clone().map(|position| position as u64),
+					stack: if filter.enable_stack || is_log {
+						Some(stack.data().clone())
+					} else {
+						None
+					},
+					memory: if filter.enable_memory || is_log {
+						Some(memory.data().clone())
+					} else {
+						None
+					},
+					memory_size: memory.len() as u64,
+				}
+			}
+			Event::StepResult {
+				result,
+				return_value,
+			} => Self::StepResult {
+				result: match result {
+					Ok(()) => Ok(()),
+					Err(evm::Capture::Exit(reason)) => Err(Capture::Exit(reason.clone())),
+					Err(evm::Capture::Trap(opcode)) => Err(Capture::Trap(opcode.0)),
+				},
+				return_value: return_value.to_vec(),
+			},
+			Event::SLoad {
+				address,
+				index,
+				value,
+			} => Self::SLoad {
+				address,
+				index,
+				value,
+			},
+			Event::SStore {
+				address,
+				index,
+				value,
+			} => Self::SStore {
+				address,
+				index,
+				value,
+			},
+		}
+	}
+}
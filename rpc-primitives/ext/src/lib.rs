@@ -0,0 +1,68 @@
+// Copyright 2019-2021 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Host functions used by the runtime to send the events of a traced EVM execution to the
+//! client.
+//!
+//! Their `HostFunctions` must be registered by the executor of the node, tracing runtimes
+//! importing them.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use sp_runtime_interface::runtime_interface;
+
+#[cfg(feature = "std")]
+use codec::Decode;
+#[cfg(feature = "std")]
+use hedgeware_evm_tracing_events::Event;
+
+#[runtime_interface]
+pub trait HedgewareExt {
+	/// Send a SCALE-encoded `hedgeware_evm_tracing_events::Event` to the listener of the
+	/// client, if any. Events which cannot be decoded are logged and dropped, the runtime not
+	/// being able to abort the execution.
+	fn tracing_event(event: &[u8]) {
+		if let Some(event) = decode_event(event) {
+			event.emit();
+		}
+	}
+
+	/// Same as the first version, also returning whether the traced execution must be aborted,
+	/// for example once the deadline of the request has been reached. Events which cannot be
+	/// decoded abort it, the trace missing them.
+	#[version(2)]
+	fn tracing_event(event: &[u8]) -> bool {
+		match decode_event(event) {
+			Some(event) => event.emit(),
+			None => true,
+		}
+	}
+}
+
+/// Decode an event sent by the runtime, which is likely built with other event types than the
+/// client if it fails.
+#[cfg(feature = "std")]
+fn decode_event(event: &[u8]) -> Option<Event> {
+	Event::decode(&mut &event[..])
+		.map_err(|e| {
+			log::error!(
+				target: "evm-tracing",
+				"Failed to decode a tracing event of the runtime, the trace is incomplete : {}",
+				e
+			)
+		})
+		.ok()
+}
@@ -68,6 +68,7 @@ use pallet_contracts::weights::WeightInfo;
 use sp_runtime::traits::ConvertInto;
 use static_assertions::const_assert;
 use sp_std::marker::PhantomData;
+use sp_std::collections::btree_map::BTreeMap;
 use frame_support::traits::FindAuthor;
 use sp_core::crypto::Public;
 
@@ -880,6 +881,7 @@ impl treasury_reward::Config for Runtime {
 	type MinimumTreasuryPct = MinimumTreasuryPct;
 	type MaximumRecipientPct = MaximumRecipientPct;
 	type DefaultRewardAddress = TreasuryPalletId;
+	type WeightInfo = ();
 }
 
 /// Fixed gas price of `1`.
@@ -1072,6 +1074,10 @@ pub type UncheckedExtrinsic = generic::UncheckedExtrinsic<Address, Call, Signatu
 pub type SignedPayload = generic::SignedPayload<Call, SignedExtra>;
 /// Extrinsic type that has already been checked.
 pub type CheckedExtrinsic = generic::CheckedExtrinsic<AccountId, Call, SignedExtra>;
+/// Runtime migrations executed on the next runtime upgrade, before the
+/// `on_runtime_upgrade` hooks of the pallets. Empty until one is needed.
+pub type Migrations = ();
+
 /// Executive: handles dispatch to the various modules.
 pub type Executive = frame_executive::Executive<
 	Runtime,
@@ -1079,9 +1085,169 @@ pub type Executive = frame_executive::Executive<
 	frame_system::ChainContext<Runtime>,
 	Runtime,
 	AllPallets,
-	(),
+	Migrations,
 >;
 
+/// Trace an execution (an Ethereum extrinsic or an unsigned call), the events being sent to
+/// the client which builds the trace. The changes of the execution are kept if
+/// `keep_changes` is set, so that the following ones can be traced on top of it.
+///
+/// The prestate tracer reads the state from before the execution by rolling it back, so
+/// keeping its changes executes it a second time.
+#[cfg(feature = "evm-tracing")]
+fn trace_execution<R>(
+	execute: impl Fn() -> R,
+	trace_type: hedgeware_rpc_primitives_debug::single::TraceType,
+	keep_changes: bool,
+) -> R {
+	use hedgeware_rpc_primitives_debug::single::TraceType;
+	use hedgeware_evm_tracer::{EventFilter, EvmTracer, StateTracer};
+
+	match trace_type {
+		TraceType::Raw {
+			disable_memory,
+			disable_stack,
+			..
+		} => {
+			EvmTracer::<Runtime>::new(EventFilter {
+				enable_memory: !disable_memory,
+				enable_stack: !disable_stack,
+				enable_code: false,
+			})
+			.trace(execute)
+		},
+		TraceType::CallList | TraceType::CallTracer { .. } => {
+			EvmTracer::<Runtime>::new(EventFilter::default()).trace(execute)
+		}
+		TraceType::Prestate { diff_mode } => {
+			let result = StateTracer::<Runtime>::new(diff_mode).trace(&execute);
+			// The changes of the traced execution have been discarded.
+			if keep_changes {
+				execute()
+			} else {
+				result
+			}
+		}
+	}
+}
+
+/// Replay an execution (an Ethereum extrinsic or an unsigned call) with the outputs of
+/// OpenEthereum's `trace_replay*` RPCs, built by the client from the events.
+///
+/// The state of the touched accounts is sent by an execution which is rolled back, if the
+/// state diff is requested. The changes of the last execution are kept, so that the
+/// following executions are replayed on top of it.
+#[cfg(feature = "evm-tracing")]
+fn replay_execution<R>(
+	execute: impl Fn() -> R,
+	trace_type: hedgeware_rpc_primitives_debug::replay::ReplayTraceType,
+) -> R {
+	use hedgeware_evm_tracer::{EventFilter, EvmTracer, StateTracer};
+
+	if trace_type.state_diff {
+		StateTracer::<Runtime>::new(true).trace(&execute);
+	}
+
+	// The VM trace needs the whole machine state.
+	EvmTracer::<Runtime>::new(EventFilter {
+		enable_memory: trace_type.vm_trace,
+		enable_stack: trace_type.vm_trace,
+		enable_code: trace_type.vm_trace,
+	})
+	.trace(execute)
+}
+
+/// Gas limit of a call made through a runtime API, rejected instead of being truncated when
+/// it doesn't fit the `u64` of the EVM.
+fn checked_gas_limit(gas_limit: U256) -> Result<u64, sp_runtime::DispatchError> {
+	if gas_limit > U256::from(u64::MAX) {
+		return Err(sp_runtime::DispatchError::Other("Gas limit does not fit in 64 bits"));
+	}
+	Ok(gas_limit.low_u64())
+}
+
+/// Execute an unsigned call on top of the current state, as `EthereumRuntimeRPCApi::call` and
+/// `EthereumRuntimeRPCApi::create` do.
+#[cfg(feature = "evm-tracing")]
+fn execute_call(
+	call: &hedgeware_rpc_primitives_debug::call::TraceCall,
+) -> Result<(), sp_runtime::DispatchError> {
+	let config = <Runtime as pallet_evm::Config>::config();
+	let call = call.clone();
+	let gas_limit = checked_gas_limit(call.gas_limit)?;
+
+	match call.to {
+		Some(to) => <Runtime as pallet_evm::Config>::Runner::call(
+			call.from,
+			to,
+			call.data,
+			call.value,
+			gas_limit,
+			call.gas_price,
+			call.nonce,
+			config,
+		).map(|_| ()),
+		None => <Runtime as pallet_evm::Config>::Runner::create(
+			call.from,
+			call.data,
+			call.value,
+			gas_limit,
+			call.gas_price,
+			call.nonce,
+			config,
+		).map(|_| ()),
+	}.map_err(|err| err.into())
+}
+
+/// Override the state of some accounts before tracing calls.
+///
+/// Balances and nonces that don't fit the types of the runtime are rejected, before anything
+/// is overridden.
+#[cfg(feature = "evm-tracing")]
+fn apply_state_overrides(
+	state_overrides: BTreeMap<H160, hedgeware_rpc_primitives_debug::call::AccountOverride>,
+) -> Result<(), sp_runtime::DispatchError> {
+	use frame_support::{storage::IterableStorageDoubleMap, traits::Currency};
+	use pallet_evm::AddressMapping;
+
+	for account in state_overrides.values() {
+		if account.balance.map_or(false, |balance| balance > U256::from(Balance::MAX)) {
+			return Err(sp_runtime::DispatchError::Other("Balance override does not fit in 128 bits"));
+		}
+		if account.nonce.map_or(false, |nonce| nonce > U256::from(Index::MAX)) {
+			return Err(sp_runtime::DispatchError::Other("Nonce override does not fit in 32 bits"));
+		}
+	}
+
+	for (address, account) in state_overrides {
+		let account_id = <Runtime as pallet_evm::Config>::AddressMapping::into_account_id(address);
+
+		// Balances and nonces are checked to fit above.
+		if let Some(balance) = account.balance {
+			Balances::make_free_balance_be(&account_id, balance.low_u128());
+		}
+		if let Some(nonce) = account.nonce {
+			frame_system::Account::<Runtime>::mutate(&account_id, |info| {
+				info.nonce = nonce.low_u32();
+			});
+		}
+		if let Some(code) = account.code {
+			pallet_evm::AccountCodes::<Runtime>::insert(address, code);
+		}
+		if let Some(state) = account.state {
+			pallet_evm::AccountStorages::<Runtime>::drain_prefix(address).for_each(drop);
+			for (index, value) in state {
+				pallet_evm::AccountStorages::<Runtime>::insert(address, index, value);
+			}
+		}
+		for (index, value) in account.state_diff {
+			pallet_evm::AccountStorages::<Runtime>::insert(address, index, value);
+		}
+	}
+
+	Ok(())
+}
+
 impl_runtime_apis! {
 	impl sp_api::Core<Block> for Runtime {
 		fn version() -> RuntimeVersion {
@@ -1194,194 +1360,212 @@ impl_runtime_apis! {
 
 	impl hedgeware_rpc_primitives_debug::DebugRuntimeApi<Block> for Runtime {
 		fn trace_transaction(
-			extrinsics: Vec<<Block as BlockT>::Extrinsic>,
-			transaction: &EthereumTransaction,
-			trace_type: hedgeware_rpc_primitives_debug::single::TraceType,
-		) -> Result<
-			hedgeware_rpc_primitives_debug::single::TransactionTrace,
-			sp_runtime::DispatchError
-		> {
-			use hedgeware_rpc_primitives_debug::single::TraceType;
-			use hedgeware_evm_tracer::{RawTracer, CallListTracer};
-
-			// Apply the a subset of extrinsics: all the substrate-specific or ethereum transactions
-			// that preceded the requested transaction.
-			for ext in extrinsics.into_iter() {
-				let _ = match &ext.function {
-					Call::Ethereum(transact(t)) => {
-						if t == transaction {
-							return match trace_type {
-								TraceType::Raw {
-									disable_storage,
-									disable_memory,
-									disable_stack,
-								} => {
-									Ok(RawTracer::new(disable_storage,
-										disable_memory,
-										disable_stack,)
-										.trace(|| Executive::apply_extrinsic(ext))
-										.0
-										.into_tx_trace()
-									)
-								},
-								TraceType::CallList => {
-									Ok(CallListTracer::new()
-										.trace(|| Executive::apply_extrinsic(ext))
-										.0
-										.into_tx_trace()
-									)
-								}
+			_extrinsics: Vec<<Block as BlockT>::Extrinsic>,
+			_transaction: &EthereumTransaction,
+			_trace_type: hedgeware_rpc_primitives_debug::single::TraceType,
+		) -> Result<(), sp_runtime::DispatchError> {
+			#[cfg(feature = "evm-tracing")]
+			{
+				// Apply the a subset of extrinsics: all the substrate-specific or ethereum
+				// transactions that preceded the requested transaction.
+				for ext in _extrinsics.into_iter() {
+					let _ = match &ext.function {
+						Call::Ethereum(transact(t)) => {
+							if t == _transaction {
+								// Nothing is traced on top of it.
+								let _ = trace_execution(
+									|| Executive::apply_extrinsic(ext.clone()),
+									_trace_type,
+									false,
+								);
+								return Ok(());
+							} else {
+								Executive::apply_extrinsic(ext)
 							}
+						},
+						_ => Executive::apply_extrinsic(ext)
+					};
+				}
+
+				Err(sp_runtime::DispatchError::Other(
+					"Failed to find Ethereum transaction among the extrinsics."
+				))
+			}
+			#[cfg(not(feature = "evm-tracing"))]
+			Err(sp_runtime::DispatchError::Other(
+				"Missing `evm-tracing` compile time feature flag."
+			))
+		}
 
-						} else {
-							Executive::apply_extrinsic(ext)
-						}
-					},
-					_ => Executive::apply_extrinsic(ext)
-				};
+		fn trace_block(
+			_extrinsics: Vec<<Block as BlockT>::Extrinsic>,
+		) -> Result<(), sp_runtime::DispatchError> {
+			#[cfg(feature = "evm-tracing")]
+			{
+				use hedgeware_evm_tracer::{EventFilter, EvmTracer, TransactionOrigin};
+
+				// Apply all extrinsics. Ethereum extrinsics are traced, as well as the EVM
+				// executions started by other extrinsics.
+				for (ext_index, ext) in _extrinsics.into_iter().enumerate() {
+					let origin = match &ext.function {
+						Call::Ethereum(transact(_transaction)) => TransactionOrigin::Ethereum,
+						_ => TransactionOrigin::Substrate {
+							extrinsic_index: ext_index as u32,
+							extrinsic_hash: H256::from(sp_io::hashing::blake2_256(&ext.encode())),
+						},
+					};
+
+					EvmTracer::<Runtime>::emit_new_transaction(origin);
+					let _ = EvmTracer::<Runtime>::new(EventFilter::default())
+						.trace(|| Executive::apply_extrinsic(ext));
+				}
+
+				Ok(())
 			}
+			#[cfg(not(feature = "evm-tracing"))]
+			Err(sp_runtime::DispatchError::Other(
+				"Missing `evm-tracing` compile time feature flag."
+			))
+		}
 
+		fn trace_block_transactions(
+			_extrinsics: Vec<<Block as BlockT>::Extrinsic>,
+			_trace_type: hedgeware_rpc_primitives_debug::single::TraceType,
+		) -> Result<(), sp_runtime::DispatchError> {
+			#[cfg(feature = "evm-tracing")]
+			{
+				use hedgeware_evm_tracer::{EvmTracer, TransactionOrigin};
+
+				// Apply all extrinsics. Ethereum extrinsics are traced, as well as the EVM
+				// executions started by other extrinsics.
+				for (ext_index, ext) in _extrinsics.into_iter().enumerate() {
+					let origin = match &ext.function {
+						Call::Ethereum(transact(_transaction)) => TransactionOrigin::Ethereum,
+						_ => TransactionOrigin::Substrate {
+							extrinsic_index: ext_index as u32,
+							extrinsic_hash: H256::from(sp_io::hashing::blake2_256(&ext.encode())),
+						},
+					};
+
+					EvmTracer::<Runtime>::emit_new_transaction(origin);
+					let _ = trace_execution(
+						|| Executive::apply_extrinsic(ext.clone()),
+						_trace_type,
+						true,
+					);
+				}
+
+				Ok(())
+			}
+			#[cfg(not(feature = "evm-tracing"))]
 			Err(sp_runtime::DispatchError::Other(
-				"Failed to find Ethereum transaction among the extrinsics."
+				"Missing `evm-tracing` compile time feature flag."
 			))
 		}
 
-		fn trace_block(
-			extrinsics: Vec<<Block as BlockT>::Extrinsic>,
-		) -> Result<
-			Vec<
-				hedgeware_rpc_primitives_debug::block::TransactionTrace>,
-				sp_runtime::DispatchError
-			> {
-			use hedgeware_rpc_primitives_debug::{single, block, CallResult, CreateResult, CreateType};
-			use hedgeware_evm_tracer::CallListTracer;
-
-			let mut config = <Runtime as pallet_evm::Config>::config().clone();
-			config.estimate = true;
-
-			let mut traces = vec![];
-			let mut eth_tx_index = 0;
-
-			// Apply all extrinsics. Ethereum extrinsics are traced.
-			for ext in extrinsics.into_iter() {
-				match &ext.function {
-					Call::Ethereum(transact(_transaction)) => {
-						let tx_traces = CallListTracer::new()
-							.trace(|| Executive::apply_extrinsic(ext))
-							.0
-							.into_tx_trace();
-
-						let tx_traces = match tx_traces {
-							single::TransactionTrace::CallList(t) => t,
-							_ => return Err(sp_runtime::DispatchError::Other("Runtime API error")),
-						};
-
-						// Convert traces from "single" format to "block" format.
-						let mut tx_traces: Vec<_> = tx_traces.into_iter().map(|trace|
-							match trace.inner {
-								single::CallInner::Call {
-									input, to, res, call_type
-								} => block::TransactionTrace {
-									action: block::TransactionTraceAction::Call {
-										call_type,
-										from: trace.from,
-										gas: trace.gas,
-										input,
-										to,
-										value: trace.value,
-									},
-									// Can't be known here, must be inserted upstream.
-									block_hash: H256::default(),
-									// Can't be known here, must be inserted upstream.
-									block_number: 0,
-									output: match res {
-										CallResult::Output(output) => {
-											block::TransactionTraceOutput::Result(
-												block::TransactionTraceResult::Call {
-													gas_used: trace.gas_used,
-													output
-												})
-										},
-										CallResult::Error(error) =>
-											block::TransactionTraceOutput::Error(error),
-									},
-									subtraces: trace.subtraces,
-									trace_address: trace.trace_address,
-									// Can't be known here, must be inserted upstream.
-									transaction_hash: H256::default(),
-									transaction_position: eth_tx_index,
-								},
-								single::CallInner::Create { init, res } => block::TransactionTrace {
-									action: block::TransactionTraceAction::Create {
-										creation_method: CreateType::Create,
-										from: trace.from,
-										gas: trace.gas,
-										init,
-										value: trace.value,
-									},
-									// Can't be known here, must be inserted upstream.
-									block_hash: H256::default(),
-									// Can't be known here, must be inserted upstream.
-									block_number: 0,
-									output: match res {
-										CreateResult::Success {
-											created_contract_address_hash,
-											created_contract_code
-										} => {
-											block::TransactionTraceOutput::Result(
-												block::TransactionTraceResult::Create {
-													gas_used: trace.gas_used,
-													code: created_contract_code,
-													address: created_contract_address_hash,
-												}
-											)
-										},
-										CreateResult::Error {
-											error
-										} => block::TransactionTraceOutput::Error(error),
-									},
-									subtraces: trace.subtraces,
-									trace_address: trace.trace_address,
-									// Can't be known here, must be inserted upstream.
-									transaction_hash: H256::default(),
-									transaction_position: eth_tx_index,
-
-								},
-								single::CallInner::SelfDestruct {
-									balance,
-									refund_address
-								} => block::TransactionTrace {
-									action: block::TransactionTraceAction::Suicide {
-										address: trace.from,
-										balance,
-										refund_address,
-									},
-									// Can't be known here, must be inserted upstream.
-									block_hash: H256::default(),
-									// Can't be known here, must be inserted upstream.
-									block_number: 0,
-									output: block::TransactionTraceOutput::Result(
-												block::TransactionTraceResult::Suicide
-											),
-									subtraces: trace.subtraces,
-									trace_address: trace.trace_address,
-									// Can't be known here, must be inserted upstream.
-									transaction_hash: H256::default(),
-									transaction_position: eth_tx_index,
-
-								},
-							}
-						).collect();
+		fn trace_call(
+			_call: hedgeware_rpc_primitives_debug::call::TraceCall,
+			_state_overrides: BTreeMap<H160, hedgeware_rpc_primitives_debug::call::AccountOverride>,
+			_trace_type: hedgeware_rpc_primitives_debug::single::TraceType,
+		) -> Result<(), sp_runtime::DispatchError> {
+			#[cfg(feature = "evm-tracing")]
+			{
+				apply_state_overrides(_state_overrides)?;
+
+				trace_execution(|| execute_call(&_call), _trace_type, false)
+			}
+			#[cfg(not(feature = "evm-tracing"))]
+			Err(sp_runtime::DispatchError::Other(
+				"Missing `evm-tracing` compile time feature flag."
+			))
+		}
 
-						traces.append(&mut tx_traces);
+		fn replay_calls(
+			_calls: Vec<(
+				hedgeware_rpc_primitives_debug::call::TraceCall,
+				hedgeware_rpc_primitives_debug::replay::ReplayTraceType,
+			)>,
+			_state_overrides: BTreeMap<H160, hedgeware_rpc_primitives_debug::call::AccountOverride>,
+		) -> Result<(), sp_runtime::DispatchError> {
+			#[cfg(feature = "evm-tracing")]
+			{
+				use hedgeware_evm_tracer::{EvmTracer, TransactionOrigin};
+
+				apply_state_overrides(_state_overrides)?;
+
+				// Each call is executed on top of the previous ones.
+				for (call, trace_type) in _calls {
+					EvmTracer::<Runtime>::emit_new_transaction(TransactionOrigin::Ethereum);
+					replay_execution(|| execute_call(&call), trace_type)?;
+				}
+
+				Ok(())
+			}
+			#[cfg(not(feature = "evm-tracing"))]
+			Err(sp_runtime::DispatchError::Other(
+				"Missing `evm-tracing` compile time feature flag."
+			))
+		}
 
-						eth_tx_index += 1;
-					},
-					_ => {let _ = Executive::apply_extrinsic(ext); }
-				};
+		fn replay_transaction(
+			_extrinsics: Vec<<Block as BlockT>::Extrinsic>,
+			_transaction: &EthereumTransaction,
+			_trace_type: hedgeware_rpc_primitives_debug::replay::ReplayTraceType,
+		) -> Result<(), sp_runtime::DispatchError> {
+			#[cfg(feature = "evm-tracing")]
+			{
+				// Apply the extrinsics preceding the requested transaction.
+				for ext in _extrinsics.into_iter() {
+					let _ = match &ext.function {
+						Call::Ethereum(transact(t)) if t == _transaction => {
+							let _ = replay_execution(
+								|| Executive::apply_extrinsic(ext.clone()),
+								_trace_type,
+							);
+							return Ok(());
+						},
+						_ => Executive::apply_extrinsic(ext)
+					};
+				}
+
+				Err(sp_runtime::DispatchError::Other(
+					"Failed to find Ethereum transaction among the extrinsics."
+				))
 			}
+			#[cfg(not(feature = "evm-tracing"))]
+			Err(sp_runtime::DispatchError::Other(
+				"Missing `evm-tracing` compile time feature flag."
+			))
+		}
 
-			Ok(traces)
+		fn replay_block(
+			_extrinsics: Vec<<Block as BlockT>::Extrinsic>,
+			_trace_type: hedgeware_rpc_primitives_debug::replay::ReplayTraceType,
+		) -> Result<(), sp_runtime::DispatchError> {
+			#[cfg(feature = "evm-tracing")]
+			{
+				use hedgeware_evm_tracer::{EvmTracer, TransactionOrigin};
+
+				// Apply all extrinsics. Ethereum extrinsics are replayed.
+				for ext in _extrinsics.into_iter() {
+					match &ext.function {
+						Call::Ethereum(transact(_transaction)) => {
+							EvmTracer::<Runtime>::emit_new_transaction(TransactionOrigin::Ethereum);
+							let _ = replay_execution(
+								|| Executive::apply_extrinsic(ext.clone()),
+								_trace_type,
+							);
+						},
+						_ => {let _ = Executive::apply_extrinsic(ext); }
+					};
+				}
+
+				Ok(())
+			}
+			#[cfg(not(feature = "evm-tracing"))]
+			Err(sp_runtime::DispatchError::Other(
+				"Missing `evm-tracing` compile time feature flag."
+			))
 		}
 	}
 
@@ -1454,7 +1638,7 @@ impl_runtime_apis! {
 				to,
 				data,
 				value,
-				gas_limit.low_u64(),
+				checked_gas_limit(gas_limit)?,
 				gas_price,
 				nonce,
 				config.as_ref().unwrap_or(<Runtime as pallet_evm::Config>::config()),
@@ -1482,7 +1666,7 @@ impl_runtime_apis! {
 				from,
 				data,
 				value,
-				gas_limit.low_u64(),
+				checked_gas_limit(gas_limit)?,
 				gas_price,
 				nonce,
 				config.as_ref().unwrap_or(<Runtime as pallet_evm::Config>::config()),
@@ -1528,6 +1712,50 @@ impl_runtime_apis! {
 			ParachainSystem::collect_collation_info()
 		}
 	}
+
+	#[cfg(feature = "try-runtime")]
+	impl frame_try_runtime::TryRuntime<Block> for Runtime {
+		fn on_runtime_upgrade() -> Result<(Weight, Weight), sp_runtime::RuntimeString> {
+			log::info!("try-runtime::on_runtime_upgrade hedgeware.");
+			let weight = Executive::try_runtime_upgrade()?;
+			Ok((weight, RuntimeBlockWeights::get().max_block))
+		}
+	}
+
+	#[cfg(feature = "runtime-benchmarks")]
+	impl frame_benchmarking::Benchmark<Block> for Runtime {
+		fn dispatch_benchmark(
+			config: frame_benchmarking::BenchmarkConfig
+		) -> Result<Vec<frame_benchmarking::BenchmarkBatch>, sp_runtime::RuntimeString> {
+			use frame_benchmarking::{Benchmarking, BenchmarkBatch, add_benchmark, TrackedStorageKey};
+
+			use frame_system_benchmarking::Pallet as SystemBench;
+			impl frame_system_benchmarking::Config for Runtime {}
+
+			let whitelist: Vec<TrackedStorageKey> = vec![
+				// Block Number
+				hex_literal::hex!("26aa394eea5630e07c48ae0c9558cef702a5c1b19ab7a04f536c519aca4983ac").to_vec().into(),
+				// Total Issuance
+				hex_literal::hex!("c2261276cc9d1f8598ea4b6a74b15c2f57c875e4cff74148e4628f264b974c80").to_vec().into(),
+				// Execution Phase
+				hex_literal::hex!("26aa394eea5630e07c48ae0c9558cef7ff553b5a9862a516939d82b3d3d8661a").to_vec().into(),
+				// Event Count
+				hex_literal::hex!("26aa394eea5630e07c48ae0c9558cef70a98fdbe9ce6c55837576c60c7af3850").to_vec().into(),
+				// System Events
+				hex_literal::hex!("26aa394eea5630e07c48ae0c9558cef780d41e5e16056765bc8461851072c9d7").to_vec().into(),
+			];
+
+			let mut batches = Vec::<BenchmarkBatch>::new();
+			let params = (&config, &whitelist);
+
+			add_benchmark!(params, batches, frame_system, SystemBench::<Runtime>);
+			add_benchmark!(params, batches, pallet_balances, Balances);
+			add_benchmark!(params, batches, treasury_reward, TreasuryReward);
+
+			if batches.is_empty() { return Err("Benchmark not found for this pallet.".into()) }
+			Ok(batches)
+		}
+	}
 }
 
 cumulus_pallet_parachain_system::register_validate_block!(
@@ -0,0 +1,26 @@
+#!/usr/bin/env bash
+# Regenerate the weights of a pallet from a `--features runtime-benchmarks` build.
+#
+#   cargo build --release --features runtime-benchmarks
+#   ./scripts/benchmark.sh treasury_reward
+set -e
+
+pallet=$1
+if [ -z "$pallet" ]; then
+	echo "usage: $0 <pallet>" >&2
+	exit 1
+fi
+
+mkdir -p ./runtime/src/weights
+./target/release/hedgeware-collator benchmark \
+	--chain=local \
+	--execution=wasm \
+	--wasm-execution=compiled \
+	--pallet="$pallet" \
+	--extrinsic='*' \
+	--steps=50 \
+	--repeat=20 \
+	--heap-pages=4096 \
+	--raw \
+	--template=./scripts/frame-weight-template.hbs \
+	--output="./runtime/src/weights/$pallet.rs"
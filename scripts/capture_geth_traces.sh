@@ -0,0 +1,50 @@
+#!/usr/bin/env bash
+# Capture the raw traces geth returns for the code of the serialization tests of
+# `hedgeware-rpc-primitives-debug`, to compare our `structLogs` against.
+#
+# Needs geth 1.10, `curl` and `jq`. Later geth versions charge for the size of init
+# code, which changes the gas limit of the out of gas case. Each code is run as the
+# init code of a contract created by the developer account of a throwaway `geth --dev`
+# node, and the output of `debug_traceTransaction` is written to
+# `rpc-primitives/debug/geth/<case>.json`.
+set -e
+
+out=./rpc-primitives/debug/geth
+port=8545
+mkdir -p "$out"
+
+datadir=$(mktemp -d)
+geth --dev --datadir "$datadir" --http --http.port "$port" --http.api eth,debug \
+	--verbosity 1 &
+geth_pid=$!
+trap 'kill $geth_pid; rm -rf "$datadir"' EXIT
+
+rpc() {
+	curl -sf -X POST -H 'Content-Type: application/json' \
+		--data "{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"$1\",\"params\":$2}" \
+		"http://127.0.0.1:$port" | jq '.result'
+}
+
+until rpc eth_accounts '[]' > /dev/null 2>&1; do
+	sleep 1
+done
+from=$(rpc eth_accounts '[]' | jq -r '.[0]')
+
+# capture <case> <code> <gas limit> <trace options>
+capture() {
+	local hash
+	hash=$(rpc eth_sendTransaction "[{\"from\":\"$from\",\"data\":\"$2\",\"gas\":\"$3\"}]" | jq -r .)
+	until [ "$(rpc eth_getTransactionReceipt "[\"$hash\"]")" != "null" ]; do
+		sleep 1
+	done
+	rpc debug_traceTransaction "[\"$hash\",$4]" > "$out/$1.json"
+	echo "Captured $1 from $hash"
+}
+
+# PUSH1 0x2a PUSH1 0 SSTORE STOP
+capture struct_logs_success 0x602a60005500 0x186a0 '{"enableMemory":true}'
+# PUSH1 0 PUSH1 0 SSTORE PUSH1 0 DUP1 REVERT
+capture struct_logs_revert 0x6000600055600080fd 0x186a0 '{"enableMemory":true}'
+# The code of the successful case, with 10 gas left after the intrinsic gas
+capture struct_logs_out_of_gas 0x602a60005500 0xcf5a \
+	'{"disableStorage":true,"disableStack":true}'
@@ -21,7 +21,7 @@ use hedgeware_parachain_runtime::{AuraId};
 use sc_chain_spec::{ChainSpecExtension, ChainSpecGroup};
 use sc_service::ChainType;
 use serde::{Deserialize, Serialize};
-use sp_core::{sr25519, Pair, Public};
+use sp_core::{sr25519, storage::Storage, Pair, Public};
 use sp_runtime::traits::{IdentifyAccount, Verify, One};
 use hedgeware_parachain_primitives::{AccountId, Signature};
 
@@ -139,6 +139,33 @@ pub fn get_chain_spec(id: ParaId) -> ChainSpec {
 	)
 }
 
+/// Chain spec of a development chain that continues from forked state.
+///
+/// The genesis of such a chain is the raw forked storage, so it is never built from a
+/// `GenesisConfig`.
+pub fn fork_chain_spec(
+	id: ParaId,
+	properties: sc_chain_spec::Properties,
+	storage: Storage,
+) -> ChainSpec {
+	let mut chain_spec = ChainSpec::from_genesis(
+		"Hedgeware Fork",
+		"hedgeware_fork",
+		ChainType::Development,
+		|| unreachable!("the genesis of a fork is its raw storage; qed"),
+		vec![],
+		None,
+		None,
+		Some(properties),
+		Extensions {
+			relay_chain: "rococo-local".into(),
+			para_id: id.into(),
+		},
+	);
+	sc_service::ChainSpec::set_storage(&mut chain_spec, storage);
+	chain_spec
+}
+
 fn testnet_genesis(
 	root_key: AccountId,
 	initial_authorities: Vec<AuraId>,
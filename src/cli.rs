@@ -16,7 +16,7 @@
 
 use crate::chain_spec;
 use sc_cli;
-use std::path::PathBuf;
+use std::{path::PathBuf, str::FromStr};
 use structopt::StructOpt;
 
 /// Sub-commands supported by the collator.
@@ -50,6 +50,32 @@ pub enum Subcommand {
 
 	/// Revert the chain to a previous state.
 	Revert(sc_cli::RevertCmd),
+
+	/// The custom benchmark subcommmand benchmarking runtime pallets.
+	#[structopt(name = "benchmark", about = "Benchmark runtime pallets.")]
+	Benchmark(frame_benchmarking_cli::BenchmarkCmd),
+
+	/// Fork the state of a chain into a chain spec for the development service.
+	#[structopt(name = "fork")]
+	Fork(ForkCmd),
+
+	/// Verify, rebuild or prune the Frontier mapping database.
+	#[structopt(name = "frontier-db")]
+	FrontierDb(FrontierDbCmd),
+
+	/// Run an in-process relay chain with a Hedgeware collator and check that
+	/// parachain blocks are produced and included.
+	#[cfg(feature = "local-testnet")]
+	#[structopt(name = "local-testnet")]
+	LocalTestnet(LocalTestnetCmd),
+
+	/// Try some command against runtime state.
+	#[cfg(feature = "try-runtime")]
+	TryRuntime(try_runtime_cli::TryRuntimeCmd),
+
+	/// Try some command against runtime state. Note: `try-runtime` feature must be enabled.
+	#[cfg(not(feature = "try-runtime"))]
+	TryRuntime,
 }
 
 /// Command for exporting the genesis state of the parachain
@@ -90,6 +116,102 @@ pub struct ExportGenesisWasmCommand {
 	pub chain: Option<String>,
 }
 
+/// Command for forking live chain state into a development chain spec.
+#[derive(Debug, StructOpt)]
+pub struct ForkCmd {
+	/// Chain spec holding the state to fork, as written by `export-state`.
+	///
+	/// If unspecified, the state is read from the database of the chain given by
+	/// `--chain` and `--base-path`.
+	#[structopt(long, parse(from_os_str))]
+	pub state: Option<PathBuf>,
+
+	/// Block to fork the state at when reading it from the database.
+	///
+	/// Default: the best block.
+	#[structopt(long, value_name = "HASH or NUMBER", conflicts_with = "state")]
+	pub at: Option<sc_cli::BlockNumberOrHash>,
+
+	/// Output file name or stdout if unspecified.
+	#[structopt(long, parse(from_os_str))]
+	pub output: Option<PathBuf>,
+
+	/// Replace the forked runtime code with the runtime built into this node.
+	#[structopt(long)]
+	pub use_local_runtime: bool,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub shared_params: sc_cli::SharedParams,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub import_params: sc_cli::ImportParams,
+}
+
+/// Maintenance operation on the Frontier mapping database.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FrontierDbMode {
+	/// Check that the mappings of every canonical block are present and consistent.
+	Verify,
+	/// Regenerate the database from the substrate chain.
+	Rebuild,
+	/// Remove the entries of finalized-away forks.
+	Prune,
+}
+
+impl FromStr for FrontierDbMode {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(match s {
+			"verify" => Self::Verify,
+			"rebuild" => Self::Rebuild,
+			"prune" => Self::Prune,
+			_ => return Err(format!("`{}` is not a supported frontier-db mode", s)),
+		})
+	}
+}
+
+/// Command for maintaining the Frontier mapping database.
+///
+/// The node must not be running while this command runs.
+#[derive(Debug, StructOpt)]
+pub struct FrontierDbCmd {
+	/// What to do with the database.
+	#[structopt(possible_values = &["verify", "rebuild", "prune"])]
+	pub mode: FrontierDbMode,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub shared_params: sc_cli::SharedParams,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub import_params: sc_cli::ImportParams,
+}
+
+/// Command for running the in-process local testnet.
+#[cfg(feature = "local-testnet")]
+#[derive(Debug, StructOpt)]
+pub struct LocalTestnetCmd {
+	/// Id the parachain is registered with on the test relay chain.
+	#[structopt(long, default_value = "2000")]
+	pub parachain_id: u32,
+
+	/// Number of parachain blocks that must be produced and included.
+	#[structopt(long, default_value = "3")]
+	pub blocks: usize,
+
+	/// Time (in seconds) to wait for the blocks before giving up.
+	#[structopt(long, default_value = "300")]
+	pub timeout: u64,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub shared_params: sc_cli::SharedParams,
+}
+
 #[derive(Debug, StructOpt)]
 #[structopt(settings = &[
 	structopt::clap::AppSettings::GlobalVersion,
@@ -158,15 +280,80 @@ pub struct RunCmd {
 	pub ethapi_trace_max_count: u32,
 
 	/// Duration (in seconds) after which the cache of `trace_filter` for a given block will be
-	/// discarded.
+	/// discarded. Also the duration for which the block replays of `debug_traceTransaction`
+	/// are kept.
 	#[structopt(long, default_value = "300")]
 	pub ethapi_trace_cache_duration: u64,
 
+	/// Size (in MiB) above which the cache of `trace_filter` evicts the least recently used
+	/// blocks which are not needed by a running request. The block replays of
+	/// `debug_traceTransaction` are bounded by the same size.
+	#[structopt(long, default_value = "1024")]
+	pub ethapi_trace_cache_size: u64,
+
+	/// Duration (in seconds) after which `debug_traceTransaction` and `debug_traceCall` are
+	/// aborted, unless the request gives its own `timeout`.
+	#[structopt(long, default_value = "30")]
+	pub ethapi_debug_timeout: u64,
+
+	/// Duration (in seconds) after which `debug_traceBlockByNumber` and
+	/// `debug_traceBlockByHash` are aborted, unless the request gives its own `timeout`.
+	#[structopt(long, default_value = "60")]
+	pub ethapi_debug_block_timeout: u64,
+
+	/// Duration (in seconds) after which a request of the "trace" module other than
+	/// `trace_filter` is aborted.
+	#[structopt(long, default_value = "30")]
+	pub ethapi_trace_timeout: u64,
+
+	/// Duration (in seconds) after which a `trace_filter` request is aborted, the blocks of its
+	/// range not traced yet included.
+	#[structopt(long, default_value = "120")]
+	pub ethapi_trace_filter_timeout: u64,
+
+	/// Store the traces of the canonical chain in a database next to the Frontier one, indexed
+	/// by address. `trace_filter` reads the indexed blocks instead of replaying them. Blocks are
+	/// indexed once imported, or only once finalized.
+	#[structopt(long, possible_values = &["imported", "finalized"])]
+	pub ethapi_trace_index: Option<cli_opt::TraceIndex>,
+
+	/// Also index the traces of past blocks in the background, down to this block number.
+	#[structopt(long, requires = "ethapi-trace-index")]
+	pub ethapi_trace_index_backfill: Option<u32>,
+
+	/// Directory of tracing runtimes, built with the `evm-tracing` feature, used instead of the
+	/// on-chain runtimes to trace their blocks. A runtime named `<name>-<from>-<to>.wasm`
+	/// replaces the runtimes with spec versions from `from` to `to`, other ones the runtime with
+	/// their own spec version.
+	#[structopt(long, parse(from_os_str))]
+	pub ethapi_tracing_runtimes: Option<PathBuf>,
+
 	/// Maximum number of logs in a query.
 	#[structopt(long, default_value = "10000")]
 	pub max_past_logs: u32,
 }
 
+impl RunCmd {
+	/// The RPC configuration selected by these arguments.
+	pub fn rpc_config(&self) -> cli_opt::RpcConfig {
+		cli_opt::RpcConfig {
+			ethapi: self.ethapi.clone(),
+			ethapi_max_permits: self.ethapi_max_permits,
+			ethapi_trace_max_count: self.ethapi_trace_max_count,
+			ethapi_trace_cache_duration: self.ethapi_trace_cache_duration,
+			ethapi_trace_cache_size: self.ethapi_trace_cache_size,
+			ethapi_debug_timeout: self.ethapi_debug_timeout,
+			ethapi_debug_block_timeout: self.ethapi_debug_block_timeout,
+			ethapi_trace_timeout: self.ethapi_trace_timeout,
+			ethapi_trace_filter_timeout: self.ethapi_trace_filter_timeout,
+			ethapi_trace_index: self.ethapi_trace_index,
+			ethapi_trace_index_backfill: self.ethapi_trace_index_backfill,
+			ethapi_tracing_runtimes: self.ethapi_tracing_runtimes.clone(),
+			max_past_logs: self.max_past_logs,
+		}
+	}
+}
+
 impl std::ops::Deref for RunCmd {
 	type Target = cumulus_client_cli::RunCmd;
 
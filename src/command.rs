@@ -19,7 +19,6 @@ use crate::{
 	cli::{Cli, RelayChainCli, Subcommand},
 };
 use codec::Encode;
-use cli_opt::RpcConfig;
 use cumulus_client_service::genesis::generate_genesis_block;
 use cumulus_primitives_core::ParaId;
 use log::info;
@@ -28,9 +27,13 @@ use sc_cli::{
 	ChainSpec, CliConfiguration, DefaultConfigurationValues, ImportParams, KeystoreParams,
 	NetworkParams, Result, RuntimeVersion, SharedParams, SubstrateCli,
 };
-use sc_service::config::{BasePath, PrometheusConfig};
+use sc_service::{
+	config::{BasePath, PrometheusConfig},
+	PartialComponents,
+};
 use sp_core::hexdisplay::HexDisplay;
-use sp_runtime::traits::Block as BlockT;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
 use std::{io::Write, net::SocketAddr};
 
 fn load_spec(
@@ -222,6 +225,105 @@ pub fn run() -> Result<()> {
 		Some(Subcommand::Revert(cmd)) => construct_async_run!(|components, cli, cmd, config| {
 			Ok(cmd.run(components.client, components.backend))
 		}),
+		Some(Subcommand::Benchmark(cmd)) => {
+			if cfg!(feature = "runtime-benchmarks") {
+				let runner = cli.create_runner(cmd)?;
+
+				runner.sync_run(|config| {
+					cmd.run::<crate::service::Block, HedgewareParachainRuntimeExecutor>(config)
+				})
+			} else {
+				Err("Benchmarking wasn't enabled when building the node. \
+				You can enable it with `--features runtime-benchmarks`."
+					.into())
+			}
+		}
+		Some(Subcommand::Fork(cmd)) => match &cmd.state {
+			Some(state) => {
+				let source: Box<dyn sc_service::ChainSpec> =
+					Box::new(chain_spec::ChainSpec::from_json_file(state.clone())?);
+				let storage = source.build_storage()?;
+
+				crate::fork::run(cmd, &*source, storage)
+			}
+			None => construct_async_run!(|components, cli, cmd, config| {
+				let block_id = match &cmd.at {
+					Some(at) => at.parse::<crate::service::Block>()?,
+					None => BlockId::Hash(components.client.info().best_hash),
+				};
+				let storage = sc_service::chain_ops::export_raw_state(components.client, &block_id)?;
+
+				Ok(async move { crate::fork::run(cmd, &*config.chain_spec, storage) })
+			}),
+		},
+		Some(Subcommand::FrontierDb(cmd)) => {
+			let runner = cli.create_runner(cmd)?;
+
+			runner.sync_run(|config| {
+				let PartialComponents { client, backend, other, .. } = new_partial::<
+					hedgeware_parachain_runtime::RuntimeApi,
+					HedgewareParachainRuntimeExecutor,
+					_
+				>(
+					&config,
+					crate::service::hedgeware_parachain_build_import_queue,
+				)?;
+				// Close the mapping database, the command opens it on its own terms.
+				drop(other);
+
+				crate::frontier_db::run(cmd.mode, &client, &backend, &config)
+			})
+		}
+		#[cfg(feature = "local-testnet")]
+		Some(Subcommand::LocalTestnet(cmd)) => {
+			let runner = cli.create_runner(cmd)?;
+
+			let task_manager =
+				sc_service::TaskManager::new(runner.config().task_executor.clone(), None)
+					.map_err(|e| format!("Error: {:?}", e))?;
+			let task_executor = runner.config().task_executor.clone();
+			let para_id = ParaId::from(cmd.parachain_id);
+			let blocks = cmd.blocks;
+			let timeout = std::time::Duration::from_secs(cmd.timeout);
+
+			runner.async_run(|_| {
+				Ok((
+					async move {
+						let testnet =
+							crate::local_testnet::LocalTestnet::start(task_executor, para_id).await?;
+						testnet.wait_for_blocks(blocks, timeout).await?;
+						info!("Parachain {:?} produced and included {} blocks", para_id, blocks);
+						Ok(())
+					},
+					task_manager,
+				))
+			})
+		}
+		#[cfg(feature = "try-runtime")]
+		Some(Subcommand::TryRuntime(cmd)) => {
+			let runner = cli.create_runner(cmd)?;
+
+			// grab the task manager.
+			let registry = &runner
+				.config()
+				.prometheus_config
+				.as_ref()
+				.map(|cfg| &cfg.registry);
+			let task_manager =
+				sc_service::TaskManager::new(runner.config().task_executor.clone(), *registry)
+					.map_err(|e| format!("Error: {:?}", e))?;
+
+			runner.async_run(|config| {
+				Ok((
+					cmd.run::<crate::service::Block, HedgewareParachainRuntimeExecutor>(config),
+					task_manager,
+				))
+			})
+		}
+		#[cfg(not(feature = "try-runtime"))]
+		Some(Subcommand::TryRuntime) => Err("TryRuntime wasn't enabled when building the node. \
+			You can enable it with `--features try-runtime`."
+			.into()),
 		Some(Subcommand::ExportGenesisState(params)) => {
 			let mut builder = sc_cli::LoggerBuilder::new("");
 			builder.with_profiling(sc_tracing::TracingReceiver::Log, "");
@@ -271,13 +373,11 @@ pub fn run() -> Result<()> {
 			let runner = cli.create_runner(&cli.run.normalize())?;
 
 			runner.run_node_until_exit(|config| async move {
-				let rpc_config = RpcConfig {
-					ethapi: cli.run.ethapi,
-					ethapi_max_permits: cli.run.ethapi_max_permits,
-					ethapi_trace_max_count: cli.run.ethapi_trace_max_count,
-					ethapi_trace_cache_duration: cli.run.ethapi_trace_cache_duration,
-					max_past_logs: cli.run.max_past_logs,
-				};
+				let rpc_config = cli.run.rpc_config();
+
+				if cli.run.dev_service {
+					return crate::service::new_dev(config, rpc_config).map_err(Into::into);
+				}
 
 				let para_id =
 					chain_spec::Extensions::try_get(&*config.chain_spec).map(|e| e.para_id);
@@ -342,6 +442,33 @@ impl DefaultConfigurationValues for RelayChainCli {
 	}
 }
 
+impl CliConfiguration for crate::cli::ForkCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	fn import_params(&self) -> Option<&ImportParams> {
+		Some(&self.import_params)
+	}
+}
+
+impl CliConfiguration for crate::cli::FrontierDbCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	fn import_params(&self) -> Option<&ImportParams> {
+		Some(&self.import_params)
+	}
+}
+
+#[cfg(feature = "local-testnet")]
+impl CliConfiguration for crate::cli::LocalTestnetCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+}
+
 impl CliConfiguration<Self> for RelayChainCli {
 	fn shared_params(&self) -> &SharedParams {
 		self.base.base.shared_params()
@@ -0,0 +1,113 @@
+// Copyright 2019-2021 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Forking of live chain state into a development chain.
+//!
+//! The forked state is kept as is, including the `treasury_reward` and EVM state,
+//! except for what ties the chain to its collators and its relay chain:
+//!
+//! - the sudo key and the Aura authorities are replaced with Alice,
+//! - the `ParachainSystem` storage is wiped, so that the mocked relay chain data of
+//!   the development service is accepted from the first block on,
+//! - Alice is endowed so that she can pay for transactions.
+//!
+//! The resulting chain spec boots with `--dev-service`.
+
+use crate::{
+	chain_spec::{self, get_account_id_from_seed, get_from_seed, Extensions},
+	cli::ForkCmd,
+};
+use codec::Encode;
+use frame_support::traits::Currency;
+use hedgeware_parachain_primitives::Balance;
+use hedgeware_parachain_runtime::{currency::DOLLARS, AuraId, Balances};
+use log::info;
+use sc_service::ChainSpec;
+use sp_core::{
+	hashing::twox_128,
+	sr25519,
+	storage::{well_known_keys, Storage},
+};
+use sp_state_machine::BasicExternalities;
+use std::io::Write;
+
+/// Amount Alice is endowed with on top of what she holds in the forked state.
+pub(crate) const DEV_ACCOUNT_ENDOWMENT: Balance = 1_000_000 * DOLLARS;
+
+/// Write the chain spec of a development chain continuing from `storage`.
+///
+/// `source` is the chain spec of the chain the state was taken from.
+pub fn run(cmd: &ForkCmd, source: &dyn ChainSpec, mut storage: Storage) -> sc_cli::Result<()> {
+	let para_id = Extensions::try_get(source).map(|e| e.para_id).unwrap_or(2000);
+
+	fork_state(&mut storage, cmd.use_local_runtime)?;
+
+	let chain_spec = chain_spec::fork_chain_spec(para_id.into(), source.properties(), storage);
+	let json = ChainSpec::as_json(&chain_spec, true)?;
+
+	if let Some(output) = &cmd.output {
+		std::fs::write(output, json)?;
+		info!("Forked chain spec written to {}", output.display());
+	} else {
+		std::io::stdout().write_all(json.as_bytes())?;
+	}
+
+	Ok(())
+}
+
+/// Rewrite `storage` so that it can be authored on by the development service.
+pub fn fork_state(storage: &mut Storage, use_local_runtime: bool) -> Result<(), String> {
+	let alice = get_account_id_from_seed::<sr25519::Public>("Alice");
+	let alice_aura = get_from_seed::<AuraId>("Alice");
+
+	storage.top.insert(storage_key(b"Sudo", b"Key"), alice.encode());
+	storage.top.insert(
+		storage_key(b"Aura", b"Authorities"),
+		vec![alice_aura.clone()].encode(),
+	);
+	storage.top.insert(
+		storage_key(b"AuraExt", b"Authorities"),
+		vec![alice_aura].encode(),
+	);
+
+	// The relay chain of the development service is mocked and starts from scratch, so
+	// the validation data, message queue heads and pending upgrades of the real relay
+	// chain would make the first blocks fail.
+	let parachain_system = twox_128(b"ParachainSystem");
+	storage
+		.top
+		.retain(|key, _| !key.starts_with(&parachain_system));
+
+	if use_local_runtime {
+		let code = hedgeware_parachain_runtime::WASM_BINARY
+			.ok_or("WASM binary was not build, please build it!")?;
+		storage
+			.top
+			.insert(well_known_keys::CODE.to_vec(), code.to_vec());
+	}
+
+	BasicExternalities::execute_with_storage(storage, || {
+		// Dropping the imbalance increases the total issuance accordingly.
+		let _ = Balances::deposit_creating(&alice, DEV_ACCOUNT_ENDOWMENT);
+	});
+
+	Ok(())
+}
+
+/// Storage key of a plain storage value.
+pub(crate) fn storage_key(pallet: &[u8], item: &[u8]) -> Vec<u8> {
+	[twox_128(pallet), twox_128(item)].concat()
+}
@@ -0,0 +1,377 @@
+// Copyright 2019-2021 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Maintenance of the Frontier mapping database.
+//!
+//! The database maps Ethereum block and transaction hashes to substrate blocks. It is
+//! only written by the `MappingSyncWorker` while blocks are imported, so a corrupted or
+//! partially synced database is never repaired on its own.
+
+use crate::{
+	cli::FrontierDbMode,
+	service::{frontier_database_dir, open_frontier_backend, Block, HedgewareParachainRuntimeExecutor},
+};
+use codec::{Decode, Encode};
+use hedgeware_parachain_runtime::RuntimeApi;
+use kvdb::DBTransaction;
+use log::{info, warn};
+use sc_client_api::Backend as _;
+use sc_service::{Configuration, TFullBackend, TFullClient};
+use sp_blockchain::HeaderBackend;
+use sp_core::H256;
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Header as HeaderT, NumberFor, Zero},
+};
+
+type FullClient = TFullClient<Block, RuntimeApi, HedgewareParachainRuntimeExecutor>;
+type FullBackend = TFullBackend<Block>;
+
+/// Number of blocks between two progress reports.
+const PROGRESS_INTERVAL: u32 = 1000;
+
+/// Layout of the mapping database, as written by `fc_db`.
+///
+/// `fc_db` only exposes lookups by hash, while pruning has to iterate over and delete
+/// entries, so this mirrors its private `columns` module. The layout is the one of `fc_db`
+/// 1.0.0 from the `polkadot-v0.9.4` branch of webb-tools/frontier, at the revision pinned by
+/// `Cargo.lock`. Keys are the SCALE encoded hashes and values the SCALE encoded
+/// `Block::Hash`, `Vec<TransactionMetadata<Block>>` and `true` respectively. `prune` checks
+/// the layout against the public `fc_db` API before writing anything, and must be revisited
+/// whenever `fc_db` is updated.
+pub(crate) mod columns {
+	pub const NUM_COLUMNS: u32 = 4;
+
+	pub const META: u32 = 0;
+	pub const BLOCK_MAPPING: u32 = 1;
+	pub const TRANSACTION_MAPPING: u32 = 2;
+	pub const SYNCED_MAPPING: u32 = 3;
+}
+
+/// Key of the syncing tips of the `MappingSyncWorker` in the `META` column.
+pub(crate) const CURRENT_SYNCING_TIPS: &[u8] = b"CURRENT_SYNCING_TIPS";
+
+/// Run `mode` against the mapping database of the node configured by `config`.
+///
+/// The mapping database must not be opened by anyone else.
+pub fn run(
+	mode: FrontierDbMode,
+	client: &FullClient,
+	backend: &FullBackend,
+	config: &Configuration,
+) -> sc_cli::Result<()> {
+	match mode {
+		FrontierDbMode::Verify => verify(client, config),
+		FrontierDbMode::Rebuild => rebuild(client, backend, config),
+		FrontierDbMode::Prune => prune(client, config),
+	}
+	.map_err(Into::into)
+}
+
+/// Check that every canonical block is synced and that its Ethereum block and
+/// transactions map back to it.
+fn verify(client: &FullClient, config: &Configuration) -> Result<(), String> {
+	let frontier_backend = open_frontier_backend(config)?;
+	let mapping = frontier_backend.mapping();
+	let best_number = client.info().best_number;
+
+	let mut unsynced_blocks = 0u32;
+	let mut bad_blocks = 0u32;
+	let mut bad_transactions = 0u32;
+
+	info!("Verifying the Frontier mappings of blocks #0 to #{}", best_number);
+	for number in 0..=best_number {
+		let hash = client
+			.hash(number)
+			.map_err(|e| format!("{:?}", e))?
+			.ok_or_else(|| format!("Canonical block #{} not found", number))?;
+
+		if !mapping.is_synced(&hash)? {
+			warn!("Block #{} ({:?}) is not synced", number, hash);
+			unsynced_blocks += 1;
+			continue;
+		}
+
+		let header = client
+			.header(BlockId::Hash(hash))
+			.map_err(|e| format!("{:?}", e))?
+			.ok_or_else(|| format!("Header of block #{} not found", number))?;
+
+		// The genesis block has no Ethereum digest, being synced is all there is to check.
+		if let Ok(log) = fp_consensus::find_log(header.digest()) {
+			let hashes = log.into_hashes();
+
+			match mapping.block_hash(&hashes.block_hash)? {
+				Some(mapped) if mapped == hash => {}
+				mapped => {
+					warn!(
+						"Ethereum block {:?} of block #{} maps to {:?} instead of {:?}",
+						hashes.block_hash, number, mapped, hash,
+					);
+					bad_blocks += 1;
+				}
+			}
+
+			for (index, transaction_hash) in hashes.transaction_hashes.iter().enumerate() {
+				let found = mapping
+					.transaction_metadata(transaction_hash)?
+					.iter()
+					.any(|metadata| {
+						metadata.block_hash == hash
+							&& metadata.ethereum_block_hash == hashes.block_hash
+							&& metadata.ethereum_index as usize == index
+					});
+
+				if !found {
+					warn!(
+						"Ethereum transaction {:?} of block #{} is not mapped to index {}",
+						transaction_hash, number, index,
+					);
+					bad_transactions += 1;
+				}
+			}
+		}
+
+		if number % PROGRESS_INTERVAL == 0 {
+			info!("Verified {}/{} blocks", number, best_number);
+		}
+	}
+
+	if unsynced_blocks + bad_blocks + bad_transactions > 0 {
+		return Err(format!(
+			"Frontier mapping database is inconsistent: {} unsynced blocks, {} bad block \
+			mappings, {} bad transaction mappings. Run `frontier-db rebuild` to fix it.",
+			unsynced_blocks, bad_blocks, bad_transactions,
+		));
+	}
+
+	info!("Frontier mapping database is consistent up to block #{}", best_number);
+	Ok(())
+}
+
+/// Wipe the mapping database and sync it again from the substrate chain.
+fn rebuild(
+	client: &FullClient,
+	backend: &FullBackend,
+	config: &Configuration,
+) -> Result<(), String> {
+	let path = frontier_database_dir(config);
+	if path.exists() {
+		std::fs::remove_dir_all(&path)
+			.map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+		info!("Removed the Frontier mapping database at {}", path.display());
+	}
+
+	let frontier_backend = open_frontier_backend(config)?;
+	let best_number = client.info().best_number;
+
+	// Same walk as the `MappingSyncWorker`: from the leaves down to the genesis block.
+	let mut synced = 0u32;
+	while fc_mapping_sync::sync_one_block(client, backend.blockchain(), &*frontier_backend)? {
+		synced += 1;
+		if synced % PROGRESS_INTERVAL == 0 {
+			info!("Synced {} blocks (best block is #{})", synced, best_number);
+		}
+	}
+
+	info!("Rebuilt the Frontier mapping database from {} blocks", synced);
+	Ok(())
+}
+
+/// Find a block whose Ethereum block is mapped by the public `fc_db` API, to later check
+/// that the raw database still has the layout of `columns`.
+///
+/// The search goes from the best block down to the genesis block, as the latest blocks may
+/// not be synced yet and blocks without Ethereum transactions may have no Ethereum block.
+/// Returns the Ethereum block hash and the substrate block hash it maps to, if any block
+/// is mapped at all.
+fn layout_sample(
+	client: &FullClient,
+	config: &Configuration,
+) -> Result<Option<(H256, H256)>, String> {
+	// Dropped before the raw database is opened, RocksDB only allows one owner.
+	let frontier_backend = open_frontier_backend(config)?;
+	let mapping = frontier_backend.mapping();
+
+	let mut hash = client.info().best_hash;
+	loop {
+		let header = client
+			.header(BlockId::Hash(hash))
+			.map_err(|e| format!("{:?}", e))?
+			.ok_or_else(|| format!("Header of block {:?} not found", hash))?;
+
+		if let Ok(log) = fp_consensus::find_log(header.digest()) {
+			let ethereum_block_hash = log.into_hashes().block_hash;
+			if let Some(mapped) = mapping.block_hash(&ethereum_block_hash)? {
+				return Ok(Some((ethereum_block_hash, mapped)));
+			}
+		}
+
+		if header.number().is_zero() {
+			return Ok(None);
+		}
+		hash = *header.parent_hash();
+	}
+}
+
+/// Check that the raw database maps the Ethereum block of `sample` to its substrate block,
+/// where the layout of `columns` says it does.
+pub(crate) fn has_layout(
+	db: &kvdb_rocksdb::Database,
+	sample: (H256, H256),
+) -> Result<bool, String> {
+	let (ethereum_block_hash, hash) = sample;
+	let raw = db
+		.get(columns::BLOCK_MAPPING, &ethereum_block_hash.encode())
+		.map_err(|e| format!("{:?}", e))?;
+	Ok(raw.map(|value| H256::decode(&mut &value[..]).ok()) == Some(Some(hash)))
+}
+
+/// Whether the mappings of block `hash` are stale, which is when the block is at or below
+/// the last finalized block without being canonical, or unknown to `client` altogether.
+pub(crate) fn is_stale<C: HeaderBackend<Block>>(
+	client: &C,
+	finalized_number: NumberFor<Block>,
+	hash: &H256,
+) -> Result<bool, String> {
+	match client.number(*hash).map_err(|e| format!("{:?}", e))? {
+		Some(number) if number > finalized_number => Ok(false),
+		Some(number) => Ok(client.hash(number).map_err(|e| format!("{:?}", e))? != Some(*hash)),
+		// Unknown to the client, so never going to be canonical.
+		None => Ok(true),
+	}
+}
+
+/// Number of entries removed by `prune_mappings`.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Pruned {
+	pub blocks: u32,
+	pub transactions: u32,
+	pub synced: u32,
+}
+
+/// Remove the entries of the blocks `is_stale` returns `true` for from the raw database.
+pub(crate) fn prune_mappings(
+	db: &kvdb_rocksdb::Database,
+	is_stale: impl Fn(&H256) -> Result<bool, String>,
+) -> Result<Pruned, String> {
+	let decode_hash = |bytes: &[u8]| {
+		H256::decode(&mut &bytes[..]).map_err(|e| format!("Invalid block hash: {:?}", e))
+	};
+
+	let mut pruned = Pruned::default();
+	let mut transaction = DBTransaction::new();
+
+	info!("Pruning the Ethereum block mappings");
+	for (key, value) in db.iter(columns::BLOCK_MAPPING) {
+		if is_stale(&decode_hash(&value[..])?)? {
+			transaction.delete(columns::BLOCK_MAPPING, &key);
+			pruned.blocks += 1;
+		}
+	}
+
+	info!("Pruning the Ethereum transaction mappings");
+	for (key, value) in db.iter(columns::TRANSACTION_MAPPING) {
+		let metadata = Vec::<fc_db::TransactionMetadata<Block>>::decode(&mut &value[..])
+			.map_err(|e| format!("Invalid transaction metadata: {:?}", e))?;
+		let count = metadata.len();
+
+		let mut kept = Vec::with_capacity(count);
+		for entry in metadata {
+			if !is_stale(&entry.block_hash)? {
+				kept.push(entry);
+			}
+		}
+
+		if kept.len() != count {
+			pruned.transactions += (count - kept.len()) as u32;
+			if kept.is_empty() {
+				transaction.delete(columns::TRANSACTION_MAPPING, &key);
+			} else {
+				transaction.put_vec(columns::TRANSACTION_MAPPING, &key, kept.encode());
+			}
+		}
+	}
+
+	info!("Pruning the synced block markers");
+	for (key, _) in db.iter(columns::SYNCED_MAPPING) {
+		if is_stale(&decode_hash(&key[..])?)? {
+			transaction.delete(columns::SYNCED_MAPPING, &key);
+			pruned.synced += 1;
+		}
+	}
+
+	if let Some(value) = db
+		.get(columns::META, CURRENT_SYNCING_TIPS)
+		.map_err(|e| format!("{:?}", e))?
+	{
+		let tips = Vec::<H256>::decode(&mut &value[..])
+			.map_err(|e| format!("Invalid syncing tips: {:?}", e))?;
+		let mut kept = Vec::with_capacity(tips.len());
+		for tip in tips {
+			if !is_stale(&tip)? {
+				kept.push(tip);
+			}
+		}
+		transaction.put_vec(columns::META, CURRENT_SYNCING_TIPS, kept.encode());
+	}
+
+	db.write(transaction).map_err(|e| format!("{:?}", e))?;
+	Ok(pruned)
+}
+
+/// Remove the entries of blocks that are not part of the finalized chain anymore.
+///
+/// Blocks above the last finalized one are left alone, as they may still become canonical.
+fn prune(client: &FullClient, config: &Configuration) -> Result<(), String> {
+	let path = frontier_database_dir(config);
+	let refuse = |reason: &str| {
+		format!(
+			"The Frontier mapping database at {} {}, refusing to prune it",
+			path.display(),
+			reason,
+		)
+	};
+
+	let sample = layout_sample(client, config)?
+		.ok_or_else(|| refuse("has no mapped block to check its layout against"))?;
+
+	let db = kvdb_rocksdb::Database::open(
+		&kvdb_rocksdb::DatabaseConfig::with_columns(columns::NUM_COLUMNS),
+		&path.to_string_lossy(),
+	)
+	.map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+	if !has_layout(&db, sample)? {
+		return Err(refuse(
+			"does not have the layout this version of `frontier-db prune` was written for",
+		));
+	}
+
+	let finalized_number = client.info().finalized_number;
+	let pruned = prune_mappings(&db, |hash| is_stale(client, finalized_number, hash))
+		.map_err(|e| format!("Failed to prune {}: {}", path.display(), e))?;
+
+	info!(
+		"Pruned {} block mappings, {} transaction mappings and {} synced markers below block #{}",
+		pruned.blocks, pruned.transactions, pruned.synced, finalized_number,
+	);
+	if finalized_number.is_zero() {
+		warn!("No block is finalized, nothing could be pruned");
+	}
+
+	Ok(())
+}
@@ -4,4 +4,11 @@ pub mod rpc;
 pub mod service;
 pub mod cli;
 pub mod command;
-pub mod chain_spec;
\ No newline at end of file
+pub mod chain_spec;
+pub mod fork;
+pub mod frontier_db;
+#[cfg(any(test, feature = "local-testnet"))]
+pub mod local_testnet;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,274 @@
+// Copyright 2019-2021 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! In-process local testnet.
+//!
+//! Spins up a test relay chain with two validators (Alice and Bob), registers the
+//! Hedgeware genesis on it and runs a Hedgeware collator against it, all inside the
+//! current process.
+//!
+//! The relay chain runs `polkadot-test-runtime` rather than `rococo-local`. On
+//! release-v0.9.4, `polkadot-test-service` is the only relay chain node that can be
+//! started inside another process, and it only runs that runtime, whose sudo key
+//! lets `register_parachain` register the Hedgeware genesis directly. Testing against
+//! `rococo-local` itself still needs a separate `polkadot` binary, as described in the
+//! README.
+
+use crate::{chain_spec, cli::Cli, service::Block};
+use codec::Encode;
+use cumulus_client_service::genesis::generate_genesis_block;
+use cumulus_primitives_core::ParaId;
+use futures::StreamExt;
+use hedgeware_parachain_runtime::RuntimeApi;
+use log::info;
+use polkadot_primitives::v1::CollatorPair;
+use polkadot_service::IsCollator;
+use polkadot_test_service::PolkadotTestNode;
+use sc_cli::SubstrateCli;
+use sc_client_api::{BlockchainEvents, ExecutionStrategy};
+use sc_network::{config::TransportConfig, multiaddr};
+use sc_service::{
+	config::{
+		DatabaseConfig, ExecutionStrategies, KeepBlocks, KeystoreConfig, NetworkConfiguration,
+		OffchainWorkerConfig, PruningMode, TransactionStorageMode, WasmExecutionMethod,
+	},
+	BasePath, ChainSpec, Configuration, Role, TFullClient, TaskExecutor, TaskManager,
+};
+use sp_consensus::BlockOrigin;
+use sp_core::storage::well_known_keys;
+use sp_keyring::Sr25519Keyring;
+use sp_runtime::traits::Block as BlockT;
+use std::{sync::Arc, time::Duration};
+use structopt::StructOpt;
+
+/// Full client of the collator running in the local testnet.
+pub type Client = TFullClient<Block, RuntimeApi, crate::service::HedgewareParachainRuntimeExecutor>;
+
+/// A running local testnet.
+pub struct LocalTestnet {
+	/// The first relay chain validator.
+	pub alice: PolkadotTestNode,
+	/// The second relay chain validator.
+	pub bob: PolkadotTestNode,
+	/// Task manager of the Hedgeware collator.
+	pub task_manager: TaskManager,
+	/// Client of the Hedgeware collator.
+	pub client: Arc<Client>,
+	/// Id the parachain was registered with.
+	pub para_id: ParaId,
+}
+
+impl LocalTestnet {
+	/// Start the relay chain validators, register the parachain and start the collator.
+	pub async fn start(
+		task_executor: TaskExecutor,
+		para_id: ParaId,
+	) -> Result<Self, sc_service::Error> {
+		let alice = polkadot_test_service::run_validator_node(
+			task_executor.clone(),
+			Sr25519Keyring::Alice,
+			|| {},
+			Vec::new(),
+			Some(cumulus_test_relay_validation_worker_provider::VALIDATION_WORKER.into()),
+		);
+		let bob = polkadot_test_service::run_validator_node(
+			task_executor.clone(),
+			Sr25519Keyring::Bob,
+			|| {},
+			vec![alice.addr.clone()],
+			Some(cumulus_test_relay_validation_worker_provider::VALIDATION_WORKER.into()),
+		);
+		info!("Relay chain validators started: {}, {}", alice.addr, bob.addr);
+
+		let para_spec: Box<dyn ChainSpec> = Box::new(chain_spec::get_chain_spec(para_id));
+		let genesis_head = generate_genesis_block::<Block>(&para_spec)
+			.map_err(|e| format!("Failed to build the parachain genesis block: {:?}", e))?
+			.header()
+			.encode();
+		let genesis_wasm = para_spec
+			.build_storage()?
+			.top
+			.remove(well_known_keys::CODE)
+			.ok_or("Could not find wasm file in genesis state!")?;
+
+		alice
+			.register_parachain(para_id, genesis_wasm, genesis_head)
+			.await
+			.map_err(|e| format!("Failed to register parachain {:?}: {:?}", para_id, e))?;
+		info!("Parachain {:?} registered on the relay chain", para_id);
+
+		let relay_chain_nodes = vec![alice.addr.clone(), bob.addr.clone()];
+		let polkadot_config = polkadot_test_service::node_config(
+			|| {},
+			task_executor.clone(),
+			Sr25519Keyring::Alice,
+			relay_chain_nodes,
+			false,
+		);
+		let parachain_config = parachain_config(task_executor, Sr25519Keyring::Alice, para_id)?;
+
+		let (task_manager, client) = crate::service::start_hedgeware_parachain_node_with_relay(
+			parachain_config,
+			polkadot_config,
+			para_id,
+			rpc_config(),
+			|config, _| {
+				polkadot_test_service::new_full(
+					config,
+					IsCollator::Yes(CollatorPair::generate().0),
+					None,
+				)
+				.map(|node| node.with_client(polkadot_test_service::TestClient))
+			},
+		)
+		.await?;
+
+		Ok(Self {
+			alice,
+			bob,
+			task_manager,
+			client,
+			para_id,
+		})
+	}
+
+	/// Wait until the collator has authored `count` parachain blocks.
+	pub async fn wait_for_blocks_produced(&self, count: usize) {
+		let mut produced = self
+			.client
+			.import_notification_stream()
+			.filter(|notification| futures::future::ready(notification.origin == BlockOrigin::Own))
+			.take(count);
+
+		while let Some(notification) = produced.next().await {
+			info!("Produced parachain block #{}", notification.header.number);
+		}
+	}
+
+	/// Wait until `count` parachain blocks have been included by the relay chain.
+	///
+	/// The collator only finalizes a parachain block once the relay chain has included
+	/// it, so this follows the finality notifications of the parachain client.
+	pub async fn wait_for_blocks_included(&self, count: usize) {
+		let mut included = self.client.finality_notification_stream().take(count);
+
+		while let Some(notification) = included.next().await {
+			info!("Parachain block #{} included", notification.header.number);
+		}
+	}
+
+	/// Wait until `count` blocks have been both produced and included, failing after `timeout`.
+	pub async fn wait_for_blocks(&self, count: usize, timeout: Duration) -> Result<(), String> {
+		let produced_and_included = futures::future::join(
+			self.wait_for_blocks_produced(count),
+			self.wait_for_blocks_included(count),
+		);
+
+		tokio::time::timeout(timeout, produced_and_included)
+			.await
+			.map(|_| ())
+			.map_err(|_| {
+				format!(
+					"Parachain {:?} did not get {} blocks produced and included within {:?}",
+					self.para_id, count, timeout,
+				)
+			})
+	}
+}
+
+/// The RPC configuration of the collator, the one of a collator started without any
+/// RPC arguments.
+fn rpc_config() -> cli_opt::RpcConfig {
+	Cli::from_iter(&[Cli::executable_name()]).run.rpc_config()
+}
+
+/// Create the configuration of an in-memory collator for the local chain spec.
+fn parachain_config(
+	task_executor: TaskExecutor,
+	key: Sr25519Keyring,
+	para_id: ParaId,
+) -> Result<Configuration, sc_service::Error> {
+	let base_path = BasePath::new_temp_dir()?;
+	let root = base_path.path().to_path_buf();
+	let key_seed = key.to_seed();
+
+	let mut network_config = NetworkConfiguration::new(
+		format!("{} (parachain)", key_seed),
+		"network/test/0.1",
+		Default::default(),
+		None,
+	);
+	network_config.allow_non_globals_in_dht = true;
+	network_config
+		.listen_addresses
+		.push(multiaddr::Protocol::Memory(rand::random()).into());
+	network_config.transport = TransportConfig::MemoryOnly;
+
+	Ok(Configuration {
+		impl_name: "hedgeware-local-testnet".into(),
+		impl_version: "0.1".into(),
+		role: Role::Authority,
+		task_executor,
+		transaction_pool: Default::default(),
+		network: network_config,
+		keystore: KeystoreConfig::InMemory,
+		keystore_remote: Default::default(),
+		database: DatabaseConfig::RocksDb {
+			path: root.join("db"),
+			cache_size: 128,
+		},
+		state_cache_size: 67108864,
+		state_cache_child_ratio: None,
+		state_pruning: PruningMode::ArchiveAll,
+		keep_blocks: KeepBlocks::All,
+		transaction_storage: TransactionStorageMode::BlockBody,
+		chain_spec: Box::new(chain_spec::get_chain_spec(para_id)),
+		wasm_method: WasmExecutionMethod::Interpreted,
+		// Prefer the native runtime so that failures are easier to debug.
+		execution_strategies: ExecutionStrategies {
+			syncing: ExecutionStrategy::NativeWhenPossible,
+			importing: ExecutionStrategy::NativeWhenPossible,
+			block_construction: ExecutionStrategy::NativeWhenPossible,
+			offchain_worker: ExecutionStrategy::NativeWhenPossible,
+			other: ExecutionStrategy::NativeWhenPossible,
+		},
+		rpc_http: None,
+		rpc_ws: None,
+		rpc_ipc: None,
+		rpc_ws_max_connections: None,
+		rpc_cors: None,
+		rpc_methods: Default::default(),
+		prometheus_config: None,
+		telemetry_endpoints: None,
+		telemetry_external_transport: None,
+		default_heap_pages: None,
+		offchain_worker: OffchainWorkerConfig {
+			enabled: true,
+			indexing_enabled: false,
+		},
+		force_authoring: false,
+		disable_grandpa: false,
+		dev_key_seed: Some(key_seed),
+		tracing_targets: None,
+		tracing_receiver: Default::default(),
+		max_runtime_instances: 8,
+		announce_block: true,
+		base_path: Some(base_path),
+		informant_output_format: Default::default(),
+		wasm_runtime_overrides: None,
+		disable_log_reloading: false,
+	})
+}
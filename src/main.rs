@@ -24,6 +24,10 @@ mod chain_spec;
 mod service;
 mod cli;
 mod command;
+mod fork;
+mod frontier_db;
+#[cfg(feature = "local-testnet")]
+mod local_testnet;
 mod rpc;
 
 fn main() -> sc_cli::Result<()> {
@@ -35,10 +35,12 @@ use fc_rpc_core::types::{FilterPool, PendingTransactions};
 use futures::StreamExt;
 use jsonrpc_pubsub::manager::SubscriptionManager;
 use hedgeware_parachain_primitives::{AccountId, Balance, Block, Hash, Index};
+use hedgeware_client_evm_tracing::TracingRuntimes;
 use hedgeware_rpc_debug::DebugHandler;
 use hedgeware_rpc_debug::{Debug, DebugRequester, DebugServer};
 use hedgeware_rpc_trace::{
-	CacheRequester as TraceFilterCacheRequester, CacheTask, Trace, TraceServer,
+	CacheRequester as TraceFilterCacheRequester, CacheTask, Trace, TraceDb, TraceIndexMode,
+	TraceIndexTask, TraceServer,
 };
 use hedgeware_rpc_txpool::{TxPool, TxPoolServer};
 use pallet_transaction_payment_rpc::{TransactionPayment, TransactionPaymentApi};
@@ -52,7 +54,7 @@ use sc_rpc::SubscriptionTaskExecutor;
 use sc_rpc_api::DenyUnsafe;
 use sc_service::TaskManager;
 use sc_transaction_graph::{ChainApi, Pool};
-use sp_api::{HeaderT, ProvideRuntimeApi};
+use sp_api::{CallApiAt, HeaderT, ProvideRuntimeApi};
 use sp_blockchain::{
 	Backend as BlockchainBackend, Error as BlockChainError, HeaderBackend, HeaderMetadata,
 };
@@ -61,6 +63,7 @@ use sp_runtime::traits::{BlakeTwo256, Block as BlockT};
 use sp_transaction_pool::TransactionPool;
 use std::collections::BTreeMap;
 use substrate_frame_rpc_system::{FullSystem, SystemApi};
+use substrate_prometheus_endpoint::Registry;
 
 /// A set of APIs that polkadot-like runtimes must implement.
 ///
@@ -105,6 +108,7 @@ where
 pub struct RpcRequesters {
 	pub debug: Option<DebugRequester>,
 	pub trace: Option<TraceFilterCacheRequester>,
+	pub trace_db: Option<Arc<TraceDb>>,
 }
 
 /// Full client dependencies.
@@ -135,8 +139,14 @@ pub struct FullDeps<C, P, A: ChainApi, BE> {
 	pub debug_requester: Option<DebugRequester>,
 	/// Trace filter cache server requester.
 	pub trace_filter_requester: Option<TraceFilterCacheRequester>,
+	/// Trace index database, read by `trace_filter`.
+	pub trace_db: Option<Arc<TraceDb>>,
 	/// Trace filter max count.
 	pub trace_filter_max_count: u32,
+	/// Time after which a request of the "trace" module is aborted.
+	pub trace_timeout: Duration,
+	/// Time after which a `trace_filter` request is aborted.
+	pub trace_filter_timeout: Duration,
 	/// Maximum number of logs in a query.
 	pub max_past_logs: u32,
 }
@@ -172,7 +182,10 @@ where
 		backend: _,
 		debug_requester,
 		trace_filter_requester,
+		trace_db,
 		trace_filter_max_count,
+		trace_timeout,
+		trace_filter_timeout,
 		max_past_logs,
 	} = deps;
 
@@ -248,8 +261,12 @@ where
 	if let Some(trace_filter_requester) = trace_filter_requester {
 		io.extend_with(TraceServer::to_delegate(Trace::new(
 			client,
+			frontier_backend,
 			trace_filter_requester,
+			trace_db,
 			trace_filter_max_count,
+			trace_timeout,
+			trace_filter_timeout,
 		)));
 	}
 
@@ -260,27 +277,39 @@ where
 	io
 }
 
-pub struct SpawnTasksParams<'a, B: BlockT, C, BE> {
+pub struct SpawnTasksParams<'a, B: BlockT, C, BE, A: ChainApi> {
 	pub task_manager: &'a TaskManager,
 	pub client: Arc<C>,
 	pub substrate_backend: Arc<BE>,
 	pub frontier_backend: Arc<fc_db::Backend<B>>,
+	/// Graph pool instance, providing the pending block.
+	pub graph: Arc<Pool<A>>,
 	pub pending_transactions: PendingTransactions,
 	pub filter_pool: Option<FilterPool>,
+	/// Database of the trace index, if enabled.
+	pub trace_db: Option<Arc<TraceDb>>,
+	/// Tracing runtimes replacing the on-chain runtimes, if any.
+	pub tracing_runtimes: Option<Arc<TracingRuntimes<B, BE>>>,
+	/// Whether the node keeps the state of all blocks, otherwise tracing errors on pruned
+	/// states hint at archive mode.
+	pub state_archive: bool,
+	pub prometheus_registry: Option<Registry>,
 }
 
 /// Spawn the tasks that are required to run Hedgeware.
-pub fn spawn_tasks<B, C, BE>(
+pub fn spawn_tasks<B, C, BE, A>(
 	rpc_config: &RpcConfig,
-	params: SpawnTasksParams<B, C, BE>,
+	params: SpawnTasksParams<B, C, BE, A>,
 ) -> RpcRequesters
 where
-	C: ProvideRuntimeApi<B> + BlockOf,
+	C: ProvideRuntimeApi<B> + CallApiAt<B> + BlockOf,
 	C: HeaderBackend<B> + HeaderMetadata<B, Error = BlockChainError> + 'static,
 	C: BlockchainEvents<B>,
 	C: Send + Sync + 'static,
 	C::Api: EthereumRuntimeRPCApi<B> + DebugRuntimeApi<B> + DebugRuntimeApi<B>,
+	C::Api: hedgeware_rpc_primitives_txpool::TxPoolRuntimeApi<B>,
 	C::Api: BlockBuilder<B>,
+	A: ChainApi<Block = B> + 'static,
 	B: BlockT<Hash = H256> + Send + Sync + 'static,
 	B::Header: HeaderT<Number = u32>,
 	BE: Backend<B> + 'static,
@@ -293,8 +322,14 @@ where
 			let (trace_filter_task, trace_filter_requester) = CacheTask::create(
 				Arc::clone(&params.client),
 				Arc::clone(&params.substrate_backend),
+				Arc::clone(&params.frontier_backend),
+				Arc::clone(&params.graph),
+				params.tracing_runtimes.clone(),
 				Duration::from_secs(rpc_config.ethapi_trace_cache_duration),
+				(rpc_config.ethapi_trace_cache_size as usize).saturating_mul(1024 * 1024),
+				params.state_archive,
 				Arc::clone(&permit_pool),
+				params.prometheus_registry.clone(),
 			);
 			(Some(trace_filter_task), Some(trace_filter_requester))
 		} else {
@@ -306,7 +341,14 @@ where
 			Arc::clone(&params.client),
 			Arc::clone(&params.substrate_backend),
 			Arc::clone(&params.frontier_backend),
+			Arc::clone(&params.graph),
 			Arc::clone(&permit_pool),
+			params.tracing_runtimes.clone(),
+			Duration::from_secs(rpc_config.ethapi_debug_timeout),
+			Duration::from_secs(rpc_config.ethapi_debug_block_timeout),
+			Duration::from_secs(rpc_config.ethapi_trace_cache_duration),
+			(rpc_config.ethapi_trace_cache_size as usize).saturating_mul(1024 * 1024),
+			params.state_archive,
 		);
 		(Some(debug_task), Some(debug_requester))
 	} else {
@@ -336,6 +378,28 @@ where
 			.spawn("trace-filter-cache", trace_filter_task);
 	}
 
+	// Trace index task if enabled.
+	// Stores the traces of the new blocks, read by `trace_filter`.
+	if let (Some(trace_db), Some(mode)) = (&params.trace_db, rpc_config.ethapi_trace_index) {
+		let mode = match mode {
+			cli_opt::TraceIndex::Imported => TraceIndexMode::Imported,
+			cli_opt::TraceIndex::Finalized => TraceIndexMode::Finalized,
+		};
+		params.task_manager.spawn_handle().spawn(
+			"trace-index",
+			TraceIndexTask::create(
+				Arc::clone(&params.client),
+				Arc::clone(&params.substrate_backend),
+				Arc::clone(trace_db),
+				params.tracing_runtimes.clone(),
+				Arc::clone(&permit_pool),
+				mode,
+				rpc_config.ethapi_trace_index_backfill,
+				params.state_archive,
+			),
+		);
+	}
+
 	// `debug` task if enabled. Essential.
 	// Proxies rpc requests to it's handler.
 	if let Some(debug_task) = debug_task {
@@ -377,5 +441,6 @@ where
 	RpcRequesters {
 		debug: debug_requester,
 		trace: trace_filter_requester,
+		trace_db: params.trace_db,
 	}
 }
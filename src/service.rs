@@ -30,12 +30,14 @@ use sc_network::NetworkService;
 use sc_service::{Configuration, PartialComponents, Role, TFullBackend, TFullClient, TaskManager};
 use sc_telemetry::{Telemetry, TelemetryHandle, TelemetryWorker, TelemetryWorkerHandle};
 use sp_api::ConstructRuntimeApi;
+use sp_blockchain::HeaderBackend;
 use sp_consensus::SlotData;
 use sp_keystore::SyncCryptoStorePtr;
 
 use std::{
 	collections::{BTreeMap, HashMap},
 	sync::{Arc, Mutex},
+	time::Duration,
 };
 use substrate_prometheus_endpoint::Registry;
 use fc_rpc_core::types::{FilterPool, PendingTransactions};
@@ -44,6 +46,7 @@ use fc_consensus::FrontierBlockImport;
 pub use sc_executor::NativeExecutor;
 use sc_service::BasePath;
 use crate::rpc::RuntimeApiCollection;
+use hedgeware_client_evm_tracing::TracingRuntimes;
 
 type BlockNumber = u32;
 type Header = sp_runtime::generic::Header<BlockNumber, sp_runtime::traits::BlakeTwo256>;
@@ -53,11 +56,16 @@ type Hash = sp_core::H256;
 type FullClient<RuntimeApi, Executor> = TFullClient<Block, RuntimeApi, Executor>;
 type FullBackend = TFullBackend<Block>;
 
-// Native executor instance.
+// Native executor instance. The tracing host functions are available to every runtime, only
+// tracing runtimes calling them.
 native_executor_instance!(
 	pub HedgewareParachainRuntimeExecutor,
 	hedgeware_parachain_runtime::api::dispatch,
 	hedgeware_parachain_runtime::native_version,
+	(
+		frame_benchmarking::benchmarking::HostFunctions,
+		hedgeware_primitives_ext::hedgeware_ext::HostFunctions,
+	),
 );
 
 pub fn frontier_database_dir(config: &Configuration) -> std::path::PathBuf {
@@ -84,6 +92,51 @@ pub fn open_frontier_backend(config: &Configuration) -> Result<Arc<fc_db::Backen
 	)?))
 }
 
+pub fn trace_database_dir(config: &Configuration) -> std::path::PathBuf {
+	frontier_database_dir(config).with_file_name("trace")
+}
+
+/// Open the database of the trace index, if it is enabled.
+pub fn open_trace_db(
+	config: &Configuration,
+	rpc_config: &cli_opt::RpcConfig,
+) -> Result<Option<Arc<hedgeware_rpc_trace::TraceDb>>, String> {
+	if rpc_config.ethapi_trace_index.is_none()
+		|| !rpc_config.ethapi.contains(&cli_opt::EthApi::Trace)
+	{
+		return Ok(None);
+	}
+
+	Ok(Some(Arc::new(hedgeware_rpc_trace::TraceDb::open(
+		&trace_database_dir(config),
+	)?)))
+}
+
+/// Load the tracing runtimes replacing the on-chain runtimes, if any.
+pub fn open_tracing_runtimes<Executor: NativeExecutionDispatch + 'static>(
+	config: &Configuration,
+	rpc_config: &cli_opt::RpcConfig,
+	task_manager: &TaskManager,
+) -> Result<Option<Arc<TracingRuntimes<Block, FullBackend>>>, String> {
+	let dir = match &rpc_config.ethapi_tracing_runtimes {
+		Some(dir) => dir,
+		None => return Ok(None),
+	};
+
+	// Tracing runtimes call the tracing host functions of the native executor.
+	let executor = NativeExecutor::<Executor>::new(
+		config.wasm_method,
+		config.default_heap_pages,
+		config.max_runtime_instances,
+	);
+
+	Ok(Some(Arc::new(TracingRuntimes::new(
+		dir,
+		executor,
+		task_manager.spawn_handle(),
+	)?)))
+}
+
 /// Starts a `ServiceBuilder` for a full service.
 ///
 /// Use this macro if you don't actually need the full service, but just the builder in order to
@@ -203,12 +256,13 @@ where
 ///
 /// This is the actual implementation that is abstract over the executor and the runtime api.
 #[sc_tracing::logging::prefix_logs_with("Parachain")]
-async fn start_node_impl<RuntimeApi, Executor, RB, BIQ, BIC>(
+async fn start_node_impl<RuntimeApi, Executor, RClient, RB, BRN, BIQ, BIC>(
 	parachain_config: Configuration,
 	polkadot_config: Configuration,
 	id: ParaId,
 	rpc_config: cli_opt::RpcConfig,
 	_rpc_ext_builder: RB,
+	build_relay_chain_full_node: BRN,
 	build_import_queue: BIQ,
 	build_consensus: BIC,
 ) -> sc_service::error::Result<(TaskManager, Arc<TFullClient<Block, RuntimeApi, Executor>>)>
@@ -233,11 +287,16 @@ where
 	RuntimeApi::RuntimeApi:
 		RuntimeApiCollection<StateBackend = sc_client_api::StateBackendFor<FullBackend, Block>>,
 	Executor: NativeExecutionDispatch + 'static,
+	RClient: polkadot_service::ClientHandle + Clone + Send + Sync + 'static,
 	RB: Fn(
 			Arc<TFullClient<Block, RuntimeApi, Executor>>,
 		) -> jsonrpc_core::IoHandler<sc_rpc::Metadata>
 		+ Send
 		+ 'static,
+	BRN: FnOnce(
+		Configuration,
+		Option<TelemetryWorkerHandle>,
+	) -> Result<polkadot_service::NewFull<RClient>, polkadot_service::Error>,
 	BIQ: FnOnce(
 		Arc<TFullClient<Block, RuntimeApi, Executor>>,
 		&Configuration,
@@ -252,7 +311,7 @@ where
 		Option<&Registry>,
 		Option<TelemetryHandle>,
 		&TaskManager,
-		&polkadot_service::NewFull<polkadot_service::Client>,
+		&polkadot_service::NewFull<RClient>,
 		Arc<sc_transaction_pool::FullPool<Block, TFullClient<Block, RuntimeApi, Executor>>>,
 		Arc<NetworkService<Block, Hash>>,
 		SyncCryptoStorePtr,
@@ -275,7 +334,7 @@ where
 		frontier_backend
 	) = params.other;
 
-	let relay_chain_full_node = cumulus_client_service::build_polkadot_full_node(
+	let relay_chain_full_node = build_relay_chain_full_node(
 		polkadot_config,
 		telemetry_worker_handle,
 	)
@@ -312,6 +371,9 @@ where
 
 	let subscription_task_executor = sc_rpc::SubscriptionTaskExecutor::new(task_manager.spawn_handle());
 
+	let trace_db = open_trace_db(&parachain_config, &rpc_config)?;
+	let tracing_runtimes =
+		open_tracing_runtimes::<Executor>(&parachain_config, &rpc_config, &task_manager)?;
 	let spawned_requesters = crate::rpc::spawn_tasks(
 		&rpc_config,
 		crate::rpc::SpawnTasksParams {
@@ -319,8 +381,13 @@ where
 			client: client.clone(),
 			substrate_backend: backend.clone(),
 			frontier_backend: frontier_backend.clone(),
+			graph: transaction_pool.pool().clone(),
 			pending_transactions: pending_transactions.clone(),
 			filter_pool: filter_pool.clone(),
+			trace_db,
+			tracing_runtimes,
+			state_archive: parachain_config.state_pruning.is_archive(),
+			prometheus_registry: prometheus_registry.clone(),
 		},
 	);
 
@@ -350,7 +417,10 @@ where
 				backend: backend.clone(),
 				debug_requester: spawned_requesters.debug.clone(),
 				trace_filter_requester: spawned_requesters.trace.clone(),
+				trace_db: spawned_requesters.trace_db.clone(),
 				trace_filter_max_count: rpc_config.ethapi_trace_max_count,
+				trace_timeout: Duration::from_secs(rpc_config.ethapi_trace_timeout),
+				trace_filter_timeout: Duration::from_secs(rpc_config.ethapi_trace_filter_timeout),
 				max_past_logs,
 			};
 
@@ -477,12 +547,44 @@ pub async fn start_hedgeware_parachain_node(
 ) -> sc_service::error::Result<
 	(TaskManager, Arc<TFullClient<Block, hedgeware_parachain_runtime::RuntimeApi, HedgewareParachainRuntimeExecutor>>)
 > {
-	start_node_impl::<hedgeware_parachain_runtime::RuntimeApi, HedgewareParachainRuntimeExecutor, _, _, _>(
+	start_hedgeware_parachain_node_with_relay(
+		parachain_config,
+		polkadot_config,
+		id,
+		rpc_config,
+		cumulus_client_service::build_polkadot_full_node,
+	)
+	.await
+}
+
+/// Start a rococo parachain node, building the embedded relay chain node with
+/// `build_relay_chain_full_node`.
+///
+/// This lets the local testnet harness run the collator against an in-process
+/// test relay chain instead of a real one.
+pub async fn start_hedgeware_parachain_node_with_relay<RClient, BRN>(
+	parachain_config: Configuration,
+	polkadot_config: Configuration,
+	id: ParaId,
+	rpc_config: cli_opt::RpcConfig,
+	build_relay_chain_full_node: BRN,
+) -> sc_service::error::Result<
+	(TaskManager, Arc<TFullClient<Block, hedgeware_parachain_runtime::RuntimeApi, HedgewareParachainRuntimeExecutor>>)
+>
+where
+	RClient: polkadot_service::ClientHandle + Clone + Send + Sync + 'static,
+	BRN: FnOnce(
+		Configuration,
+		Option<TelemetryWorkerHandle>,
+	) -> Result<polkadot_service::NewFull<RClient>, polkadot_service::Error>,
+{
+	start_node_impl::<hedgeware_parachain_runtime::RuntimeApi, HedgewareParachainRuntimeExecutor, RClient, _, _, _, _>(
 		parachain_config,
 		polkadot_config,
 		id,
 		rpc_config,
 		|_| Default::default(),
+		build_relay_chain_full_node,
 		hedgeware_parachain_build_import_queue,
 		|client,
 		 prometheus_registry,
@@ -561,3 +663,176 @@ pub async fn start_hedgeware_parachain_node(
 	)
 	.await
 }
+
+/// Build the development service: a single node authoring a block as soon as a
+/// transaction is ready, without a backing relay chain.
+///
+/// The relay chain data the runtime expects is mocked, so this runs on top of any
+/// state, including state forked from a live chain with the `fork` subcommand.
+pub fn new_dev(
+	config: Configuration,
+	rpc_config: cli_opt::RpcConfig,
+) -> Result<TaskManager, sc_service::error::Error> {
+	let PartialComponents {
+		client,
+		backend,
+		mut task_manager,
+		import_queue,
+		keystore_container,
+		select_chain: _,
+		transaction_pool,
+		other: (
+			frontier_block_import,
+			mut telemetry,
+			_telemetry_worker_handle,
+			pending_transactions,
+			filter_pool,
+			frontier_backend,
+		),
+	} = new_partial::<hedgeware_parachain_runtime::RuntimeApi, HedgewareParachainRuntimeExecutor, _>(
+		&config,
+		|client, config, _, task_manager| {
+			Ok(sc_consensus_manual_seal::import_queue(
+				Box::new(client),
+				&task_manager.spawn_essential_handle(),
+				config.prometheus_registry(),
+			))
+		},
+	)?;
+
+	let (network, system_rpc_tx, start_network) =
+		sc_service::build_network(sc_service::BuildNetworkParams {
+			config: &config,
+			client: client.clone(),
+			transaction_pool: transaction_pool.clone(),
+			spawn_handle: task_manager.spawn_handle(),
+			import_queue,
+			on_demand: None,
+			block_announce_validator_builder: None,
+		})?;
+
+	let prometheus_registry = config.prometheus_registry().cloned();
+	let subscription_task_executor = sc_rpc::SubscriptionTaskExecutor::new(task_manager.spawn_handle());
+
+	let trace_db = open_trace_db(&config, &rpc_config)?;
+	let tracing_runtimes = open_tracing_runtimes::<HedgewareParachainRuntimeExecutor>(
+		&config,
+		&rpc_config,
+		&task_manager,
+	)?;
+	let spawned_requesters = crate::rpc::spawn_tasks(
+		&rpc_config,
+		crate::rpc::SpawnTasksParams {
+			task_manager: &task_manager,
+			client: client.clone(),
+			substrate_backend: backend.clone(),
+			frontier_backend: frontier_backend.clone(),
+			graph: transaction_pool.pool().clone(),
+			pending_transactions: pending_transactions.clone(),
+			filter_pool: filter_pool.clone(),
+			trace_db,
+			tracing_runtimes,
+			state_archive: config.state_pruning.is_archive(),
+			prometheus_registry: prometheus_registry.clone(),
+		},
+	);
+
+	if config.role.is_authority() {
+		let proposer_factory = sc_basic_authorship::ProposerFactory::new(
+			task_manager.spawn_handle(),
+			client.clone(),
+			transaction_pool.clone(),
+			prometheus_registry.as_ref(),
+			telemetry.as_ref().map(|t| t.handle()),
+		);
+		let select_chain = sc_consensus::LongestChain::new(backend.clone());
+		let client_for_cidp = client.clone();
+
+		task_manager.spawn_essential_handle().spawn_blocking(
+			"authorship_task",
+			sc_consensus_manual_seal::run_instant_seal(sc_consensus_manual_seal::InstantSealParams {
+				block_import: frontier_block_import,
+				env: proposer_factory,
+				client: client.clone(),
+				pool: transaction_pool.pool().clone(),
+				select_chain,
+				consensus_data_provider: None,
+				create_inherent_data_providers: move |block: Hash, ()| {
+					let current_para_block = client_for_cidp
+						.number(block)
+						.expect("Header lookup should succeed")
+						.expect("Header passed in as parent should be present in backend.");
+
+					async move {
+						let time = sp_timestamp::InherentDataProvider::from_system_time();
+
+						let mocked_parachain =
+							cumulus_primitives_parachain_inherent::MockValidationDataInherentDataProvider {
+								current_para_block,
+								relay_offset: 1000,
+								relay_blocks_per_para_block: 2,
+							};
+
+						Ok((time, mocked_parachain))
+					}
+				},
+			}),
+		);
+	}
+
+	let rpc_extensions_builder = {
+		let client = client.clone();
+		let pool = transaction_pool.clone();
+		let network = network.clone();
+		let pending = pending_transactions.clone();
+		let filter_pool = filter_pool.clone();
+		let frontier_backend = frontier_backend.clone();
+		let backend = backend.clone();
+		let ethapi_cmd = rpc_config.ethapi.clone();
+		let max_past_logs = rpc_config.max_past_logs;
+
+		Box::new(move |deny_unsafe, _| {
+			let deps = crate::rpc::FullDeps {
+				client: client.clone(),
+				pool: pool.clone(),
+				graph: pool.pool().clone(),
+				deny_unsafe,
+				is_authority: true,
+				network: network.clone(),
+				pending_transactions: pending.clone(),
+				filter_pool: filter_pool.clone(),
+				ethapi_cmd: ethapi_cmd.clone(),
+				frontier_backend: frontier_backend.clone(),
+				backend: backend.clone(),
+				debug_requester: spawned_requesters.debug.clone(),
+				trace_filter_requester: spawned_requesters.trace.clone(),
+				trace_db: spawned_requesters.trace_db.clone(),
+				trace_filter_max_count: rpc_config.ethapi_trace_max_count,
+				trace_timeout: Duration::from_secs(rpc_config.ethapi_trace_timeout),
+				trace_filter_timeout: Duration::from_secs(rpc_config.ethapi_trace_filter_timeout),
+				max_past_logs,
+			};
+
+			crate::rpc::create_full(deps, subscription_task_executor.clone())
+		})
+	};
+
+	sc_service::spawn_tasks(sc_service::SpawnTasksParams {
+		on_demand: None,
+		remote_blockchain: None,
+		rpc_extensions_builder,
+		client,
+		transaction_pool,
+		task_manager: &mut task_manager,
+		config,
+		keystore: keystore_container.sync_keystore(),
+		backend,
+		network,
+		system_rpc_tx,
+		telemetry: telemetry.as_mut(),
+	})?;
+
+	start_network.start_network();
+
+	Ok(task_manager)
+}
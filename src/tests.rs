@@ -0,0 +1,275 @@
+// Copyright 2019-2021 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{
+	chain_spec::{get_account_id_from_seed, get_from_seed},
+	fork::{fork_state, storage_key, DEV_ACCOUNT_ENDOWMENT},
+	frontier_db::{columns, has_layout, is_stale, prune_mappings, Pruned},
+	local_testnet::LocalTestnet,
+	service::Block,
+};
+use codec::Encode;
+use hedgeware_parachain_runtime::{AuraId, Balances};
+use sc_service::TaskExecutor;
+use sp_blockchain::{BlockStatus, HeaderBackend, Info};
+use sp_core::{
+	hashing::twox_128,
+	sr25519,
+	storage::{well_known_keys, Storage},
+	H256,
+};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, NumberFor},
+};
+use sp_state_machine::BasicExternalities;
+use std::{path::Path, time::Duration};
+
+/// Storage with values for the keys `fork_state` rewrites, wipes and keeps.
+fn live_storage() -> Storage {
+	let mut storage = Storage::default();
+	storage.top.insert(well_known_keys::CODE.to_vec(), b"live runtime".to_vec());
+	storage.top.insert(storage_key(b"Sudo", b"Key"), vec![1u8; 32]);
+	storage.top.insert(storage_key(b"Aura", b"Authorities"), vec![0]);
+	storage.top.insert(storage_key(b"AuraExt", b"Authorities"), vec![0]);
+	storage.top.insert(storage_key(b"ParachainSystem", b"ValidationData"), vec![1, 2, 3]);
+	storage.top.insert(
+		[storage_key(b"ParachainSystem", b"DidSetValidationCode"), vec![7]].concat(),
+		vec![1],
+	);
+	storage.top.insert(storage_key(b"TreasuryReward", b"MintingInterval"), 10u32.encode());
+	storage.top.insert(storage_key(b"TreasuryReward", b"Recipients"), vec![0]);
+	storage.top.insert(
+		[storage_key(b"EVM", b"AccountCodes"), vec![0xaa; 20]].concat(),
+		vec![0x60, 0x80],
+	);
+	storage.top.insert(
+		[storage_key(b"EVM", b"AccountStorages"), vec![0xaa; 20], vec![0; 32]].concat(),
+		vec![1; 32],
+	);
+	storage
+}
+
+#[test]
+fn forked_state_is_authored_by_alice() {
+	let mut storage = live_storage();
+	fork_state(&mut storage, false).unwrap();
+
+	let alice = get_account_id_from_seed::<sr25519::Public>("Alice");
+	let alice_aura = get_from_seed::<AuraId>("Alice");
+	assert_eq!(storage.top[&storage_key(b"Sudo", b"Key")], alice.encode());
+	assert_eq!(
+		storage.top[&storage_key(b"Aura", b"Authorities")],
+		vec![alice_aura.clone()].encode(),
+	);
+	assert_eq!(
+		storage.top[&storage_key(b"AuraExt", b"Authorities")],
+		vec![alice_aura].encode(),
+	);
+}
+
+#[test]
+fn forked_state_wipes_parachain_system_only() {
+	let live = live_storage();
+	let mut storage = live.clone();
+	fork_state(&mut storage, false).unwrap();
+
+	let parachain_system = twox_128(b"ParachainSystem");
+	assert!(storage.top.keys().all(|key| !key.starts_with(&parachain_system)));
+
+	let kept = [twox_128(b"TreasuryReward"), twox_128(b"EVM")];
+	for (key, value) in live.top.iter() {
+		if kept.iter().any(|prefix| key.starts_with(prefix)) {
+			assert_eq!(storage.top.get(key), Some(value));
+		}
+	}
+	assert_eq!(storage.top[well_known_keys::CODE], b"live runtime".to_vec());
+}
+
+#[test]
+fn forked_state_endows_alice() {
+	let mut storage = live_storage();
+	fork_state(&mut storage, false).unwrap();
+
+	let alice = get_account_id_from_seed::<sr25519::Public>("Alice");
+	BasicExternalities::execute_with_storage(&mut storage, || {
+		assert_eq!(Balances::free_balance(&alice), DEV_ACCOUNT_ENDOWMENT);
+		assert_eq!(Balances::total_issuance(), DEV_ACCOUNT_ENDOWMENT);
+	});
+}
+
+#[test]
+fn forked_state_can_swap_in_the_local_runtime() {
+	let mut storage = live_storage();
+	let result = fork_state(&mut storage, true);
+
+	match hedgeware_parachain_runtime::WASM_BINARY {
+		Some(code) => {
+			result.unwrap();
+			assert_eq!(storage.top[well_known_keys::CODE], code.to_vec());
+		}
+		// Built with `SKIP_WASM_BUILD`, there is no runtime to swap in.
+		None => assert!(result.is_err()),
+	}
+}
+
+/// Chain known by a client, with `canonical[n]` the canonical block at height `n`.
+struct Chain {
+	canonical: Vec<H256>,
+	forks: Vec<(H256, NumberFor<Block>)>,
+}
+
+impl HeaderBackend<Block> for Chain {
+	fn header(&self, _: BlockId<Block>) -> sp_blockchain::Result<Option<<Block as BlockT>::Header>> {
+		unimplemented!()
+	}
+
+	fn info(&self) -> Info<Block> {
+		unimplemented!()
+	}
+
+	fn status(&self, _: BlockId<Block>) -> sp_blockchain::Result<BlockStatus> {
+		unimplemented!()
+	}
+
+	fn number(&self, hash: H256) -> sp_blockchain::Result<Option<NumberFor<Block>>> {
+		let canonical = self.canonical.iter().position(|h| *h == hash);
+		Ok(canonical.map(|number| number as NumberFor<Block>).or_else(|| {
+			self.forks.iter().find(|(h, _)| *h == hash).map(|(_, number)| *number)
+		}))
+	}
+
+	fn hash(&self, number: NumberFor<Block>) -> sp_blockchain::Result<Option<H256>> {
+		Ok(self.canonical.get(number as usize).copied())
+	}
+}
+
+#[test]
+fn only_blocks_left_behind_by_finality_are_stale() {
+	let chain = Chain {
+		canonical: (0..5).map(H256::repeat_byte).collect(),
+		forks: vec![(H256::repeat_byte(0xf2), 2), (H256::repeat_byte(0xf3), 3)],
+	};
+	let finalized_number = 2;
+	let stale = |hash: H256| is_stale(&chain, finalized_number, &hash).unwrap();
+
+	// Canonical blocks, finalized or not
+	assert!(!stale(H256::repeat_byte(1)));
+	assert!(!stale(H256::repeat_byte(2)));
+	assert!(!stale(H256::repeat_byte(4)));
+	// A fork at a finalized height can't become canonical anymore
+	assert!(stale(H256::repeat_byte(0xf2)));
+	// A fork above the finalized block still can
+	assert!(!stale(H256::repeat_byte(0xf3)));
+	// A block the client doesn't know about never will
+	assert!(stale(H256::repeat_byte(0xee)));
+}
+
+fn frontier_backend(path: &Path) -> fc_db::Backend<Block> {
+	fc_db::Backend::<Block>::new(&fc_db::DatabaseSettings {
+		source: fc_db::DatabaseSettingsSrc::RocksDb {
+			path: path.to_path_buf(),
+			cache_size: 0,
+		},
+	})
+	.unwrap()
+}
+
+fn raw_frontier_db(path: &Path) -> kvdb_rocksdb::Database {
+	kvdb_rocksdb::Database::open(
+		&kvdb_rocksdb::DatabaseConfig::with_columns(columns::NUM_COLUMNS),
+		&path.to_string_lossy(),
+	)
+	.unwrap()
+}
+
+#[test]
+fn frontier_db_is_pruned_through_the_fc_db_layout() {
+	let dir = tempfile::tempdir().unwrap();
+	let canonical = H256::repeat_byte(1);
+	let stale = H256::repeat_byte(2);
+	let (canonical_ethereum, stale_ethereum) = (H256::repeat_byte(0xc1), H256::repeat_byte(0xc2));
+	// Included in both blocks, and only in the stale one
+	let (shared_transaction, stale_transaction) =
+		(H256::repeat_byte(0xd1), H256::repeat_byte(0xd2));
+
+	{
+		let backend = frontier_backend(dir.path());
+		backend
+			.mapping()
+			.write_hashes(fc_db::MappingCommitment {
+				block_hash: canonical,
+				ethereum_block_hash: canonical_ethereum,
+				ethereum_transaction_hashes: vec![shared_transaction],
+			})
+			.unwrap();
+		backend
+			.mapping()
+			.write_hashes(fc_db::MappingCommitment {
+				block_hash: stale,
+				ethereum_block_hash: stale_ethereum,
+				ethereum_transaction_hashes: vec![shared_transaction, stale_transaction],
+			})
+			.unwrap();
+		backend
+			.meta()
+			.write_current_syncing_tips(vec![canonical, stale])
+			.unwrap();
+	}
+
+	{
+		let db = raw_frontier_db(dir.path());
+		assert!(has_layout(&db, (canonical_ethereum, canonical)).unwrap());
+		assert!(!has_layout(&db, (canonical_ethereum, stale)).unwrap());
+		assert!(!has_layout(&db, (H256::repeat_byte(0xc3), canonical)).unwrap());
+
+		let pruned = prune_mappings(&db, |hash| Ok(*hash == stale)).unwrap();
+		assert_eq!(
+			pruned,
+			Pruned {
+				blocks: 1,
+				transactions: 2,
+				synced: 1,
+			},
+		);
+	}
+
+	let backend = frontier_backend(dir.path());
+	let mapping = backend.mapping();
+	assert_eq!(mapping.block_hash(&canonical_ethereum).unwrap(), Some(canonical));
+	assert_eq!(mapping.block_hash(&stale_ethereum).unwrap(), None);
+	let shared = mapping.transaction_metadata(&shared_transaction).unwrap();
+	assert_eq!(shared.len(), 1);
+	assert_eq!(shared[0].block_hash, canonical);
+	assert!(mapping.transaction_metadata(&stale_transaction).unwrap().is_empty());
+	assert!(mapping.is_synced(&canonical).unwrap());
+	assert!(!mapping.is_synced(&stale).unwrap());
+	assert_eq!(backend.meta().current_syncing_tips().unwrap(), vec![canonical]);
+}
+
+// Starts a whole relay chain and takes minutes, run it with `cargo test -- --ignored`.
+#[substrate_test_utils::test]
+#[ignore]
+async fn collator_blocks_are_produced_and_included(task_executor: TaskExecutor) {
+	let testnet = LocalTestnet::start(task_executor, 2000.into())
+		.await
+		.expect("local testnet starts");
+
+	testnet
+		.wait_for_blocks(3, Duration::from_secs(300))
+		.await
+		.expect("parachain blocks are produced and included");
+}